@@ -0,0 +1,211 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use serde_json::{Map, Value};
+use similar::TextDiff;
+
+use crate::{jobs::flags, DissectError};
+
+/// How many lines of the diff of a changed file are printed
+const DIFF_LINES: usize = 60;
+
+/// Run an export pipeline on a fixture and compare what it writes with golden files, failing on any difference
+#[derive(Debug, clap::Args)]
+pub struct CheckArgs {
+    /// The pipeline, a toml file naming the fixture as `input` and the options of the export as keys,
+    /// like `format = "ndjson"`, `single = true` or `redact = "rules.yaml"`, paths are relative to it
+    #[clap(long)]
+    pub pipeline: PathBuf,
+
+    /// Directory of the golden files, the outputs the pipeline is expected to write
+    #[clap(long)]
+    pub golden: PathBuf,
+
+    /// Replace the golden files with what the pipeline writes now instead of comparing
+    #[clap(long)]
+    pub update: bool,
+}
+
+/// The export a pipeline file describes
+struct Pipeline {
+    /// Directory of the pipeline file, the export runs in it
+    dir: PathBuf,
+    input: String,
+    /// Name of the output inside the golden directory
+    output: String,
+    options: Vec<String>,
+}
+
+impl Pipeline {
+    fn load(path: &Path) -> Result<Self, DissectError> {
+        let mut table: Map<String, Value> = toml::from_str(&fs::read_to_string(path)?)?;
+        let mut take = |key: &str| match table.remove(key) {
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(other) => Err(DissectError::Parse(format!(
+                "{key} of {} must be a string, got {other}",
+                path.display()
+            ))),
+            None => Ok(None),
+        };
+        let input = take("input")?.ok_or_else(|| {
+            DissectError::Parse(format!("{} doesn't name the input", path.display()))
+        })?;
+        let output = take("output")?.unwrap_or_else(|| "output".into());
+        // the order of a --single output depends on which thread finishes first
+        if !table.contains_key("threads") {
+            table.insert("threads".into(), Value::from(1));
+        }
+        let options = flags(&table)?;
+        Ok(Self {
+            dir: match path.parent() {
+                Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            },
+            input,
+            output,
+            options,
+        })
+    }
+
+    /// Run the export into `dir`, the way the command line would with these options
+    fn run(&self, dir: &Path) -> Result<(), DissectError> {
+        let result = Command::new(std::env::current_exe()?)
+            .current_dir(&self.dir)
+            .arg(&self.input)
+            .arg(dir.join(&self.output))
+            .args(&self.options)
+            .output()?;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(DissectError::Unexpected(format!(
+                "The pipeline failed with {}: {}",
+                result.status,
+                stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn run(args: &CheckArgs) -> Result<(), DissectError> {
+    let pipeline = Pipeline::load(&args.pipeline)?;
+    let scratch = std::env::temp_dir().join(format!("dissbson-check-{}", process::id()));
+    fs::create_dir_all(&scratch)?;
+    let result = pipeline.run(&scratch).and_then(|_| match args.update {
+        true => update(&scratch, &args.golden),
+        false => compare(&scratch, &args.golden),
+    });
+    fs::remove_dir_all(&scratch)?;
+    result
+}
+
+/// Replace the golden files with the outputs of the run
+fn update(outputs: &Path, golden: &Path) -> Result<(), DissectError> {
+    if golden.exists() {
+        fs::remove_dir_all(golden)?;
+    }
+    let files = files(outputs)?;
+    for file in &files {
+        let target = golden.join(file);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::copy(outputs.join(file), target)?;
+    }
+    println!("Wrote {} golden files to {}", files.len(), golden.display());
+    Ok(())
+}
+
+/// Compare the outputs of the run with the golden files, printing a diff of every changed file
+fn compare(outputs: &Path, golden: &Path) -> Result<(), DissectError> {
+    if !golden.is_dir() {
+        return Err(DissectError::Parse(format!(
+            "No golden files in {}, write them with --update",
+            golden.display()
+        )));
+    }
+    let written = files(outputs)?;
+    let expected = files(golden)?;
+    let mut failures = Vec::new();
+    for file in expected.difference(&written) {
+        failures.push(format!("{}: not written", file.display()));
+    }
+    for file in written.difference(&expected) {
+        failures.push(format!(
+            "{}: written but not in the golden files",
+            file.display()
+        ));
+    }
+    for file in written.intersection(&expected) {
+        let old = fs::read(golden.join(file))?;
+        let new = fs::read(outputs.join(file))?;
+        if old != new {
+            failures.push(format!(
+                "{}: differs\n{}",
+                file.display(),
+                diff(file, &old, &new)
+            ));
+        }
+    }
+    for failure in &failures {
+        eprintln!("{failure}");
+    }
+    if !failures.is_empty() {
+        return Err(DissectError::Unexpected(format!(
+            "{} of {} files differ from the golden files in {}",
+            failures.len(),
+            written.union(&expected).count(),
+            golden.display()
+        )));
+    }
+    println!(
+        "All {} files match the golden files in {}",
+        written.len(),
+        golden.display()
+    );
+    Ok(())
+}
+
+/// A unified diff of a changed text file, or the sizes of a binary one
+fn diff(file: &Path, old: &[u8], new: &[u8]) -> String {
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return format!(
+            "binary, {} bytes expected and {} written",
+            old.len(),
+            new.len()
+        );
+    };
+    let golden = format!("golden/{}", file.display());
+    let written = format!("written/{}", file.display());
+    let diff = TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&golden, &written)
+        .to_string();
+    let lines = diff.lines().count();
+    let mut shown = diff.lines().take(DIFF_LINES).collect::<Vec<_>>().join("\n");
+    if lines > DIFF_LINES {
+        shown.push_str(&format!("\n... {} more lines", lines - DIFF_LINES));
+    }
+    shown
+}
+
+/// Every file under `root`, relative to it
+fn files(root: &Path) -> Result<BTreeSet<PathBuf>, DissectError> {
+    let mut found = BTreeSet::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(file) = path.strip_prefix(root) {
+                found.insert(file.to_path_buf());
+            }
+        }
+    }
+    Ok(found)
+}
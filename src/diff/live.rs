@@ -0,0 +1,101 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use bson::{doc, Document};
+use mongodb::sync::Client;
+
+use super::{index_keys, remaining_keys, ChangeWriter, CompareArgs};
+use crate::{
+    index::{self, document_key},
+    DissectError,
+};
+
+/// Compare a dump against a live collection without exporting either side
+#[derive(Debug, clap::Args)]
+pub struct LiveDiffArgs {
+    /// The dump to compare
+    pub dump: PathBuf,
+
+    /// The live collection in the form `mongodb://host:port/db.collection`
+    pub uri: String,
+
+    /// File to write the drift to, one json object per drifted document
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    #[clap(flatten)]
+    pub compare: CompareArgs,
+}
+
+pub(crate) fn run(args: &LiveDiffArgs) -> Result<(), DissectError> {
+    let compare = &args.compare;
+    let (uri, db, collection) = split_uri(&args.uri)?;
+
+    let idx = index::load_or_build(&args.dump, compare.inspect)?;
+    let mut file = OpenOptions::new().read(true).open(&args.dump)?;
+    println!("Indexing keys of {}", args.dump.display());
+    let mut dump_keys = index_keys(&mut file, &idx, &compare.key)?;
+
+    let out = BufWriter::new(File::create(&args.output)?);
+    let mut changes = ChangeWriter::new(out, compare.normalizer(), compare.patch);
+
+    println!("Comparing against {db}.{collection}");
+    let client = Client::with_uri_str(uri)?;
+    let cursor = client
+        .database(&db)
+        .collection::<Document>(&collection)
+        .find(doc! {})
+        .run()?;
+    for live in cursor {
+        let live = live?;
+        let key = document_key(&live, &compare.key)?;
+        match dump_keys.remove(&key) {
+            Some(offset) => {
+                let dumped = index::read_document(&mut file, &offset)?;
+                changes.compare(&key, dumped, live)?;
+            }
+            None => changes.added(&key, live, "extra")?,
+        }
+    }
+
+    // documents in the dump the cursor never returned
+    for key in remaining_keys(dump_keys) {
+        changes.removed(&key, "missing")?;
+    }
+    let summary = changes.finish()?;
+
+    println!(
+        "{} missing, {} extra, {} changed, {} unchanged",
+        summary.removed, summary.added, summary.changed, summary.unchanged
+    );
+    println!("Wrote drift to {}", args.output.display());
+    Ok(())
+}
+
+/// Split `mongodb://host/db.collection?options` into a client uri, database and collection
+fn split_uri(uri: &str) -> Result<(String, String, String), DissectError> {
+    let invalid =
+        || DissectError::Parse(format!("Expected mongodb://host/db.collection, got {uri}"));
+
+    let scheme_end = uri.find("://").ok_or_else(invalid)? + 3;
+    let (scheme, rest) = uri.split_at(scheme_end);
+    let (hosts, path) = rest.split_once('/').ok_or_else(invalid)?;
+    let (namespace, options) = match path.split_once('?') {
+        Some((namespace, options)) => (namespace, Some(options)),
+        None => (path, None),
+    };
+    let (db, collection) = namespace.split_once('.').ok_or_else(invalid)?;
+    if db.is_empty() || collection.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut client_uri = format!("{scheme}{hosts}/");
+    if let Some(options) = options {
+        client_uri.push('?');
+        client_uri.push_str(options);
+    }
+    Ok((client_uri, db.to_string(), collection.to_string()))
+}
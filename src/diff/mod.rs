@@ -0,0 +1,302 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Seek, Write},
+    path::PathBuf,
+};
+
+use bson::{Bson, Document};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+
+#[cfg(feature = "live")]
+pub(crate) mod live;
+pub(crate) mod schema;
+
+use crate::{
+    index::{self, raw_document_key, DocOffset},
+    normalize::Normalizer,
+    DissectError,
+};
+
+/// Compare two dumps document by document, documents are matched using a key field
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    /// The original dump
+    pub old: PathBuf,
+
+    /// The updated dump
+    pub new: PathBuf,
+
+    /// File to write the changes to, one json object per changed document
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    #[clap(flatten)]
+    pub compare: CompareArgs,
+}
+
+/// Options shared by the commands comparing documents
+#[derive(Debug, clap::Args)]
+pub struct CompareArgs {
+    /// Field used to match documents between both sides
+    #[clap(short, long, default_value = "_id")]
+    pub key: String,
+
+    /// Kind of patch emitted for each changed document
+    #[clap(short, long, value_enum, default_value_t = PatchFormat::JsonPatch)]
+    pub patch: PatchFormat,
+
+    /// Inspect the dumps again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+
+    /// Fields left out of the comparison, e.g. `updatedAt,__v`
+    #[clap(long, value_delimiter = ',')]
+    pub ignore_fields: Vec<String>,
+
+    /// Arrays compared without regard to the order of their elements
+    #[clap(long, value_delimiter = ',')]
+    pub ignore_order_arrays: Vec<String>,
+}
+
+impl CompareArgs {
+    pub(crate) fn normalizer(&self) -> Normalizer {
+        Normalizer::new(&self.ignore_fields, &self.ignore_order_arrays)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PatchFormat {
+    /// RFC 6902 JSON Patch, a list of operations
+    JsonPatch,
+    /// RFC 7386 JSON Merge Patch, a partial document
+    MergePatch,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DiffSummary {
+    pub(crate) added: usize,
+    pub(crate) removed: usize,
+    pub(crate) changed: usize,
+    pub(crate) unchanged: usize,
+}
+
+pub(crate) fn run(args: &DiffArgs) -> Result<(), DissectError> {
+    let compare = &args.compare;
+    let old_idx = index::load_or_build(&args.old, compare.inspect)?;
+    let new_idx = index::load_or_build(&args.new, compare.inspect)?;
+
+    let mut old_file = OpenOptions::new().read(true).open(&args.old)?;
+    let mut new_file = OpenOptions::new().read(true).open(&args.new)?;
+
+    println!("Indexing keys of {}", args.old.display());
+    let mut old_keys = index_keys(&mut old_file, &old_idx, &compare.key)?;
+
+    let out = BufWriter::new(File::create(&args.output)?);
+    let mut changes = ChangeWriter::new(out, compare.normalizer(), compare.patch);
+
+    println!("Comparing against {}", args.new.display());
+    for offset in &new_idx {
+        let raw = index::read_raw(&mut new_file, offset)?;
+        let key = raw_document_key(&raw, &compare.key)?;
+        let new_doc = bson::Document::from_reader(&mut raw.as_slice())?;
+
+        match old_keys.remove(&key) {
+            Some(old_offset) => {
+                let old_doc = index::read_document(&mut old_file, &old_offset)?;
+                changes.compare(&key, old_doc, new_doc)?;
+            }
+            None => changes.added(&key, new_doc, "added")?,
+        }
+    }
+
+    // whatever is left in the old key set is not present in the new dump
+    for key in remaining_keys(old_keys) {
+        changes.removed(&key, "removed")?;
+    }
+    let summary = changes.finish()?;
+
+    println!(
+        "{} added, {} removed, {} changed, {} unchanged",
+        summary.added, summary.removed, summary.changed, summary.unchanged
+    );
+    println!("Wrote changes to {}", args.output.display());
+    Ok(())
+}
+
+/// Map the key of every indexed document to its offset
+pub(crate) fn index_keys<R: Read + Seek>(
+    reader: &mut R,
+    idx: &[DocOffset],
+    key: &str,
+) -> Result<HashMap<String, DocOffset>, DissectError> {
+    let mut keys = HashMap::with_capacity(idx.len());
+    for offset in idx {
+        let raw = index::read_raw(reader, offset)?;
+        keys.insert(raw_document_key(&raw, key)?, *offset);
+    }
+    Ok(keys)
+}
+
+/// Keys left in a key map in file order
+pub(crate) fn remaining_keys(keys: HashMap<String, DocOffset>) -> Vec<String> {
+    let mut remaining = keys.into_iter().collect::<Vec<_>>();
+    remaining.sort_by_key(|(_, offset)| offset.offset);
+    remaining.into_iter().map(|(key, _)| key).collect()
+}
+
+/// Compares matched documents and writes every change as a json line
+pub(crate) struct ChangeWriter<W: Write> {
+    out: W,
+    normalizer: Normalizer,
+    patch: PatchFormat,
+    summary: DiffSummary,
+}
+
+impl<W: Write> ChangeWriter<W> {
+    pub fn new(out: W, normalizer: Normalizer, patch: PatchFormat) -> Self {
+        Self {
+            out,
+            normalizer,
+            patch,
+            summary: DiffSummary::default(),
+        }
+    }
+
+    /// Record a document only present on the new side
+    pub fn added(&mut self, key: &str, doc: Document, change: &str) -> Result<(), DissectError> {
+        self.summary.added += 1;
+        let doc = to_json(doc, &self.normalizer);
+        let patch = match self.patch {
+            PatchFormat::JsonPatch => json!([{"op": "add", "path": "", "value": doc}]),
+            PatchFormat::MergePatch => doc,
+        };
+        self.write(key, change, patch)
+    }
+
+    /// Record a document only present on the old side
+    pub fn removed(&mut self, key: &str, change: &str) -> Result<(), DissectError> {
+        self.summary.removed += 1;
+        let patch = match self.patch {
+            PatchFormat::JsonPatch => json!([{"op": "remove", "path": ""}]),
+            PatchFormat::MergePatch => Value::Null,
+        };
+        self.write(key, change, patch)
+    }
+
+    /// Compare both versions of a document and record the patch if they differ
+    pub fn compare(&mut self, key: &str, old: Document, new: Document) -> Result<(), DissectError> {
+        let old = to_json(old, &self.normalizer);
+        let new = to_json(new, &self.normalizer);
+        let patch = match self.patch {
+            PatchFormat::JsonPatch => {
+                let mut ops = Vec::new();
+                json_patch(&old, &new, "", &mut ops);
+                (!ops.is_empty()).then_some(Value::Array(ops))
+            }
+            PatchFormat::MergePatch => merge_patch(&old, &new),
+        };
+
+        match patch {
+            Some(patch) => {
+                self.summary.changed += 1;
+                self.write(key, "changed", patch)
+            }
+            None => {
+                self.summary.unchanged += 1;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Result<DiffSummary, DissectError> {
+        self.out.flush()?;
+        Ok(self.summary)
+    }
+
+    /// Write one change as a json line, `key` is already serialized json
+    fn write(&mut self, key: &str, change: &str, patch: Value) -> Result<(), DissectError> {
+        write!(self.out, r#"{{"key":{key},"change":"{change}","patch":"#)?;
+        serde_json::to_writer(&mut self.out, &patch)?;
+        self.out.write_all(b"}\n")?;
+        Ok(())
+    }
+}
+
+fn to_json(mut doc: Document, normalizer: &Normalizer) -> Value {
+    normalizer.apply(&mut doc);
+    Bson::Document(doc).into_relaxed_extjson()
+}
+
+/// Escape a key for use in a json pointer
+fn pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Collect the RFC 6902 operations turning `old` into `new`
+fn json_patch(old: &Value, new: &Value, path: &str, ops: &mut Vec<Value>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = format!("{path}/{}", pointer_token(key));
+                match new.get(key) {
+                    Some(new_value) => json_patch(old_value, new_value, &path, ops),
+                    None => ops.push(json!({"op": "remove", "path": path})),
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    let path = format!("{path}/{}", pointer_token(key));
+                    ops.push(json!({"op": "add", "path": path, "value": new_value}));
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            let common = old.len().min(new.len());
+            for (i, (old_value, new_value)) in old.iter().zip(new).enumerate() {
+                json_patch(old_value, new_value, &format!("{path}/{i}"), ops);
+            }
+            // remove from the back so the remaining indices stay valid
+            for i in (common..old.len()).rev() {
+                ops.push(json!({"op": "remove", "path": format!("{path}/{i}")}));
+            }
+            for (i, value) in new.iter().enumerate().skip(common) {
+                ops.push(json!({"op": "add", "path": format!("{path}/{i}"), "value": value}));
+            }
+        }
+        (old, new) if old != new => {
+            ops.push(json!({"op": "replace", "path": path, "value": new}));
+        }
+        _ => {}
+    }
+}
+
+/// Build the RFC 7386 merge patch turning `old` into `new`, `None` when they are equal
+fn merge_patch(old: &Value, new: &Value) -> Option<Value> {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut patch = Map::new();
+            for key in old.keys() {
+                if !new.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, new_value) in new {
+                match old.get(key) {
+                    Some(old_value) => {
+                        if let Some(p) = merge_patch(old_value, new_value) {
+                            patch.insert(key.clone(), p);
+                        }
+                    }
+                    None => {
+                        patch.insert(key.clone(), new_value.clone());
+                    }
+                }
+            }
+            (!patch.is_empty()).then_some(Value::Object(patch))
+        }
+        (old, new) if old == new => None,
+        (_, new) => Some(new.clone()),
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    index,
+    stats::{profile::FieldStats, profile_input},
+    DissectError,
+};
+
+/// Compare the fields of two dumps, which were added or removed and which changed type
+#[derive(Debug, clap::Args)]
+pub struct SchemaDiffArgs {
+    /// The original dump
+    pub old: PathBuf,
+
+    /// The updated dump
+    pub new: PathBuf,
+
+    /// Write the differences to this file as json
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// The number of threads to use
+    #[clap(short, long, default_value = "4")]
+    pub threads: usize,
+
+    /// How many documents each thread profiles at a time
+    #[clap(short, long, default_value = "100")]
+    pub batch: usize,
+
+    /// Inspect the dumps again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+pub(crate) fn run(args: &SchemaDiffArgs) -> Result<(), DissectError> {
+    let old_input = index::load_input(&args.old, args.inspect)?;
+    let new_input = index::load_input(&args.new, args.inspect)?;
+    println!("Profiling {}", args.old.display());
+    let old = profile_input(&old_input, args.threads, args.batch, 0)?;
+    println!("Profiling {}", args.new.display());
+    let new = profile_input(&new_input, args.threads, args.batch, 0)?;
+
+    let presence =
+        |stats: &FieldStats, documents: u64| stats.count as f64 / documents.max(1) as f64;
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    for (path, stats) in &new.fields {
+        if !old.fields.contains_key(path) {
+            added.push(json!({
+                "path": path,
+                "types": stats.types,
+                "presence": presence(stats, new.documents),
+            }));
+        }
+    }
+    for (path, before) in &old.fields {
+        let Some(after) = new.fields.get(path) else {
+            removed.push(json!({
+                "path": path,
+                "types": before.types,
+                "presence": presence(before, old.documents),
+            }));
+            continue;
+        };
+        let gained = missing_from(&after.types, &before.types);
+        let lost = missing_from(&before.types, &after.types);
+        if !gained.is_empty() || !lost.is_empty() {
+            changed.push(json!({
+                "path": path,
+                "old_types": before.types,
+                "new_types": after.types,
+                "added_types": gained,
+                "removed_types": lost,
+            }));
+        }
+    }
+
+    println!();
+    for field in &added {
+        println!(
+            "+ {:<40} {}",
+            field["path"].as_str().unwrap_or_default(),
+            types(&field["types"])
+        );
+    }
+    for field in &removed {
+        println!(
+            "- {:<40} {}",
+            field["path"].as_str().unwrap_or_default(),
+            types(&field["types"])
+        );
+    }
+    for field in &changed {
+        println!(
+            "~ {:<40} {} -> {}",
+            field["path"].as_str().unwrap_or_default(),
+            types(&field["old_types"]),
+            types(&field["new_types"])
+        );
+    }
+    println!(
+        "{} fields added, {} removed, {} changed type",
+        added.len(),
+        removed.len(),
+        changed.len()
+    );
+
+    if let Some(output) = &args.output {
+        let report = json!({
+            "old": { "path": args.old, "documents": old.documents },
+            "new": { "path": args.new, "documents": new.documents },
+            "added": added,
+            "removed": removed,
+            "changed": changed,
+        });
+        let mut writer = BufWriter::new(File::create(output)?);
+        serde_json::to_writer_pretty(&mut writer, &report)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        println!("Wrote schema differences to {}", output.display());
+    }
+    Ok(())
+}
+
+/// Type names counted on one side and not on the other
+fn missing_from(
+    side: &BTreeMap<&'static str, u64>,
+    other: &BTreeMap<&'static str, u64>,
+) -> Vec<&'static str> {
+    side.keys()
+        .filter(|name| !other.contains_key(*name))
+        .copied()
+        .collect()
+}
+
+/// The type names of a field, like `string|null`
+fn types(counts: &Value) -> String {
+    counts
+        .as_object()
+        .map(|counts| counts.keys().cloned().collect::<Vec<_>>().join("|"))
+        .unwrap_or_default()
+}
@@ -0,0 +1,183 @@
+use std::{fmt, str::FromStr};
+
+use bson::{Bson, Document};
+
+use super::{glob, parse, wildcard};
+
+/// The fields removed from every document, written `payload.blob,internal.*`. Patterns are dotted paths where `*`
+/// stands for any part of a name and `**` for any number of names like in --redact rules, a field inside an array
+/// of documents is removed from every document of the array
+#[derive(Debug, Clone)]
+pub struct ExcludeFields {
+    patterns: Vec<Vec<String>>,
+    source: String,
+}
+
+impl FromStr for ExcludeFields {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut patterns = Vec::new();
+        for pattern in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let segments = parse(pattern);
+            if segments.iter().any(String::is_empty) {
+                return Err(format!("empty field name in {pattern}"));
+            }
+            patterns.push(segments);
+        }
+        if patterns.is_empty() {
+            return Err("expected field patterns like payload.blob,internal.*".into());
+        }
+        Ok(Self {
+            patterns,
+            source: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ExcludeFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl ExcludeFields {
+    /// Remove the matching fields from a document, returns how many were removed
+    pub fn remove(&self, doc: &mut Document) -> usize {
+        self.strip(doc, &mut Vec::new())
+    }
+
+    /// Whether the field at a dotted path is removed, itself or with a field it is in
+    pub fn excludes(&self, path: &str) -> bool {
+        let path = parse(path);
+        (1..=path.len()).any(|len| {
+            self.patterns
+                .iter()
+                .any(|pattern| glob(pattern, &path[..len]))
+        })
+    }
+
+    fn strip(&self, doc: &mut Document, path: &mut Vec<String>) -> usize {
+        let mut removed = Vec::new();
+        let mut below = 0;
+        for (key, value) in doc.iter_mut() {
+            path.push(key.clone());
+            if self.patterns.iter().any(|pattern| glob(pattern, path)) {
+                removed.push(key.clone());
+            } else if self.patterns.iter().any(|pattern| reaches(pattern, path)) {
+                below += self.strip_value(value, path);
+            }
+            path.pop();
+        }
+        for key in &removed {
+            doc.remove(key);
+        }
+        removed.len() + below
+    }
+
+    fn strip_value(&self, value: &mut Bson, path: &mut Vec<String>) -> usize {
+        match value {
+            Bson::Document(doc) => self.strip(doc, path),
+            Bson::Array(items) => items
+                .iter_mut()
+                .map(|item| self.strip_value(item, path))
+                .sum(),
+            _ => 0,
+        }
+    }
+}
+
+/// Whether a pattern can match a field below `path`, the walk only goes into fields some pattern reaches
+fn reaches(pattern: &[String], path: &[String]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (Some((first, _)), _) if first == "**" => true,
+        (Some(_), None) => true,
+        (None, _) => false,
+        (Some((first, rest)), Some((name, tail))) => wildcard(first, name) && reaches(rest, tail),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Document};
+
+    use super::ExcludeFields;
+
+    fn removed(patterns: &str, mut doc: Document) -> (Document, usize) {
+        let exclude = patterns.parse::<ExcludeFields>().unwrap();
+        let count = exclude.remove(&mut doc);
+        (doc, count)
+    }
+
+    #[test]
+    fn paths_and_wildcards() {
+        let doc = doc! {
+            "_id": 1,
+            "payload": { "blob": "x", "kind": "a" },
+            "internal": { "a": 1, "b": { "c": 2 } },
+            "tmp_1": 1,
+            "tmp_2": 2,
+        };
+        assert_eq!(
+            removed("payload.blob,internal.*,tmp_*", doc),
+            (
+                doc! { "_id": 1, "payload": { "kind": "a" }, "internal": {} },
+                5
+            )
+        );
+    }
+
+    #[test]
+    fn double_star_reaches_any_depth() {
+        let doc = doc! {
+            "secret": 1,
+            "a": { "secret": 2, "b": { "secret": 3, "keep": 4 } },
+        };
+        assert_eq!(
+            removed("**.secret", doc),
+            (doc! { "a": { "b": { "keep": 4 } } }, 3)
+        );
+    }
+
+    #[test]
+    fn fields_in_arrays_of_documents() {
+        let doc = doc! {
+            "items": [{ "sku": "a", "cost": 1 }, { "sku": "b", "cost": 2 }, 3],
+            "cost": 0,
+        };
+        assert_eq!(
+            removed("items.cost", doc),
+            (
+                doc! { "items": [{ "sku": "a" }, { "sku": "b" }, 3], "cost": 0 },
+                2
+            )
+        );
+    }
+
+    #[test]
+    fn excluded_paths() {
+        let exclude = "payload.*,**.secret".parse::<ExcludeFields>().unwrap();
+        assert!(exclude.excludes("payload.blob"));
+        // inside a field that is removed
+        assert!(exclude.excludes("payload.blob.size"));
+        assert!(exclude.excludes("a.b.secret"));
+        assert!(!exclude.excludes("payload"));
+        assert!(!exclude.excludes("secrets"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert_eq!(
+            "a..b".parse::<ExcludeFields>().unwrap_err(),
+            "empty field name in a..b"
+        );
+        assert_eq!(
+            " , ".parse::<ExcludeFields>().unwrap_err(),
+            "expected field patterns like payload.blob,internal.*"
+        );
+        assert_eq!(
+            " a , b.c ".parse::<ExcludeFields>().unwrap().to_string(),
+            " a , b.c "
+        );
+    }
+}
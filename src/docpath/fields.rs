@@ -0,0 +1,153 @@
+use std::{fmt, str::FromStr};
+
+use bson::{Bson, Document, RawBsonRef, RawDocument};
+
+use super::parse;
+
+/// The fields kept of every document, written `name,address.city`. A path keeps its field with everything in it,
+/// a path through an array of documents keeps the field in every document of the array, and `_id` is always kept
+/// like in a MongoDB projection. The fields stay in the order of the document
+#[derive(Debug, Clone)]
+pub struct Fields {
+    tree: Tree,
+    source: String,
+}
+
+/// The fields kept of a document and what is kept inside each, everything when there is no tree under it
+#[derive(Debug, Clone, Default)]
+struct Tree(Vec<(String, Option<Tree>)>);
+
+impl FromStr for Fields {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tree = Tree::default();
+        for path in s.split(',').map(str::trim).filter(|path| !path.is_empty()) {
+            let segments = parse(path);
+            if segments.iter().any(String::is_empty) {
+                return Err(format!("empty field name in {path}"));
+            }
+            tree.insert(&segments);
+        }
+        if tree.0.is_empty() {
+            return Err("expected field paths like name,address.city".into());
+        }
+        tree.insert(&["_id".to_string()]);
+        Ok(Self {
+            tree,
+            source: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Fields {
+    /// The kept fields of a document
+    pub fn project(&self, doc: &Document) -> Document {
+        self.tree.project(doc)
+    }
+
+    /// The kept fields of a raw document, the rest is never decoded
+    pub fn project_raw(&self, doc: &RawDocument) -> Result<Document, bson::raw::Error> {
+        self.tree.project_raw(doc)
+    }
+}
+
+impl Tree {
+    fn insert(&mut self, path: &[String]) {
+        let Some((first, rest)) = path.split_first() else {
+            return;
+        };
+        match self.0.iter_mut().find(|(key, _)| key == first) {
+            // the whole field is kept already
+            Some((_, None)) => {}
+            Some((_, inner)) if rest.is_empty() => *inner = None,
+            Some((_, Some(inner))) => inner.insert(rest),
+            None if rest.is_empty() => self.0.push((first.clone(), None)),
+            None => {
+                let mut inner = Tree::default();
+                inner.insert(rest);
+                self.0.push((first.clone(), Some(inner)));
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Option<Tree>> {
+        self.0
+            .iter()
+            .find(|(kept, _)| kept == key)
+            .map(|(_, inner)| inner)
+    }
+
+    fn project(&self, doc: &Document) -> Document {
+        let mut projected = Document::new();
+        for (key, value) in doc {
+            match self.get(key) {
+                Some(None) => {
+                    projected.insert(key, value.clone());
+                }
+                Some(Some(inner)) => {
+                    if let Some(value) = inner.project_value(value) {
+                        projected.insert(key, value);
+                    }
+                }
+                None => {}
+            }
+        }
+        projected
+    }
+
+    /// What is kept of a value the paths go on into, only documents and arrays of them have fields to keep
+    fn project_value(&self, value: &Bson) -> Option<Bson> {
+        match value {
+            Bson::Document(doc) => Some(Bson::Document(self.project(doc))),
+            Bson::Array(items) => Some(Bson::Array(
+                items
+                    .iter()
+                    .filter_map(|item| self.project_value(item))
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn project_raw(&self, doc: &RawDocument) -> Result<Document, bson::raw::Error> {
+        let mut projected = Document::new();
+        for element in doc {
+            let (key, value) = element?;
+            match self.get(key) {
+                Some(None) => {
+                    projected.insert(key, Bson::try_from(value)?);
+                }
+                Some(Some(inner)) => {
+                    if let Some(value) = inner.project_raw_value(value)? {
+                        projected.insert(key, value);
+                    }
+                }
+                None => {}
+            }
+        }
+        Ok(projected)
+    }
+
+    fn project_raw_value(&self, value: RawBsonRef) -> Result<Option<Bson>, bson::raw::Error> {
+        Ok(match value {
+            RawBsonRef::Document(doc) => Some(Bson::Document(self.project_raw(doc)?)),
+            RawBsonRef::Array(items) => {
+                let mut projected = Vec::new();
+                for item in items {
+                    if let Some(item) = self.project_raw_value(item?)? {
+                        projected.push(item);
+                    }
+                }
+                Some(Bson::Array(projected))
+            }
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,216 @@
+use std::str::FromStr;
+
+use bson::{Bson, Document, RawBsonRef, RawDocument};
+
+mod exclude;
+mod fields;
+
+pub use exclude::ExcludeFields;
+pub use fields::Fields;
+
+/// Split a dotted field path like `address.city` into its segments
+pub(crate) fn parse(path: &str) -> Vec<String> {
+    path.split('.').map(str::to_string).collect()
+}
+
+/// A field that must hold a value, written `address.city=Paris`
+#[derive(Debug, Clone)]
+pub struct FieldValue {
+    path: Vec<String>,
+    value: String,
+}
+
+impl FromStr for FieldValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((path, value)) = s.split_once('=') else {
+            return Err(format!("expected field=value, got {s}"));
+        };
+        Ok(Self {
+            path: parse(path),
+            value: value.into(),
+        })
+    }
+}
+
+impl FieldValue {
+    /// The field and value as given, like `address.city=Paris`
+    pub fn describe(&self) -> String {
+        format!("{}={}", self.path.join("."), self.value)
+    }
+
+    /// Whether any value at the path reads as the expected one
+    pub fn matches(&self, doc: &Document) -> bool {
+        let mut found = false;
+        visit(doc, &self.path, &mut |value| {
+            found |= match value {
+                Bson::String(s) => *s == self.value,
+                Bson::Null => self.value == "null",
+                Bson::ObjectId(oid) => oid.to_hex() == self.value,
+                Bson::Boolean(_) | Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) => {
+                    value.to_string() == self.value
+                }
+                _ => false,
+            }
+        });
+        found
+    }
+}
+
+/// Call `f` with every value found at `path`,
+/// arrays of documents along the way are descended into element by element
+pub(crate) fn visit(doc: &Document, path: &[String], f: &mut dyn FnMut(&Bson)) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(value) = doc.get(first) else {
+        return;
+    };
+    if rest.is_empty() {
+        f(value);
+    } else {
+        visit_value(value, rest, f);
+    }
+}
+
+fn visit_value(value: &Bson, path: &[String], f: &mut dyn FnMut(&Bson)) {
+    match value {
+        Bson::Document(d) => visit(d, path, f),
+        Bson::Array(items) => {
+            for item in items {
+                visit_value(item, path, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every value at `path` like [`visit`] finds them, borrowed from the document
+pub(crate) fn values<'a>(doc: &'a Document, path: &[String]) -> Vec<&'a Bson> {
+    let mut found = Vec::new();
+    collect(doc, path, &mut found);
+    found
+}
+
+fn collect<'a>(doc: &'a Document, path: &[String], found: &mut Vec<&'a Bson>) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(value) = doc.get(first) else {
+        return;
+    };
+    if rest.is_empty() {
+        found.push(value);
+    } else {
+        collect_value(value, rest, found);
+    }
+}
+
+fn collect_value<'a>(value: &'a Bson, path: &[String], found: &mut Vec<&'a Bson>) {
+    match value {
+        Bson::Document(d) => collect(d, path, found),
+        Bson::Array(items) => {
+            for item in items {
+                collect_value(item, path, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The value at `path` of a raw document without decoding it, arrays are not descended into
+pub(crate) fn raw_get<'a>(
+    doc: &'a RawDocument,
+    path: &[String],
+) -> Result<Option<RawBsonRef<'a>>, bson::raw::Error> {
+    let Some((last, parents)) = path.split_last() else {
+        return Ok(None);
+    };
+    let mut doc = doc;
+    for segment in parents {
+        match doc.get(segment)? {
+            Some(RawBsonRef::Document(inner)) => doc = inner,
+            _ => return Ok(None),
+        }
+    }
+    doc.get(last)
+}
+
+/// Mutable version of [`visit`]
+pub(crate) fn visit_mut(doc: &mut Document, path: &[String], f: &mut dyn FnMut(&mut Bson)) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(value) = doc.get_mut(first) else {
+        return;
+    };
+    if rest.is_empty() {
+        f(value);
+    } else {
+        visit_value_mut(value, rest, f);
+    }
+}
+
+fn visit_value_mut(value: &mut Bson, path: &[String], f: &mut dyn FnMut(&mut Bson)) {
+    match value {
+        Bson::Document(d) => visit_mut(d, path, f),
+        Bson::Array(items) => {
+            for item in items {
+                visit_value_mut(item, path, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Remove the field at `path` from the document and any nested arrays of documents,
+/// returns how many fields were removed
+pub(crate) fn remove(doc: &mut Document, path: &[String]) -> usize {
+    let Some((last, parent)) = path.split_last() else {
+        return 0;
+    };
+    if parent.is_empty() {
+        return doc.remove(last).map_or(0, |_| 1);
+    }
+    let mut removed = 0;
+    visit_mut(doc, parent, &mut |value| {
+        removed += remove_from_value(value, last);
+    });
+    removed
+}
+
+fn remove_from_value(value: &mut Bson, key: &str) -> usize {
+    match value {
+        Bson::Document(d) => d.remove(key).map_or(0, |_| 1),
+        Bson::Array(items) => items.iter_mut().map(|i| remove_from_value(i, key)).sum(),
+        _ => 0,
+    }
+}
+
+/// Whether a dotted path matches a pattern, `*` stands for any part of a name and `**` for any number of names
+pub(crate) fn glob(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| glob(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(name, tail)| wildcard(first, name) && glob(rest, tail)),
+    }
+}
+
+fn wildcard(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((head, tail)) => {
+            let Some(rest) = name.strip_prefix(head) else {
+                return false;
+            };
+            (0..=rest.len())
+                .filter(|&i| rest.is_char_boundary(i))
+                .any(|i| wildcard(tail, &rest[i..]))
+        }
+    }
+}
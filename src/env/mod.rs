@@ -0,0 +1,198 @@
+use std::{
+    collections::BTreeMap,
+    panic,
+    path::{Path, PathBuf},
+    sync::Once,
+};
+
+use parking_lot::Mutex;
+
+use crate::{Args, DissectError};
+
+/// Values shorter than this aren't scrubbed from output, they would blank out every number and short word
+const MIN_SECRET: usize = 4;
+
+/// The values of every export of the process, for the panic hook that can't tell which export panicked
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Variables given with --env and --env-file, for scripts as `env("KEY")` and in sink specs as `${KEY}`.
+/// Every value is taken for a secret and scrubbed from what the export prints and signs
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Env {
+    vars: BTreeMap<String, String>,
+}
+
+impl Env {
+    /// The variables of the --env-file files in order, then --env, later ones win
+    pub fn from_args(args: &Args) -> Result<Self, DissectError> {
+        Self::from_lists(&args.env_file, &args.env)
+    }
+
+    /// The variables of `files` in order, then the `KEY=VALUE` or `KEY` of `env`
+    pub fn from_lists(files: &[PathBuf], env: &[String]) -> Result<Self, DissectError> {
+        let mut vars = BTreeMap::new();
+        for file in files {
+            for (key, value) in read_file(file)? {
+                vars.insert(key, value);
+            }
+        }
+        for var in env {
+            let (key, value) = parse_var(var)?;
+            vars.insert(key, value);
+        }
+        Ok(Self { vars })
+    }
+
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    /// Replace every `${KEY}` in `text` by the value of the variable, unknown variables are an error
+    pub fn expand(&self, text: &str) -> Result<String, DissectError> {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            let key = &rest[start + 2..start + end];
+            let value = self.vars.get(key).ok_or_else(|| {
+                DissectError::Parse(format!("${{{key}}} isn't set with --env or --env-file"))
+            })?;
+            expanded.push_str(&rest[..start]);
+            expanded.push_str(value);
+            rest = &rest[start + end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    /// The text with the value of every variable replaced by `***`
+    pub fn scrub(&self, text: &str) -> String {
+        scrub(text, self.vars.values())
+    }
+
+    /// The error with the values scrubbed from its message, the errors of sinks quote their expanded specs
+    pub fn scrub_error(&self, e: DissectError) -> DissectError {
+        match e {
+            DissectError::Parse(text) => DissectError::Parse(self.scrub(&text)),
+            DissectError::Unexpected(text) => DissectError::Unexpected(self.scrub(&text)),
+            e => {
+                let debug = format!("{e:?}");
+                if self.scrub(&debug) == debug {
+                    e
+                } else {
+                    DissectError::Unexpected(self.scrub(&e.to_string()))
+                }
+            }
+        }
+    }
+
+    /// Scrub the values from the message of every panic from here on, like the one of a sink that failed to write
+    pub fn scrub_panics(&self) {
+        static HOOK: Once = Once::new();
+        SECRETS.lock().extend(self.vars.values().cloned());
+        HOOK.call_once(|| {
+            let default = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                let message = panic_message(info.payload());
+                let scrubbed = scrub_panic(&message);
+                if scrubbed == message {
+                    return default(info);
+                }
+                let thread = std::thread::current();
+                let location = info
+                    .location()
+                    .map(|location| format!(" at {location}"))
+                    .unwrap_or_default();
+                eprintln!(
+                    "thread '{}' panicked{location}:\n{scrubbed}",
+                    thread.name().unwrap_or("<unnamed>")
+                );
+            }));
+        });
+    }
+
+    /// Expand the variables in the sink specs, routes and http headers of an export
+    pub fn expand_args(&self, args: &mut Args) -> Result<(), DissectError> {
+        if self.vars.is_empty() {
+            return Ok(());
+        }
+        args.sink = args
+            .sink
+            .iter()
+            .map(|spec| spec.expand(self))
+            .collect::<Result<_, _>>()?;
+        args.route = args
+            .route
+            .iter()
+            .map(|spec| spec.expand(self))
+            .collect::<Result<_, _>>()?;
+        args.http_header = args
+            .http_header
+            .iter()
+            .map(|header| self.expand(header))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+}
+
+/// A variable given as `KEY=VALUE`, or as `KEY` to pass on the variable of the same name from the environment
+fn parse_var(var: &str) -> Result<(String, String), DissectError> {
+    match var.split_once('=') {
+        Some((key, value)) => Ok((key.trim().to_string(), value.to_string())),
+        None => std::env::var(var)
+            .map(|value| (var.to_string(), value))
+            .map_err(|_| DissectError::Parse(format!("--env {var} has no value and isn't set"))),
+    }
+}
+
+/// The `KEY=VALUE` lines of a dotenv style file, blank lines and lines starting with # are skipped
+/// and values may be quoted
+fn read_file(path: &Path) -> Result<Vec<(String, String)>, DissectError> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                DissectError::Parse(format!(
+                    "Expected KEY=VALUE in {}, got {line}",
+                    path.display()
+                ))
+            })?;
+            let value = value.trim();
+            let value = ['"', '\'']
+                .into_iter()
+                .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+                .unwrap_or(value);
+            Ok((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// The message of a panic with the values of every export that asked for it scrubbed
+pub(crate) fn scrub_panic(message: &str) -> String {
+    scrub(message, SECRETS.lock().iter())
+}
+
+/// The message a panic was started with
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_else(|| "panicked".into())
+}
+
+fn scrub<'a>(text: &str, values: impl Iterator<Item = &'a String>) -> String {
+    let mut secrets = values
+        .filter(|value| value.len() >= MIN_SECRET)
+        .collect::<Vec<_>>();
+    // longer values first so a secret containing another one is scrubbed whole
+    secrets.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    secrets.into_iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret.as_str(), "***")
+    })
+}
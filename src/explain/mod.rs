@@ -0,0 +1,357 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use humansize::{format_size, DECIMAL};
+
+use crate::{
+    env::Env,
+    index::{self, DocOffset, Input},
+    limits,
+    output::{compress::Compression, encrypt::Encryption, shard, store, OutputFormat},
+    select_documents,
+    transform::Transforms,
+    Args, DissectError,
+};
+
+/// Width of the labels in front of every section of the plan
+const LABEL: usize = 11;
+
+/// The name of a value on the command line, like `truncate` for `ArrayOverflow::Truncate`
+pub(crate) fn value_name<T: ValueEnum>(value: T) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// The plan of an export as resolved from its arguments, one section of lines per part of the run
+struct Plan<'a> {
+    env: &'a Env,
+    sections: Vec<(&'static str, Vec<String>)>,
+}
+
+impl Plan<'_> {
+    fn section(&mut self, label: &'static str, lines: Vec<String>) {
+        if !lines.is_empty() {
+            self.sections.push((label, lines));
+        }
+    }
+
+    /// Print the sections with their lines under each other, the values of --env scrubbed like in the summary
+    fn print(&self) {
+        for (label, lines) in &self.sections {
+            for (nth, line) in lines.iter().enumerate() {
+                let label = if nth == 0 { *label } else { "" };
+                println!("{label:<LABEL$}{}", self.env.scrub(line));
+            }
+        }
+    }
+}
+
+/// Print what an export with these arguments would do and stop, the input is indexed and the documents selected
+/// the way the export does it but nothing is written to the output
+pub(crate) fn run(args: &Args, env: &Env, path: &Path, output: &Path) -> Result<(), DissectError> {
+    let index_path = index::index_path(path);
+    let index_status = if args.inspect {
+        "rebuilt by inspecting the input, --inspect is set"
+    } else if index_path.exists() {
+        "found"
+    } else {
+        "missing, built by inspecting the input"
+    };
+    let input = index::load_input(path, args.inspect)?;
+    let idx = select_documents(args, &input)?;
+    let write_threads = limits::check(args, input.files.len(), &idx)?;
+
+    let mut plan = Plan {
+        env,
+        sections: Vec::new(),
+    };
+    let files = match input.files.len() {
+        1 => String::new(),
+        n => format!("{n} files, "),
+    };
+    plan.section(
+        "Input:",
+        vec![format!(
+            "{}, {files}{} documents, {}",
+            path.display(),
+            input.offsets.len(),
+            format_size(bytes(&input.offsets), DECIMAL)
+        )],
+    );
+    plan.section(
+        "Index:",
+        vec![format!("{}, {index_status}", index_path.display())],
+    );
+    plan.section("Selection:", selection(args, &input, &idx));
+    plan.section("Pipeline:", pipeline(args));
+    plan.section("Output:", outputs(args, output));
+    plan.section("Threads:", threads(args, write_threads));
+
+    println!();
+    plan.print();
+    println!();
+    println!("Nothing was written, run again without --explain to export");
+    Ok(())
+}
+
+fn bytes(offsets: &[DocOffset]) -> usize {
+    offsets.iter().map(|offset| offset.size).sum()
+}
+
+/// How many documents the selection keeps and what narrows them down
+fn selection(args: &Args, input: &Input, idx: &[DocOffset]) -> Vec<String> {
+    let total = input.offsets.len();
+    let share = match total {
+        0 => 0.0,
+        total => 100.0 * idx.len() as f64 / total as f64,
+    };
+    let mut lines = vec![format!(
+        "{} of {total} documents ({share:.1}%), {} of {}",
+        idx.len(),
+        format_size(bytes(idx), DECIMAL),
+        format_size(bytes(&input.offsets), DECIMAL)
+    )];
+    if let Some(id) = &args.id {
+        lines.push(match args.bloom {
+            true => format!("only _id {id}, files ruled out by their bloom filters are skipped"),
+            false => format!("only _id {id}"),
+        });
+    }
+    let date = |time: Option<index::oid::OidTime>| {
+        time.map(|time| {
+            time.0
+                .try_to_rfc3339_string()
+                .unwrap_or_else(|_| time.0.to_string())
+        })
+    };
+    match (date(args.oid_after), date(args.oid_before)) {
+        (Some(after), Some(before)) => lines.push(format!(
+            "ObjectIds created from {after} until before {before}"
+        )),
+        (Some(after), None) => lines.push(format!("ObjectIds created from {after}")),
+        (None, Some(before)) => lines.push(format!("ObjectIds created before {before}")),
+        (None, None) => {}
+    }
+    if args.order_by_oid {
+        lines.push("in the order of the ObjectId _id".into());
+    }
+    if let Some(field) = &args.merge_by {
+        lines.push(match args.presorted {
+            true => format!("the files merged by {field} as they are read, --presorted"),
+            false => format!("each file sorted by {field} and the files merged"),
+        });
+    }
+    if let Some(slice) = &args.slice {
+        lines.push(format!("the slice {slice} of them"));
+    }
+    // these decide per document while exporting, only a run can count them
+    if args.query.is_some()
+        || args.filter.is_some()
+        || args.script.is_some()
+        || args.jq.is_some()
+        || args.jmespath.is_some()
+        || args.exec_filter.is_some()
+    {
+        lines.push(
+            "--query, --filter, the script, --jq, --jmespath and --exec-filter may drop more, they aren't estimated"
+                .into(),
+        );
+    }
+    lines
+}
+
+/// Every stage a document goes through in the order it goes through them
+fn pipeline(args: &Args) -> Vec<String> {
+    let mut stages = vec![
+        match args.prefetch {
+            0 => format!("read batches of {} documents", args.batch),
+            ahead => format!(
+                "read batches of {} documents, {ahead} read ahead",
+                args.batch
+            ),
+        },
+        match &args.fields {
+            Some(fields) => format!(
+                "decode only the fields {fields}, --invalid-utf8 {}",
+                value_name(args.invalid_utf8)
+            ),
+            None => format!("decode, --invalid-utf8 {}", value_name(args.invalid_utf8)),
+        },
+    ];
+    if let Some(query) = &args.query {
+        stages.push(format!("keep the documents matching the query {query}"));
+    }
+    if let Some(filter) = &args.filter {
+        stages.push(format!("keep the documents the filter {filter} holds for"));
+    }
+    stages.extend(Transforms::plan(args));
+    if let Some(script) = &args.script {
+        stages.push(format!("run the lua script {}", script.display()));
+    }
+    if let Some(jq) = &args.jq {
+        stages.push(format!("run the jq filter {jq}"));
+    }
+    if let Some(jmespath) = &args.jmespath {
+        stages.push(format!("run the JMESPath expression {jmespath}"));
+    }
+    if let Some(command) = &args.exec_filter {
+        stages.push(format!("pipe through `{command}`"));
+    }
+    if let Some(exclude) = &args.exclude_fields {
+        stages.push(format!("remove the fields {exclude}"));
+    }
+    if !args.numeric_stats.is_empty() {
+        stages.push(format!(
+            "count the numeric fields {}",
+            args.numeric_stats.join(", ")
+        ));
+    }
+    if let Some(rate) = &args.rate {
+        stages.push(format!("hold back to {}", rate.describe()));
+    }
+    if let Some(pace) = &args.pace_by {
+        stages.push(format!("replay as far apart as {}", pace.describe()));
+    }
+    if let Some(path) = &args.anomalies {
+        stages.push(format!(
+            "move flagged documents to {} instead of the output",
+            path.display()
+        ));
+    }
+    for route in &args.route {
+        stages.push(format!("route {}", route.describe()));
+    }
+    for sink in &args.sink {
+        stages.push(format!("copy to the {}", sink.describe()));
+    }
+    stages.push("write the output".into());
+    stages
+        .into_iter()
+        .enumerate()
+        .map(|(nth, stage)| format!("{}. {stage}", nth + 1))
+        .collect()
+}
+
+/// Where the export goes and in what form
+fn outputs(args: &Args, output: &Path) -> Vec<String> {
+    let kind = args.output_format();
+    let format = value_name(kind);
+    let mut encoding = Vec::new();
+    if args.pretty {
+        encoding.push("pretty".to_string());
+    }
+    if matches!(kind, OutputFormat::Json | OutputFormat::Ndjson) {
+        encoding.push(format!("--json-writer {}", value_name(args.json_writer)));
+    }
+    if args.sort_keys {
+        encoding.push("keys sorted".into());
+    }
+    if !kind.is_database() && kind != OutputFormat::Bson {
+        encoding.push(value_name(args.encoding));
+    }
+
+    let target = if args.stdout {
+        "stdout".to_string()
+    } else if let Some(url) = store::object_url(output) {
+        format!(
+            "the object store {url}, {} uploads at once",
+            args.upload_concurrency
+        )
+    } else {
+        output.display().to_string()
+    };
+    let mut lines = vec![if kind.is_database() {
+        format!("{format} database {target}, table {}", args.table)
+    } else if args.single {
+        format!("{format} in one file, {target}")
+    } else if let Some(archive) = args.archive {
+        format!(
+            "a {format} file per document in a {} archive, {target}",
+            value_name(archive)
+        )
+    } else {
+        format!(
+            "a {format} file per document in {target}, named by {}",
+            value_name(args.name_by)
+        )
+    }];
+    if !encoding.is_empty() {
+        lines.push(encoding.join(", "));
+    }
+    if let Some(max) = args.max_output_size {
+        lines.push(format!(
+            "split into files of up to {} from {}",
+            format_size(max, DECIMAL),
+            shard::path(output, 1).display()
+        ));
+    }
+    if let Some(spec) = &args.partition_by {
+        lines.push(format!("in a directory per value of {}", spec.field));
+    }
+    if args.array_safe {
+        lines.push("one document a line, flushed after every batch, --array-safe".into());
+    }
+    if let Some(compression) = args.compress {
+        lines.push(match compression {
+            Compression::Gzip(level) => format!("compressed with gzip level {level}"),
+            Compression::Zstd(level) => format!("compressed with zstd level {level}"),
+        });
+    }
+    if let Some(encryption) = &args.encrypt {
+        lines.push(match encryption {
+            Encryption::Recipients(path) => {
+                format!("encrypted to the age recipients in {}", path.display())
+            }
+            Encryption::Passphrase => "encrypted with the passphrase in DISSBSON_PASSPHRASE".into(),
+        });
+    }
+    if let Some(notice) = &args.notice {
+        lines.push(format!("starting with the notice {}", notice.display()));
+    }
+    if args.checksums {
+        lines.push(match &args.sign {
+            Some(key) => format!("checksum manifest signed with {}", key.display()),
+            None => "checksum manifest".into(),
+        });
+    }
+    if args.manifest_json {
+        lines.push("manifest.json of the output files and the documents in them".into());
+    }
+    if args.verify {
+        lines.push(format!(
+            "read back and verified, {:.0}% of the documents",
+            100.0 * args.verify_sample
+        ));
+    }
+    if args.pg_ddl {
+        lines.push("a psql script creating and loading the table next to it".into());
+    }
+    lines
+}
+
+/// The threads decoding and writing and how batches are read
+fn threads(args: &Args, write_threads: Option<usize>) -> Vec<String> {
+    let writers = if args.single {
+        "one stream written in turn by the decoding threads".to_string()
+    } else if args.writes_file() {
+        "one file written in turn by the decoding threads".to_string()
+    } else {
+        match write_threads.filter(|&threads| threads > 0) {
+            Some(threads) => format!("{threads} writer threads"),
+            None => "the decoding threads write their own files".into(),
+        }
+    };
+    let mut lines = vec![
+        format!("{} decoding threads, {writers}", args.threads),
+        format!(
+            "{} retries of failed reads and writes, the first after {}ms",
+            args.retries, args.retry_backoff
+        ),
+    ];
+    if args.direct_io {
+        lines.push("input read with O_DIRECT".into());
+    }
+    lines
+}
@@ -0,0 +1,397 @@
+use std::{borrow::Cow, cmp::Ordering, fmt, str::FromStr};
+
+use bson::{Bson, Document};
+
+use super::{candidates, compare, equal};
+use crate::docpath;
+
+/// A predicate documents are kept by, like `user.country == "DE" && items.#count > 3`.
+/// Comparisons of field paths and literals are joined with `&&`, `||`, `!` and parentheses, a path alone holds
+/// when its value is there and isn't null or false. A path ending in `#count` is the length of the array or the
+/// number of fields of the document at it. Paths descend into arrays and a comparison holds when any value does
+#[derive(Debug, Clone)]
+pub struct Expr {
+    node: Node,
+    source: String,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Or(Box<Node>, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Cmp(Operand, Cmp, Operand),
+    Truthy(Operand),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Path(Vec<String>),
+    Count(Vec<String>),
+    Literal(Bson),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Path(String),
+    Str(String),
+    Number(Bson),
+    Op(&'static str),
+}
+
+/// The operators in the order they are tried, longer ones before their prefixes
+const OPERATORS: [&str; 11] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")"];
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            at: 0,
+        };
+        let node = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.at) {
+            return Err(format!("unexpected {} in filter", describe(token)));
+        }
+        Ok(Self {
+            node,
+            source: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expr {
+    /// Whether the document satisfies the expression
+    pub fn matches(&self, doc: &Document) -> bool {
+        self.node.eval(doc)
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, end)) if end == c => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, escaped)) => text.push(escaped),
+                        None => return Err(format!("unterminated string at column {}", at + 1)),
+                    },
+                    Some((_, other)) => text.push(other),
+                    None => return Err(format!("unterminated string at column {}", at + 1)),
+                }
+            }
+            tokens.push(Token::Str(text));
+        } else if c.is_ascii_digit() || (c == '-' && !follows_operand(&tokens)) {
+            let mut number = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            let value = match number.parse::<i64>() {
+                Ok(n) => Bson::Int64(n),
+                Err(_) => Bson::Double(
+                    number
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid number {number} at column {}", at + 1))?,
+                ),
+            };
+            tokens.push(Token::Number(value));
+        } else if c.is_alphanumeric() || matches!(c, '_' | '$' | '#') {
+            let mut path = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || matches!(c, '_' | '$' | '#' | '.')) {
+                    break;
+                }
+                path.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Path(path));
+        } else {
+            let rest = &s[at..];
+            let Some(op) = OPERATORS.into_iter().find(|op| rest.starts_with(op)) else {
+                return Err(format!("unexpected {c} at column {}", at + 1));
+            };
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Whether a `-` after these tokens is part of a number rather than standing between two operands
+fn follows_operand(tokens: &[Token]) -> bool {
+    matches!(
+        tokens.last(),
+        Some(Token::Path(_) | Token::Str(_) | Token::Number(_) | Token::Op(")"))
+    )
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Path(path) => path.clone(),
+        Token::Str(text) => format!("{text:?}"),
+        Token::Number(n) => n.to_string(),
+        Token::Op(op) => (*op).to_string(),
+    }
+}
+
+/// A recursive descent parser, `||` binds looser than `&&` which binds looser than `!` and the comparisons
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.tokens.get(self.at), Some(Token::Op(o)) if *o == op);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.eat("||") {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.not()?;
+        while self.eat("&&") {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        if self.eat("!") {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        if self.eat("(") {
+            let node = self.or()?;
+            if !self.eat(")") {
+                return Err("missing ) in filter".into());
+            }
+            return Ok(node);
+        }
+        let left = self.operand()?;
+        let cmp = match self.tokens.get(self.at) {
+            Some(Token::Op("==")) => Cmp::Eq,
+            Some(Token::Op("!=")) => Cmp::Ne,
+            Some(Token::Op("<")) => Cmp::Lt,
+            Some(Token::Op("<=")) => Cmp::Le,
+            Some(Token::Op(">")) => Cmp::Gt,
+            Some(Token::Op(">=")) => Cmp::Ge,
+            _ => return Ok(Node::Truthy(left)),
+        };
+        self.at += 1;
+        Ok(Node::Cmp(left, cmp, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let Some(token) = self.tokens.get(self.at).cloned() else {
+            return Err("filter ends where a field or value was expected".into());
+        };
+        self.at += 1;
+        Ok(match token {
+            Token::Str(text) => Operand::Literal(Bson::String(text)),
+            Token::Number(n) => Operand::Literal(n),
+            Token::Path(path) => match path.as_str() {
+                "true" => Operand::Literal(Bson::Boolean(true)),
+                "false" => Operand::Literal(Bson::Boolean(false)),
+                "null" => Operand::Literal(Bson::Null),
+                _ => path_operand(&path)?,
+            },
+            Token::Op(op) => return Err(format!("expected a field or value, got {op}")),
+        })
+    }
+}
+
+fn path_operand(path: &str) -> Result<Operand, String> {
+    let mut segments = docpath::parse(path);
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format!("invalid field path {path}"));
+    }
+    match segments.last().map(String::as_str) {
+        Some("#count") if segments.len() > 1 => {
+            segments.pop();
+            Ok(Operand::Count(segments))
+        }
+        _ if segments.iter().any(|segment| segment.starts_with('#')) => Err(format!(
+            "invalid field path {path}, #count comes last after the field it counts"
+        )),
+        _ => Ok(Operand::Path(segments)),
+    }
+}
+
+impl Node {
+    fn eval(&self, doc: &Document) -> bool {
+        match self {
+            Self::Or(a, b) => a.eval(doc) || b.eval(doc),
+            Self::And(a, b) => a.eval(doc) && b.eval(doc),
+            Self::Not(node) => !node.eval(doc),
+            Self::Truthy(operand) => operand
+                .values(doc)
+                .iter()
+                .any(|value| !matches!(**value, Bson::Null | Bson::Boolean(false))),
+            Self::Cmp(left, cmp, right) => {
+                let (left, right) = (left.values(doc), right.values(doc));
+                let ordered = |holds: fn(Ordering) -> bool| {
+                    left.iter()
+                        .any(|l| right.iter().any(|r| compare(l, r).is_some_and(holds)))
+                };
+                match cmp {
+                    Cmp::Eq => equals(&left, &right),
+                    Cmp::Ne => !equals(&left, &right),
+                    Cmp::Lt => ordered(Ordering::is_lt),
+                    Cmp::Le => ordered(Ordering::is_le),
+                    Cmp::Gt => ordered(Ordering::is_gt),
+                    Cmp::Ge => ordered(Ordering::is_ge),
+                }
+            }
+        }
+    }
+}
+
+/// Whether any value of one side equals one of the other, null stands for missing fields too like in --query
+fn equals(left: &[Cow<Bson>], right: &[Cow<Bson>]) -> bool {
+    if left.is_empty() || right.is_empty() {
+        return left.iter().chain(right).all(|value| **value == Bson::Null);
+    }
+    left.iter().any(|l| right.iter().any(|r| equal(l, r)))
+}
+
+impl Operand {
+    /// The values the operand stands for in a document, none when a path is missing
+    fn values<'a>(&'a self, doc: &'a Document) -> Vec<Cow<'a, Bson>> {
+        match self {
+            Self::Literal(value) => vec![Cow::Borrowed(value)],
+            Self::Path(path) => candidates(doc, path)
+                .into_iter()
+                .map(Cow::Borrowed)
+                .collect(),
+            Self::Count(path) => docpath::values(doc, path)
+                .into_iter()
+                .filter_map(|value| match value {
+                    Bson::Array(items) => Some(Cow::Owned(Bson::Int64(items.len() as i64))),
+                    Bson::Document(fields) => Some(Cow::Owned(Bson::Int64(fields.len() as i64))),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::Expr;
+
+    fn matches(expr: &str, doc: &bson::Document) -> bool {
+        expr.parse::<Expr>()
+            .unwrap_or_else(|e| panic!("{expr} failed to parse: {e}"))
+            .matches(doc)
+    }
+
+    fn invalid(expr: &str) -> String {
+        match expr.parse::<Expr>() {
+            Ok(parsed) => panic!("{expr} parsed as {parsed:?}"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let doc = doc! { "a": 1, "b": 0, "c": 1 };
+        // a || (b && c), not (a || b) && c
+        assert!(matches("a == 1 || b == 1 && c == 0", &doc));
+        assert!(!matches("(a == 1 || b == 1) && c == 0", &doc));
+        assert!(matches("b == 1 && c == 0 || a == 1", &doc));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let doc = doc! { "a": true, "b": false };
+        assert!(matches("!b && a", &doc));
+        assert!(!matches("!(b || a)", &doc));
+        assert!(matches("!!a", &doc));
+    }
+
+    #[test]
+    fn comparisons() {
+        let doc = doc! { "n": 3, "x": 2.5, "t": -2, "tags": ["a", "b"], "none": null };
+        assert!(matches("n > 2 && n >= 3 && n < 4 && n <= 3", &doc));
+        assert!(matches("n != 4 && x == 2.5", &doc));
+        assert!(matches("n == 3.0", &doc));
+        assert!(matches("t == -2 && t < -1 && n>-1", &doc));
+        assert!(matches("tags == \"b\"", &doc));
+        assert!(matches("missing == null && none == null", &doc));
+        assert!(!matches("missing", &doc));
+        assert!(!matches("none", &doc));
+        assert!(matches("tags.#count == 2", &doc));
+    }
+
+    #[test]
+    fn quoting() {
+        let doc = doc! { "name": "it's \"quoted\"\n" };
+        assert!(matches(r#"name == 'it\'s "quoted"\n'"#, &doc));
+        assert!(matches(r#"name == "it's \"quoted\"\n""#, &doc));
+        assert!(matches("'it\\'s \"quoted\"\\n' == name", &doc));
+        // operators inside strings are text
+        assert!(matches(r#"s == "a && b" || name != "||""#, &doc));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(invalid("a == 'open"), "unterminated string at column 6");
+        assert_eq!(invalid("a == 1 )"), "unexpected ) in filter");
+        assert_eq!(invalid("(a == 1"), "missing ) in filter");
+        assert_eq!(
+            invalid("a =="),
+            "filter ends where a field or value was expected"
+        );
+        assert_eq!(invalid("a == && b"), "expected a field or value, got &&");
+        assert_eq!(invalid("a ~ 1"), "unexpected ~ at column 3");
+        assert_eq!(invalid("n > 1.2.3"), "invalid number 1.2.3 at column 5");
+        assert_eq!(invalid("a..b"), "invalid field path a..b");
+        assert_eq!(
+            invalid("a.#count.b"),
+            "invalid field path a.#count.b, #count comes last after the field it counts"
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::cmp::Ordering;
+
+use bson::{Bson, Document};
+
+use crate::docpath;
+
+mod expr;
+mod query;
+
+pub use expr::Expr;
+pub use query::Query;
+
+/// The values a condition on `path` is checked against, arrays stand for themselves and each of their items
+fn candidates<'a>(doc: &'a Document, path: &[String]) -> Vec<&'a Bson> {
+    let mut all = Vec::new();
+    for value in docpath::values(doc, path) {
+        all.push(value);
+        if let Bson::Array(items) = value {
+            all.extend(items);
+        }
+    }
+    all
+}
+
+fn equal(value: &Bson, operand: &Bson) -> bool {
+    value == operand || compare(value, operand) == Some(Ordering::Equal)
+}
+
+/// Order two values of the same kind, numbers of every type compare with each other
+fn compare(value: &Bson, operand: &Bson) -> Option<Ordering> {
+    let integer = |value: &Bson| match value {
+        Bson::Int32(n) => Some(i64::from(*n)),
+        Bson::Int64(n) => Some(*n),
+        _ => None,
+    };
+    let number = |value: &Bson| match value {
+        Bson::Double(n) => Some(*n),
+        other => integer(other).map(|n| n as f64),
+    };
+    match (value, operand) {
+        (Bson::String(a), Bson::String(b)) => Some(a.cmp(b)),
+        (Bson::DateTime(a), Bson::DateTime(b)) => Some(a.cmp(b)),
+        (Bson::ObjectId(a), Bson::ObjectId(b)) => Some(a.bytes().cmp(&b.bytes())),
+        (Bson::Boolean(a), Bson::Boolean(b)) => Some(a.cmp(b)),
+        (a, b) => match (integer(a), integer(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => number(a)?.partial_cmp(&number(b)?),
+        },
+    }
+}
@@ -0,0 +1,345 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use bson::{Bson, Document};
+use regex::{Regex, RegexBuilder};
+use serde_json::{Map, Value};
+
+use super::{candidates, compare, equal};
+use crate::docpath;
+
+/// The operators a field condition may use, an object with any other key is a value like `{"$date": ...}`
+const OPERATORS: [&str; 11] = [
+    "$eq", "$ne", "$gt", "$gte", "$lt", "$lte", "$in", "$nin", "$exists", "$regex", "$options",
+];
+
+/// A MongoDB style query documents are kept by, like `{"status": "active", "age": {"$gt": 30}}`.
+/// Fields are dotted paths that descend into arrays of documents, values are extended json so
+/// `{"$oid": ...}` and `{"$date": ...}` compare with ObjectIds and dates
+#[derive(Debug, Clone)]
+pub struct Query {
+    clauses: Vec<Clause>,
+    /// The query as compact json, for the plan of --explain
+    source: String,
+}
+
+#[derive(Debug, Clone)]
+enum Clause {
+    Field { path: Vec<String>, ops: Vec<Op> },
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Nor(Vec<Query>),
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Eq(Bson),
+    Ne(Bson),
+    /// The value compares to the operand with one of these orderings, `$gte` is greater or equal
+    Cmp(Bson, [Ordering; 2]),
+    In(Vec<Bson>),
+    Nin(Vec<Bson>),
+    Exists(bool),
+    Regex(Regex),
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match serde_json::from_str(s) {
+            Ok(Value::Object(query)) => Self::parse(&query),
+            Ok(_) => Err(format!(
+                "expected a json object like {{\"status\": \"active\"}}, got {s}"
+            )),
+            Err(e) => Err(format!("invalid json in query: {e}")),
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Query {
+    fn parse(query: &Map<String, Value>) -> Result<Self, String> {
+        let clauses = query
+            .iter()
+            .map(|(key, value)| match key.as_str() {
+                "$and" => Ok(Clause::And(queries(key, value)?)),
+                "$or" => Ok(Clause::Or(queries(key, value)?)),
+                "$nor" => Ok(Clause::Nor(queries(key, value)?)),
+                key if key.starts_with('$') => Err(format!("unsupported query operator {key}")),
+                field => Ok(Clause::Field {
+                    path: docpath::parse(field),
+                    ops: ops(field, value)?,
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            clauses,
+            source: Value::Object(query.clone()).to_string(),
+        })
+    }
+
+    /// Whether the document matches every clause of the query
+    pub fn matches(&self, doc: &Document) -> bool {
+        self.clauses.iter().all(|clause| match clause {
+            Clause::Field { path, ops } => {
+                let values = candidates(doc, path);
+                ops.iter().all(|op| op.matches(&values))
+            }
+            Clause::And(queries) => queries.iter().all(|query| query.matches(doc)),
+            Clause::Or(queries) => queries.iter().any(|query| query.matches(doc)),
+            Clause::Nor(queries) => !queries.iter().any(|query| query.matches(doc)),
+        })
+    }
+}
+
+/// The queries of `$and`, `$or` and `$nor`
+fn queries(key: &str, value: &Value) -> Result<Vec<Query>, String> {
+    let Value::Array(items) = value else {
+        return Err(format!("{key} takes an array of queries"));
+    };
+    items
+        .iter()
+        .map(|item| match item {
+            Value::Object(query) => Query::parse(query),
+            _ => Err(format!("{key} takes an array of queries, got {item}")),
+        })
+        .collect()
+}
+
+/// The conditions on one field, a plain value is a condition of equality
+fn ops(field: &str, condition: &Value) -> Result<Vec<Op>, String> {
+    let operators = match condition {
+        Value::Object(operators)
+            if operators
+                .keys()
+                .any(|key| OPERATORS.contains(&key.as_str())) =>
+        {
+            operators
+        }
+        value => return Ok(vec![Op::Eq(bson(value)?)]),
+    };
+    let mut ops = Vec::with_capacity(operators.len());
+    for (operator, operand) in operators {
+        ops.push(match operator.as_str() {
+            "$eq" => Op::Eq(bson(operand)?),
+            "$ne" => Op::Ne(bson(operand)?),
+            "$gt" => Op::Cmp(bson(operand)?, [Ordering::Greater; 2]),
+            "$gte" => Op::Cmp(bson(operand)?, [Ordering::Greater, Ordering::Equal]),
+            "$lt" => Op::Cmp(bson(operand)?, [Ordering::Less; 2]),
+            "$lte" => Op::Cmp(bson(operand)?, [Ordering::Less, Ordering::Equal]),
+            "$in" | "$nin" => {
+                let Value::Array(items) = operand else {
+                    return Err(format!("{operator} of {field} takes an array of values"));
+                };
+                let items = items.iter().map(bson).collect::<Result<Vec<_>, _>>()?;
+                match operator.as_str() {
+                    "$in" => Op::In(items),
+                    _ => Op::Nin(items),
+                }
+            }
+            "$exists" => match operand {
+                Value::Bool(exists) => Op::Exists(*exists),
+                _ => return Err(format!("$exists of {field} takes true or false")),
+            },
+            "$regex" => {
+                let Value::String(pattern) = operand else {
+                    return Err(format!("$regex of {field} takes a pattern string"));
+                };
+                let options = operators
+                    .get("$options")
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                Op::Regex(
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(options.contains('i'))
+                        .multi_line(options.contains('m'))
+                        .dot_matches_new_line(options.contains('s'))
+                        .ignore_whitespace(options.contains('x'))
+                        .build()
+                        .map_err(|e| format!("invalid $regex of {field}: {e}"))?,
+                )
+            }
+            // read along with $regex
+            "$options" => continue,
+            other => return Err(format!("unsupported operator {other} on {field}")),
+        });
+    }
+    Ok(ops)
+}
+
+/// A value of the query as bson, read as extended json
+fn bson(value: &Value) -> Result<Bson, String> {
+    Bson::try_from(value.clone()).map_err(|e| format!("invalid value {value} in query: {e}"))
+}
+
+impl Op {
+    fn matches(&self, values: &[&Bson]) -> bool {
+        match self {
+            // null stands for missing fields too, like in MongoDB
+            Self::Eq(Bson::Null) => values.is_empty() || values.iter().any(|v| v == &&Bson::Null),
+            Self::Eq(operand) => values.iter().any(|value| equal(value, operand)),
+            Self::Ne(operand) => !Self::Eq(operand.clone()).matches(values),
+            Self::Cmp(operand, orderings) => values
+                .iter()
+                .any(|value| compare(value, operand).is_some_and(|o| orderings.contains(&o))),
+            Self::In(items) => items
+                .iter()
+                .any(|item| Self::Eq(item.clone()).matches(values)),
+            Self::Nin(items) => !Self::In(items.clone()).matches(values),
+            Self::Exists(exists) => values.is_empty() != *exists,
+            Self::Regex(regex) => values.iter().any(|value| match value {
+                Bson::String(s) => regex.is_match(s),
+                _ => false,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, oid::ObjectId, Document};
+
+    use super::Query;
+
+    fn matches(query: &str, doc: &Document) -> bool {
+        query
+            .parse::<Query>()
+            .unwrap_or_else(|e| panic!("{query} failed to parse: {e}"))
+            .matches(doc)
+    }
+
+    fn invalid(query: &str) -> String {
+        match query.parse::<Query>() {
+            Ok(parsed) => panic!("{query} parsed as {parsed:?}"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn equality_and_comparisons() {
+        let doc = doc! { "status": "active", "age": 31, "score": 2.5 };
+        assert!(matches(r#"{"status": "active", "age": 31.0}"#, &doc));
+        assert!(!matches(r#"{"status": "active", "age": 30}"#, &doc));
+        assert!(matches(r#"{"age": {"$gt": 30, "$lte": 31}}"#, &doc));
+        assert!(!matches(r#"{"age": {"$gte": 32}}"#, &doc));
+        assert!(matches(
+            r#"{"score": {"$lt": 3}, "age": {"$ne": 30}}"#,
+            &doc
+        ));
+        // strings and numbers don't order against each other
+        assert!(!matches(r#"{"status": {"$gt": 1}}"#, &doc));
+    }
+
+    #[test]
+    fn missing_fields_and_null() {
+        let doc = doc! { "a": null, "b": 1 };
+        assert!(matches(r#"{"a": null, "missing": null}"#, &doc));
+        assert!(matches(
+            r#"{"missing": {"$exists": false}, "a": {"$exists": true}}"#,
+            &doc
+        ));
+        assert!(!matches(r#"{"b": null}"#, &doc));
+        assert!(matches(r#"{"b": {"$ne": null}}"#, &doc));
+    }
+
+    #[test]
+    fn arrays_and_paths() {
+        let doc = doc! {
+            "tags": ["red", "blue"],
+            "items": [{ "sku": "a", "qty": 1 }, { "sku": "b", "qty": 5 }],
+        };
+        assert!(matches(r#"{"tags": "blue"}"#, &doc));
+        assert!(matches(r#"{"tags": ["red", "blue"]}"#, &doc));
+        assert!(matches(r#"{"items.qty": {"$gt": 4}}"#, &doc));
+        assert!(matches(r#"{"items.sku": {"$in": ["c", "b"]}}"#, &doc));
+        assert!(!matches(r#"{"items.sku": {"$nin": ["a"]}}"#, &doc));
+    }
+
+    #[test]
+    fn logical_operators() {
+        let doc = doc! { "a": 1, "b": 2 };
+        assert!(matches(r#"{"$or": [{"a": 2}, {"b": 2}]}"#, &doc));
+        assert!(!matches(r#"{"$and": [{"a": 1}, {"b": 1}]}"#, &doc));
+        assert!(matches(r#"{"$nor": [{"a": 2}, {"b": 1}]}"#, &doc));
+        assert!(matches(
+            r#"{"a": 1, "$or": [{"b": {"$in": [2, 3]}}]}"#,
+            &doc
+        ));
+    }
+
+    #[test]
+    fn regex_and_options() {
+        let doc = doc! { "name": "Ada\nLovelace" };
+        assert!(matches(
+            r#"{"name": {"$regex": "^ada", "$options": "i"}}"#,
+            &doc
+        ));
+        assert!(!matches(r#"{"name": {"$regex": "^ada"}}"#, &doc));
+        assert!(matches(
+            r#"{"name": {"$regex": "^Love", "$options": "m"}}"#,
+            &doc
+        ));
+        assert!(matches(
+            r#"{"name": {"$regex": "Ada.Love", "$options": "s"}}"#,
+            &doc
+        ));
+    }
+
+    #[test]
+    fn extended_json_values() {
+        let id = ObjectId::parse_str("65f0a1b2c3d4e5f601234567").expect("valid id");
+        let doc = doc! { "_id": id, "at": bson::DateTime::from_millis(1_700_000_000_000) };
+        assert!(matches(
+            r#"{"_id": {"$oid": "65f0a1b2c3d4e5f601234567"}}"#,
+            &doc
+        ));
+        assert!(matches(
+            r#"{"_id": {"$gt": {"$oid": "65f0a1b2c3d4e5f600000000"}}}"#,
+            &doc
+        ));
+        assert!(matches(
+            r#"{"at": {"$lt": {"$date": "2024-01-01T00:00:00Z"}}}"#,
+            &doc
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(invalid("[1]").starts_with("expected a json object"));
+        assert!(invalid("{").starts_with("invalid json in query"));
+        assert_eq!(
+            invalid(r#"{"$where": "1"}"#),
+            "unsupported query operator $where"
+        );
+        assert_eq!(
+            invalid(r#"{"$or": {"a": 1}}"#),
+            "$or takes an array of queries"
+        );
+        assert_eq!(
+            invalid(r#"{"$or": [1]}"#),
+            "$or takes an array of queries, got 1"
+        );
+        assert_eq!(
+            invalid(r#"{"a": {"$in": 1}}"#),
+            "$in of a takes an array of values"
+        );
+        assert_eq!(
+            invalid(r#"{"a": {"$exists": 1}}"#),
+            "$exists of a takes true or false"
+        );
+        assert_eq!(
+            invalid(r#"{"a": {"$regex": 1}}"#),
+            "$regex of a takes a pattern string"
+        );
+        assert!(invalid(r#"{"a": {"$regex": "("}}"#).starts_with("invalid $regex of a"));
+        assert_eq!(
+            invalid(r#"{"a": {"$gt": 1, "$size": 2}}"#),
+            "unsupported operator $size on a"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use crate::DissectError;
+
+/// How far back from the end a file is read at a time while looking for the last complete line
+const CHUNK: u64 = 64 * 1024;
+
+/// Close the json array of an --array-safe export that was cut short, documents written only partly are dropped
+#[derive(Debug, clap::Args)]
+pub struct FinalizeArgs {
+    /// The --single json output of an export run with --array-safe
+    pub file: PathBuf,
+}
+
+pub(crate) fn run(args: &FinalizeArgs) -> Result<(), DissectError> {
+    let mut file = OpenOptions::new().read(true).write(true).open(&args.file)?;
+    let mut head = [0u8; 2];
+    if file.read(&mut head)? < 2 || head != *b"[\n" {
+        return Err(DissectError::Parse(format!(
+            "{} isn't json written with --array-safe",
+            args.file.display()
+        )));
+    }
+
+    let mut end = file.metadata()?.len();
+    if last_line(&mut file, end)?.1.trim_ascii() == b"]" {
+        println!("{} is complete", args.file.display());
+        return Ok(());
+    }
+
+    // the newline after a document is only written once all of it is, whatever follows the last one goes
+    let mut dropped = 0;
+    loop {
+        let (start, line) = last_line(&mut file, end)?;
+        if !line.ends_with(b"\n") {
+            end = start;
+            continue;
+        }
+        let json = line.strip_prefix(b",").unwrap_or(&line);
+        if start == 0 || serde_json::from_slice::<serde_json::Value>(json).is_ok() {
+            break;
+        }
+        // a complete line that doesn't parse, the write of it was torn
+        dropped += 1;
+        end = start;
+    }
+    file.set_len(end)?;
+    file.seek(SeekFrom::End(0))?;
+    file.write_all(b"]")?;
+    file.sync_all()?;
+    println!(
+        "Closed the array of {} at {end} bytes{}",
+        args.file.display(),
+        if dropped > 0 {
+            format!(", dropped {dropped} torn documents")
+        } else {
+            String::new()
+        }
+    );
+    Ok(())
+}
+
+/// The offset and bytes of the last line of the first `end` bytes, with its newline when it has one
+fn last_line(file: &mut File, end: u64) -> Result<(u64, Vec<u8>), DissectError> {
+    let mut line = Vec::new();
+    let mut pos = end;
+    // a trailing newline belongs to the last line, the search for its start begins before it
+    let mut skip = 1;
+    while pos > 0 {
+        let start = pos.saturating_sub(CHUNK);
+        let mut chunk = vec![0u8; (pos - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        let search = chunk.len().saturating_sub(skip);
+        skip = 0;
+        if let Some(newline) = chunk[..search].iter().rposition(|&b| b == b'\n') {
+            chunk.drain(..=newline);
+            chunk.extend_from_slice(&line);
+            let start = start + newline as u64 + 1;
+            return Ok((start, chunk));
+        }
+        chunk.extend_from_slice(&line);
+        line = chunk;
+        pos = start;
+    }
+    Ok((0, line))
+}
@@ -0,0 +1,136 @@
+use bson::{oid::ObjectId, Bson};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    raw_document_key, read_compressed, value_key, write_compressed, DocOffset, DocReader, Input,
+};
+use crate::DissectError;
+
+/// Rate of false positives the filters are sized for
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Bloom filter over the `_id` values of one file, stored next to it as `.bloom.dat`
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BloomFilter {
+    /// Length of the file when the filter was built, a filter for another length is stale
+    len: u64,
+    hashes: u32,
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    pub fn new(len: u64, items: usize) -> Self {
+        let items = items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-items * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0);
+        let hashes = ((bits / items) * ln2).round().max(1.0) as u32;
+        Self {
+            len,
+            hashes,
+            bits: vec![0; (bits as usize).div_ceil(64)],
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.positions(key) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.positions(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Bit positions of a key using double hashing
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let h1 = seahash::hash(key);
+        let h2 = seahash::hash_seeded(key, 1, 2, 3, 4) | 1;
+        let size = (self.bits.len() * 64) as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+    }
+}
+
+/// Parse an id given on the command line,
+/// 24 hex characters are read as an ObjectId, integers as numbers and anything else as a string
+pub(crate) fn parse_id(id: &str) -> Bson {
+    if let Ok(oid) = ObjectId::parse_str(id) {
+        Bson::ObjectId(oid)
+    } else if let Ok(n) = id.parse::<i64>() {
+        Bson::Int64(n)
+    } else {
+        Bson::String(id.to_string())
+    }
+}
+
+/// Load the bloom filter of every file of the input,
+/// missing or stale filters are built when `build` is set and left out otherwise
+pub(crate) fn load_filters(
+    input: &Input,
+    build: bool,
+    rebuild: bool,
+) -> Result<Vec<Option<BloomFilter>>, DissectError> {
+    let mut reader = DocReader::new(input);
+    let mut filters = Vec::with_capacity(input.files.len());
+    for (source, file) in input.files.iter().enumerate() {
+        let path = file.with_extension("bloom.dat");
+        let len = file.metadata()?.len();
+        let existing = if path.exists() && !rebuild {
+            let mut dat = read_compressed(&path)?;
+            Some(postcard::from_bytes_cobs::<BloomFilter>(&mut dat)?).filter(|f| f.len == len)
+        } else {
+            None
+        };
+
+        let filter = match existing {
+            Some(filter) => Some(filter),
+            None if build => {
+                println!("Building bloom filter for {}", file.display());
+                let offsets = input.offsets.iter().filter(|o| o.source == source);
+                let mut filter = BloomFilter::new(len, offsets.clone().count());
+                for offset in offsets {
+                    let raw = reader.read_raw(offset)?;
+                    filter.insert(raw_document_key(&raw, "_id")?.as_bytes());
+                }
+                write_compressed(&path, &filter)?;
+                Some(filter)
+            }
+            None => None,
+        };
+        filters.push(filter);
+    }
+    Ok(filters)
+}
+
+/// Offsets of the documents whose `_id` equals `id`,
+/// files whose filter rules the id out are skipped without reading them
+pub(crate) fn find_id(
+    input: &Input,
+    filters: &[Option<BloomFilter>],
+    id: &Bson,
+) -> Result<Vec<DocOffset>, DissectError> {
+    let key = value_key(id.clone())?;
+    let ruled_out = filters
+        .iter()
+        .map(|f| f.as_ref().is_some_and(|f| !f.contains(key.as_bytes())))
+        .collect::<Vec<_>>();
+    let skipped = ruled_out.iter().filter(|r| **r).count();
+    if skipped > 0 {
+        println!(
+            "Skipping {skipped} of {} files that can't contain the id",
+            input.files.len()
+        );
+    }
+
+    let mut reader = DocReader::new(input);
+    let mut found = Vec::new();
+    for offset in input.offsets.iter().filter(|o| !ruled_out[o.source]) {
+        let raw = reader.read_raw(offset)?;
+        if raw_document_key(&raw, "_id")? == key {
+            found.push(*offset);
+        }
+    }
+    Ok(found)
+}
@@ -0,0 +1,149 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use super::{index_path, DocOffset};
+use crate::{manifest::with_suffix, DissectError};
+
+/// How often the offsets found since the last checkpoint are written out
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Bytes of an offset in a checkpoint, its position and its size
+const RECORD: usize = 12;
+
+/// The offsets found so far while a dump is inspected, kept in `.idx.dat.partial` next to where its index goes so
+/// an inspection that was stopped, like with Ctrl-C, goes on from there the next time. The file starts with the
+/// length of the dump, a checkpoint of a dump that grew or shrank since is started over, and holds the position and
+/// size of every document after it. Written as its buffer fills and at least every few seconds, removed once the
+/// index is complete
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    written: Instant,
+}
+
+impl Checkpoint {
+    /// The checkpoint of the dump `file` of `len` bytes and the offsets it holds already
+    pub fn resume(file: &Path, len: u64) -> Result<(Self, Vec<DocOffset>), DissectError> {
+        let path = with_suffix(&index_path(file), ".partial");
+        let mut data = Vec::new();
+        if let Ok(mut partial) = File::open(&path) {
+            partial.read_to_end(&mut data)?;
+        }
+        let mut offsets = Vec::new();
+        let writer = if data.len() >= 8 && data[..8] == len.to_le_bytes() {
+            // a record cut short when the inspection stopped is left out and found again
+            let whole = 8 + (data.len() - 8) / RECORD * RECORD;
+            offsets = data[8..whole]
+                .chunks_exact(RECORD)
+                .map(|record| DocOffset {
+                    offset: u64::from_le_bytes(record[..8].try_into().expect("8 bytes")) as usize,
+                    size: u32::from_le_bytes(record[8..].try_into().expect("4 bytes")) as usize,
+                    source: 0,
+                })
+                .collect();
+            let partial = OpenOptions::new().append(true).open(&path)?;
+            partial.set_len(whole as u64)?;
+            partial
+        } else {
+            let mut partial = File::create(&path)?;
+            partial.write_all(&len.to_le_bytes())?;
+            partial
+        };
+        let checkpoint = Self {
+            path,
+            writer: BufWriter::new(writer),
+            written: Instant::now(),
+        };
+        Ok((checkpoint, offsets))
+    }
+
+    /// Add the offset of the next document
+    pub fn record(&mut self, offset: &DocOffset) -> Result<(), DissectError> {
+        self.writer
+            .write_all(&(offset.offset as u64).to_le_bytes())?;
+        self.writer.write_all(&(offset.size as u32).to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Write out the offsets added since the last checkpoint when it is time for the next one
+    pub fn tick(&mut self) -> Result<(), DissectError> {
+        if self.written.elapsed() >= INTERVAL {
+            self.writer.flush()?;
+            self.written = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Remove the checkpoint of a complete inspection
+    pub fn finish(self) -> Result<(), DissectError> {
+        drop(self.writer);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, OpenOptions},
+        io::Write,
+    };
+
+    use super::Checkpoint;
+    use crate::index::DocOffset;
+
+    fn offset(offset: usize, size: usize) -> DocOffset {
+        DocOffset {
+            offset,
+            size,
+            source: 0,
+        }
+    }
+
+    fn found(offsets: &[DocOffset]) -> Vec<(usize, usize)> {
+        offsets.iter().map(|o| (o.offset, o.size)).collect()
+    }
+
+    #[test]
+    fn resume_where_it_stopped() {
+        let dir = std::env::temp_dir().join(format!("dissbson-checkpoint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dump = dir.join("dump.bson");
+        let partial = dir.join("dump.idx.dat.partial");
+
+        let (mut checkpoint, offsets) = Checkpoint::resume(&dump, 100).unwrap();
+        assert!(offsets.is_empty());
+        checkpoint.record(&offset(0, 40)).unwrap();
+        checkpoint.record(&offset(40, 25)).unwrap();
+        // stopped without finishing
+        drop(checkpoint);
+
+        let (mut checkpoint, offsets) = Checkpoint::resume(&dump, 100).unwrap();
+        assert_eq!(found(&offsets), [(0, 40), (40, 25)]);
+        checkpoint.record(&offset(65, 35)).unwrap();
+        drop(checkpoint);
+
+        // a record cut short is left out and written again
+        OpenOptions::new()
+            .append(true)
+            .open(&partial)
+            .unwrap()
+            .write_all(&[1, 2, 3, 4, 5])
+            .unwrap();
+        let (checkpoint, offsets) = Checkpoint::resume(&dump, 100).unwrap();
+        assert_eq!(found(&offsets), [(0, 40), (40, 25), (65, 35)]);
+        assert_eq!(fs::metadata(&partial).unwrap().len(), 8 + 3 * 12);
+        drop(checkpoint);
+
+        // the dump changed since, its checkpoint starts over
+        let (checkpoint, offsets) = Checkpoint::resume(&dump, 120).unwrap();
+        assert!(offsets.is_empty());
+        checkpoint.finish().unwrap();
+        assert!(!partial.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bson::{Document, RawDocument};
+use clap::ValueEnum;
+
+use crate::{docpath::Fields, DissectError};
+
+/// What to do with documents holding strings that aren't valid UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InvalidUtf8 {
+    /// Replace invalid sequences with U+FFFD
+    Replace,
+    /// Stop the export
+    Error,
+    /// Leave the document out of the output
+    SkipDoc,
+}
+
+/// Decodes raw documents, applying the invalid UTF-8 policy and counting how often it was needed
+#[derive(Debug)]
+pub(crate) struct Decoder {
+    policy: InvalidUtf8,
+    /// Only these fields are decoded, from --fields
+    fields: Option<Fields>,
+    replaced: AtomicUsize,
+    skipped: AtomicUsize,
+}
+
+impl Decoder {
+    pub fn new(policy: InvalidUtf8) -> Self {
+        Self {
+            policy,
+            fields: None,
+            replaced: AtomicUsize::new(0),
+            skipped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Decode only these fields of every document
+    pub fn fields(mut self, fields: Option<Fields>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Decode a raw document, `None` when it is skipped
+    pub fn decode(&self, raw: &[u8]) -> Result<Option<Document>, DissectError> {
+        let Some(fields) = &self.fields else {
+            return self.decode_whole(raw);
+        };
+        match RawDocument::from_bytes(raw).and_then(|doc| fields.project_raw(doc)) {
+            Ok(doc) => Ok(Some(doc)),
+            // strings that aren't UTF-8 go through the policy like without --fields
+            Err(_) => Ok(self.decode_whole(raw)?.map(|doc| fields.project(&doc))),
+        }
+    }
+
+    fn decode_whole(&self, raw: &[u8]) -> Result<Option<Document>, DissectError> {
+        let error = match Document::from_reader(raw) {
+            Ok(doc) => return Ok(Some(doc)),
+            Err(e) => e,
+        };
+        // only documents a lossy decode can read failed because of their strings,
+        // newer bson releases deprecate this for a serde wrapper the locked version doesn't have
+        #[allow(deprecated)]
+        let Ok(lossy) = Document::from_reader_utf8_lossy(raw) else {
+            return Err(error.into());
+        };
+        match self.policy {
+            InvalidUtf8::Replace => {
+                self.replaced.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(lossy))
+            }
+            InvalidUtf8::SkipDoc => {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                Ok(None)
+            }
+            InvalidUtf8::Error => Err(DissectError::Parse(format!(
+                "Document with invalid UTF-8 ({error}), pass --invalid-utf8 replace or skip-doc to get past it"
+            ))),
+        }
+    }
+
+    /// Documents whose strings were repaired and documents skipped
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.replaced.load(Ordering::Relaxed),
+            self.skipped.load(Ordering::Relaxed),
+        )
+    }
+}
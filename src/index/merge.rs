@@ -0,0 +1,308 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+use bson::{oid::ObjectId, RawBsonRef, RawDocument};
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use super::{DocOffset, DocReader, Input};
+use crate::{docpath, Args, DissectError};
+
+/// A field value documents are merged by, types sort like in MongoDB:
+/// missing and null first, then numbers, strings, ObjectIds, booleans and dates
+#[derive(Debug, Clone)]
+enum SortKey {
+    Null,
+    Number(f64),
+    Text(String),
+    ObjectId(ObjectId),
+    Boolean(bool),
+    DateTime(i64),
+    /// Any other type, compared by its json text
+    Other(String),
+}
+
+impl SortKey {
+    fn of(value: Option<RawBsonRef>) -> Self {
+        match value {
+            None | Some(RawBsonRef::Null) | Some(RawBsonRef::Undefined) => Self::Null,
+            Some(RawBsonRef::Int32(n)) => Self::Number(n as f64),
+            Some(RawBsonRef::Int64(n)) => Self::Number(n as f64),
+            Some(RawBsonRef::Double(n)) => Self::Number(n),
+            Some(RawBsonRef::String(s)) | Some(RawBsonRef::Symbol(s)) => Self::Text(s.into()),
+            Some(RawBsonRef::ObjectId(oid)) => Self::ObjectId(oid),
+            Some(RawBsonRef::Boolean(b)) => Self::Boolean(b),
+            Some(RawBsonRef::DateTime(date)) => Self::DateTime(date.timestamp_millis()),
+            Some(other) => Self::Other(
+                bson::Bson::try_from(other.to_raw_bson())
+                    .map(|value| value.into_relaxed_extjson().to_string())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::Number(_) => 1,
+            Self::Text(_) => 2,
+            Self::ObjectId(_) => 3,
+            Self::Boolean(_) => 4,
+            Self::DateTime(_) => 5,
+            Self::Other(_) => 6,
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a.total_cmp(b),
+            (Self::Text(a), Self::Text(b)) | (Self::Other(a), Self::Other(b)) => a.cmp(b),
+            (Self::ObjectId(a), Self::ObjectId(b)) => a.cmp(b),
+            (Self::Boolean(a), Self::Boolean(b)) => a.cmp(b),
+            (Self::DateTime(a), Self::DateTime(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+/// The key of a raw document at a dotted path
+fn raw_key(raw: &[u8], path: &[String]) -> Result<SortKey, DissectError> {
+    Ok(SortKey::of(docpath::raw_get(
+        RawDocument::from_bytes(raw)?,
+        path,
+    )?))
+}
+
+/// Interleave the documents of every input file into one sequence ordered by the --merge-by field:
+/// the documents of each file are sorted on their own, then the files are merged a document at a time,
+/// equal keys keep the order of the files and of the documents in them.
+/// With --presorted the files are taken to be in order already and only the next key of each is read during the merge
+pub(crate) fn select(
+    args: &Args,
+    input: &Input,
+    offsets: Vec<DocOffset>,
+) -> Result<Vec<DocOffset>, DissectError> {
+    let Some(field) = &args.merge_by else {
+        return Ok(offsets);
+    };
+    let path = docpath::parse(field);
+    let files = offsets
+        .iter()
+        .map(|offset| offset.source)
+        .collect::<HashSet<_>>()
+        .len();
+
+    let merged = if args.presorted {
+        let mut runs = vec![Vec::new(); input.files.len()];
+        for offset in offsets {
+            runs[offset.source].push(offset);
+        }
+        let runs = runs
+            .into_iter()
+            .map(|offsets| {
+                let mut reader = DocReader::new(input);
+                let path = &path;
+                offsets
+                    .into_iter()
+                    .map(move |offset| Ok((raw_key(&reader.read_raw(&offset)?, path)?, offset)))
+            })
+            .collect();
+        merge(runs, input, field)?
+    } else {
+        let thread_pool = ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+        let runs = thread_pool.install(|| {
+            let batches = offsets
+                .par_chunks(args.batch.max(1))
+                .map(|offsets| {
+                    let mut reader = DocReader::new(input);
+                    offsets
+                        .iter()
+                        .map(|offset| Ok((raw_key(&reader.read_raw(offset)?, &path)?, *offset)))
+                        .collect::<Result<Vec<_>, DissectError>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut runs = vec![Vec::new(); input.files.len()];
+            for (key, offset) in batches.into_iter().flatten() {
+                runs[offset.source].push((key, offset));
+            }
+            runs.par_iter_mut()
+                .for_each(|run| run.par_sort_by(|a, b| a.0.cmp(&b.0)));
+            Ok::<_, DissectError>(runs)
+        })?;
+        let runs = runs
+            .into_iter()
+            .map(|run| run.into_iter().map(Ok))
+            .collect();
+        merge(runs, input, field)?
+    };
+    println!(
+        "Merged {} documents from {files} files by {field}",
+        merged.len()
+    );
+    Ok(merged)
+}
+
+/// K-way merge of runs that are each in key order, a run out of order stops the merge
+fn merge<I>(mut runs: Vec<I>, input: &Input, field: &str) -> Result<Vec<DocOffset>, DissectError>
+where
+    I: Iterator<Item = Result<(SortKey, DocOffset), DissectError>>,
+{
+    let mut merged = Vec::new();
+    // the next document of every run, its key waits in the heap
+    let mut heads = vec![None; runs.len()];
+    let mut heap = BinaryHeap::new();
+    for (run, docs) in runs.iter_mut().enumerate() {
+        if let Some((key, offset)) = docs.next().transpose()? {
+            heads[run] = Some(offset);
+            heap.push(Reverse((key, run)));
+        }
+    }
+    while let Some(Reverse((last, run))) = heap.pop() {
+        merged.extend(heads[run].take());
+        if let Some((key, offset)) = runs[run].next().transpose()? {
+            if key < last {
+                return Err(DissectError::Parse(format!(
+                    "{} isn't sorted by {field}, the document at byte {} belongs before the one preceding it, \
+                     leave out --presorted to sort it",
+                    input.files[offset.source].display(),
+                    offset.offset
+                )));
+            }
+            heads[run] = Some(offset);
+            heap.push(Reverse((key, run)));
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bson::{doc, oid::ObjectId, DateTime};
+
+    use super::{merge, raw_key, SortKey};
+    use crate::{
+        index::{DocOffset, Input},
+        DissectError,
+    };
+
+    fn key(doc: bson::Document, path: &str) -> SortKey {
+        let mut raw = Vec::new();
+        doc.to_writer(&mut raw).unwrap();
+        raw_key(&raw, &crate::docpath::parse(path)).unwrap()
+    }
+
+    /// Files of documents with these keys, a document's offset is its place in its file
+    fn merged(files: &[&[i64]]) -> Result<Vec<(usize, usize)>, DissectError> {
+        let input = Input {
+            files: (0..files.len())
+                .map(|n| PathBuf::from(format!("{n}.bson")))
+                .collect(),
+            offsets: Vec::new(),
+        };
+        let runs = files
+            .iter()
+            .enumerate()
+            .map(|(source, keys)| {
+                keys.iter()
+                    .enumerate()
+                    .map(move |(offset, &key)| {
+                        Ok((
+                            SortKey::Number(key as f64),
+                            DocOffset {
+                                offset,
+                                size: 5,
+                                source,
+                            },
+                        ))
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter()
+            })
+            .collect();
+        Ok(merge(runs, &input, "n")?
+            .into_iter()
+            .map(|offset| (offset.source, offset.offset))
+            .collect())
+    }
+
+    #[test]
+    fn types_sort_like_mongodb() {
+        let keys = [
+            key(doc! {}, "a"),
+            key(doc! { "a": null }, "a"),
+            key(doc! { "a": -1.5 }, "a"),
+            key(doc! { "a": 2 }, "a"),
+            key(doc! { "a": 3_i64 }, "a"),
+            key(doc! { "a": "B" }, "a"),
+            key(doc! { "a": "a" }, "a"),
+            key(doc! { "a": ObjectId::from_bytes([0; 12]) }, "a"),
+            key(doc! { "a": false }, "a"),
+            key(doc! { "a": true }, "a"),
+            key(doc! { "a": DateTime::from_millis(0) }, "a"),
+            key(doc! { "a": [1] }, "a"),
+        ];
+        assert_eq!(keys[0], keys[1]);
+        for pair in keys[1..].windows(2) {
+            assert!(pair[0] < pair[1], "{:?} sorts after {:?}", pair[0], pair[1]);
+        }
+        // integers and doubles are numbers alike
+        assert_eq!(key(doc! { "a": 2 }, "a"), key(doc! { "a": 2.0 }, "a"));
+    }
+
+    #[test]
+    fn keys_at_dotted_paths() {
+        let doc = doc! { "user": { "age": 30 } };
+        assert_eq!(key(doc.clone(), "user.age"), SortKey::Number(30.0));
+        assert_eq!(key(doc, "user.name"), SortKey::Null);
+    }
+
+    #[test]
+    fn runs_interleave_and_ties_keep_the_order_of_the_files() {
+        assert_eq!(
+            merged(&[&[1, 3, 3, 7], &[2, 3], &[], &[0, 9]]).unwrap(),
+            [
+                (3, 0),
+                (0, 0),
+                (1, 0),
+                (0, 1),
+                (0, 2),
+                (1, 1),
+                (0, 3),
+                (3, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_presorted_file_out_of_order_stops_the_merge() {
+        let Err(DissectError::Parse(e)) = merged(&[&[1, 2], &[1, 5, 4]]) else {
+            panic!("merged a file out of order");
+        };
+        assert_eq!(
+            e,
+            "1.bson isn't sorted by n, the document at byte 2 belongs before the one preceding it, \
+             leave out --presorted to sort it"
+        );
+        // equal keys in a row are in order
+        assert!(merged(&[&[2, 2, 2], &[2]]).is_ok());
+    }
+}
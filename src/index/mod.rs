@@ -0,0 +1,550 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bson::{Bson, Document, RawDocument};
+use clap::Subcommand;
+use flate2::write::{ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+use humansize::{format_size, DECIMAL};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+
+use crate::{manifest::with_suffix, workspace::lock::Lock, DissectError};
+use checkpoint::Checkpoint;
+
+pub(crate) mod bloom;
+pub(crate) mod checkpoint;
+pub(crate) mod decode;
+pub(crate) mod estimate;
+pub(crate) mod merge;
+pub(crate) mod oid;
+pub(crate) mod prefetch;
+
+/// Convert offset indexes between the compressed `.idx.dat` form and portable json
+#[derive(Debug, clap::Args)]
+pub struct IndexArgs {
+    #[clap(subcommand)]
+    pub command: IndexCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IndexCommand {
+    /// Write an index file as a json array of offsets
+    Export {
+        /// The index file to read
+        index: PathBuf,
+
+        /// The json file to write
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Build an index file from a json array of offsets
+    Import {
+        /// The json file to read
+        json: PathBuf,
+
+        /// The index file to write
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub(crate) struct DocOffset {
+    pub(crate) offset: usize,
+    pub(crate) size: usize,
+    /// Position of the file holding the document in [`Input::files`],
+    /// not stored since every index file section covers a single file
+    #[serde(skip)]
+    pub(crate) source: usize,
+}
+
+/// Name of the combined index written into input directories
+const COMBINED_INDEX: &str = "dissbson.idx.dat";
+
+/// Offsets of one file in a combined index
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexSection {
+    /// File name relative to the indexed directory
+    file: String,
+    /// Length of the file when it was indexed, used to detect stale sections
+    len: u64,
+    offsets: Vec<DocOffset>,
+}
+
+/// The files making up an input and the offsets of all their documents,
+/// an input is either a single dump or every `.bson` file of a directory
+#[derive(Debug)]
+pub(crate) struct Input {
+    pub(crate) files: Vec<PathBuf>,
+    pub(crate) offsets: Vec<DocOffset>,
+}
+
+/// Where the index of a dump or a directory of dumps is kept
+pub(crate) fn index_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join(COMBINED_INDEX)
+    } else {
+        path.with_extension("idx.dat")
+    }
+}
+
+/// Load the index of a dump or a directory of dumps,
+/// directories share a single index with one section per file
+pub(crate) fn load_input<P: AsRef<Path>>(path: P, reindex: bool) -> Result<Input, DissectError> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return Ok(Input {
+            files: vec![path.to_path_buf()],
+            offsets: load_or_build(path, reindex)?,
+        });
+    }
+
+    let files = input_files(path)?;
+    let index_path = path.join(COMBINED_INDEX);
+    // taken before the first file is inspected, so runs sharing the directory don't inspect it twice
+    let mut lock = None;
+    let sections = 'load: loop {
+        let mut previous = if index_path.exists() && !reindex {
+            load_sections(&index_path)?
+        } else {
+            Vec::new()
+        };
+
+        let mut stale = previous.len() != files.len();
+        let mut sections = Vec::with_capacity(files.len());
+        for file in &files {
+            let name = file
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let len = file.metadata()?.len();
+            match previous.iter().position(|s| s.file == name && s.len == len) {
+                Some(pos) => sections.push(previous.swap_remove(pos)),
+                None if lock.is_none() => {
+                    lock = Some(Lock::take(&with_suffix(&index_path, ".lock"))?);
+                    // the index is read again, a run that had the lock may have brought it up to date
+                    continue 'load;
+                }
+                None => {
+                    println!("Inspecting file: {}", file.display());
+                    stale = true;
+                    sections.push(IndexSection {
+                        file: name,
+                        len,
+                        offsets: inspect_bson(file)?,
+                    });
+                }
+            }
+        }
+        if stale {
+            save_sections(&index_path, &sections)?;
+        } else {
+            println!("Found index file, skipping inspection...");
+        }
+        break sections;
+    };
+
+    let mut offsets = Vec::with_capacity(sections.iter().map(|s| s.offsets.len()).sum());
+    for (source, section) in sections.into_iter().enumerate() {
+        offsets.extend(
+            section
+                .offsets
+                .into_iter()
+                .map(|o| DocOffset { source, ..o }),
+        );
+    }
+    Ok(Input { files, offsets })
+}
+
+/// The `.bson` files of an input directory in the order their documents are read
+pub(crate) fn input_files(path: &Path) -> Result<Vec<PathBuf>, DissectError> {
+    let mut files = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|f| f.is_file() && f.extension().is_some_and(|e| e == "bson"));
+    files.sort();
+    Ok(files)
+}
+
+/// Reads documents of an input, opening its files as they are needed
+pub(crate) struct DocReader<'a> {
+    files: &'a [PathBuf],
+    open: Vec<Option<File>>,
+    direct: bool,
+    /// The aligned blocks of O_DIRECT reads, kept between reads
+    blocks: Vec<u8>,
+}
+
+impl<'a> DocReader<'a> {
+    pub fn new(input: &'a Input) -> Self {
+        Self {
+            files: &input.files,
+            open: input.files.iter().map(|_| None).collect(),
+            direct: false,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Bypass the page cache with O_DIRECT, only supported on Linux
+    pub fn direct_io(mut self, direct: bool) -> Self {
+        self.direct = direct;
+        self
+    }
+
+    pub fn read_raw(&mut self, offset: &DocOffset) -> Result<Vec<u8>, DissectError> {
+        let mut buf = Vec::new();
+        self.read_raw_into(offset, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Append the raw bytes of the document at `offset` to `buf`, a buffer reused between documents saves
+    /// allocating one for each
+    pub fn read_raw_into(
+        &mut self,
+        offset: &DocOffset,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), DissectError> {
+        if self.direct {
+            let mut blocks = std::mem::take(&mut self.blocks);
+            let read = read_direct(self.file(offset.source)?, offset, &mut blocks)
+                .map(|doc| buf.extend_from_slice(doc));
+            self.blocks = blocks;
+            return read;
+        }
+        read_raw_into(self.file(offset.source)?, offset, buf)
+    }
+
+    /// Append the raw bytes of several documents to `buf` one after the other,
+    /// documents stored back to back are read in one go
+    pub fn read_batch(
+        &mut self,
+        offsets: &[DocOffset],
+        buf: &mut Vec<u8>,
+    ) -> Result<(), DissectError> {
+        let mut start = 0;
+        while start < offsets.len() {
+            let first = &offsets[start];
+            let mut end = start + 1;
+            while end < offsets.len()
+                && offsets[end].source == first.source
+                && offsets[end].offset == offsets[end - 1].offset + offsets[end - 1].size
+            {
+                end += 1;
+            }
+            let last = &offsets[end - 1];
+            let span = DocOffset {
+                size: last.offset + last.size - first.offset,
+                ..*first
+            };
+            // the span holds exactly the documents in between, in their order
+            self.read_raw_into(&span, buf)?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    pub fn read_document(&mut self, offset: &DocOffset) -> Result<Document, DissectError> {
+        let buf = self.read_raw(offset)?;
+        Ok(Document::from_reader(&mut buf.as_slice())?)
+    }
+
+    fn file(&mut self, source: usize) -> Result<&mut File, DissectError> {
+        let file = match self.open[source].take() {
+            Some(file) => file,
+            None => open_input(&self.files[source], self.direct)?,
+        };
+        Ok(self.open[source].insert(file))
+    }
+}
+
+/// Load the index of `path` from its `.idx.dat` checkpoint,
+/// the file is inspected and a new checkpoint written when there is none or `reindex` is set
+pub(crate) fn load_or_build<P: AsRef<Path>>(
+    path: P,
+    reindex: bool,
+) -> Result<Vec<DocOffset>, DissectError> {
+    let path = path.as_ref();
+    let index = path.with_extension("idx.dat");
+    // runs sharing the dump take turns inspecting it, one that waited finds the index the other wrote
+    let _lock = if !index.exists() || reindex {
+        Some(Lock::take(&with_suffix(&index, ".lock"))?)
+    } else {
+        None
+    };
+    if index.exists() && !reindex {
+        println!("Found index file, skipping inspection...");
+        load_index_data(index)
+    } else {
+        println!("Inspecting file: {}", path.display());
+        let offsets = inspect_bson(path)?;
+        save_index_data(index, &offsets)?;
+        Ok(offsets)
+    }
+}
+
+pub(crate) fn run(args: &IndexArgs) -> Result<(), DissectError> {
+    match &args.command {
+        IndexCommand::Export { index, output } => {
+            let offsets = load_index_data(index)?;
+            let mut writer = BufWriter::new(File::create(output)?);
+            serde_json::to_writer_pretty(&mut writer, &offsets)?;
+            writer.flush()?;
+            println!("Exported {} offsets to {}", offsets.len(), output.display());
+        }
+        IndexCommand::Import { json, output } => {
+            let reader = BufReader::new(File::open(json)?);
+            let offsets: Vec<DocOffset> = serde_json::from_reader(reader)?;
+            // the smallest valid document is 5 bytes, a length and a terminator
+            if let Some(bad) = offsets.iter().position(|o| o.size < 5) {
+                return Err(DissectError::Parse(format!(
+                    "Offset {bad} has an invalid document size"
+                )));
+            }
+            save_index_data(output, &offsets)?;
+            println!("Imported {} offsets to {}", offsets.len(), output.display());
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn save_index_data<P: AsRef<Path>>(
+    path: P,
+    offsets: &[DocOffset],
+) -> Result<(), DissectError> {
+    write_compressed(path.as_ref(), offsets)
+}
+
+pub(crate) fn load_index_data<P: AsRef<Path>>(path: P) -> Result<Vec<DocOffset>, DissectError> {
+    let mut dat = read_compressed(path.as_ref())?;
+    let offsets = postcard::from_bytes_cobs::<Vec<DocOffset>>(&mut dat)?;
+
+    Ok(offsets)
+}
+
+fn save_sections(path: &Path, sections: &[IndexSection]) -> Result<(), DissectError> {
+    write_compressed(path, sections)
+}
+
+fn load_sections(path: &Path) -> Result<Vec<IndexSection>, DissectError> {
+    let mut dat = read_compressed(path)?;
+    Ok(postcard::from_bytes_cobs::<Vec<IndexSection>>(&mut dat)?)
+}
+
+pub(crate) fn write_compressed<T: Serialize + ?Sized>(
+    path: &Path,
+    value: &T,
+) -> Result<(), DissectError> {
+    // written next to it and renamed into place, so runs sharing an index never read one cut short
+    let temp = crate::manifest::with_suffix(path, &format!(".{}.tmp", std::process::id()));
+    let mut offsets_checkpoint = File::create(&temp)?;
+    let ser = postcard::to_allocvec_cobs(value)?;
+    let mut enc = ZlibEncoder::new(&mut offsets_checkpoint, Compression::default());
+    enc.write_all(&ser)?;
+    enc.finish()?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+pub(crate) fn read_compressed(path: &Path) -> Result<Vec<u8>, DissectError> {
+    let mut file = OpenOptions::new().read(true).open(path)?;
+    let mut dat = Vec::new();
+    let mut reader = BufReader::new(&mut file);
+    let mut dec = ZlibDecoder::new(&mut dat);
+    let mut buf = [0u8; 8192];
+    while let Ok(n) = reader.read(&mut buf[..]) {
+        if n == 0 {
+            break;
+        }
+        dec.write_all(&buf[..n])?;
+    }
+    dec.finish()?;
+    Ok(dat)
+}
+
+/// Documents inspected between updates of the progress bar and looks at whether a checkpoint is due
+const PROGRESS_EVERY: usize = 4096;
+
+/// Find the offset of every document of a dump by following their sizes, with a progress bar of the bytes covered.
+/// The offsets are checkpointed as they are found and an inspection that was stopped goes on from its checkpoint
+pub(crate) fn inspect_bson<P: AsRef<Path>>(bson_file: P) -> Result<Vec<DocOffset>, DissectError> {
+    let path = bson_file.as_ref();
+    let mut file = OpenOptions::new().read(true).open(path)?;
+    let len = file.metadata()?.len();
+    let (mut checkpoint, mut offsets) = Checkpoint::resume(path, len)?;
+    let start = offsets
+        .last()
+        .map_or(0, |last| (last.offset + last.size) as u64);
+    if start > 0 {
+        println!(
+            "Resuming from a checkpoint of {} documents, {} of {}",
+            offsets.len(),
+            format_size(start, DECIMAL),
+            format_size(len, DECIMAL)
+        );
+    }
+    let pb = ProgressBar::new(len).with_position(start);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{eta_precise}] [{bar:40.red/blue}] {bytes}/{total_bytes} {bytes_per_sec}",
+            )
+            .expect("Failed to set progress bar style"),
+    );
+    let mut reader = BufReader::new(&mut file);
+    reader.seek(SeekFrom::Start(start))?;
+    index_file(&mut reader, &mut offsets, &mut checkpoint, &pb)?;
+    pb.finish_and_clear();
+    checkpoint.finish()?;
+    Ok(offsets)
+}
+
+fn index_file<R: Read + Seek>(
+    mut reader: R,
+    offsets: &mut Vec<DocOffset>,
+    checkpoint: &mut Checkpoint,
+    pb: &ProgressBar,
+) -> Result<(), DissectError> {
+    let mut buf = [0u8; 4];
+
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        // little endian 4 byte int
+        let size = i32::from_le_bytes(buf);
+        let offset = DocOffset {
+            offset: reader.stream_position()? as usize - 4,
+            size: size as usize,
+            source: 0,
+        };
+        checkpoint.record(&offset)?;
+        offsets.push(offset);
+        if offsets.len().is_multiple_of(PROGRESS_EVERY) {
+            pb.set_position((offset.offset + offset.size) as u64);
+            checkpoint.tick()?;
+        }
+        // seek to the end of the document minus the 4 bytes that were just read
+        reader.seek(SeekFrom::Current((size - 4) as i64))?;
+    }
+    Ok(())
+}
+
+/// Read the raw bytes of the document at `offset`
+pub(crate) fn read_raw<R: Read + Seek>(
+    reader: &mut R,
+    offset: &DocOffset,
+) -> Result<Vec<u8>, DissectError> {
+    let mut buf = Vec::new();
+    read_raw_into(reader, offset, &mut buf)?;
+    Ok(buf)
+}
+
+/// Append the raw bytes of the document at `offset` to `buf`, which is left as it was when the read fails
+fn read_raw_into<R: Read + Seek>(
+    reader: &mut R,
+    offset: &DocOffset,
+    buf: &mut Vec<u8>,
+) -> Result<(), DissectError> {
+    let start = buf.len();
+    reader.seek(SeekFrom::Start(offset.offset as u64))?;
+    buf.resize(start + offset.size, 0);
+    if let Err(e) = reader.read_exact(&mut buf[start..]) {
+        buf.truncate(start);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Alignment of the offset, length and buffer of O_DIRECT reads
+const DIRECT_ALIGN: usize = 4096;
+
+#[cfg(target_os = "linux")]
+fn open_input(path: &Path, direct: bool) -> Result<File, DissectError> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut options = OpenOptions::new();
+    options.read(true);
+    if direct {
+        options.custom_flags(libc::O_DIRECT);
+    }
+    Ok(options.open(path)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_input(path: &Path, direct: bool) -> Result<File, DissectError> {
+    if direct {
+        return Err(DissectError::Io(std::io::Error::other(
+            "--direct-io is only supported on Linux",
+        )));
+    }
+    Ok(OpenOptions::new().read(true).open(path)?)
+}
+
+/// Read the raw bytes of the document at `offset` from a file opened with O_DIRECT,
+/// the read is widened to aligned boundaries into an aligned window of `buf` and the document cut out of it
+fn read_direct<'b>(
+    file: &mut File,
+    offset: &DocOffset,
+    buf: &'b mut Vec<u8>,
+) -> Result<&'b [u8], DissectError> {
+    let start = offset.offset / DIRECT_ALIGN * DIRECT_ALIGN;
+    let end = (offset.offset + offset.size).div_ceil(DIRECT_ALIGN) * DIRECT_ALIGN;
+    // the blocks are read straight into the buffer, it isn't zeroed again when it is large enough already
+    let len = end - start + DIRECT_ALIGN;
+    if buf.len() < len {
+        buf.resize(len, 0);
+    }
+    let pad = buf.as_ptr().align_offset(DIRECT_ALIGN);
+    let window = &mut buf[pad..pad + end - start];
+
+    file.seek(SeekFrom::Start(start as u64))?;
+    let mut filled = 0;
+    // the last block of the file comes back short
+    while filled < window.len() {
+        match file.read(&mut window[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    let at = offset.offset - start;
+    if filled < at + offset.size {
+        return Err(DissectError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(&window[at..at + offset.size])
+}
+
+/// Read and decode the document at `offset`
+pub(crate) fn read_document<R: Read + Seek>(
+    reader: &mut R,
+    offset: &DocOffset,
+) -> Result<Document, DissectError> {
+    let buf = read_raw(reader, offset)?;
+    Ok(Document::from_reader(&mut buf.as_slice())?)
+}
+
+/// Extract a field of a raw document serialized as relaxed extended json,
+/// equal values always give the same key regardless of their integer width
+pub(crate) fn raw_document_key(raw: &[u8], key: &str) -> Result<String, DissectError> {
+    let doc = RawDocument::from_bytes(raw)?;
+    let value = doc.get(key)?.ok_or_else(|| missing_key(key))?;
+    value_key(Bson::try_from(value.to_raw_bson())?)
+}
+
+/// Extract a field of a document serialized as relaxed extended json
+pub(crate) fn document_key(doc: &Document, key: &str) -> Result<String, DissectError> {
+    value_key(doc.get(key).ok_or_else(|| missing_key(key))?.clone())
+}
+
+pub(crate) fn value_key(value: Bson) -> Result<String, DissectError> {
+    Ok(serde_json::to_string(&value.into_relaxed_extjson())?)
+}
+
+fn missing_key(key: &str) -> DissectError {
+    DissectError::Parse(format!("Document without a {key} field"))
+}
@@ -0,0 +1,108 @@
+use std::str::FromStr;
+
+use bson::{oid::ObjectId, DateTime, RawBsonRef, RawDocument};
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use super::{DocOffset, DocReader, Input};
+use crate::{Args, DissectError};
+
+/// A point in time to compare ObjectId creation times with, given as `2023-01-01` or RFC 3339
+#[derive(Debug, Clone, Copy)]
+pub struct OidTime(pub(crate) DateTime);
+
+impl FromStr for OidTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let full = if s.contains('T') {
+            s.to_string()
+        } else {
+            format!("{s}T00:00:00Z")
+        };
+        DateTime::parse_rfc3339_str(&full).map(Self).map_err(|_| {
+            format!("expected a date like 2023-01-01 or 2023-01-01T12:00:00Z, got {s}")
+        })
+    }
+}
+
+/// Apply the ObjectId based selections of `args` to `offsets`: keep documents created in the
+/// --oid-after/--oid-before range and sort them with --order-by-oid, only the id is read from each document
+pub(crate) fn select(
+    args: &Args,
+    input: &Input,
+    offsets: Vec<DocOffset>,
+) -> Result<Vec<DocOffset>, DissectError> {
+    let ranged = args.oid_after.is_some() || args.oid_before.is_some();
+    if !ranged && !args.order_by_oid {
+        return Ok(offsets);
+    }
+
+    let thread_pool = ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+    let batches = thread_pool.install(|| {
+        offsets
+            .par_chunks(args.batch.max(1))
+            .map(|offsets| {
+                let mut reader = DocReader::new(input);
+                let mut head = Vec::with_capacity(LEADING_OID);
+                offsets
+                    .iter()
+                    .map(|offset| Ok((read_oid(&mut reader, offset, &mut head)?, *offset)))
+                    .collect::<Result<Vec<_>, DissectError>>()
+            })
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    let mut keyed = batches.into_iter().flatten().collect::<Vec<_>>();
+
+    if ranged {
+        let before = keyed.len();
+        keyed.retain(|(oid, _)| {
+            oid.is_some_and(|oid| {
+                let created = oid.timestamp();
+                args.oid_after.is_none_or(|after| created >= after.0)
+                    && args.oid_before.is_none_or(|before| created < before.0)
+            })
+        });
+        println!(
+            "Selected {} of {before} documents by ObjectId creation time",
+            keyed.len()
+        );
+    }
+    // documents without an ObjectId id keep their order at the end
+    if args.order_by_oid {
+        keyed.par_sort_by_key(|(oid, _)| (oid.is_none(), *oid));
+    }
+    Ok(keyed.into_iter().map(|(_, offset)| offset).collect())
+}
+
+/// Bytes of a document up to the end of a leading ObjectId `_id`, its length, the element type, `_id\0` and the id
+const LEADING_OID: usize = 4 + 1 + 4 + 12;
+
+/// The `_id` of the document at `offset` when it is an ObjectId, only the start of the document is read when the
+/// id comes first like in every mongodump, the whole document otherwise
+fn read_oid(
+    reader: &mut DocReader,
+    offset: &DocOffset,
+    head: &mut Vec<u8>,
+) -> Result<Option<ObjectId>, DissectError> {
+    let leading = DocOffset {
+        size: offset.size.min(LEADING_OID),
+        ..*offset
+    };
+    head.clear();
+    reader.read_raw_into(&leading, head)?;
+    if head.get(5..9) == Some(b"_id\0") {
+        return Ok(match (head[4], head.get(9..LEADING_OID)) {
+            (0x07, Some(id)) => Some(ObjectId::from_bytes(id.try_into().expect("12 bytes"))),
+            _ => None,
+        });
+    }
+    raw_oid(&reader.read_raw(offset)?)
+}
+
+/// The `_id` of a raw document when it is an ObjectId
+fn raw_oid(raw: &[u8]) -> Result<Option<ObjectId>, DissectError> {
+    match RawDocument::from_bytes(raw)?.get("_id")? {
+        Some(RawBsonRef::ObjectId(oid)) => Ok(Some(oid)),
+        _ => Ok(None),
+    }
+}
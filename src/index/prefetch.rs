@@ -0,0 +1,97 @@
+use std::{
+    sync::mpsc::{sync_channel, Receiver},
+    thread,
+};
+
+use bson::Document;
+use parking_lot::Mutex;
+
+use super::{decode::Decoder, DocOffset, DocReader};
+use crate::{retry::Retry, DissectError};
+
+/// Buffers of decoded batches, handed back to the reading thread so reading a batch doesn't allocate
+/// once there are as many as batches in flight
+#[derive(Default)]
+struct Buffers(Mutex<Vec<Vec<u8>>>);
+
+impl Buffers {
+    fn take(&self) -> Vec<u8> {
+        self.0.lock().pop().unwrap_or_default()
+    }
+
+    fn give(&self, mut buf: Vec<u8>) {
+        buf.clear();
+        self.0.lock().push(buf);
+    }
+}
+
+/// Raw documents of a batch read ahead of decoding
+pub(crate) struct Batch<'p> {
+    /// Position of the batch among all batches of the export
+    pub(crate) index: usize,
+    pub(crate) offsets: Vec<DocOffset>,
+    /// The documents back to back, in the order of `offsets`
+    raw: Vec<u8>,
+    buffers: &'p Buffers,
+}
+
+impl Batch<'_> {
+    /// Decode the documents, paired with their place in the input, skipped documents are left out
+    pub fn decode(self, decoder: &Decoder) -> Result<Vec<(Document, DocOffset)>, DissectError> {
+        let mut docs = Vec::with_capacity(self.offsets.len());
+        let mut at = 0;
+        for offset in &self.offsets {
+            if let Some(doc) = decoder.decode(&self.raw[at..at + offset.size])? {
+                docs.push((doc, *offset));
+            }
+            at += offset.size;
+        }
+        Ok(docs)
+    }
+}
+
+impl Drop for Batch<'_> {
+    fn drop(&mut self) {
+        self.buffers.give(std::mem::take(&mut self.raw));
+    }
+}
+
+/// Read `offsets` in batches of `batch` documents with `reader` on a background thread, staying at most `depth`
+/// batches ahead of `consume`, which receives them in order, failed reads are retried with `retry`
+pub(crate) fn run<F>(
+    mut reader: DocReader,
+    retry: &Retry,
+    offsets: &[DocOffset],
+    batch: usize,
+    depth: usize,
+    consume: F,
+) where
+    F: FnOnce(Receiver<Result<Batch<'_>, DissectError>>),
+{
+    let buffers = Buffers::default();
+    let (sender, receiver) = sync_channel(depth.max(1));
+    thread::scope(|scope| {
+        let buffers = &buffers;
+        scope.spawn(move || {
+            for (index, offsets) in offsets.chunks(batch.max(1)).enumerate() {
+                let mut raw = buffers.take();
+                let batch = retry
+                    .run(|| {
+                        raw.clear();
+                        reader.read_batch(offsets, &mut raw)
+                    })
+                    .map(|()| Batch {
+                        index,
+                        offsets: offsets.to_vec(),
+                        raw,
+                        buffers,
+                    });
+                // the consumer hung up, nothing left to read for
+                if sender.send(batch).is_err() {
+                    return;
+                }
+            }
+        });
+        consume(receiver);
+    });
+}
@@ -0,0 +1,274 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use indicatif::{MultiProgress, ProgressBar};
+use parking_lot::{Condvar, Mutex};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{env, export, stats::sizes::parse_size, Args, DissectError};
+
+/// Run the export jobs of a yaml file in one process, sharing one thread pool, a memory and a read budget
+/// and one progress display instead of competing as separate processes
+#[derive(Debug, clap::Args)]
+pub struct RunArgs {
+    /// The jobs file, a `jobs` list of exports each with an `input`, an `output` and the options of the export as
+    /// keys, like `format: ndjson` or `single: true`, and optionally `threads`, `parallel`, `memory` and `io`
+    /// for all of them
+    pub jobs: PathBuf,
+}
+
+/// The jobs file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Jobs {
+    /// Threads of the pool every job decodes on, the number of cpus by default
+    threads: Option<usize>,
+    /// Jobs run at once, all of them by default
+    parallel: Option<usize>,
+    /// Bytes of documents the jobs hold in batches at once, like 2GB
+    memory: Option<String>,
+    /// Bytes the jobs read per second together, like 200MB/s
+    io: Option<String>,
+    jobs: Vec<Job>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Job {
+    /// Shown in front of its progress bar and in the report, the output by default
+    name: Option<String>,
+    input: String,
+    output: String,
+    /// The options of the export, without the leading --
+    #[serde(flatten)]
+    options: Map<String, Value>,
+}
+
+/// The command line options a table of options stands for, `key: value` is `--key=value`, `true` a flag alone
+/// and a list the option given once per item, underscores in keys are read as dashes
+pub(crate) fn flags(options: &Map<String, Value>) -> Result<Vec<String>, DissectError> {
+    let mut flags = Vec::new();
+    for (key, value) in options {
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            Value::Bool(true) => flags.push(flag),
+            Value::Bool(false) | Value::Null => {}
+            Value::Array(items) => {
+                for item in items {
+                    flags.push(format!("{flag}={}", scalar(key, item)?));
+                }
+            }
+            value => flags.push(format!("{flag}={}", scalar(key, value)?)),
+        }
+    }
+    Ok(flags)
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String, DissectError> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        other => Err(DissectError::Parse(format!(
+            "{key} takes strings and numbers, got {other}"
+        ))),
+    }
+}
+
+/// What the exports of `run` share, an export of its own shares nothing
+#[derive(Default)]
+pub(crate) struct Shared {
+    pub(crate) pool: Option<Arc<ThreadPool>>,
+    pub(crate) progress: Option<(MultiProgress, String)>,
+    pub(crate) budget: Option<Arc<Budget>>,
+}
+
+impl Shared {
+    /// Show the progress bar of an export with those of the other jobs, under the name of its job
+    pub(crate) fn track(&self, bar: ProgressBar) -> ProgressBar {
+        match &self.progress {
+            Some((multi, name)) => {
+                let bar = multi.add(bar);
+                bar.set_prefix(format!("{name} "));
+                bar
+            }
+            None => bar,
+        }
+    }
+
+    /// Wait until a batch of `bytes` fits the budgets of the jobs, the lease gives its memory back when dropped
+    pub(crate) fn lease(&self, bytes: usize) -> Option<Lease<'_>> {
+        self.budget.as_ref().map(|budget| budget.acquire(bytes))
+    }
+}
+
+/// The memory and read budgets all jobs draw from
+pub(crate) struct Budget {
+    memory: Option<u64>,
+    /// Bytes of the batches being worked on
+    held: Mutex<u64>,
+    freed: Condvar,
+    /// Bytes per second
+    io: Option<f64>,
+    /// When the next batch may be read, reads are spaced out by the size of the ones before them
+    next_read: Mutex<Instant>,
+}
+
+impl Budget {
+    fn acquire(&self, bytes: usize) -> Lease<'_> {
+        let mut held = 0;
+        if let Some(memory) = self.memory {
+            // a batch larger than the whole budget waits until it has the budget to itself
+            held = (bytes as u64).min(memory);
+            let mut used = self.held.lock();
+            while *used + held > memory {
+                self.freed.wait(&mut used);
+            }
+            *used += held;
+        }
+        if let Some(rate) = self.io {
+            let start = {
+                let mut next = self.next_read.lock();
+                let start = (*next).max(Instant::now());
+                *next = start + Duration::from_secs_f64(bytes as f64 / rate);
+                start
+            };
+            thread::sleep(start.saturating_duration_since(Instant::now()));
+        }
+        Lease { budget: self, held }
+    }
+}
+
+pub(crate) struct Lease<'a> {
+    budget: &'a Budget,
+    held: u64,
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        if self.held > 0 {
+            *self.budget.held.lock() -= self.held;
+            self.budget.freed.notify_all();
+        }
+    }
+}
+
+pub(crate) fn run(args: &RunArgs) -> Result<(), DissectError> {
+    let file: Jobs = serde_yaml::from_str(&std::fs::read_to_string(&args.jobs)?)?;
+    if file.jobs.is_empty() {
+        return Err(DissectError::Parse(format!(
+            "{} lists no jobs",
+            args.jobs.display()
+        )));
+    }
+    // every job is checked before the first one starts
+    let mut jobs = Vec::with_capacity(file.jobs.len());
+    for job in &file.jobs {
+        let name = job.name.clone().unwrap_or_else(|| job.output.clone());
+        let mut command_line = vec![
+            "dissbson".to_string(),
+            job.input.clone(),
+            job.output.clone(),
+        ];
+        command_line.extend(flags(&job.options)?);
+        let export = Args::try_parse_from(&command_line)
+            .map_err(|e| DissectError::Parse(format!("Job {name}: {e}")))?;
+        if export.stdout || job.output == "-" || export.command.is_some() {
+            return Err(DissectError::Parse(format!(
+                "Job {name}: the jobs of run write to files, not to stdout"
+            )));
+        }
+        jobs.push((name, export));
+    }
+
+    let budget = Budget {
+        memory: file
+            .memory
+            .as_deref()
+            .map(parse_size)
+            .transpose()
+            .map_err(DissectError::Parse)?,
+        held: Mutex::new(0),
+        freed: Condvar::new(),
+        io: file
+            .io
+            .as_deref()
+            .map(|io| parse_size(io.strip_suffix("/s").unwrap_or(io)))
+            .transpose()
+            .map_err(DissectError::Parse)?
+            .map(|rate| rate as f64),
+        next_read: Mutex::new(Instant::now()),
+    };
+    let budget = (budget.memory.is_some() || budget.io.is_some()).then(|| Arc::new(budget));
+    let mut pool = ThreadPoolBuilder::new();
+    if let Some(threads) = file.threads {
+        pool = pool.num_threads(threads);
+    }
+    let pool = Arc::new(pool.build()?);
+    let multi = MultiProgress::new();
+    let parallel = file.parallel.unwrap_or(jobs.len()).clamp(1, jobs.len());
+    println!(
+        "Running {} jobs, {parallel} at once on {} threads",
+        jobs.len(),
+        pool.current_num_threads()
+    );
+
+    let queue = Mutex::new(jobs.into_iter());
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..parallel {
+            scope.spawn(|| {
+                loop {
+                    // taken on a line of its own so the queue isn't locked while the job runs
+                    let job = queue.lock().next();
+                    let Some((name, export_args)) = job else {
+                        break;
+                    };
+                    let shared = Shared {
+                        pool: Some(pool.clone()),
+                        progress: Some((multi.clone(), name.clone())),
+                        budget: budget.clone(),
+                    };
+                    let started = Instant::now();
+                    // a failing job panics in places, that ends the job and not the others
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        export(export_args, None, &shared)
+                    }));
+                    let outcome = match result {
+                        Ok(Ok(())) => Ok(()),
+                        Ok(Err(e)) => Err(e.to_string()),
+                        Err(panic) => Err(env::scrub_panic(&env::panic_message(&*panic))),
+                    };
+                    results.lock().push((name, outcome, started.elapsed()));
+                }
+            });
+        }
+    });
+
+    let results = results.into_inner();
+    println!();
+    let mut failed = 0;
+    for (name, outcome, elapsed) in &results {
+        match outcome {
+            Ok(()) => println!("{name}: done in {:.1}s", elapsed.as_secs_f64()),
+            Err(e) => {
+                failed += 1;
+                println!("{name}: failed after {:.1}s, {e}", elapsed.as_secs_f64());
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(DissectError::Unexpected(format!(
+            "{failed} of {} jobs failed",
+            results.len()
+        )));
+    }
+    println!("All {} jobs finished", results.len());
+    Ok(())
+}
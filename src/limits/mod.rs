@@ -0,0 +1,124 @@
+use crate::{index::DocOffset, Args, DissectError};
+
+/// Descriptors left for stdio, index and manifest files, the lock of the run and whatever libraries open
+const RESERVED: u64 = 32;
+
+/// Check the export fits in the open file limit before it starts rather than failing with EMFILE deep into it.
+/// With `--raise-fd-limit` the soft limit is raised as far as the hard limit allows, when that isn't enough
+/// fewer writer threads are used, returns the --write-threads to run with
+pub(crate) fn check(
+    args: &Args,
+    input_files: usize,
+    idx: &[DocOffset],
+) -> Result<Option<usize>, DissectError> {
+    let Some((soft, hard)) = nofile()? else {
+        return Ok(args.write_threads);
+    };
+
+    // a decoding thread keeps the input files of its batch open, the reader of --prefetch every file it
+    // got to, plugins and exec filters hold pipes
+    let readers = if args.prefetch > 0 {
+        input_files.max(1) as u64
+    } else {
+        (args.threads * batch_span(idx, args.batch)) as u64
+    };
+    let pipes = if args.exec_filter.is_some() {
+        2 * args.threads as u64
+    } else {
+        0
+    };
+    let sinks =
+        2 * (args.sink.len() + args.route.len() + usize::from(args.anomalies.is_some())) as u64;
+    let fixed = RESERVED + readers + pipes + sinks;
+    let writers = match args.write_threads {
+        _ if args.writes_file() => 4,
+        Some(threads) if threads > 0 => threads as u64,
+        _ => args.threads as u64,
+    };
+    let needed = fixed + writers;
+    if needed <= soft {
+        return Ok(args.write_threads);
+    }
+
+    let limit = if args.raise_fd_limit && hard > soft {
+        let raised = needed.min(hard);
+        set_nofile(raised, hard)?;
+        println!("Raised the open file limit from {soft} to {raised}");
+        raised
+    } else {
+        soft
+    };
+    if needed <= limit {
+        return Ok(args.write_threads);
+    }
+
+    if let Some(threads) = args.write_threads.filter(|&threads| threads > 0) {
+        if !args.writes_file() && fixed < limit {
+            let fit = (limit - fixed) as usize;
+            println!(
+                "Lowered --write-threads from {threads} to {fit} to stay within the open file limit of {limit}"
+            );
+            return Ok(Some(fit));
+        }
+    }
+    Err(DissectError::Parse(format!(
+        "The export keeps up to {needed} files open but only {limit} are allowed, {}",
+        if args.raise_fd_limit || hard <= soft {
+            format!("the hard limit is {hard}, use fewer --threads or input files")
+        } else {
+            format!("raise the limit with --raise-fd-limit or `ulimit -n {needed}`")
+        }
+    )))
+}
+
+/// The most input files the documents of one batch are read from
+fn batch_span(idx: &[DocOffset], batch: usize) -> usize {
+    idx.chunks(batch.max(1))
+        .map(|chunk| {
+            let mut sources = chunk.iter().map(|offset| offset.source).collect::<Vec<_>>();
+            sources.sort_unstable();
+            sources.dedup();
+            sources.len()
+        })
+        .max()
+        .unwrap_or(0)
+        .max(1)
+}
+
+/// The soft and hard RLIMIT_NOFILE of the process
+#[cfg(unix)]
+fn nofile() -> Result<Option<(u64, u64)>, DissectError> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes to the struct it is given
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(Some((limit.rlim_cur, limit.rlim_max)))
+}
+
+#[cfg(unix)]
+fn set_nofile(soft: u64, hard: u64) -> Result<(), DissectError> {
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // SAFETY: setrlimit only reads the struct it is given
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Other platforms have no descriptor limit to check
+#[cfg(not(unix))]
+fn nofile() -> Result<Option<(u64, u64)>, DissectError> {
+    Ok(None)
+}
+
+#[cfg(not(unix))]
+fn set_nofile(_soft: u64, _hard: u64) -> Result<(), DissectError> {
+    Ok(())
+}
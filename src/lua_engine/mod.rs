@@ -1,11 +1,13 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{collections::HashMap, error::Error, rc::Rc};
 
 use bson::{oid::ObjectId, Bson, Document};
 use rlua::{Context, FromLua, Lua, ToLua, Value};
 
+use crate::env::Env;
+
 #[derive(Clone)]
 pub(crate) struct LuaEngine {
-    pub(crate) state: Arc<Lua>,
+    pub(crate) state: Rc<Lua>,
 }
 
 #[derive(Debug)]
@@ -139,10 +141,20 @@ impl FromLua<'_> for LuaObjectIdRepr {
 }
 
 impl LuaEngine {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    /// A fresh interpreter, scripts read the variables of --env with `env("KEY")`
+    pub fn new(env: &Env) -> Result<Self, Box<dyn Error>> {
         let state = Lua::new();
+        let vars = env.vars().clone();
 
         state.context(|ctx| {
+            ctx.globals()
+                .set(
+                    "env",
+                    ctx.create_function(move |_, key: String| Ok(vars.get(&key).cloned()))
+                        .unwrap(),
+                )
+                .unwrap();
+
             ctx.globals()
                 .set(
                     "print",
@@ -201,7 +213,7 @@ impl LuaEngine {
         });
 
         Ok(Self {
-            state: Arc::new(state),
+            state: Rc::new(state),
         })
     }
 
@@ -1,26 +1,87 @@
 use bson::Document;
-use clap::Parser;
-use flate2::write::{ZlibDecoder, ZlibEncoder};
-use flate2::Compression;
+use clap::{Parser, Subcommand};
+use diff::DiffArgs;
+use docpath::{ExcludeFields, Fields};
+use env::Env;
+use filter::{Expr, Query};
+use index::{
+    bloom,
+    decode::{Decoder, InvalidUtf8},
+    oid::OidTime,
+    prefetch, DocOffset, DocReader, IndexArgs,
+};
+use jobs::Shared;
 use lua_engine::LuaEngine;
-use neoncore::streams::{read::read_pattern, SeekRead};
-use parking_lot::RwLock;
-use rayon::prelude::IndexedParallelIterator;
+use output::{
+    archive::{Archive, ArchiveFormat},
+    checksum::{Manifest, Sha256Writer},
+    compress::{Compressed, Compression},
+    dialect::CsvDialect,
+    direct::JsonWriter,
+    encoding::TextEncoding,
+    encrypt::{Encrypted, Encryption},
+    inventory::Inventory,
+    naming::{Names, Naming},
+    partition::{PartitionSpec, Partitions},
+    store::{Destination, ObjectStore},
+    table::Schema,
+    Encoder, OutputFormat, StreamWriter,
+};
+use parking_lot::{Mutex, RwLock};
+use rayon::prelude::{IndexedParallelIterator, ParallelBridge};
 use rayon::{
-    prelude::{IntoParallelRefIterator, ParallelIterator},
-    ThreadPoolBuilder,
+    prelude::{IntoParallelRefIterator, ParallelIterator, ParallelSlice},
+    ThreadPool, ThreadPoolBuilder,
+};
+use retry::Retry;
+use sink::{PaceBy, Pacer, Rate, RouteSpec, Routes, SinkSpec, Sinks};
+use stats::{anomaly::ANOMALY_FIELD, numeric::NumericFields};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
 };
-use serde::{ser::SerializeSeq, Deserialize, Serialize, Serializer};
-use std::sync::Arc;
 use std::{
+    cell::RefCell,
+    collections::BTreeMap,
     fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    io::{BufWriter, Write},
     ops::Bound,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use thiserror::Error;
+use trace::Tracer;
+use transform::{
+    ArrayOverflow, DecompressField, ExecFilter, JmesPath, Jq, MissingAs, NullAs, RefMode,
+    Transforms,
+};
 
+mod check;
+mod diff;
+mod docpath;
+mod env;
+mod explain;
+mod filter;
+mod finalize;
+mod index;
+mod jobs;
+mod limits;
 mod lua_engine;
+mod manifest;
+mod normalize;
+mod output;
+mod query;
+mod relational;
+mod repl;
+mod retry;
+mod serve;
+mod show;
+mod similar;
+mod sink;
+mod stats;
+mod trace;
+mod transform;
+mod workspace;
 
 /// Tool to dissect a bson file into json files for each document
 ///
@@ -28,12 +89,23 @@ mod lua_engine;
 /// and gigabytes of data.
 #[derive(Debug, Parser)]
 #[clap(version=env!("CARGO_PKG_VERSION"), author="Matheus Xavier <mxavier@neonimp.com>", about)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
-    /// The input file to read
-    pub input: PathBuf,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// The input file to read, or a directory of bson files sharing one index
+    #[clap(required = true)]
+    pub input: Option<PathBuf>,
+
+    /// The output directory to write to, or - to write the single output stream to stdout like --stdout
+    #[clap(required_unless_present_any = ["stdout", "estimate"])]
+    pub output: Option<PathBuf>,
 
-    /// The output directory to write to
-    pub output: PathBuf,
+    /// Write the single output stream to stdout instead of a file, status lines go to stderr,
+    /// with --format bson dissbson can sit in a pipe in front of mongorestore or another dissbson
+    #[clap(long, conflicts_with_all = ["output", "verify", "checksums"])]
+    pub stdout: bool,
 
     /// The number of threads to use
     #[clap(short, long, default_value = "4")]
@@ -49,22 +121,535 @@ pub struct Args {
     #[clap(long)]
     pub inspect: bool,
 
+    /// Print the plan of the export and stop before writing anything: the input and its index, how many documents
+    /// the selection keeps, the stages every document goes through in order, the outputs and the thread settings
+    #[clap(long, conflicts_with = "redact_dry_run")]
+    pub explain: bool,
+
+    /// Estimate the number and average size of the documents in seconds and stop, from runs of documents found
+    /// at places spread over every file, without reading the input through or building an index
+    #[clap(long, conflicts_with_all = ["explain", "redact_dry_run"])]
+    pub estimate: bool,
+
+    /// Places sampled in every file by --estimate, more give a narrower range
+    #[clap(long, default_value = "200", requires = "estimate")]
+    pub estimate_samples: usize,
+
+    /// Only export the documents with this _id,
+    /// 24 hex characters are read as an ObjectId and integers as numbers
+    #[clap(long)]
+    pub id: Option<String>,
+
+    /// Build a bloom filter over the _id values next to the index,
+    /// --id lookups skip files whose filter rules the id out
+    #[clap(long)]
+    pub bloom: bool,
+
     /// pretty json output
     #[clap(long)]
     pub pretty: bool,
 
+    /// How compact json and ndjson are written, direct skips serde for the common value types
+    /// and is faster once encoding rather than IO is what an export waits on
+    #[clap(long, value_enum, default_value_t = JsonWriter::Serde)]
+    pub json_writer: JsonWriter,
+
     /// Limit using a rust slice expression
     #[clap(short, long)]
     pub slice: Option<String>,
 
+    /// Write documents in the order of their ObjectId _id, roughly the order they were inserted in,
+    /// the slice applies after sorting
+    #[clap(long)]
+    pub order_by_oid: bool,
+
+    /// Interleave the documents of every file of an input directory into one sequence ordered by this field,
+    /// each file is sorted on its own and the files are then merged
+    #[clap(long, value_name = "FIELD", conflicts_with = "order_by_oid")]
+    pub merge_by: Option<String>,
+
+    /// The files are each sorted by the --merge-by field already, they are merged as they are read
+    /// without sorting them first, a file found out of order stops the export
+    #[clap(long, requires = "merge_by")]
+    pub presorted: bool,
+
+    /// Only export documents whose ObjectId _id was created at or after this date, like 2023-01-01
+    #[clap(long)]
+    pub oid_after: Option<OidTime>,
+
+    /// Only export documents whose ObjectId _id was created before this date
+    #[clap(long)]
+    pub oid_before: Option<OidTime>,
+
     /// Lua script to run on each document
     #[clap(short = 'S', long)]
     pub script: Option<PathBuf>,
 
+    /// Set a variable for scripts, as env("KEY"), and for sink urls, paths and --http-header, as ${KEY},
+    /// KEY alone passes on the environment variable, values are scrubbed from the summary and signed manifests
+    #[clap(long, value_name = "KEY=VALUE")]
+    pub env: Vec<String>,
+
+    /// Read variables like --env from a file of KEY=VALUE lines, can be given many times
+    #[clap(long)]
+    pub env_file: Vec<PathBuf>,
+
+    /// Keep only these field paths of every document, like name,address.city, and _id, the rest is never decoded.
+    /// Every later stage sees the documents pruned, --query and --filter included
+    #[clap(long, value_name = "PATHS")]
+    pub fields: Option<Fields>,
+
+    /// Remove these fields from every document right before it is written, dotted paths where `*` matches any part
+    /// of a name and `**` any number of names, like payload.blob,internal.*. The stages before still see them
+    #[clap(long, value_name = "PATTERNS")]
+    pub exclude_fields: Option<ExcludeFields>,
+
+    /// Keep only the documents matching a MongoDB style query, like '{"status": "active", "age": {"$gt": 30}}',
+    /// supports $eq, $ne, $gt, $gte, $lt, $lte, $in, $nin, $exists, $regex, $and, $or, $nor and dotted paths,
+    /// checked before the transforms and the script
+    #[clap(long, value_name = "JSON")]
+    pub query: Option<Query>,
+
+    /// Keep only the documents an expression holds for, like 'user.country == "DE" && items.#count > 3',
+    /// comparisons of field paths and values joined by &&, || and !, `#count` is the length of the array before it
+    #[clap(long, value_name = "EXPR")]
+    pub filter: Option<Expr>,
+
+    /// Print document N of the input, numbered from 0, to stderr as it is read and a diff of what every stage
+    /// from --query to --exec-filter changed in it, or which one dropped it
+    #[clap(long, value_name = "N")]
+    pub trace_doc: Option<usize>,
+
+    /// Transform and filter the documents with a jq filter after the script, like '.payload | {id, total}',
+    /// every object it yields is a document, yielding nothing or null drops the document
+    #[clap(long, value_name = "FILTER")]
+    pub jq: Option<String>,
+
+    /// Reshape and filter the documents with a JMESPath expression after the script instead, like
+    /// 'payload.{id: id, total: total}', an object is the document, null drops it and an array splits it into its objects
+    #[clap(long, value_name = "EXPRESSION", conflicts_with = "jq")]
+    pub jmespath: Option<String>,
+
+    /// Pipe the documents through a command after the script, one process per worker reading a line of extended
+    /// json per document on stdin and answering each with the new document, or an empty line or null to drop it
+    #[clap(long, value_name = "COMMAND")]
+    pub exec_filter: Option<String>,
+
+    /// Number of batches to read ahead on a background thread while earlier ones are decoded and written,
+    /// keeps the disk busy instead of every thread alternating between reading and working
+    #[clap(long, default_value = "0")]
+    pub prefetch: usize,
+
+    /// Read documents with O_DIRECT so a full export doesn't evict the page cache of the host, Linux only
+    #[clap(long)]
+    pub direct_io: bool,
+
+    /// How often a failed batch read or document write is retried before giving up,
+    /// network filesystems occasionally return transient errors
+    #[clap(long, default_value = "3")]
+    pub retries: usize,
+
+    /// Milliseconds to wait before the first retry, doubled for every further one
+    #[clap(long, default_value = "100")]
+    pub retry_backoff: u64,
+
+    /// Re-read the output after the export and check every document parses and matches the checksum
+    /// recorded while writing it
+    #[clap(long)]
+    pub verify: bool,
+
+    /// Share of the documents checked by --verify, e.g. 0.05 for a quick spot check
+    #[clap(long, default_value = "1.0", requires = "verify")]
+    pub verify_sample: f64,
+
+    /// Encrypt the --single and --anomalies outputs as they are written, `age:recipients.txt` encrypts to the age
+    /// public keys in the file, `passphrase` to the passphrase in the DISSBSON_PASSPHRASE environment variable
+    #[clap(long, requires = "single", conflicts_with = "verify")]
+    pub encrypt: Option<Encryption>,
+
+    /// Compress every output file as it is written, `gzip` or `zstd` with an optional level like `zstd:19`,
+    /// files of their own get a .gz or .zst suffix, e.g. `0-12.json.zst`
+    #[clap(long, conflicts_with_all = ["verify", "pg_ddl"])]
+    pub compress: Option<Compression>,
+
+    /// Collect the files of the documents in one tar or zip archive at the output path instead of a directory,
+    /// entries are appended as the batches complete and --compress applies to the whole tar
+    #[clap(long, value_enum, conflicts_with_all = ["single", "verify"])]
+    pub archive: Option<ArchiveFormat>,
+
+    /// How many files or parts of the --single output are uploaded at once when the output is an object store url
+    #[clap(long, default_value = "16")]
+    pub upload_concurrency: usize,
+
+    /// Write a sha256sum compatible checksum manifest covering every output file,
+    /// `SHA256SUMS` in the output directory or `<output>.sha256` with --single
+    #[clap(long)]
+    pub checksums: bool,
+
+    /// Write manifest.json listing every output file with the input file and byte offset of its first document,
+    /// how many documents it holds, its size and sha256, `<output>.manifest.json` with --single,
+    /// an interrupted export leaves the files it finished in manifest.json.partial
+    #[clap(long, conflicts_with_all = ["stdout", "archive"])]
+    pub manifest_json: bool,
+
+    /// Sign the checksum manifest with this ed25519 key, see `dissbson manifest keygen`,
+    /// the signature also covers the tool version, the command line and the script used
+    #[clap(long, requires = "checksums")]
+    pub sign: Option<PathBuf>,
+
+    /// A json or yaml document describing how the export may be used, like its license or the PII it had removed,
+    /// written as the first document of --single and database outputs or as _notice.<ext> next to the documents,
+    /// it gets `_id: "_notice"` unless it has an _id and is recorded in the --sign statement
+    #[clap(long)]
+    pub notice: Option<PathBuf>,
+
+    /// Number of threads writing output files, separate from the decoding --threads,
+    /// raise it for slow targets like network filesystems, by default decoding threads write themselves
+    #[clap(long, conflicts_with = "single")]
+    pub write_threads: Option<usize>,
+
+    /// Raise the soft limit on open files as far as the hard limit allows when the export needs more,
+    /// without it an export that would run out of file descriptors stops before it starts
+    #[clap(long)]
+    pub raise_fd_limit: bool,
+
+    /// What to do with documents holding strings that aren't valid UTF-8, as found in some corrupt dumps
+    #[clap(long, value_enum, default_value_t = InvalidUtf8::Error)]
+    pub invalid_utf8: InvalidUtf8,
+
+    /// Flag documents whose size or field types deviate strongly from the rest of the input,
+    /// the reasons are listed in an `_anomalies` field
+    #[clap(long)]
+    pub flag_anomalies: bool,
+
+    /// Write flagged documents to this file instead of the output, implies --flag-anomalies
+    #[clap(long)]
+    pub anomalies: Option<PathBuf>,
+
+    /// How many standard deviations (of the log size) a document may be from the typical size
+    #[clap(long, default_value = "3.0")]
+    pub anomaly_sigma: f64,
+
+    /// Field presence or value types rarer than this share of the input are flagged
+    #[clap(long, default_value = "0.01")]
+    pub anomaly_rate: f64,
+
+    /// Inflate a compressed binary field, given as `path:snappy|zlib`,
+    /// append `:string` or `:bson` to decode the inflated bytes, can be repeated
+    #[clap(long)]
+    pub decompress_field: Vec<DecompressField>,
+
+    /// Redact fields by the rules of a YAML file: dotted path patterns where `*` matches any part of a name
+    /// and `**` any depth, exceptions, and a drop, null, mask, hash or replace strategy per rule
+    #[clap(long)]
+    pub redact: Option<PathBuf>,
+
+    /// Only report how many fields and documents each --redact rule would touch, nothing is written
+    #[clap(long, requires = "redact")]
+    pub redact_dry_run: bool,
+
+    /// Longest array kept as is, longer ones are handled as set by --array-overflow
+    #[clap(long)]
+    pub max_array_len: Option<usize>,
+
+    /// What happens to arrays longer than --max-array-len
+    #[clap(long, value_enum, default_value_t = ArrayOverflow::Truncate)]
+    pub array_overflow: ArrayOverflow,
+
+    /// Longest string kept as is in characters, longer ones are cut and end in a marker saying how much is missing
+    #[clap(long)]
+    pub max_string_len: Option<usize>,
+
+    /// Save the full text of strings cut by --max-string-len into this directory, the marker names the file
+    #[clap(long, requires = "max_string_len")]
+    pub extract_strings: Option<PathBuf>,
+
+    /// How fields other documents have but a document lacks are written, null needs a pass over the input
+    #[clap(long, value_enum, default_value_t = MissingAs::Omit)]
+    pub missing_as: MissingAs,
+
+    /// How fields explicitly set to null are written
+    #[clap(long, value_enum, default_value_t = NullAs::Null)]
+    pub null_as: NullAs,
+
+    /// Record where every document comes from in a field, _dissbson unless named:
+    /// its source file, index in that file, byte offset and when it was exported
+    #[clap(long, value_name = "FIELD", num_args = 0..=1, default_missing_value = "_dissbson")]
+    pub add_meta: Option<String>,
+
+    /// Name every document by the collection it comes from in a field, _source unless named,
+    /// the file name of the input without its extension, useful when exporting a directory of dumps
+    #[clap(long, value_name = "FIELD", num_args = 0..=1, default_missing_value = "_source")]
+    pub tag_source: Option<String>,
+
+    /// Resolve DBRefs against the collection dumps in this directory, a reference into `users` is looked up by
+    /// its id in `users.bson`
+    #[clap(long)]
+    pub resolve_refs: Option<PathBuf>,
+
+    /// How many levels of references --resolve-refs follows, references in resolved documents are one level deeper
+    #[clap(long, default_value = "1", requires = "resolve_refs")]
+    pub ref_depth: usize,
+
+    /// What --resolve-refs does with a resolved reference
+    #[clap(long, value_enum, default_value_t = RefMode::Embed, requires = "resolve_refs")]
+    pub ref_mode: RefMode,
+
+    /// Give every document a fresh ObjectId,
+    /// references to the old ids in the --reid-refs fields are rewritten to match
+    #[clap(long)]
+    pub reid: bool,
+
+    /// Fields holding references to ids rewritten by --reid, e.g. `parent,owner.$id`
+    #[clap(long, value_delimiter = ',')]
+    pub reid_refs: Vec<String>,
+
+    /// File keeping the id mapping of --reid, defaults to a .reid.dat next to the input,
+    /// share it between runs to rewrite references across collections consistently
+    #[clap(long)]
+    pub reid_map: Option<PathBuf>,
+
+    /// Also send the exported documents to another output, can be given many times: a file whose extension picks
+    /// the format (.json, .ndjson, .yaml, .xml, .graphml, .cypher), a directory ending in /, stats:<file> for field statistics,
+    /// kafka://<brokers>/<topic>, an http(s) endpoint, which can name fields like http://api/{type}, redis://<host>
+    /// clickhouse://<host>/<table> or plugin:<name>[:<argument>] for a dissbson-sink-<name> program reading ndjson
+    #[clap(long, value_name = "SINK")]
+    pub sink: Vec<SinkSpec>,
+
+    /// How many documents http sinks post in one request
+    #[clap(long, default_value = "100")]
+    pub http_batch: usize,
+
+    /// Post newline delimited json to http sinks instead of json arrays
+    #[clap(long)]
+    pub http_ndjson: bool,
+
+    /// Header sent with every http sink request, like 'Authorization: Bearer ...', can be given many times
+    #[clap(long, value_name = "NAME: VALUE")]
+    pub http_header: Vec<String>,
+
+    /// How many rows clickhouse sinks insert in one request
+    #[clap(long, default_value = "10000")]
+    pub clickhouse_batch: usize,
+
+    /// Key redis sinks store every document under, naming fields in braces
+    #[clap(long, default_value = "{_id}")]
+    pub redis_key: String,
+
+    /// Append documents to this redis stream instead of storing them by key, can name fields in braces
+    #[clap(long)]
+    pub redis_stream: Option<String>,
+
+    /// Expire the keys written by redis sinks after this many seconds
+    #[clap(long, conflicts_with = "redis_stream")]
+    pub redis_ttl: Option<u64>,
+
+    /// Send documents whose field has a value to their own sink instead of the outputs, like type=error:errors.ndjson,
+    /// can be given many times and the first matching rule wins
+    #[clap(long, value_name = "FIELD=VALUE:SINK")]
+    pub route: Vec<RouteSpec>,
+
+    /// Hold documents back to write at most this many, like 500/s, 300/m or 1000/h, to replay a dump as load
+    #[clap(long)]
+    pub rate: Option<Rate>,
+
+    /// Replay documents as far apart as the dates in this field, created_at:60 replays an hour in a minute,
+    /// combine with --order-by-oid or --threads 1 to keep them in order
+    #[clap(long, value_name = "FIELD[:SPEEDUP]")]
+    pub pace_by: Option<PaceBy>,
+
     /// Single file output
-    /// write all documents to a single file as a json array
+    /// write all documents to a single file, as a json array with --format json
     #[clap(long)]
     pub single: bool,
+
+    /// Write the fields of every document in name order at every depth, _id first, so per-document yaml or json
+    /// files of the same kind of record line up in a code review or diff whatever order they were stored in
+    #[clap(long)]
+    pub sort_keys: bool,
+
+    /// Write --single json with every document on a line of its own, flushed after every batch and straight to the
+    /// output path, an export that is cut short leaves an array `dissbson finalize` closes
+    #[clap(long, requires = "single", conflicts_with_all = ["compress", "encrypt", "encoding"])]
+    pub array_safe: bool,
+
+    /// Split --single output into files of about this size, like 1GB or 512MiB, each a complete output of its own
+    /// numbered from out.0001.json, a file ends with the document that takes it past the size
+    #[clap(
+        long,
+        value_parser = stats::sizes::parse_size,
+        requires = "single",
+        conflicts_with_all = ["stdout", "verify", "pg_ddl"]
+    )]
+    pub max_output_size: Option<u64>,
+
+    /// Write the file of every document into a directory named after a field of it, like `type=a`,
+    /// `created_at:month` groups dates by the minute, hour, day, week, month or year
+    #[clap(long, conflicts_with = "single")]
+    pub partition_by: Option<PartitionSpec>,
+
+    /// How the files of documents are named, by their place in the input or by a hash of their content,
+    /// which keeps names stable across runs and writes a single file for documents that are equal
+    #[clap(long, value_enum, default_value_t = Naming::Position, conflicts_with = "single")]
+    pub name_by: Naming,
+
+    /// Print count, min, max, mean, standard deviation and percentiles of these numeric fields over the exported
+    /// documents, like amount,latency_ms, and with --checksums write them to a json file covered by the manifest
+    #[clap(long, value_delimiter = ',', value_name = "FIELDS")]
+    pub numeric_stats: Vec<String>,
+
+    /// Percentiles of the --numeric-stats fields, estimated with a t-digest
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "50,90,99",
+        requires = "numeric_stats"
+    )]
+    pub percentiles: Vec<f64>,
+
+    /// Output format, taken from the extension of a --single or --archive output when not given, like .ndjson,
+    /// .csv or .parquet (also under .gz or .zst, which compress it), and json otherwise
+    #[clap(short, long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Text encoding of the written files, for tools that want a byte order mark or UTF-16,
+    /// --verify reads outputs back as plain UTF-8 so it can't be combined with it
+    #[clap(long, value_enum, default_value_t = TextEncoding::Utf8, conflicts_with = "verify")]
+    pub encoding: TextEncoding,
+
+    #[clap(flatten)]
+    pub csv: CsvDialect,
+
+    /// Columns of csv output as dotted field paths, like _id,name,address.city
+    #[clap(long, value_delimiter = ',')]
+    pub csv_columns: Vec<String>,
+
+    /// How many of the first documents, as the filters and transforms leave them, are profiled to pick the columns
+    /// of csv output when --csv-columns isn't given, of database outputs and the types of --promote columns
+    #[clap(long, default_value = "1000")]
+    pub schema_sample: usize,
+
+    /// Fields of sqlite output copied into indexed columns of their own next to the json of the document,
+    /// like status,address.city, typed from the first --schema-sample documents
+    #[clap(long, value_delimiter = ',')]
+    pub promote: Vec<String>,
+
+    /// Rows per row group of parquet output
+    #[clap(long, default_value = "100000")]
+    pub row_group_size: usize,
+
+    /// Table the documents are loaded into with database formats, and the node label of cypher output
+    #[clap(long, default_value = "docs")]
+    pub table: String,
+
+    /// Load duckdb output into a table per shape of document, the set of its top level fields, named after --table
+    /// with a number, 1 for the most common shape, each table only has the columns of its own documents
+    #[clap(long)]
+    pub table_per_shape: bool,
+
+    /// Index named in the action lines of es-bulk output, the name of the input without its extension by default
+    #[clap(long)]
+    pub es_index: Option<String>,
+
+    /// Field the document ids of es-bulk output are taken from, ObjectIds are written as their hex string
+    #[clap(long, default_value = "_id")]
+    pub es_id_field: String,
+
+    /// Leave the ids of es-bulk output to Elasticsearch, the source lines never carry _id either way
+    #[clap(long, conflicts_with = "es_id_field")]
+    pub es_auto_id: bool,
+
+    /// Write a psql script next to --single pg-copy output, <output>.sql, creating the --table and loading the file
+    #[clap(long, requires = "single", conflicts_with = "stdout")]
+    pub pg_ddl: bool,
+
+    /// Name of the root element when writing xml with --single
+    #[clap(long, default_value = "documents")]
+    pub xml_root: String,
+
+    /// Name of the element wrapping each document when writing xml
+    #[clap(long, default_value = "document")]
+    pub xml_element: String,
+
+    /// Map scalar fields to xml attributes instead of child elements
+    #[clap(long)]
+    pub xml_attributes: bool,
+}
+
+impl Args {
+    /// The format written, json when it wasn't given or taken from the output
+    pub(crate) fn output_format(&self) -> OutputFormat {
+        self.format.unwrap_or(OutputFormat::Json)
+    }
+
+    /// Take the format and the compression of a --single or --archive output from its name when they aren't given,
+    /// like `dump.ndjson.gz`, the directory of a document per file output can be named anything
+    fn infer_output(&mut self) -> Result<(), DissectError> {
+        let Some(output) = self
+            .output
+            .as_deref()
+            .filter(|_| self.single || self.archive.is_some())
+        else {
+            return Ok(());
+        };
+        if self.format.is_none() {
+            self.format = OutputFormat::of_path(output);
+        }
+        if self.compress.is_none() {
+            self.compress = Compression::of_path(output);
+            if self.compress.is_some() && (self.verify || self.pg_ddl) {
+                return Err(DissectError::Parse(format!(
+                    "{} is compressed, --verify and --pg-ddl need a plain output",
+                    output.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the export is one file rather than a directory of document files
+    fn writes_file(&self) -> bool {
+        self.single || self.archive.is_some() || self.output_format().is_database()
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run an export pipeline on a fixture and fail if its outputs differ from golden files
+    Check(check::CheckArgs),
+    /// Compare two dumps and write a patch for every changed document
+    Diff(DiffArgs),
+    /// Compare a dump against a live collection and report the drift
+    #[cfg(feature = "live")]
+    DiffLive(diff::live::LiveDiffArgs),
+    /// Download the documents a serve-bson sends into a local dump, resuming where a broken transfer stopped
+    Fetch(serve::fetch::FetchArgs),
+    /// Close the json array of an --array-safe export that was cut short
+    Finalize(finalize::FinalizeArgs),
+    /// Export or import offset indexes as json
+    Index(IndexArgs),
+    /// Create signing keys and verify signed checksum manifests
+    Manifest(manifest::ManifestArgs),
+    /// Flag fields likely holding personal data or secrets
+    PiiScan(stats::pii::PiiScanArgs),
+    /// Answer an SQL query over a dump without exporting it
+    Query(query::QueryArgs),
+    /// Export a directory of collection dumps into one relational database
+    Relational(relational::RelationalArgs),
+    /// Run several export jobs from a yaml file in one process with a shared thread pool and budgets
+    Run(jobs::RunArgs),
+    /// Explore a dump in a Lua prompt, fetch documents and try scripts and filters on them
+    Repl(repl::ReplArgs),
+    /// Compare the fields of two dumps and report the added, removed and retyped ones
+    SchemaDiff(diff::schema::SchemaDiffArgs),
+    /// Stream the documents matching filters as bson to clients over tcp
+    ServeBson(serve::ServeArgs),
+    /// Print one document of a dump, as json or as a tree with --tree
+    Show(show::ShowArgs),
+    /// Find clusters of near-duplicate documents
+    Similar(similar::SimilarArgs),
+    /// Print document size statistics and optionally profile every field
+    Stats(stats::StatsArgs),
 }
 
 #[derive(Debug, Error)]
@@ -75,8 +660,23 @@ enum DissectError {
     Postcard(#[from] postcard::Error),
     #[error("Json Error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Csv Error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("SQLite Error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Yaml Error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Toml Error: {0}")]
+    Toml(#[from] toml::de::Error),
     #[error("Bson Error: {0}")]
     Bson(#[from] bson::de::Error),
+    #[error("Bson Serialization Error: {0}")]
+    BsonSer(#[from] bson::ser::Error),
+    #[error("Raw Bson Error: {0}")]
+    RawBson(#[from] bson::raw::Error),
+    #[cfg(feature = "live")]
+    #[error("MongoDB Error: {0}")]
+    Mongo(#[from] mongodb::error::Error),
     #[error("Lua Error: {0}")]
     LuaError(#[from] rlua::Error),
     #[error("Thread Pool Error: {0}")]
@@ -87,180 +687,808 @@ enum DissectError {
     Unexpected(String),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
-struct DocOffset {
-    offset: usize,
-    size: usize,
-}
-
 fn main() -> Result<(), DissectError> {
+    let mut args = Args::parse();
+    if args.output.as_deref() == Some(Path::new("-")) {
+        if args.verify || args.checksums || args.manifest_json {
+            return Err(DissectError::Parse(
+                "Output - is stdout, it can't be read back for --verify, --checksums or --manifest-json"
+                    .into(),
+            ));
+        }
+        args.output = None;
+        args.stdout = true;
+    }
+    // everything printed goes to stderr from here on, the real stdout only carries the export
+    let stdout = if args.stdout {
+        args.single = args.archive.is_none();
+        Some(take_stdout()?)
+    } else {
+        None
+    };
+
     println!("---------------------------------------");
     println!("BSON Dissector v{}", env!("CARGO_PKG_VERSION"));
     println!("Copyright (c) 2023 DuplexLayer");
     println!("Licensed under the BSD-3-Clause License");
     println!("---------------------------------------\n");
 
-    let args = Args::parse();
-    let path = args.input.as_path();
-    let output = args.output.as_path();
+    if let Some(command) = &args.command {
+        return match command {
+            Command::Check(check) => check::run(check),
+            Command::Diff(diff) => diff::run(diff),
+            #[cfg(feature = "live")]
+            Command::DiffLive(live) => diff::live::run(live),
+            Command::Fetch(fetch) => serve::fetch::run(fetch),
+            Command::Finalize(finalize) => finalize::run(finalize),
+            Command::Index(index) => index::run(index),
+            Command::Manifest(manifest) => manifest::run(manifest),
+            Command::PiiScan(scan) => stats::pii::run(scan),
+            Command::Query(query) => query::run(query),
+            Command::Relational(relational) => relational::run(relational),
+            Command::Repl(repl) => repl::run(repl),
+            Command::Run(run) => jobs::run(run),
+            Command::SchemaDiff(schema) => diff::schema::run(schema),
+            Command::ServeBson(serve) => serve::run(serve),
+            Command::Show(show) => show::run(show),
+            Command::Similar(similar) => similar::run(similar),
+            Command::Stats(stats) => stats::run(stats),
+        };
+    }
+
+    export(args, stdout, &Shared::default())
+}
+
+/// Export the input of `args` to its output, `stdout` is the real stdout when the export goes there
+fn export(mut args: Args, stdout: Option<File>, shared: &Shared) -> Result<(), DissectError> {
+    let env = Env::from_args(&args)?;
+    env.expand_args(&mut args)?;
+    args.infer_output()?;
+    // sinks quote their expanded specs in errors and panics, like a url with a token in it
+    env.scrub_panics();
+    export_with(args, stdout, shared, &env).map_err(|e| env.scrub_error(e))
+}
+
+/// Export with the variables of `args` expanded already
+fn export_with(
+    args: Args,
+    mut stdout: Option<File>,
+    shared: &Shared,
+    env: &Env,
+) -> Result<(), DissectError> {
+    // clap makes sure both are present when no subcommand is given, or --stdout replaces the output
+    let path = args.input.as_deref().expect("Missing input path");
+    let output = args.output.as_deref().unwrap_or(Path::new("-"));
+
+    if args.estimate {
+        return index::estimate::run(path, args.estimate_samples);
+    }
+
+    if args.stdout && args.output_format().is_database() {
+        return Err(DissectError::Parse(format!(
+            "{:?} output is a database file, it can't be written to stdout",
+            args.output_format()
+        )));
+    }
+
+    if args.output_format() == OutputFormat::Bson && args.encoding != TextEncoding::Utf8 {
+        return Err(DissectError::Parse(
+            "bson output is binary, --encoding only applies to text formats".into(),
+        ));
+    }
+
+    if args.array_safe && args.output_format() != OutputFormat::Json {
+        return Err(DissectError::Parse(
+            "--array-safe writes json arrays, use --format json".into(),
+        ));
+    }
+
+    if args.max_output_size.is_some() && (args.stdout || args.output_format().is_database()) {
+        return Err(DissectError::Parse(
+            "--max-output-size splits a --single output into files, it can't be stdout or a database".into(),
+        ));
+    }
+
+    if args.pg_ddl && args.output_format() != OutputFormat::PgCopy {
+        return Err(DissectError::Parse(
+            "--pg-ddl writes the load script of pg-copy output, use --format pg-copy".into(),
+        ));
+    }
+
+    if args.manifest_json && args.output_format().is_database() {
+        return Err(DissectError::Parse(format!(
+            "{:?} output is a database file, --manifest-json lists files of documents",
+            args.output_format()
+        )));
+    }
+
+    if args.compress.is_some() && args.output_format().is_database() {
+        return Err(DissectError::Parse(format!(
+            "{:?} output is a database file, it can't be compressed",
+            args.output_format()
+        )));
+    }
 
     if args.single && output.is_dir() {
-        return Err(DissectError::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        return Err(DissectError::Io(std::io::Error::other(
             "Output path must be a file when using --single",
         )));
     }
 
-    if !output.exists() && !args.single {
-        std::fs::create_dir(output)?;
+    if args.archive.is_some() && (output.is_dir() || args.output_format().is_database()) {
+        return Err(DissectError::Parse(
+            "--archive writes a file of document files, the output must be a file and the format not a database"
+                .into(),
+        ));
     }
 
-    let idx = if args.input.with_extension("idx.dat").exists() && !args.inspect {
-        println!("Found index file, skipping inspection...");
-        load_index_data(path.with_extension("idx.dat"))?
-    } else {
-        println!("Inspecting file: {}", path.display());
-        let offsets = inspect_bson(path)?;
-        let mut offsets_checkpoint = File::create(path.with_extension("idx.dat"))?;
-        let ser = postcard::to_allocvec_cobs(&offsets)?;
-        let mut enc = ZlibEncoder::new(&mut offsets_checkpoint, Compression::default());
-        enc.write_all(&ser)?;
-        enc.finish()?;
-        offsets
+    if args.archive == Some(ArchiveFormat::Zip) && args.compress.is_some() {
+        return Err(DissectError::Parse(
+            "zip archives deflate their entries themselves, --compress only applies to tar".into(),
+        ));
+    }
+
+    if args.output_format().is_graph() && !args.single {
+        return Err(DissectError::Parse(format!(
+            "{:?} output is one graph of every document, use --single",
+            args.output_format()
+        )));
+    }
+
+    let store = match output::store::object_url(output) {
+        Some(url) => {
+            if args.checksums
+                || args.manifest_json
+                || args.verify
+                || args.pg_ddl
+                || args.output_format().is_database()
+            {
+                return Err(DissectError::Parse(format!(
+                    "{url} is an object store, --checksums, --manifest-json, --verify, --pg-ddl and database formats need a local output"
+                )));
+            }
+            Some(ObjectStore::open(url, args.upload_concurrency)?)
+        }
+        None => None,
     };
 
-    let idx = if let Some(slice) = args.slice {
-        idx[parse_slice(&slice)?].to_vec()
+    if args.explain {
+        return explain::run(&args, env, path, output);
+    }
+
+    let workspace = if args.stdout || args.redact_dry_run || store.is_some() {
+        None
     } else {
-        idx
+        Some(workspace::Workspace::acquire(output)?)
     };
 
+    if !output.exists() && !args.writes_file() && !args.redact_dry_run && store.is_none() {
+        std::fs::create_dir(output)?;
+    }
+
+    let input = index::load_input(path, args.inspect)?;
+    let idx = select_documents(&args, &input)?;
+    let write_threads = limits::check(&args, input.files.len(), &idx)?;
+
+    if let (Some(rules), true) = (&args.redact, args.redact_dry_run) {
+        return transform::redaction_dry_run(rules, &input, &idx, args.threads, args.batch);
+    }
+
     // progress bar
-    let pb = indicatif::ProgressBar::new(idx.len() as u64);
+    let pb = shared.track(indicatif::ProgressBar::new(idx.len() as u64));
     pb.set_style(indicatif::ProgressStyle::default_bar().template(
-        "{spinner:.green} [{elapsed_precise}] [{eta_precise}] [{bar:40.red/blue}] {pos:>7}/{len:7} \n {msg}",
+        "{spinner:.green} {prefix}[{elapsed_precise}] [{eta_precise}] [{bar:40.red/blue}] {pos:>7}/{len:7} \n {msg}",
     ).expect("Failed to set progress bar style"));
 
-    let thread_pool = ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+    let thread_pool = match &shared.pool {
+        Some(pool) => pool.clone(),
+        None => Arc::new(ThreadPoolBuilder::new().num_threads(args.threads).build()?),
+    };
+    let transforms = Transforms::from_args(&args, path, &input)?;
+    let script = args
+        .script
+        .as_ref()
+        .map(std::fs::read_to_string)
+        .transpose()?;
+    let jq = args.jq.as_deref().map(Jq::new).transpose()?;
+    let jmespath = args.jmespath.as_deref().map(JmesPath::new).transpose()?;
+    let exec_filter = args.exec_filter.as_deref().map(ExecFilter::new);
+    let tracer = args
+        .trace_doc
+        .map(|position| Tracer::new(&input, position))
+        .transpose()?;
+
+    let retry = Retry::new(args.retries, Duration::from_millis(args.retry_backoff));
+    let decoder = Decoder::new(args.invalid_utf8).fields(args.fields.clone());
+    let stages = Stages {
+        query: args.query.as_ref(),
+        filter: args.filter.as_ref(),
+        unmatched: AtomicUsize::new(0),
+        transforms: &transforms,
+        script: script.as_deref(),
+        jq: jq.as_ref(),
+        jmespath: jmespath.as_ref(),
+        exec_filter: exec_filter.as_ref(),
+        exclude_fields: args.exclude_fields.as_ref(),
+        excluded: AtomicUsize::new(0),
+        excluded_from: AtomicUsize::new(0),
+        tracer: tracer.as_ref(),
+        env,
+    };
+
+    let mut encoder = Encoder::from_args(&args);
+    // csv and database columns are picked from the first documents as the stages leave them, those batches
+    // are run ahead of the others and handed to the outputs first
+    let sample_size = match args.output_format() {
+        OutputFormat::Csv if encoder.columns.is_none() => Some(args.schema_sample),
+        OutputFormat::Sqlite if args.promote.is_empty() => None,
+        format if format.is_database() => Some(args.schema_sample),
+        _ => None,
+    };
+    let sampled = match sample_size {
+        Some(count) => {
+            let read = |offsets: &[&DocOffset]| {
+                retry.run(|| {
+                    let reader = DocReader::new(&input).direct_io(args.direct_io);
+                    load_docs(reader, &decoder, offsets)
+                })
+            };
+            stages.sample(&idx, count, args.batch, &thread_pool, read)?
+        }
+        None => Vec::new(),
+    };
+    let sample = sampled
+        .iter()
+        .flat_map(|(_, docs)| docs.iter().map(|(doc, _)| doc.clone()))
+        .take(sample_size.unwrap_or(0))
+        .collect::<Vec<_>>();
+    let sampled = Mutex::new(sampled);
+    if args.output_format() == OutputFormat::Csv && encoder.columns.is_none() {
+        let columns = Schema::of_documents(&sample);
+        println!(
+            "Picked {} csv columns from the first {} documents",
+            columns.columns.len(),
+            sample.len()
+        );
+        encoder.columns = Some(Arc::new(columns));
+    }
+    let notice = args
+        .notice
+        .as_deref()
+        .map(output::notice::load)
+        .transpose()?;
+
+    let manifest = args.checksums.then(|| {
+        Manifest::new(if args.writes_file() {
+            manifest::with_suffix(output, ".sha256")
+        } else {
+            output.join("SHA256SUMS")
+        })
+    });
+    let inventory = if args.manifest_json {
+        Some(Inventory::create(
+            if args.writes_file() {
+                manifest::with_suffix(output, ".manifest.json")
+            } else {
+                output.join("manifest.json")
+            },
+            &input,
+        )?)
+    } else {
+        None
+    };
 
-    if args.single {
-        let mut file = File::create(output).expect("Failed to create output file");
-        let mut bufwriter = BufWriter::new(&mut file);
-        let mut ser = serde_json::Serializer::new(&mut bufwriter);
-        let writer = Arc::new(RwLock::new(ser.serialize_seq(Some(idx.len())).expect("Failed to serialize json array")));
+    let anomalies = match &args.anomalies {
+        Some(path) => Some(RwLock::new(encoder.stream(open_stream(&args, path)?))),
+        None => None,
+    };
+    let sinks = Sinks::from_args(&args)?;
+    let routes = Routes::from_args(&args)?;
+    let pacer = Pacer::from_args(&args);
+    let numeric = NumericFields::from_args(&args);
+    let flagged = AtomicUsize::new(0);
+    // documents handed to the outputs, what the stages added or dropped included
+    let exported = AtomicUsize::new(0);
+    // holds the document back when pacing, counts flagged documents and moves them to the anomalies output when there is one,
+    // then hands the rest to the --route rules and counts what is left, returns whether the document was taken away from the outputs
+    let route = |doc: &Document| -> Result<bool, DissectError> {
+        if let Some(pacer) = &pacer {
+            pacer.wait(doc);
+        }
+        if doc.contains_key(ANOMALY_FIELD) {
+            flagged.fetch_add(1, Ordering::Relaxed);
+            if let Some(sink) = &anomalies {
+                sink.write().write(doc)?;
+                return Ok(true);
+            }
+        }
+        let routed = routes.route(doc)?;
+        if !routed {
+            exported.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(routed)
+    };
 
+    // loads every batch of `idx`, runs the transforms and the script over it and hands the documents to `f`
+    // with where they were read from
+    let for_each_batch = |f: &(dyn Fn(usize, Vec<(Document, DocOffset)>) + Sync)| {
+        // `read` is the number of offsets of the batch, the progress is of the input whatever the stages keep
+        let hand_on = |chunk: usize, read: usize, docs: Vec<(Document, DocOffset)>| {
+            if let Some(numeric) = &numeric {
+                numeric.record(docs.iter().map(|(doc, _)| doc));
+            }
+            f(chunk, docs);
+            pb.inc(read as u64);
+        };
+        let handle =
+            |chunk: usize, read: usize, docs: Result<Vec<(Document, DocOffset)>, DissectError>| {
+                let docs = docs
+                    .and_then(|docs| stages.process_batch(docs))
+                    .expect("Failed to process batch");
+                hand_on(chunk, read, docs)
+            };
+        // the batches run ahead for the columns go first, the others are numbered after them
+        let ahead = sampled.lock().drain(..).collect::<Vec<_>>();
+        let skip = ahead.len();
+        for (chunk, (read, docs)) in ahead.into_iter().enumerate() {
+            hand_on(chunk, read, docs);
+        }
+        let rest = &idx[(skip * args.batch).min(idx.len())..];
         thread_pool.install(|| {
-            let chunk_ct = Arc::new(RwLock::new(0));
-            idx.par_iter().chunks(args.batch).for_each(|offsets| {
-                let docs = if let Some(script) = &args.script {
-                    apply_script(path, script, offsets).expect("Failed to apply script")
-                } else {
-                    load_docs(path, offsets).expect("Failed to load docs")
-                };
+            if args.prefetch == 0 {
+                rest.par_iter()
+                    .chunks(args.batch)
+                    .enumerate()
+                    .map(|(chunk, offsets)| (skip + chunk, offsets))
+                    .for_each(|(chunk, offsets)| {
+                        // held until the batch is handed on, keeps the jobs of `run` within their budgets
+                        let _lease = shared.lease(offsets.iter().map(|offset| offset.size).sum());
+                        let docs = retry.run(|| {
+                            let reader = DocReader::new(&input).direct_io(args.direct_io);
+                            load_docs(reader, &decoder, &offsets)
+                        });
+                        handle(chunk, offsets.len(), docs)
+                    });
+            } else {
+                let reader = DocReader::new(&input).direct_io(args.direct_io);
+                prefetch::run(reader, &retry, rest, args.batch, args.prefetch, |batches| {
+                    batches.into_iter().par_bridge().for_each(|batch| {
+                        let batch = batch.expect("Failed to read batch");
+                        // read ahead already, the budgets only hold back its processing
+                        let _lease =
+                            shared.lease(batch.offsets.iter().map(|offset| offset.size).sum());
+                        handle(
+                            skip + batch.index,
+                            batch.offsets.len(),
+                            batch.decode(&decoder),
+                        )
+                    })
+                });
+            }
+        });
+    };
 
-                let mut writer_lock = writer.write();
-                for doc in docs {
-                    writer_lock.serialize_element(&doc).expect("Failed to serialize element");
+    if args.output_format().is_database() {
+        let database = sink::open_database(&args, output, &sample)?;
+        if let Some(notice) = &notice {
+            database.write(notice)?;
+        }
+        for_each_batch(&|_, docs| {
+            let mut kept = Vec::with_capacity(docs.len());
+            for (doc, _) in docs {
+                if route(&doc).expect("Failed to write anomaly") {
+                    continue;
                 }
+                sinks.write(&doc).expect("Failed to write to sink");
+                kept.push(doc);
+            }
+            database
+                .write_batch(&kept)
+                .expect("Failed to write to database");
+        });
+        println!("{}", database.finish()?);
+        if let Some(manifest) = &manifest {
+            manifest.add(output, output::checksum::file_digest(output)?);
+        }
+    } else if args.single {
+        // a partial array-safe output is worth keeping, it is written in place
+        let partial = workspace.as_ref().filter(|_| !args.array_safe);
+        // the file being written, the output itself or its numbered shard with --max-output-size
+        let target = |shard: usize| match args.max_output_size {
+            Some(_) => output::shard::path(output, shard),
+            None => output.to_path_buf(),
+        };
+        // every shard is a complete output of its own and starts with the notice
+        let open = |file: Destination, encoder: &Encoder| -> Result<_, DissectError> {
+            let mut stream = encoder.stream(wrap_stream(&args, file)?);
+            if args.verify {
+                stream = stream.track();
+            }
+            if let Some(notice) = &notice {
+                stream.write(notice)?;
+            }
+            Ok(stream)
+        };
+        let close = |mut stream: StreamWriter<OutputStream>,
+                     path: &Path,
+                     first: Option<DocOffset>,
+                     documents: usize| {
+            let written = stream.take_written();
+            let (file, digest) = stream.finish()?.finish()?.finish()?.finish();
+            commit_output(file, partial, path)?;
+            if let (Some(inventory), Some(digest)) = (&inventory, &digest) {
+                let size = std::fs::metadata(path)?.len();
+                inventory.add(path, first.as_ref(), documents, size, digest)?;
+            }
+            if let (Some(manifest), Some(digest)) = (&manifest, digest) {
+                manifest.add(path, digest);
+            }
+            Ok::<_, DissectError>(written)
+        };
+        let file = output_file(stdout.take(), store.as_ref(), partial, &target(1))?;
+        let writer = Arc::new(RwLock::new(Single {
+            stream: Some(open(file, &encoder)?),
+            encoder: encoder.clone(),
+            shard: 1,
+            first: None,
+            documents: 0,
+        }));
+        // batches finish out of order, when the order matters they wait here for the ones before them
+        let pending = Mutex::new((0, BTreeMap::new()));
 
-                pb.inc(args.batch as u64);
-                *chunk_ct.write() += 1;
-            });
+        for_each_batch(&|chunk, docs| {
+            // held while the ready batches are taken so they are written in the order they are taken
+            let mut writer_lock = writer.write();
+            let ready = if args.order_by_oid || args.merge_by.is_some() {
+                let mut pending = pending.lock();
+                let (next, waiting) = &mut *pending;
+                waiting.insert(chunk, docs);
+                let mut ready = Vec::new();
+                while let Some(docs) = waiting.remove(next) {
+                    ready.push(docs);
+                    *next += 1;
+                }
+                ready
+            } else {
+                vec![docs]
+            };
+
+            for (doc, offset) in ready.into_iter().flatten() {
+                if route(&doc).expect("Failed to write anomaly") {
+                    continue;
+                }
+                sinks.write(&doc).expect("Failed to write to sink");
+                let single = &mut *writer_lock;
+                // the next shard is only started once there is a document for it
+                if single.stream.is_none() {
+                    single.shard += 1;
+                    let path = target(single.shard);
+                    let file = output_file(None, store.as_ref(), partial, &path)
+                        .expect("Failed to create output shard");
+                    single.stream =
+                        Some(open(file, &single.encoder).expect("Failed to start output shard"));
+                }
+                let stream = single.stream.as_mut().expect("Shard is open");
+                stream.write(&doc).expect("Failed to serialize element");
+                single.first.get_or_insert(offset);
+                single.documents += 1;
+                if args
+                    .max_output_size
+                    .is_some_and(|max| stream.written() >= max)
+                {
+                    let full = single.stream.take().expect("Shard is open");
+                    // csv shards keep the columns of the first one
+                    single.encoder = full.encoder().clone();
+                    close(
+                        full,
+                        &target(single.shard),
+                        single.first.take(),
+                        std::mem::take(&mut single.documents),
+                    )
+                    .expect("Failed to finish output shard");
+                }
+            }
+            if let (true, Some(stream)) = (args.array_safe, &mut writer_lock.stream) {
+                stream.flush().expect("Failed to flush output");
+            }
         });
         match Arc::try_unwrap(writer) {
             Ok(l) => {
-                let l = l.into_inner();
-                l.end().unwrap();
+                let single = l.into_inner();
+                let written = match single.stream {
+                    Some(stream) => close(
+                        stream,
+                        &target(single.shard),
+                        single.first,
+                        single.documents,
+                    )?,
+                    None => Vec::new(),
+                };
+                if let Some(max) = args.max_output_size {
+                    println!(
+                        "Split the output into {} files of up to {max} bytes, {} to {}",
+                        single.shard,
+                        target(1).display(),
+                        target(single.shard).display()
+                    );
+                    if store.is_none() {
+                        let stale = output::shard::remove_stale(output, single.shard)?;
+                        if stale > 0 {
+                            println!(
+                                "Removed {stale} shards left by an earlier export of {}",
+                                output.display()
+                            );
+                        }
+                    }
+                }
+                if args.verify {
+                    output::verify::verify_stream(&encoder, output, &written, args.verify_sample)?;
+                }
+                if args.pg_ddl {
+                    let script = manifest::with_suffix(output, ".sql");
+                    let mut out = BufWriter::new(File::create(&script)?);
+                    output::postgres::copy_script(&mut out, &args.table, &output.canonicalize()?)?;
+                    out.flush()?;
+                    if let Some(manifest) = &manifest {
+                        manifest.add(&script, output::checksum::file_digest(&script)?);
+                    }
+                    println!("Wrote the table and load script to {}", script.display());
+                }
             }
             Err(_) => {
                 panic!("Failed to unwrap writer");
             }
         };
     } else {
-        thread_pool.install(|| {
-            let chunk_ct = Arc::new(RwLock::new(0));
-            idx.par_iter().chunks(args.batch).for_each(|offsets| {
-                let docs = if let Some(script) = &args.script {
-                    apply_script(path, script, offsets).unwrap()
-                } else {
-                    load_docs(path, offsets).unwrap()
+        let archive = match args.archive {
+            Some(format) => {
+                let file = output_file(stdout.take(), store.as_ref(), workspace.as_ref(), output)?;
+                Some(Archive::new(format, wrap_stream(&args, file)?))
+            }
+            None => None,
+        };
+        let files = Mutex::new(Vec::new());
+        let partitions = Partitions::default();
+        let names = Names::default();
+        // writes the file of a document into the archive, the object store or the output directory,
+        // `offset` is where it was read from, the notice has none
+        let save =
+            |name: &str, doc: &Document, offset: Option<&DocOffset>| -> Result<(), DissectError> {
+                if let Some(archive) = &archive {
+                    return SCRATCH.with_borrow_mut(|bytes| {
+                        bytes.clear();
+                        encoder.encode(&mut *bytes, doc)?;
+                        archive.append(&format!("{name}.{}", encoder.extension()), bytes)
+                    });
+                }
+                if let Some(store) = &store {
+                    let mut name = format!("{name}.{}", encoder.extension());
+                    let mut bytes = Vec::new();
+                    encoder.encode(&mut bytes, doc)?;
+                    if let Some(compression) = args.compress {
+                        name = format!("{name}.{}", compression.extension());
+                        bytes = compression.compress(&bytes)?;
+                    }
+                    return store.put(&name, bytes);
+                }
+                SCRATCH.with_borrow_mut(|bytes| {
+                    let path = retry.run(|| {
+                        save_single_doc(doc, output, name, &encoder, args.compress, bytes)
+                    })?;
+                    if manifest.is_some() || inventory.is_some() {
+                        let digest = output::checksum::digest(bytes);
+                        if let Some(inventory) = &inventory {
+                            inventory.add(&path, offset, 1, bytes.len() as u64, &digest)?;
+                        }
+                        if let Some(manifest) = &manifest {
+                            manifest.add(&path, digest);
+                        }
+                    }
+                    if args.verify {
+                        files.lock().push((path, seahash::hash(bytes)));
+                    }
+                    Ok(())
+                })
+            };
+        if let Some(notice) = &notice {
+            save(output::notice::NOTICE_ID, notice, None)?;
+        }
+        let write_threads = write_threads.unwrap_or(0);
+        output::pool::run(
+            write_threads,
+            write_threads * args.batch,
+            |write| {
+                for_each_batch(&|chunk, docs| {
+                    for (nth, (doc, offset)) in docs.into_iter().enumerate() {
+                        if route(&doc).expect("Failed to write anomaly") {
+                            continue;
+                        }
+                        sinks.write(&doc).expect("Failed to write to sink");
+                        let name = args
+                            .name_by
+                            .name(chunk, nth, &doc)
+                            .expect("Failed to name doc");
+                        write((name, doc, offset)).expect("Failed to save doc");
+                    }
+                })
+            },
+            |(name, doc, offset)| {
+                let name = match &args.partition_by {
+                    Some(spec) => {
+                        let dir = spec.dir(&doc);
+                        let local = archive.is_none() && store.is_none();
+                        partitions.enter(&dir, local.then_some(output))?;
+                        format!("{dir}/{name}")
+                    }
+                    None => name,
                 };
-
-                for (nth, doc) in docs.into_iter().enumerate() {
-                    save_single_doc(
-                        doc,
-                        output,
-                        format!("{}-{}", chunk_ct.read(), nth),
-                        args.pretty,
-                    )
-                    .expect("Failed to save doc");
+                // a document equal to one written already has its file
+                if args.name_by.by_content() && !names.claim(&name) {
+                    return Ok(());
                 }
-
-                pb.inc(args.batch as u64);
-                *chunk_ct.write() += 1;
-            });
-        });
+                save(&name, &doc, Some(&offset))
+            },
+        )?;
+        if names.duplicates() > 0 {
+            println!(
+                "Skipped {} duplicate documents, a document with the same content was written already",
+                names.duplicates()
+            );
+        }
+        if let Some(spec) = &args.partition_by {
+            println!(
+                "Partitioned the documents into {} directories by {}",
+                partitions.count(),
+                spec.field
+            );
+        }
+        if let Some(archive) = archive {
+            let entries = archive.entries();
+            let (file, digest) = archive.finish()?.finish()?.finish()?.finish();
+            commit_output(file, workspace.as_ref(), output)?;
+            if let (Some(manifest), Some(digest)) = (&manifest, digest) {
+                manifest.add(output, digest);
+            }
+            println!("Archived {entries} files in {}", output.display());
+        } else if let Some(store) = &store {
+            let uploaded = store.finish()?;
+            println!("Uploaded {uploaded} files to {}", output.display());
+        }
+        if args.verify {
+            output::verify::verify_files(
+                &encoder,
+                output,
+                &files.into_inner(),
+                args.verify_sample,
+            )?;
+        }
     }
 
     pb.finish_with_message("");
-    println!("Exported {} documents to {}", idx.len(), output.display());
-
-    Ok(())
-}
-
-fn load_index_data<P: AsRef<Path>>(path: P) -> Result<Vec<DocOffset>, DissectError> {
-    let path = path.as_ref();
-
-    let mut file = OpenOptions::new().read(true).open(path)?;
-    let mut dat = Vec::new();
-    let mut reader = BufReader::new(&mut file);
-    let mut dec = ZlibDecoder::new(&mut dat);
-    let mut buf = [0u8; 8192];
-    while let Ok(n) = reader.read(&mut buf[..]) {
-        if n == 0 {
-            break;
+    let flagged = flagged.into_inner();
+    let (replaced, skipped) = decoder.counts();
+    let unmatched = stages.unmatched.into_inner();
+    let (excluded, excluded_from) = (
+        stages.excluded.into_inner(),
+        stages.excluded_from.into_inner(),
+    );
+    println!(
+        "Exported {} documents to {}",
+        exported.into_inner(),
+        output.display()
+    );
+    match (&args.query, &args.filter) {
+        (Some(_), Some(_)) => {
+            println!("Left out {unmatched} documents not matching --query and --filter")
+        }
+        (Some(_), None) => println!("Left out {unmatched} documents not matching --query"),
+        (None, Some(_)) => println!("Left out {unmatched} documents not matching --filter"),
+        (None, None) => {}
+    }
+    if args.flag_anomalies || anomalies.is_some() {
+        println!("Flagged {flagged} anomalous documents");
+    }
+    if replaced + skipped > 0 {
+        println!(
+            "Found invalid UTF-8 in {} documents, repaired {replaced} and skipped {skipped}",
+            replaced + skipped
+        );
+    }
+    for line in transforms.summary() {
+        println!("{line}");
+    }
+    if let Some(jq) = &jq {
+        println!("{}", jq.summary());
+    }
+    if let Some(jmespath) = &jmespath {
+        println!("{}", jmespath.summary());
+    }
+    if let Some(exec_filter) = exec_filter {
+        println!("{}", exec_filter.finish()?);
+    }
+    if args.exclude_fields.is_some() {
+        println!(
+            "Removed {excluded} fields matching --exclude-fields from {excluded_from} documents"
+        );
+    }
+    if let Some(line) = tracer.and_then(Tracer::finish) {
+        println!("{line}");
+    }
+    for line in routes.finish()?.into_iter().chain(sinks.finish()?) {
+        println!("{}", env.scrub(&line));
+    }
+    let (retried, recovered) = retry.counts();
+    if retried > 0 {
+        println!("Retried {retried} times after io errors, {recovered} reads or writes recovered");
+    }
+    if let (Some(sink), Some(path)) = (anomalies, &args.anomalies) {
+        if let (Some(manifest), (_, Some(digest))) = (
+            &manifest,
+            sink.into_inner().finish()?.finish()?.finish()?.finish(),
+        ) {
+            manifest.add(path, digest);
+        }
+        println!("Wrote anomalous documents to {}", path.display());
+    }
+    if let Some(numeric) = numeric {
+        let report = numeric.finish();
+        if let Some(manifest) = &manifest {
+            let path = if args.writes_file() {
+                manifest::with_suffix(output, ".stats.json")
+            } else {
+                output.join("numeric-stats.json")
+            };
+            stats::numeric::save(&report, &path)?;
+            manifest.add(&path, output::checksum::file_digest(&path)?);
+            println!("Wrote numeric statistics to {}", path.display());
+        }
+    }
+    if let Some(inventory) = inventory {
+        let path = inventory.finish()?;
+        if let Some(manifest) = &manifest {
+            manifest.add(&path, output::checksum::file_digest(&path)?);
+        }
+        println!("Wrote the file manifest to {}", path.display());
+    }
+    if let Some(manifest) = manifest {
+        let path = manifest.save()?;
+        println!("Wrote checksums to {}", path.display());
+        if let Some(key) = &args.sign {
+            let signature = manifest::sign(&args, env, notice.as_ref(), key, &path)?;
+            println!("Signed the checksums in {}", signature.display());
         }
-        dec.write_all(&buf[..n])?;
     }
-    dec.finish()?;
-
-    let offsets = postcard::from_bytes_cobs::<Vec<DocOffset>>(&mut dat)?;
-
-    Ok(offsets)
-}
 
-fn inspect_bson<P: AsRef<Path>>(bson_file: P) -> Result<Vec<DocOffset>, DissectError> {
-    let path = bson_file.as_ref();
-    let mut file = OpenOptions::new().read(true).open(path)?;
-    let mut reader = BufReader::new(&mut file);
-    let (offsets, _) = index_file(&mut reader)?;
-    Ok(offsets)
+    Ok(())
 }
 
-fn index_file<R: SeekRead>(mut reader: R) -> Result<(Vec<DocOffset>, usize), DissectError> {
-    let mut count = 0;
-    // little endian 4 byte int
-    let pat = "@W";
-    let mut offsets = Vec::new();
+/// The documents of the input the export writes, narrowed down by --id, the ObjectId dates and --slice
+/// and put in the order of --order-by-oid or --merge-by
+fn select_documents(args: &Args, input: &index::Input) -> Result<Vec<DocOffset>, DissectError> {
+    let filters = if args.bloom || args.id.is_some() {
+        bloom::load_filters(input, args.bloom, args.inspect)?
+    } else {
+        Vec::new()
+    };
+    let idx = match &args.id {
+        Some(id) => {
+            let found = bloom::find_id(input, &filters, &bloom::parse_id(id))?;
+            if found.is_empty() {
+                println!("No document with _id {id}");
+            }
+            found
+        }
+        None => input.offsets.clone(),
+    };
 
-    let mut buf = [0u8; 4];
+    let idx = index::oid::select(args, input, idx)?;
+    let idx = index::merge::select(args, input, idx)?;
 
-    loop {
-        let n = reader.read(&mut buf)?;
-        if n == 0 {
-            break;
-        }
-        count += 1;
-        let size: i32 = read_pattern(&buf[..], pat)?[0].try_into()?;
-        offsets.push(DocOffset {
-            offset: reader.stream_position()? as usize - 4,
-            size: size as usize,
-        });
-        // seek to the end of the document minus the 4 bytes that were just read
-        reader.seek(SeekFrom::Current((size - 4) as i64))?;
-    }
-    reader.rewind()?;
-    Ok((offsets, count))
+    Ok(if let Some(slice) = &args.slice {
+        idx[parse_slice(slice)?].to_vec()
+    } else {
+        idx
+    })
 }
 
 /// Split a string in the form of `start..end` into a tuple of `start` and `end`
@@ -289,61 +1517,294 @@ fn parse_slice(slice: &str) -> Result<(Bound<usize>, Bound<usize>), DissectError
     // Ok((start, end))
 }
 
-fn apply_script<P: AsRef<Path>>(
-    input: P,
-    script: P,
-    offsets: Vec<&DocOffset>,
-) -> Result<Vec<Document>, DissectError> {
-    let script = script.as_ref();
-    let script = std::fs::read_to_string(script)?;
+/// The documents of a batch with where they were read from
+type DocBatch = Vec<(Document, DocOffset)>;
+
+/// What every batch goes through between being read and handed to the outputs
+struct Stages<'a> {
+    query: Option<&'a Query>,
+    filter: Option<&'a Expr>,
+    /// Documents dropped for not matching the query or the filter
+    unmatched: AtomicUsize,
+    transforms: &'a Transforms,
+    script: Option<&'a str>,
+    jq: Option<&'a Jq>,
+    jmespath: Option<&'a JmesPath>,
+    exec_filter: Option<&'a ExecFilter>,
+    exclude_fields: Option<&'a ExcludeFields>,
+    /// Fields removed by --exclude-fields and the documents they were removed from
+    excluded: AtomicUsize,
+    excluded_from: AtomicUsize,
+    tracer: Option<&'a Tracer>,
+    env: &'a Env,
+}
+
+impl Stages<'_> {
+    /// Run the first batches of `idx` through the stages until they left `count` documents, returns the number
+    /// of offsets and the documents of every batch in order
+    fn sample(
+        &self,
+        idx: &[DocOffset],
+        count: usize,
+        batch: usize,
+        thread_pool: &ThreadPool,
+        read: impl Fn(&[&DocOffset]) -> Result<Vec<(Document, DocOffset)>, DissectError> + Sync,
+    ) -> Result<Vec<(usize, DocBatch)>, DissectError> {
+        let mut sampled = Vec::new();
+        let mut documents = 0;
+        // a round of a batch per thread at a time, stages dropping most documents may take several
+        for round in idx.chunks(batch * thread_pool.current_num_threads().max(1)) {
+            if documents >= count {
+                break;
+            }
+            let batches = thread_pool.install(|| {
+                round
+                    .par_chunks(batch)
+                    .map(|offsets| {
+                        let docs = read(&offsets.iter().collect::<Vec<_>>())?;
+                        Ok((offsets.len(), self.process_batch(docs)?))
+                    })
+                    .collect::<Result<Vec<_>, DissectError>>()
+            })?;
+            for (read, docs) in batches {
+                documents += docs.len();
+                sampled.push((read, docs));
+            }
+        }
+        Ok(sampled)
+    }
+
+    /// Drop the documents not matching the query or the filter, then run the transforms, the script, jq or JMESPath, the exec filter
+    /// and --exclude-fields over the rest of a batch, the document of --trace-doc is followed through each of them
+    fn process_batch(
+        &self,
+        mut docs: Vec<(Document, DocOffset)>,
+    ) -> Result<Vec<(Document, DocOffset)>, DissectError> {
+        // the tracer while the batch holds the traced document, the other batches are left alone
+        let mut tracer = self.tracer.filter(|tracer| tracer.read(&docs));
+        if let Some(query) = self.query {
+            let before = docs.len();
+            docs.retain(|(doc, _)| query.matches(doc));
+            self.unmatched
+                .fetch_add(before - docs.len(), Ordering::Relaxed);
+            tracer = tracer.filter(|tracer| tracer.kept("--query", &docs));
+        }
+        if let Some(filter) = self.filter {
+            let before = docs.len();
+            docs.retain(|(doc, _)| filter.matches(doc));
+            self.unmatched
+                .fetch_add(before - docs.len(), Ordering::Relaxed);
+            tracer = tracer.filter(|tracer| tracer.kept("--filter", &docs));
+        }
+        for (doc, offset) in &mut docs {
+            match tracer.filter(|tracer| tracer.is(offset)) {
+                Some(tracer) => self
+                    .transforms
+                    .apply_traced(doc, offset, &mut |stage, doc| tracer.stage(stage, doc))?,
+                None => self.transforms.apply(doc, offset)?,
+            }
+        }
+        if let Some(script) = self.script {
+            docs = apply_script(docs, script, self.env)?;
+            tracer = tracer.filter(|tracer| tracer.after("--script", &docs));
+        }
+        if let Some(jq) = self.jq {
+            docs = jq.apply(docs)?;
+            tracer = tracer.filter(|tracer| tracer.after("--jq", &docs));
+        }
+        if let Some(jmespath) = self.jmespath {
+            docs = jmespath.apply(docs)?;
+            tracer = tracer.filter(|tracer| tracer.after("--jmespath", &docs));
+        }
+        if let Some(exec_filter) = self.exec_filter {
+            docs = exec_filter.apply(docs)?;
+            tracer = tracer.filter(|tracer| tracer.after("--exec-filter", &docs));
+        }
+        if let Some(exclude) = self.exclude_fields {
+            for (doc, _) in &mut docs {
+                let removed = exclude.remove(doc);
+                if removed > 0 {
+                    self.excluded.fetch_add(removed, Ordering::Relaxed);
+                    self.excluded_from.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            tracer = tracer.filter(|tracer| tracer.after("--exclude-fields", &docs));
+        }
+        if let Some(tracer) = tracer {
+            tracer.done();
+        }
+        Ok(docs)
+    }
+}
 
-    let docs = load_docs(input, offsets)?;
+fn apply_script(
+    docs: Vec<(Document, DocOffset)>,
+    script: &str,
+    env: &Env,
+) -> Result<Vec<(Document, DocOffset)>, DissectError> {
     let mut res = Vec::with_capacity(docs.len());
-    let lctx = LuaEngine::new()
+    let lctx = LuaEngine::new(env)
         .map_err(|e| DissectError::Unexpected(format!("Failed to create Lua context: {e}")))?;
-    for doc in docs {
+    for (doc, offset) in docs {
         lctx.load_document(doc)?;
-        lctx.load_script(&script)?;
-        res.push(lctx.get_document()?);
+        lctx.load_script(script)?;
+        res.push((lctx.get_document()?, offset));
     }
     Ok(res)
 }
 
-fn load_docs<P: AsRef<Path>>(
-    input: P,
-    offsets: Vec<&DocOffset>,
-) -> Result<Vec<Document>, DissectError> {
-    let path = input.as_ref();
-    let mut file = OpenOptions::new().read(true).open(path)?;
-    let mut docs = Vec::new();
-    for offset in offsets {
-        file.seek(SeekFrom::Start(offset.offset as u64))?;
-        let mut buf = vec![0u8; offset.size];
-        file.read_exact(&mut buf)?;
-        docs.push(Document::from_reader(&mut buf.as_slice())?);
-    }
-    Ok(docs)
+fn load_docs(
+    mut reader: DocReader,
+    decoder: &Decoder,
+    offsets: &[&DocOffset],
+) -> Result<Vec<(Document, DocOffset)>, DissectError> {
+    RAW.with_borrow_mut(|raw| {
+        let mut docs = Vec::with_capacity(offsets.len());
+        for offset in offsets {
+            raw.clear();
+            reader.read_raw_into(offset, raw)?;
+            if let Some(doc) = decoder.decode(raw)? {
+                docs.push((doc, **offset));
+            }
+        }
+        Ok(docs)
+    })
+}
+
+/// The writers between the encoder and the file of a single output, innermost last
+type OutputStream = Compressed<Encrypted<Sha256Writer<BufWriter<Destination>>>>;
+
+/// The --single output being written, with --max-output-size the file of the current shard
+/// or none between a full shard and the next document
+struct Single {
+    stream: Option<StreamWriter<OutputStream>>,
+    /// Starts the next shard
+    encoder: Encoder,
+    shard: usize,
+    /// The first document of the current shard and how many it holds, for manifest.json
+    first: Option<DocOffset>,
+    documents: usize,
+}
+
+/// Create the file of a single output, hashed for the checksum manifest and manifest.json, encrypted and compressed as requested
+fn open_stream(args: &Args, path: &Path) -> Result<OutputStream, DissectError> {
+    wrap_stream(args, Destination::File(File::create(path)?))
 }
 
+fn wrap_stream(args: &Args, file: Destination) -> Result<OutputStream, DissectError> {
+    let writer = Sha256Writer::new(BufWriter::new(file), args.checksums || args.manifest_json);
+    let writer = match &args.encrypt {
+        Some(encryption) => encryption.wrap(writer)?,
+        None => Encrypted::Plain(writer),
+    };
+    // compressed before it is encrypted, encrypted data doesn't compress
+    Ok(match args.compress {
+        Some(compression) => compression.wrap(writer)?,
+        None => Compressed::Plain(writer),
+    })
+}
+
+/// Where a --single or --archive output is written to, stdout, an upload to the object store,
+/// the partial file of the run or the output itself
+fn output_file(
+    stdout: Option<File>,
+    store: Option<&ObjectStore>,
+    workspace: Option<&workspace::Workspace>,
+    output: &Path,
+) -> Result<Destination, DissectError> {
+    Ok(match (stdout, store, workspace) {
+        (Some(stdout), _, _) => Destination::File(stdout),
+        (None, Some(store), _) => store.writer(&output.to_string_lossy())?,
+        (None, None, Some(workspace)) => Destination::File(File::create(workspace.partial())?),
+        (None, None, None) => Destination::File(File::create(output)?),
+    })
+}
+
+/// Flush a complete --single or --archive output and move it from the partial file of the run to `output`,
+/// or complete its upload
+fn commit_output(
+    writer: BufWriter<Destination>,
+    workspace: Option<&workspace::Workspace>,
+    output: &Path,
+) -> Result<(), DissectError> {
+    match writer.into_inner().map_err(|e| e.into_error())? {
+        Destination::File(file) => {
+            if let Some(workspace) = workspace {
+                // on disk before it replaces the output, a crash leaves either the old or the new file
+                file.sync_all()?;
+                workspace.commit(output)?;
+            }
+        }
+        #[cfg(feature = "object-store")]
+        Destination::Object(upload) => upload.finish()?,
+    }
+    Ok(())
+}
+
+/// Take stdout over for the exported data and point the standard output of the process at stderr,
+/// so no status line can end up in the middle of a stream piped into another program
+#[cfg(unix)]
+fn take_stdout() -> Result<File, DissectError> {
+    use std::os::fd::FromRawFd;
+
+    std::io::stdout().flush()?;
+    // SAFETY: only the standard descriptors of this process are duplicated,
+    // the duplicate of stdout is owned by the returned file from here on
+    unsafe {
+        let data = libc::dup(libc::STDOUT_FILENO);
+        if data < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(File::from_raw_fd(data))
+    }
+}
+
+#[cfg(not(unix))]
+fn take_stdout() -> Result<File, DissectError> {
+    Err(DissectError::Unexpected(
+        "--stdout is only supported on unix".into(),
+    ))
+}
+
+thread_local! {
+    /// The raw document being decoded by this thread, reused so reading a document doesn't allocate
+    static RAW: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+
+    /// The file of a document being written by this thread, reused so writing a file doesn't allocate and
+    /// grow a buffer of its own, pretty json is a good deal larger than the document
+    static SCRATCH: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+
+    /// The --compress output of a document, swapped with the file it was compressed from so both buffers are kept
+    static COMPRESSED: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Write a document to its own file, returns the path, the bytes written are left in `bytes`
 fn save_single_doc<P: AsRef<Path>>(
-    doc: Document,
+    doc: &Document,
     out_dir: P,
-    idx: String,
-    pretty: bool,
-) -> Result<(), DissectError> {
-    let out_dir = out_dir.as_ref();
+    idx: &str,
+    encoder: &Encoder,
+    compress: Option<Compression>,
+    bytes: &mut Vec<u8>,
+) -> Result<PathBuf, DissectError> {
+    let mut name = format!("{idx}.{}", encoder.extension());
+    bytes.clear();
+    encoder.encode(&mut *bytes, doc)?;
+    if let Some(compression) = compress {
+        name = format!("{name}.{}", compression.extension());
+        COMPRESSED.with_borrow_mut(|compressed| {
+            compression.compress_into(bytes, compressed)?;
+            std::mem::swap(bytes, compressed);
+            Ok::<_, DissectError>(())
+        })?;
+    }
+    let path = out_dir.as_ref().join(name);
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(out_dir.join(format!("{idx}.json")))?;
-    let writer = BufWriter::new(&mut file);
-    if pretty {
-        let mut ser = serde_json::Serializer::pretty(writer);
-        doc.serialize(&mut ser)?;
-    } else {
-        let mut ser = serde_json::Serializer::new(writer);
-        doc.serialize(&mut ser)?;
-    }
-    Ok(())
+        .open(&path)?;
+    file.write_all(bytes)?;
+    file.flush()?;
+    Ok(path)
 }
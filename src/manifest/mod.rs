@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bson::{Bson, Document};
+use clap::Subcommand;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{env::Env, output::checksum::file_digest, Args, DissectError};
+
+/// Create signing keys and check signed export manifests
+#[derive(Debug, clap::Args)]
+pub struct ManifestArgs {
+    #[clap(subcommand)]
+    pub command: ManifestCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ManifestCommand {
+    /// Generate an ed25519 key for --sign, the public key is written next to it with a .pub extension
+    Keygen {
+        /// The secret key file to write
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Check the signature of a manifest and that every file it lists is present and unchanged
+    Verify {
+        /// The .sig file written next to the manifest
+        signature: PathBuf,
+
+        /// Only accept signatures made with this public key, given as hex or as a .pub file
+        #[clap(long)]
+        public_key: Option<String>,
+    },
+}
+
+/// Signed statement of how an export was produced, covering its checksum manifest
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Statement {
+    tool: String,
+    args: Vec<String>,
+    /// sha256 of the files that shaped the output, like scripts
+    rules: BTreeMap<String, String>,
+    /// The manifest file, relative to the statement
+    manifest: String,
+    manifest_sha256: String,
+    /// The --notice document of the export, as relaxed extended json
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notice: Option<serde_json::Value>,
+    public_key: String,
+    #[serde(default)]
+    signature: String,
+}
+
+/// Sign the checksum manifest at `manifest` with the key at `key`, the statement is saved as `<manifest>.sig`
+/// Arguments holding a value of --env or --env-file are recorded with it scrubbed
+pub(crate) fn sign(
+    args: &Args,
+    env: &Env,
+    notice: Option<&Document>,
+    key: &Path,
+    manifest: &Path,
+) -> Result<PathBuf, DissectError> {
+    let key = read_signing_key(key)?;
+    let mut rules = BTreeMap::new();
+    for rule in args.script.iter() {
+        rules.insert(rule.display().to_string(), file_digest(rule)?);
+    }
+
+    let mut statement = Statement {
+        tool: format!("dissbson {}", env!("CARGO_PKG_VERSION")),
+        args: std::env::args()
+            .skip(1)
+            .map(|arg| env.scrub(&arg))
+            .collect(),
+        rules,
+        manifest: manifest
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        manifest_sha256: file_digest(manifest)?,
+        notice: notice.map(|doc| Bson::Document(doc.clone()).into_relaxed_extjson()),
+        public_key: hex::encode(key.verifying_key().as_bytes()),
+        signature: String::new(),
+    };
+    statement.signature = hex::encode(key.sign(&serde_json::to_vec(&statement)?).to_bytes());
+
+    let path = with_suffix(manifest, ".sig");
+    let mut writer = BufWriter::new(File::create(&path)?);
+    serde_json::to_writer_pretty(&mut writer, &statement)?;
+    writer.flush()?;
+    Ok(path)
+}
+
+pub(crate) fn run(args: &ManifestArgs) -> Result<(), DissectError> {
+    match &args.command {
+        ManifestCommand::Keygen { output } => {
+            let mut seed = [0u8; 32];
+            getrandom::getrandom(&mut seed)
+                .map_err(|e| DissectError::Unexpected(format!("No randomness available: {e}")))?;
+            let key = SigningKey::from_bytes(&seed);
+            let public = hex::encode(key.verifying_key().as_bytes());
+            std::fs::write(output, hex::encode(seed))?;
+            std::fs::write(with_suffix(output, ".pub"), &public)?;
+            println!("Wrote key to {}, public key {public}", output.display());
+        }
+        ManifestCommand::Verify {
+            signature,
+            public_key,
+        } => verify(signature, public_key.as_deref())?,
+    }
+    Ok(())
+}
+
+fn verify(path: &Path, pinned: Option<&str>) -> Result<(), DissectError> {
+    let mut statement: Statement = serde_json::from_slice(&std::fs::read(path)?)?;
+    let signature = Signature::from_slice(&decode_hex(&statement.signature)?)
+        .map_err(|e| DissectError::Parse(format!("Invalid signature: {e}")))?;
+    let key = parse_public_key(&statement.public_key)?;
+    statement.signature.clear();
+    key.verify(&serde_json::to_vec(&statement)?, &signature)
+        .map_err(|_| DissectError::Unexpected("Signature does not match the statement".into()))?;
+
+    match pinned {
+        Some(pinned) => {
+            let pinned = if Path::new(pinned).is_file() {
+                std::fs::read_to_string(pinned)?
+            } else {
+                pinned.to_string()
+            };
+            if parse_public_key(pinned.trim())? != key {
+                return Err(DissectError::Unexpected(
+                    "Manifest was signed with a different key".into(),
+                ));
+            }
+        }
+        None => println!(
+            "Signature is valid but made with an unpinned key {}, pass --public-key to check it",
+            statement.public_key
+        ),
+    }
+
+    println!(
+        "Produced by {} {}",
+        statement.tool,
+        statement.args.join(" ")
+    );
+    for (rule, digest) in &statement.rules {
+        println!("  using {rule} (sha256 {digest})");
+    }
+    if let Some(notice) = &statement.notice {
+        println!("  with the notice {notice}");
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let manifest = dir.join(&statement.manifest);
+    if file_digest(&manifest)? != statement.manifest_sha256 {
+        return Err(DissectError::Unexpected(format!(
+            "{} was changed after it was signed",
+            manifest.display()
+        )));
+    }
+
+    let mut checked = 0;
+    let mut damaged = Vec::new();
+    for line in std::fs::read_to_string(&manifest)?.lines() {
+        let Some((digest, name)) = line.split_once("  ") else {
+            continue;
+        };
+        let file = dir.join(name);
+        checked += 1;
+        match file_digest(&file) {
+            Ok(actual) if actual == digest => {}
+            Ok(_) => damaged.push(format!("{name}: checksum mismatch")),
+            Err(e) => damaged.push(format!("{name}: {e}")),
+        }
+    }
+    for failure in damaged.iter().take(10) {
+        eprintln!("{failure}");
+    }
+    if !damaged.is_empty() {
+        return Err(DissectError::Unexpected(format!(
+            "{} of {checked} files listed in {} are missing or changed",
+            damaged.len(),
+            manifest.display()
+        )));
+    }
+    println!(
+        "Verified the signature and all {checked} files listed in {}",
+        manifest.display()
+    );
+    Ok(())
+}
+
+fn read_signing_key(path: &Path) -> Result<SigningKey, DissectError> {
+    let seed: [u8; 32] = decode_hex(std::fs::read_to_string(path)?.trim())?
+        .try_into()
+        .map_err(|_| DissectError::Parse(format!("{} is not an ed25519 key", path.display())))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+fn parse_public_key(text: &str) -> Result<VerifyingKey, DissectError> {
+    let bytes: [u8; 32] = decode_hex(text)?
+        .try_into()
+        .map_err(|_| DissectError::Parse(format!("{text} is not an ed25519 public key")))?;
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|e| DissectError::Parse(format!("Invalid public key {text}: {e}")))
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, DissectError> {
+    hex::decode(text).map_err(|e| DissectError::Parse(format!("Invalid hex {text}: {e}")))
+}
+
+/// `path` with `suffix` appended to its file name
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
@@ -0,0 +1,58 @@
+use bson::{Bson, Document};
+
+use crate::docpath;
+
+/// Normalization pass bringing documents into a canonical form,
+/// keys are sorted recursively so equal documents always serialize to the same json,
+/// ignored fields are dropped and unordered arrays are sorted by their canonical json
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Normalizer {
+    ignore_fields: Vec<Vec<String>>,
+    unordered_arrays: Vec<Vec<String>>,
+}
+
+impl Normalizer {
+    pub fn new(ignore_fields: &[String], unordered_arrays: &[String]) -> Self {
+        Self {
+            ignore_fields: ignore_fields.iter().map(|p| docpath::parse(p)).collect(),
+            unordered_arrays: unordered_arrays.iter().map(|p| docpath::parse(p)).collect(),
+        }
+    }
+
+    pub fn apply(&self, doc: &mut Document) {
+        for path in &self.ignore_fields {
+            docpath::remove(doc, path);
+        }
+        canonicalize_document(doc);
+        for path in &self.unordered_arrays {
+            docpath::visit_mut(doc, path, &mut |value| {
+                if let Bson::Array(items) = value {
+                    items.sort_by_cached_key(canonical_json);
+                }
+            });
+        }
+    }
+}
+
+/// Sort the keys of a document and all nested documents
+pub(crate) fn canonicalize_document(doc: &mut Document) {
+    let mut fields = std::mem::take(doc).into_iter().collect::<Vec<_>>();
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (key, mut value) in fields {
+        canonicalize(&mut value);
+        doc.insert(key, value);
+    }
+}
+
+fn canonicalize(value: &mut Bson) {
+    match value {
+        Bson::Document(doc) => canonicalize_document(doc),
+        Bson::Array(items) => items.iter_mut().for_each(canonicalize),
+        _ => {}
+    }
+}
+
+/// Canonical json text of an already canonicalized value
+pub(crate) fn canonical_json(value: &Bson) -> String {
+    value.clone().into_relaxed_extjson().to_string()
+}
@@ -0,0 +1,91 @@
+use std::{
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use parking_lot::Mutex;
+use zip::{
+    write::{SimpleFileOptions, StreamWriter},
+    CompressionMethod, ZipWriter,
+};
+
+use crate::DissectError;
+
+/// Archives the documents of their own can be collected in instead of a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+    /// A tar file, compressed as a whole with --compress into a .tar.gz or .tar.zst
+    Tar,
+    /// A zip file with every entry deflated, opens with a double click on Windows and macOS
+    Zip,
+}
+
+enum Builder<W: Write> {
+    Tar(tar::Builder<W>),
+    Zip(Box<ZipWriter<StreamWriter<W>>>),
+}
+
+/// Collects the files of the documents in one archive, every entry is written out as soon as it is added
+pub(crate) struct Archive<W: Write> {
+    builder: Mutex<Builder<W>>,
+    /// Modification time of every tar entry, the start of the export
+    mtime: u64,
+    entries: Mutex<u64>,
+}
+
+impl<W: Write> Archive<W> {
+    pub fn new(format: ArchiveFormat, writer: W) -> Self {
+        let builder = match format {
+            ArchiveFormat::Tar => Builder::Tar(tar::Builder::new(writer)),
+            // zip entries are streamed with data descriptors, the output is never seeked back into
+            ArchiveFormat::Zip => Builder::Zip(Box::new(ZipWriter::new_stream(writer))),
+        };
+        Self {
+            builder: Mutex::new(builder),
+            mtime: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            entries: Mutex::new(0),
+        }
+    }
+
+    /// Append a file holding `bytes`
+    pub fn append(&self, name: &str, bytes: &[u8]) -> Result<(), DissectError> {
+        match &mut *self.builder.lock() {
+            Builder::Tar(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(bytes.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(self.mtime);
+                builder.append_data(&mut header, name, bytes)?;
+            }
+            Builder::Zip(writer) => {
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .unix_permissions(0o644);
+                writer.start_file(name, options).map_err(zip_failed)?;
+                writer.write_all(bytes)?;
+            }
+        }
+        *self.entries.lock() += 1;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> u64 {
+        *self.entries.lock()
+    }
+
+    /// Write the end of the archive and return the inner writer
+    pub fn finish(self) -> Result<W, DissectError> {
+        match self.builder.into_inner() {
+            Builder::Tar(builder) => Ok(builder.into_inner()?),
+            Builder::Zip(writer) => Ok(writer.finish().map_err(zip_failed)?.into_inner()),
+        }
+    }
+}
+
+fn zip_failed(e: zip::result::ZipError) -> DissectError {
+    DissectError::Unexpected(format!("Failed to write zip archive: {e}"))
+}
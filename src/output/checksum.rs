@@ -0,0 +1,92 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+
+use crate::DissectError;
+
+/// Passes writes on while hashing them with sha256, when enabled
+pub(crate) struct Sha256Writer<W: Write> {
+    inner: W,
+    hasher: Option<Sha256>,
+}
+
+impl<W: Write> Sha256Writer<W> {
+    pub fn new(inner: W, enabled: bool) -> Self {
+        Self {
+            inner,
+            hasher: enabled.then(Sha256::new),
+        }
+    }
+
+    /// The inner writer and the hex digest of everything written, if hashing was enabled
+    pub fn finish(self) -> (W, Option<String>) {
+        (self.inner, self.hasher.map(|h| hex::encode(h.finalize())))
+    }
+}
+
+impl<W: Write> Write for Sha256Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hex sha256 digest of `bytes`
+pub(crate) fn digest(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Hex sha256 digest of the file at `path`
+pub(crate) fn file_digest(path: &Path) -> Result<String, DissectError> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Checksums of the written output files, saved in the format of `sha256sum` so `sha256sum -c` can check them
+pub(crate) struct Manifest {
+    path: PathBuf,
+    entries: Mutex<Vec<(PathBuf, String)>>,
+}
+
+impl Manifest {
+    /// Manifest saved at `path`, files next to or below it are listed relative to it and others by absolute path
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn add(&self, file: &Path, digest: String) {
+        self.entries.lock().push((file.to_path_buf(), digest));
+    }
+
+    pub fn save(self) -> Result<PathBuf, DissectError> {
+        let dir = self.path.parent().unwrap_or(Path::new(""));
+        let mut entries = self.entries.into_inner();
+        entries.sort();
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        for (file, digest) in &entries {
+            let name = match file.strip_prefix(dir) {
+                Ok(name) => name.to_path_buf(),
+                Err(_) => std::fs::canonicalize(file)?,
+            };
+            writeln!(writer, "{digest}  {}", name.display())?;
+        }
+        writer.flush()?;
+        Ok(self.path)
+    }
+}
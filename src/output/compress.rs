@@ -0,0 +1,129 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use flate2::write::GzEncoder;
+
+/// How output files are compressed, given as `gzip` or `zstd` with an optional level, e.g. `zstd:19`
+#[derive(Debug, Clone, Copy)]
+pub enum Compression {
+    /// gzip at a level from 0 to 9
+    Gzip(u32),
+    /// zstd at a level from 1 to 22
+    Zstd(i32),
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, level) = match s.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (s, None),
+        };
+        let level = |default: i32, range: std::ops::RangeInclusive<i32>| {
+            let Some(level) = level else {
+                return Ok(default);
+            };
+            match level.parse() {
+                Ok(level) if range.contains(&level) => Ok(level),
+                _ => Err(format!(
+                    "{name} levels go from {} to {}, got {level}",
+                    range.start(),
+                    range.end()
+                )),
+            }
+        };
+        match name {
+            "gzip" | "gz" => Ok(Self::Gzip(level(6, 0..=9)? as u32)),
+            "zstd" | "zst" => Ok(Self::Zstd(level(3, 1..=22)?)),
+            _ => Err(format!(
+                "expected gzip or zstd with an optional :level, got {s}"
+            )),
+        }
+    }
+}
+
+impl Compression {
+    /// Suffix added to the name of every compressed file
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip(_) => "gz",
+            Self::Zstd(_) => "zst",
+        }
+    }
+
+    /// The compression a `.gz` or `.zst` suffix of an output names, at the default level
+    pub fn of_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Self::Gzip(6)),
+            "zst" => Some(Self::Zstd(3)),
+            _ => None,
+        }
+    }
+
+    /// Start a compressed stream on `writer`
+    pub(crate) fn wrap<W: Write>(self, writer: W) -> io::Result<Compressed<W>> {
+        Ok(match self {
+            Self::Gzip(level) => {
+                Compressed::Gzip(GzEncoder::new(writer, flate2::Compression::new(level)))
+            }
+            Self::Zstd(level) => Compressed::Zstd(zstd::Encoder::new(writer, level)?),
+        })
+    }
+
+    /// Compress the whole content of a file
+    pub(crate) fn compress(self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut compressed = Vec::with_capacity(bytes.len() / 4);
+        self.compress_into(bytes, &mut compressed)?;
+        Ok(compressed)
+    }
+
+    /// Compress the whole content of a file into `out`, replacing what it held, a buffer reused between files
+    /// saves allocating one for each
+    pub(crate) fn compress_into(self, bytes: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        out.clear();
+        let mut writer = self.wrap(out)?;
+        writer.write_all(bytes)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// A writer that compresses what it is given, or passes it on as is
+pub(crate) enum Compressed<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Compressed<W> {
+    /// Write the end of the compressed stream and return the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Plain(writer) => Ok(writer),
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Compressed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
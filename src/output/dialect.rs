@@ -0,0 +1,209 @@
+use std::io::Write;
+
+use bson::DateTime;
+use clap::ValueEnum;
+use csv::{QuoteStyle, Terminator, WriterBuilder};
+
+use super::table::Cell;
+
+/// How fields are quoted in csv output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CsvQuote {
+    /// Only fields holding the delimiter, a quote or a line break
+    Necessary,
+    /// Every field
+    Always,
+    /// Every field that isn't a number
+    NonNumeric,
+    /// No field, for loaders that take quotes literally
+    Never,
+}
+
+/// The csv dialect written, loaders like Redshift, Excel and Hive each expect their own
+#[derive(Debug, Clone, clap::Args)]
+pub struct CsvDialect {
+    /// Field delimiter of csv output, a single character or `tab` for tsv
+    #[clap(long, default_value = ",", value_parser = parse_delimiter)]
+    pub csv_delimiter: u8,
+
+    /// Which csv fields are quoted
+    #[clap(long, value_enum, default_value_t = CsvQuote::Necessary)]
+    pub csv_quote: CsvQuote,
+
+    /// End csv lines with CRLF instead of LF
+    #[clap(long)]
+    pub csv_crlf: bool,
+
+    /// Text written for missing and null values in csv output, like \N for Hive
+    #[clap(long, default_value = "")]
+    pub csv_null: String,
+
+    /// Leave out the csv header line
+    #[clap(long)]
+    pub csv_no_header: bool,
+}
+
+impl CsvDialect {
+    pub(crate) fn writer<W: Write>(&self, writer: W) -> csv::Writer<W> {
+        WriterBuilder::new()
+            .delimiter(self.csv_delimiter)
+            .quote_style(match self.csv_quote {
+                CsvQuote::Necessary => QuoteStyle::Necessary,
+                CsvQuote::Always => QuoteStyle::Always,
+                CsvQuote::NonNumeric => QuoteStyle::NonNumeric,
+                CsvQuote::Never => QuoteStyle::Never,
+            })
+            .terminator(if self.csv_crlf {
+                Terminator::CRLF
+            } else {
+                Terminator::Any(b'\n')
+            })
+            .from_writer(writer)
+    }
+
+    /// Write the header unless it is turned off
+    pub(crate) fn header<W: Write, I>(
+        &self,
+        writer: &mut csv::Writer<W>,
+        names: I,
+    ) -> csv::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        if self.csv_no_header {
+            Ok(())
+        } else {
+            writer.write_record(names)
+        }
+    }
+
+    /// The text of a value that may be null
+    pub(crate) fn cell(&self, value: Option<String>) -> String {
+        value.unwrap_or_else(|| self.csv_null.clone())
+    }
+
+    /// The fields of a table row
+    pub(crate) fn row(&self, cells: Vec<Cell>) -> Vec<String> {
+        cells
+            .into_iter()
+            .map(|cell| {
+                self.cell(match cell {
+                    Cell::Null => None,
+                    Cell::Boolean(b) => Some(b.to_string()),
+                    Cell::BigInt(n) => Some(n.to_string()),
+                    Cell::Double(n) => Some(n.to_string()),
+                    Cell::Timestamp(millis) => Some(
+                        DateTime::from_millis(millis)
+                            .try_to_rfc3339_string()
+                            .unwrap_or_else(|_| millis.to_string()),
+                    ),
+                    Cell::Text(s) => Some(s),
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(format!("expected a single ascii character or tab, got {s}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_delimiter, CsvDialect, CsvQuote};
+    use crate::output::table::Cell;
+
+    fn dialect(quote: CsvQuote) -> CsvDialect {
+        CsvDialect {
+            csv_delimiter: b',',
+            csv_quote: quote,
+            csv_crlf: false,
+            csv_null: String::new(),
+            csv_no_header: false,
+        }
+    }
+
+    fn csv(dialect: &CsvDialect, rows: &[&[&str]]) -> String {
+        let mut writer = dialect.writer(Vec::new());
+        dialect
+            .header(&mut writer, ["id", "name"])
+            .expect("writing to a vec");
+        for row in rows {
+            writer.write_record(*row).expect("writing to a vec");
+        }
+        String::from_utf8(writer.into_inner().expect("flushing a vec")).expect("utf-8")
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(parse_delimiter(","), Ok(b','));
+        assert_eq!(parse_delimiter("|"), Ok(b'|'));
+        for tab in ["tab", "\\t", "\t"] {
+            assert_eq!(parse_delimiter(tab), Ok(b'\t'));
+        }
+        assert!(parse_delimiter("").is_err());
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("§").is_err());
+    }
+
+    #[test]
+    fn quoting() {
+        let rows: &[&[&str]] = &[&["1", "plain"], &["2", "a,b \"c\""], &["3", "two\nlines"]];
+        assert_eq!(
+            csv(&dialect(CsvQuote::Necessary), rows),
+            "id,name\n1,plain\n2,\"a,b \"\"c\"\"\"\n3,\"two\nlines\"\n"
+        );
+        assert_eq!(
+            csv(&dialect(CsvQuote::Always), &rows[..1]),
+            "\"id\",\"name\"\n\"1\",\"plain\"\n"
+        );
+        assert_eq!(
+            csv(&dialect(CsvQuote::NonNumeric), &rows[..1]),
+            "\"id\",\"name\"\n1,\"plain\"\n"
+        );
+        assert_eq!(
+            csv(&dialect(CsvQuote::Never), &rows[..2]),
+            "id,name\n1,plain\n2,a,b \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn tsv_with_crlf_and_no_header() {
+        let tsv = CsvDialect {
+            csv_delimiter: b'\t',
+            csv_crlf: true,
+            csv_no_header: true,
+            ..dialect(CsvQuote::Necessary)
+        };
+        assert_eq!(
+            csv(&tsv, &[&["1", "a,b"], &["2", "c\td"]]),
+            "1\ta,b\r\n2\t\"c\td\"\r\n"
+        );
+    }
+
+    #[test]
+    fn rows_and_nulls() {
+        let hive = CsvDialect {
+            csv_null: "\\N".into(),
+            ..dialect(CsvQuote::Necessary)
+        };
+        assert_eq!(
+            hive.row(vec![
+                Cell::Null,
+                Cell::Boolean(false),
+                Cell::BigInt(42),
+                Cell::Double(0.5),
+                Cell::Timestamp(86_400_000),
+                Cell::Text(String::new()),
+            ]),
+            ["\\N", "false", "42", "0.5", "1970-01-02T00:00:00Z", ""]
+        );
+        assert_eq!(hive.cell(None), "\\N");
+        assert_eq!(dialect(CsvQuote::Necessary).cell(None), "");
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+
+use bson::{Bson, Document};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::DissectError;
+
+/// How compact json is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum JsonWriter {
+    /// Documents go through serde like every other format
+    Serde,
+    /// Documents are walked and written directly, byte for byte the same json with less work per value
+    Direct,
+}
+
+/// Write a document as compact json the way serde_json writes it, without going through its `Serialize` impl.
+/// The common value types are written here, the rare ones are still handed to serde
+pub(crate) fn write_document<W: Write>(writer: &mut W, doc: &Document) -> Result<(), DissectError> {
+    writer.write_all(b"{")?;
+    for (nth, (key, value)) in doc.iter().enumerate() {
+        if nth > 0 {
+            writer.write_all(b",")?;
+        }
+        scalar(writer, key)?;
+        writer.write_all(b":")?;
+        write_value(writer, value)?;
+    }
+    writer.write_all(b"}")?;
+    Ok(())
+}
+
+fn write_value<W: Write>(writer: &mut W, value: &Bson) -> Result<(), DissectError> {
+    match value {
+        Bson::Document(doc) => write_document(writer, doc)?,
+        Bson::Array(items) => {
+            writer.write_all(b"[")?;
+            for (nth, item) in items.iter().enumerate() {
+                if nth > 0 {
+                    writer.write_all(b",")?;
+                }
+                write_value(writer, item)?;
+            }
+            writer.write_all(b"]")?;
+        }
+        Bson::String(s) => scalar(writer, s)?,
+        Bson::Double(n) => scalar(writer, n)?,
+        Bson::Int32(n) => scalar(writer, n)?,
+        Bson::Int64(n) => scalar(writer, n)?,
+        Bson::Boolean(b) => writer.write_all(if *b { b"true" } else { b"false" })?,
+        Bson::Null => writer.write_all(b"null")?,
+        // serde formats both through a String and a struct of their own
+        Bson::ObjectId(oid) => {
+            let mut hex = [0u8; 24];
+            hex::encode_to_slice(oid.bytes(), &mut hex).expect("12 bytes fit 24 hex digits");
+            writer.write_all(b"{\"$oid\":\"")?;
+            writer.write_all(&hex)?;
+            writer.write_all(b"\"}")?;
+        }
+        Bson::DateTime(date) => {
+            writer.write_all(b"{\"$date\":{\"$numberLong\":\"")?;
+            scalar(writer, &date.timestamp_millis())?;
+            writer.write_all(b"\"}}")?;
+        }
+        other => other.serialize(&mut serde_json::Serializer::new(writer))?,
+    }
+    Ok(())
+}
+
+/// A string or number written by serde_json, escaped and formatted exactly like the serde path does
+fn scalar<W: Write, T: Serialize + ?Sized>(writer: &mut W, value: &T) -> Result<(), DissectError> {
+    value.serialize(&mut serde_json::Serializer::new(writer))?;
+    Ok(())
+}
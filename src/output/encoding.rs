@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+
+/// Text encoding of the written files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TextEncoding {
+    /// UTF-8 without a byte order mark
+    Utf8,
+    /// UTF-8 starting with a byte order mark, as Excel and other Windows tools expect
+    Utf8Bom,
+    /// UTF-16 little endian with a byte order mark
+    Utf16le,
+    /// UTF-16 big endian with a byte order mark
+    Utf16be,
+}
+
+impl TextEncoding {
+    /// Name of the encoding in xml declarations
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => "UTF-8",
+            Self::Utf16le | Self::Utf16be => "UTF-16",
+        }
+    }
+
+    /// Wrap a writer so the UTF-8 written to it comes out in this encoding
+    pub(crate) fn writer<W: Write>(self, inner: W) -> Transcoder<W> {
+        Transcoder {
+            inner,
+            encoding: self,
+            started: false,
+            pending: Vec::new(),
+        }
+    }
+}
+
+/// Re-encodes UTF-8 on its way to `inner`, sequences split between writes are held back until they are complete
+pub(crate) struct Transcoder<W: Write> {
+    inner: W,
+    encoding: TextEncoding,
+    started: bool,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Transcoder<W> {
+    pub fn into_inner(self) -> io::Result<W> {
+        if self.pending.is_empty() {
+            Ok(self.inner)
+        } else {
+            Err(invalid_utf8())
+        }
+    }
+}
+
+impl<W: Write> Write for Transcoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.started {
+            self.started = true;
+            match self.encoding {
+                TextEncoding::Utf8 => {}
+                TextEncoding::Utf8Bom => self.inner.write_all(b"\xEF\xBB\xBF")?,
+                TextEncoding::Utf16le => self.inner.write_all(&[0xFF, 0xFE])?,
+                TextEncoding::Utf16be => self.inner.write_all(&[0xFE, 0xFF])?,
+            }
+        }
+        if matches!(self.encoding, TextEncoding::Utf8 | TextEncoding::Utf8Bom) {
+            return self.inner.write(buf);
+        }
+
+        self.pending.extend_from_slice(buf);
+        let text = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text,
+            // an incomplete sequence at the end waits for the rest of it
+            Err(e) if e.error_len().is_none() => {
+                std::str::from_utf8(&self.pending[..e.valid_up_to()]).expect("checked prefix")
+            }
+            Err(_) => return Err(invalid_utf8()),
+        };
+        let mut encoded = Vec::with_capacity(text.len() * 2);
+        for unit in text.encode_utf16() {
+            encoded.extend_from_slice(&match self.encoding {
+                TextEncoding::Utf16be => unit.to_be_bytes(),
+                _ => unit.to_le_bytes(),
+            });
+        }
+        let used = text.len();
+        self.inner.write_all(&encoded)?;
+        self.pending.drain(..used);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "output is not valid UTF-8")
+}
@@ -0,0 +1,102 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use age::{secrecy::SecretString, x25519, Encryptor};
+
+use crate::DissectError;
+
+/// Environment variable holding the passphrase for `--encrypt passphrase`
+const PASSPHRASE_VAR: &str = "DISSBSON_PASSPHRASE";
+
+/// How outputs are encrypted, given as `age:recipients.txt` or `passphrase`
+#[derive(Debug, Clone)]
+pub enum Encryption {
+    /// Encrypt to the age public keys listed in a file, one per line
+    Recipients(PathBuf),
+    /// Encrypt with the passphrase in `DISSBSON_PASSPHRASE`
+    Passphrase,
+}
+
+impl FromStr for Encryption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("age", path)) if !path.is_empty() => Ok(Self::Recipients(path.into())),
+            None if s == "passphrase" => Ok(Self::Passphrase),
+            _ => Err(format!(
+                "expected age:<recipients file> or passphrase, got {s}"
+            )),
+        }
+    }
+}
+
+impl Encryption {
+    /// Start an encrypted stream on `writer`
+    pub(crate) fn wrap<W: Write>(&self, writer: W) -> Result<Encrypted<W>, DissectError> {
+        let encryptor = match self {
+            Self::Recipients(path) => {
+                let recipients = std::fs::read_to_string(path)?
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| {
+                        x25519::Recipient::from_str(line).map_err(|e| {
+                            DissectError::Parse(format!("Invalid recipient {line}: {e}"))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Encryptor::with_recipients(recipients.iter().map(|r| r as _))
+                    .map_err(|e| DissectError::Unexpected(format!("Failed to encrypt: {e}")))?
+            }
+            Self::Passphrase => {
+                let passphrase = std::env::var(PASSPHRASE_VAR).map_err(|_| {
+                    DissectError::Parse(format!(
+                        "Set {PASSPHRASE_VAR} to encrypt with a passphrase"
+                    ))
+                })?;
+                Encryptor::with_user_passphrase(SecretString::from(passphrase))
+            }
+        };
+        Ok(Encrypted::Age(encryptor.wrap_output(writer)?))
+    }
+}
+
+/// A writer that encrypts what it is given, or passes it on as is
+pub(crate) enum Encrypted<W: Write> {
+    Plain(W),
+    Age(age::stream::StreamWriter<W>),
+}
+
+impl<W: Write> Encrypted<W> {
+    /// Write the last encrypted chunk and return the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Plain(writer) => Ok(writer),
+            Self::Age(stream) => {
+                let mut writer = stream.finish()?;
+                writer.flush()?;
+                Ok(writer)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Encrypted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Age(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Age(stream) => stream.flush(),
+        }
+    }
+}
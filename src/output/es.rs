@@ -0,0 +1,65 @@
+use std::io::Write;
+
+use bson::{Bson, Document};
+use serde_json::{json, Map, Value};
+
+use super::flat::plain;
+use crate::{docpath, DissectError};
+
+/// Options controlling the action lines of Elasticsearch bulk output
+#[derive(Debug, Clone)]
+pub(crate) struct EsOptions {
+    pub(crate) index: String,
+    /// Field the document id is taken from, ids are left to Elasticsearch when unset
+    pub(crate) id_field: Option<Vec<String>>,
+}
+
+/// Write the action line and the source line of a document for the `_bulk` API,
+/// `_id` is a metadata field in Elasticsearch so it is left out of the source
+pub(crate) fn write_document<W: Write>(
+    mut writer: W,
+    options: &EsOptions,
+    doc: &Document,
+) -> Result<(), DissectError> {
+    let mut action = json!({ "_index": options.index });
+    if let Some(id) = options.id_field.as_ref().and_then(|path| id_of(doc, path)) {
+        action["_id"] = Value::String(id);
+    }
+    serde_json::to_writer(&mut writer, &json!({ "index": action }))?;
+    writer.write_all(b"\n")?;
+    let source = doc
+        .iter()
+        .filter(|(key, _)| *key != "_id")
+        .map(|(key, value)| (key.clone(), source(value)))
+        .collect::<Map<_, _>>();
+    serde_json::to_writer(&mut writer, &source)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// A value as Elasticsearch maps it, dates as RFC 3339 strings and ObjectIds as hex rather than extended json
+fn source(value: &Bson) -> Value {
+    match value {
+        Bson::Document(doc) => Value::Object(
+            doc.iter()
+                .map(|(key, value)| (key.clone(), source(value)))
+                .collect(),
+        ),
+        Bson::Array(items) => Value::Array(items.iter().map(source).collect()),
+        other => plain(other),
+    }
+}
+
+/// The id of a document as Elasticsearch takes it, ObjectIds as their hex string
+fn id_of(doc: &Document, path: &[String]) -> Option<String> {
+    let mut id = None;
+    docpath::visit(doc, path, &mut |value| {
+        if id.is_none() && !matches!(value, Bson::Null | Bson::Array(_) | Bson::Document(_)) {
+            id = Some(match plain(value) {
+                Value::String(s) => s,
+                other => other.to_string(),
+            });
+        }
+    });
+    id
+}
@@ -0,0 +1,54 @@
+use bson::{Bson, Document};
+use serde_json::{Map, Value};
+
+/// Flatten nested documents into one level of dotted keys like `address.city` with plain json values,
+/// the shape tables and spreadsheets expect
+pub(crate) fn flatten(doc: &Document) -> Map<String, Value> {
+    let mut row = Map::new();
+    flatten_into(&mut row, "", doc);
+    row
+}
+
+fn flatten_into(row: &mut Map<String, Value>, prefix: &str, doc: &Document) {
+    for (key, value) in doc {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            Bson::Document(inner) => flatten_into(row, &path, inner),
+            value => {
+                row.insert(path, plain(value));
+            }
+        }
+    }
+}
+
+/// A bson value as the json a column of its type takes, arrays holding documents become json text
+pub(crate) fn plain(value: &Bson) -> Value {
+    match value {
+        Bson::Null | Bson::Undefined => Value::Null,
+        Bson::Boolean(b) => Value::Bool(*b),
+        Bson::Int32(n) => Value::from(*n),
+        Bson::Int64(n) => Value::from(*n),
+        Bson::Double(n) => Value::from(*n),
+        Bson::String(s) => Value::String(s.clone()),
+        Bson::ObjectId(oid) => Value::String(oid.to_hex()),
+        Bson::DateTime(date) => Value::String(
+            date.try_to_rfc3339_string()
+                .unwrap_or_else(|_| date.timestamp_millis().to_string()),
+        ),
+        Bson::Decimal128(d) => Value::String(d.to_string()),
+        Bson::Binary(binary) => Value::String(hex::encode(&binary.bytes)),
+        Bson::Array(items)
+            if items
+                .iter()
+                .any(|i| matches!(i, Bson::Document(_) | Bson::Array(_))) =>
+        {
+            Value::String(value.clone().into_relaxed_extjson().to_string())
+        }
+        Bson::Array(items) => Value::Array(items.iter().map(plain).collect()),
+        other => Value::String(other.clone().into_relaxed_extjson().to_string()),
+    }
+}
@@ -0,0 +1,175 @@
+use std::io::{self, Write};
+
+use bson::{Bson, Document};
+use serde_json::Value;
+
+use super::{
+    encoding::TextEncoding,
+    flat::{flatten, plain},
+    xml,
+};
+
+/// Label every node of a Cypher export carries, so references can be matched without knowing the collection
+const NODE_LABEL: &str = "Document";
+
+/// A reference from a document to another one, named after the field holding it
+struct Edge {
+    field: String,
+    target: String,
+}
+
+/// The node id of a document, `fallback` for documents without an `_id`
+fn node_id(doc: &Document, fallback: usize) -> String {
+    doc.get("_id")
+        .map(id_text)
+        .unwrap_or_else(|| format!("#{fallback}"))
+}
+
+fn id_text(id: &Bson) -> String {
+    match plain(id) {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Every ObjectId and DBRef in a document apart from its own `_id`
+fn references(doc: &Document) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    for (key, value) in doc {
+        if key != "_id" {
+            collect(value, key, &mut edges);
+        }
+    }
+    edges
+}
+
+fn collect(value: &Bson, path: &str, edges: &mut Vec<Edge>) {
+    match value {
+        Bson::ObjectId(oid) => edges.push(Edge {
+            field: path.to_string(),
+            target: oid.to_hex(),
+        }),
+        Bson::Document(doc) => match (doc.get_str("$ref"), doc.get("$id")) {
+            (Ok(_), Some(id)) => edges.push(Edge {
+                field: path.to_string(),
+                target: id_text(id),
+            }),
+            _ => {
+                for (key, value) in doc {
+                    collect(value, &format!("{path}.{key}"), edges);
+                }
+            }
+        },
+        Bson::Array(items) => {
+            for item in items {
+                collect(item, path, edges);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub(super) fn begin_graphml<W: Write>(writer: &mut W, encoding: TextEncoding) -> io::Result<()> {
+    xml::write_declaration(writer, encoding)?;
+    writeln!(
+        writer,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    writeln!(
+        writer,
+        r#"<key id="document" for="node" attr.name="document" attr.type="string"/>"#
+    )?;
+    writeln!(
+        writer,
+        r#"<key id="field" for="edge" attr.name="field" attr.type="string"/>"#
+    )?;
+    writeln!(writer, r#"<graph id="documents" edgedefault="directed">"#)
+}
+
+/// Write the node of a document holding it as json and an edge per reference,
+/// edges may point at nodes written later or missing from the dump
+pub(super) fn write_graphml<W: Write>(
+    writer: &mut W,
+    doc: &Document,
+    nth: usize,
+) -> io::Result<()> {
+    let id = xml::escape(&node_id(doc, nth));
+    let json = Bson::Document(doc.clone()).into_relaxed_extjson();
+    writeln!(
+        writer,
+        r#"<node id="{id}"><data key="document">{}</data></node>"#,
+        xml::escape(&json.to_string())
+    )?;
+    for edge in references(doc) {
+        writeln!(
+            writer,
+            r#"<edge source="{id}" target="{}"><data key="field">{}</data></edge>"#,
+            xml::escape(&edge.target),
+            xml::escape(&edge.field)
+        )?;
+    }
+    Ok(())
+}
+
+pub(super) fn end_graphml<W: Write>(writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "</graph>\n</graphml>")
+}
+
+/// Write statements merging the node of a document, labelled with `label` besides [`NODE_LABEL`],
+/// and a relationship per reference, referenced nodes are merged by `_id` so the order of the input doesn't matter
+pub(super) fn write_cypher<W: Write>(
+    writer: &mut W,
+    doc: &Document,
+    label: &str,
+) -> io::Result<()> {
+    let properties = flatten(doc)
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(key, value)| format!("{}: {}", name(&key), literal(&value)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let Some(id) = doc.get("_id").map(id_text) else {
+        // nothing can reference a document without an id
+        return writeln!(
+            writer,
+            "CREATE (:{NODE_LABEL}:{} {{{properties}}});",
+            name(label)
+        );
+    };
+    let id = quote(&id);
+    writeln!(
+        writer,
+        "MERGE (n:{NODE_LABEL} {{_id: {id}}}) SET n:{}, n += {{{properties}}};",
+        name(label)
+    )?;
+    for edge in references(doc) {
+        writeln!(
+            writer,
+            "MERGE (a:{NODE_LABEL} {{_id: {id}}}) MERGE (b:{NODE_LABEL} {{_id: {}}}) MERGE (a)-[:{}]->(b);",
+            quote(&edge.target),
+            name(&edge.field)
+        )?;
+    }
+    Ok(())
+}
+
+/// A Cypher literal of a plain json value
+fn literal(value: &Value) -> String {
+    match value {
+        Value::String(s) => quote(s),
+        Value::Array(items) => format!(
+            "[{}]",
+            items.iter().map(literal).collect::<Vec<_>>().join(", ")
+        ),
+        other => other.to_string(),
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// A backquoted Cypher name, so dotted paths and any other field name can be used as keys and types
+fn name(text: &str) -> String {
+    format!("`{}`", text.replace('`', "``"))
+}
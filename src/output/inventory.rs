@@ -0,0 +1,99 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{
+    index::{DocOffset, Input},
+    manifest::with_suffix,
+    DissectError,
+};
+
+/// One output file of manifest.json
+#[derive(Serialize)]
+struct Entry<'a> {
+    /// Relative to the directory of manifest.json
+    file: String,
+    /// The input file and byte offset of the document, or of the first document of a file holding many
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<usize>,
+    documents: usize,
+    size: u64,
+    sha256: &'a str,
+}
+
+/// The manifest.json of an export listing every output file, where its documents come from, its size and sha256.
+/// Entries are appended to `manifest.json.partial` one json object a line as the files are finished, an interrupted
+/// export leaves the list of the files it completed there, `finish` turns it into the json array of manifest.json
+pub(crate) struct Inventory {
+    path: PathBuf,
+    /// The directory files are listed relative to
+    dir: PathBuf,
+    sources: Vec<String>,
+    partial: Mutex<File>,
+}
+
+impl Inventory {
+    pub fn create(path: PathBuf, input: &Input) -> Result<Self, DissectError> {
+        let partial = File::create(with_suffix(&path, ".partial"))?;
+        Ok(Self {
+            dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            path,
+            sources: input
+                .files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect(),
+            partial: Mutex::new(partial),
+        })
+    }
+
+    /// Add a finished file holding `documents` documents, the first of them at `first` in the input
+    pub fn add(
+        &self,
+        file: &Path,
+        first: Option<&DocOffset>,
+        documents: usize,
+        size: u64,
+        sha256: &str,
+    ) -> Result<(), DissectError> {
+        let entry = Entry {
+            file: file
+                .strip_prefix(&self.dir)
+                .unwrap_or(file)
+                .display()
+                .to_string(),
+            source: first.map(|first| self.sources[first.source].as_str()),
+            offset: first.map(|first| first.offset),
+            documents,
+            size,
+            sha256,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        // a line at a time so the partial list stays whole whenever the export stops
+        self.partial.lock().write_all(&line)?;
+        Ok(())
+    }
+
+    /// Write manifest.json from the partial list and remove it, returns the path of manifest.json
+    pub fn finish(self) -> Result<PathBuf, DissectError> {
+        let partial = with_suffix(&self.path, ".partial");
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        writer.write_all(b"[")?;
+        for (nth, line) in BufReader::new(File::open(&partial)?).lines().enumerate() {
+            writer.write_all(if nth == 0 { b"\n  " } else { b",\n  " })?;
+            writer.write_all(line?.as_bytes())?;
+        }
+        writer.write_all(b"\n]\n")?;
+        writer.flush()?;
+        fs::remove_file(partial)?;
+        Ok(self.path)
+    }
+}
@@ -0,0 +1,508 @@
+use std::{borrow::Cow, io::Write, path::Path, sync::Arc};
+
+use bson::{Bson, Document};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{docpath, Args, DissectError};
+
+pub(crate) mod archive;
+pub(crate) mod checksum;
+pub(crate) mod compress;
+pub(crate) mod dialect;
+pub(crate) mod direct;
+pub(crate) mod encoding;
+pub(crate) mod encrypt;
+pub(crate) mod es;
+pub(crate) mod flat;
+mod graph;
+pub(crate) mod inventory;
+pub(crate) mod naming;
+pub(crate) mod notice;
+pub(crate) mod partition;
+pub(crate) mod pool;
+pub(crate) mod postgres;
+mod pretty;
+pub(crate) mod shard;
+pub(crate) mod sqlite;
+pub(crate) mod store;
+pub(crate) mod table;
+pub(crate) mod verify;
+mod xml;
+
+use dialect::CsvDialect;
+use direct::JsonWriter;
+use encoding::{TextEncoding, Transcoder};
+use es::EsOptions;
+use table::Schema;
+use verify::Written;
+
+/// Supported output formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One json document per file or a json array with --single
+    Json,
+    /// One json document per line, written to a single file with --single
+    Ndjson,
+    /// One xml document per file or a single xml tree with --single
+    Xml,
+    /// One csv row per document with nested fields flattened into dotted columns, picked from the first
+    /// --schema-sample documents or given with --csv-columns, every file starts with a header
+    Csv,
+    /// Raw bson, one document per file or a stream like a mongodump file with --single,
+    /// ready for mongorestore or another dissbson
+    Bson,
+    /// One yaml document per file or a multi document yaml stream with --single
+    Yaml,
+    /// Action and document line pairs for the Elasticsearch _bulk API, into --es-index with ids from --es-id-field,
+    /// a bulk request per file or one for the whole dump with --single
+    EsBulk,
+    /// Lines of _id and the document as jsonb for PostgreSQL `COPY ... FROM`, with --single and --pg-ddl
+    /// a psql script creating the --table and loading the file is written next to it
+    PgCopy,
+    /// A SQLite database file with the _id and json of every document in the --table table,
+    /// and the --promote fields as indexed columns of their own
+    Sqlite,
+    /// A DuckDB database file with the flattened documents in the --table table
+    Duckdb,
+    /// A parquet file with the flattened documents, columns picked from the first --schema-sample documents
+    Parquet,
+    /// An Arrow IPC stream with a record batch of the flattened documents per --batch,
+    /// columns picked like parquet
+    Arrow,
+    /// A Feather v2 file, the Arrow IPC file format, with a record batch per --batch
+    Feather,
+    /// A GraphML graph with a node per document and an edge per ObjectId or DBRef it holds, needs --single
+    Graphml,
+    /// Cypher statements merging a node per document, labelled with --table, and a relationship per reference,
+    /// needs --single
+    Cypher,
+}
+
+impl OutputFormat {
+    /// Whether the output is a database file rather than encoded documents
+    pub fn is_database(self) -> bool {
+        matches!(
+            self,
+            Self::Sqlite | Self::Duckdb | Self::Parquet | Self::Arrow | Self::Feather
+        )
+    }
+
+    /// Whether the output is a graph of all documents, which only exists as a single file
+    pub fn is_graph(self) -> bool {
+        matches!(self, Self::Graphml | Self::Cypher)
+    }
+
+    /// The format a file extension stands for
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Some(match extension.to_ascii_lowercase().as_str() {
+            "json" => Self::Json,
+            "ndjson" | "jsonl" => Self::Ndjson,
+            "xml" => Self::Xml,
+            "csv" => Self::Csv,
+            "bson" => Self::Bson,
+            "yaml" | "yml" => Self::Yaml,
+            "bulk" => Self::EsBulk,
+            "copy" => Self::PgCopy,
+            "sqlite" | "sqlite3" => Self::Sqlite,
+            "duckdb" => Self::Duckdb,
+            "parquet" => Self::Parquet,
+            "arrow" | "arrows" => Self::Arrow,
+            "feather" => Self::Feather,
+            "graphml" => Self::Graphml,
+            "cypher" | "cql" => Self::Cypher,
+            _ => return None,
+        })
+    }
+
+    /// The format the extension of an output names, looking past a compression suffix like `dump.ndjson.gz`
+    pub fn of_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let name = [".gz", ".zst"]
+            .iter()
+            .find_map(|suffix| name.strip_suffix(suffix))
+            .unwrap_or(name);
+        Self::from_extension(Path::new(name).extension()?.to_str()?)
+    }
+}
+
+/// Options controlling how documents are mapped to xml
+#[derive(Debug, Clone)]
+pub(crate) struct XmlOptions {
+    pub(crate) root: String,
+    pub(crate) element: String,
+    pub(crate) attributes: bool,
+}
+
+/// Encodes documents in the selected output format
+#[derive(Debug, Clone)]
+pub(crate) struct Encoder {
+    pub(crate) format: OutputFormat,
+    pub(crate) pretty: bool,
+    pub(crate) xml: XmlOptions,
+    pub(crate) es: EsOptions,
+    pub(crate) encoding: TextEncoding,
+    /// Node label of graph formats
+    pub(crate) label: String,
+    pub(crate) csv: CsvDialect,
+    /// Columns of csv output, taken from the first document written when unset
+    pub(crate) columns: Option<Arc<Schema>>,
+    /// Write the fields of every document in name order
+    pub(crate) sort_keys: bool,
+    /// Put every document of a json array on a line of its own, see `dissbson finalize`
+    pub(crate) array_safe: bool,
+    /// Writes compact json and ndjson
+    pub(crate) json_writer: JsonWriter,
+}
+
+impl Encoder {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            format: args.output_format(),
+            pretty: args.pretty,
+            xml: XmlOptions {
+                root: args.xml_root.clone(),
+                element: args.xml_element.clone(),
+                attributes: args.xml_attributes,
+            },
+            es: EsOptions {
+                index: args.es_index.clone().unwrap_or_else(|| {
+                    args.input
+                        .as_ref()
+                        .and_then(|input| input.file_stem())
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| args.table.clone())
+                }),
+                id_field: (!args.es_auto_id).then(|| docpath::parse(&args.es_id_field)),
+            },
+            encoding: args.encoding,
+            label: args.table.clone(),
+            csv: args.csv.clone(),
+            columns: (!args.csv_columns.is_empty())
+                .then(|| Arc::new(Schema::named(&args.csv_columns))),
+            sort_keys: args.sort_keys,
+            array_safe: args.array_safe,
+            json_writer: args.json_writer,
+        }
+    }
+
+    /// File extension used for files written in this format
+    pub fn extension(&self) -> &'static str {
+        match self.format {
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::EsBulk => "bulk",
+            OutputFormat::PgCopy => "copy",
+            OutputFormat::Bson => "bson",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Duckdb => "duckdb",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
+            OutputFormat::Feather => "feather",
+            OutputFormat::Graphml => "graphml",
+            OutputFormat::Cypher => "cypher",
+        }
+    }
+
+    /// Write a single document as a standalone file
+    pub fn encode<W: Write>(&self, writer: W, doc: &Document) -> Result<(), DissectError> {
+        let mut writer = self.encoding.writer(writer);
+        let doc = &*self.ordered(doc);
+        match self.format {
+            OutputFormat::Json => {
+                if self.pretty {
+                    let mut ser =
+                        serde_json::Serializer::with_formatter(writer, pretty::Pretty::default());
+                    doc.serialize(&mut ser)?;
+                } else {
+                    self.write_json(&mut writer, doc)?;
+                }
+            }
+            OutputFormat::Ndjson => {
+                self.write_json(&mut writer, doc)?;
+                writer.write_all(b"\n")?;
+            }
+            OutputFormat::Xml => {
+                xml::write_declaration(&mut writer, self.encoding)?;
+                xml::write_document(&mut writer, &self.xml, doc, self.indent(0))?;
+            }
+            OutputFormat::Csv => {
+                let columns = self.columns_for(doc);
+                let mut csv = self.csv.writer(writer);
+                self.csv
+                    .header(&mut csv, columns.columns.iter().map(|c| &c.name))?;
+                csv.write_record(self.csv.row(columns.row(doc)))?;
+                csv.flush()?;
+            }
+            OutputFormat::Yaml => serde_yaml::to_writer(writer, doc)?,
+            OutputFormat::EsBulk => es::write_document(writer, &self.es, doc)?,
+            OutputFormat::PgCopy => postgres::copy_document(&mut writer, doc)?,
+            OutputFormat::Bson => doc.to_writer(writer)?,
+            OutputFormat::Sqlite
+            | OutputFormat::Duckdb
+            | OutputFormat::Parquet
+            | OutputFormat::Arrow
+            | OutputFormat::Feather
+            | OutputFormat::Graphml
+            | OutputFormat::Cypher => return Err(not_a_document(self.format)),
+        }
+        Ok(())
+    }
+
+    /// Create a writer that places many documents in a single output
+    pub fn stream<W: Write>(&self, writer: W) -> StreamWriter<W> {
+        StreamWriter {
+            encoder: self.clone(),
+            writer: Counting {
+                inner: self.encoding.writer(writer),
+                written: 0,
+            },
+            count: 0,
+            buf: Vec::new(),
+            track: None,
+        }
+    }
+
+    /// Write a document as compact json with the --json-writer
+    fn write_json<W: Write>(&self, writer: &mut W, doc: &Document) -> Result<(), DissectError> {
+        match self.json_writer {
+            JsonWriter::Serde => doc.serialize(&mut serde_json::Serializer::new(writer))?,
+            JsonWriter::Direct => direct::write_document(writer, doc)?,
+        }
+        Ok(())
+    }
+
+    fn columns_for(&self, doc: &Document) -> Arc<Schema> {
+        self.columns
+            .clone()
+            .unwrap_or_else(|| Arc::new(Schema::of(doc)))
+    }
+
+    fn indent(&self, depth: usize) -> Option<usize> {
+        self.pretty.then_some(depth)
+    }
+
+    /// The document as it is written, with its fields sorted when asked to
+    fn ordered<'a>(&self, doc: &'a Document) -> Cow<'a, Document> {
+        if self.sort_keys {
+            Cow::Owned(sort_keys(doc))
+        } else {
+            Cow::Borrowed(doc)
+        }
+    }
+}
+
+/// The document with the fields at every depth in name order, `_id` first,
+/// so documents built by different code paths line up in a diff
+fn sort_keys(doc: &Document) -> Document {
+    let mut fields = doc.iter().collect::<Vec<_>>();
+    fields.sort_by(|(a, _), (b, _)| (*a != "_id", *a).cmp(&(*b != "_id", *b)));
+    fields
+        .into_iter()
+        .map(|(key, value)| (key.clone(), sort_value(value)))
+        .collect()
+}
+
+fn sort_value(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(doc) => Bson::Document(sort_keys(doc)),
+        Bson::Array(items) => Bson::Array(items.iter().map(sort_value).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Writes a sequence of documents into a single output,
+/// the output is only complete once [`StreamWriter::finish`] is called
+pub(crate) struct StreamWriter<W: Write> {
+    encoder: Encoder,
+    writer: Counting<Transcoder<W>>,
+    count: usize,
+    /// The element being written, encoded before it is passed on so it can be checksummed
+    buf: Vec<u8>,
+    track: Option<Vec<Written>>,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Record where every document ends up and its checksum, see [`StreamWriter::take_written`]
+    pub fn track(mut self) -> Self {
+        self.track = Some(Vec::new());
+        self
+    }
+
+    pub fn write(&mut self, doc: &Document) -> Result<(), DissectError> {
+        let doc = &*self.encoder.ordered(doc);
+        if self.count == 0 {
+            if self.encoder.format == OutputFormat::Csv {
+                self.encoder.columns = Some(self.encoder.columns_for(doc));
+            }
+            self.begin()?;
+        }
+        self.buf.clear();
+        match self.encoder.format {
+            OutputFormat::Json => {
+                if self.count > 0 {
+                    self.writer.write_all(b",")?;
+                }
+                self.encoder.write_json(&mut self.buf, doc)?;
+            }
+            OutputFormat::Ndjson => {
+                self.encoder.write_json(&mut self.buf, doc)?;
+                self.buf.push(b'\n');
+            }
+            OutputFormat::Xml => {
+                let indent = self.encoder.indent(1);
+                xml::write_document(&mut self.buf, &self.encoder.xml, doc, indent)?;
+            }
+            OutputFormat::Csv => {
+                let columns = self.encoder.columns_for(doc);
+                let mut csv = self.encoder.csv.writer(&mut self.buf);
+                csv.write_record(self.encoder.csv.row(columns.row(doc)))?;
+                csv.flush()?;
+            }
+            OutputFormat::Yaml => {
+                self.writer.write_all(b"---\n")?;
+                serde_yaml::to_writer(&mut self.buf, doc)?;
+            }
+            OutputFormat::Bson => doc.to_writer(&mut self.buf)?,
+            OutputFormat::EsBulk => es::write_document(&mut self.buf, &self.encoder.es, doc)?,
+            OutputFormat::PgCopy => postgres::copy_document(&mut self.buf, doc)?,
+            OutputFormat::Graphml => graph::write_graphml(&mut self.buf, doc, self.count)?,
+            OutputFormat::Cypher => graph::write_cypher(&mut self.buf, doc, &self.encoder.label)?,
+            OutputFormat::Sqlite
+            | OutputFormat::Duckdb
+            | OutputFormat::Parquet
+            | OutputFormat::Arrow
+            | OutputFormat::Feather => return Err(not_a_document(self.encoder.format)),
+        }
+        if let Some(track) = &mut self.track {
+            track.push(Written::new(self.writer.written, &self.buf));
+        }
+        self.writer.write_all(&self.buf)?;
+        if self.encoder.array_safe {
+            // the line of a document only ends once all of it is written
+            self.writer.write_all(b"\n")?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Bytes written so far, before compression and encryption
+    pub fn written(&self) -> u64 {
+        self.writer.written
+    }
+
+    /// The encoder of the stream, with the csv columns taken from its first document
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// Pass everything written so far on to the output
+    pub fn flush(&mut self) -> Result<(), DissectError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Documents written since tracking started, in output order
+    pub fn take_written(&mut self) -> Vec<Written> {
+        self.track.take().unwrap_or_default()
+    }
+
+    pub fn finish(mut self) -> Result<W, DissectError> {
+        if self.count == 0 {
+            self.begin()?;
+        }
+        match self.encoder.format {
+            OutputFormat::Json => self.writer.write_all(b"]")?,
+            OutputFormat::Xml => writeln!(
+                self.writer,
+                "</{}>",
+                xml::element_name(&self.encoder.xml.root)
+            )?,
+            OutputFormat::Graphml => graph::end_graphml(&mut self.writer)?,
+            OutputFormat::Ndjson
+            | OutputFormat::Csv
+            | OutputFormat::Yaml
+            | OutputFormat::EsBulk
+            | OutputFormat::PgCopy
+            | OutputFormat::Bson
+            | OutputFormat::Sqlite
+            | OutputFormat::Duckdb
+            | OutputFormat::Parquet
+            | OutputFormat::Arrow
+            | OutputFormat::Feather
+            | OutputFormat::Cypher => {}
+        }
+        self.writer.flush()?;
+        Ok(self.writer.inner.into_inner()?)
+    }
+
+    fn begin(&mut self) -> Result<(), DissectError> {
+        match self.encoder.format {
+            OutputFormat::Json if self.encoder.array_safe => self.writer.write_all(b"[\n")?,
+            OutputFormat::Json => self.writer.write_all(b"[")?,
+            OutputFormat::Xml => {
+                xml::write_declaration(&mut self.writer, self.encoder.encoding)?;
+                writeln!(
+                    self.writer,
+                    "<{}>",
+                    xml::element_name(&self.encoder.xml.root)
+                )?;
+            }
+            OutputFormat::Csv => {
+                // without a document there is nothing to take the columns from
+                if let Some(columns) = &self.encoder.columns {
+                    let mut csv = self.encoder.csv.writer(&mut self.writer);
+                    self.encoder
+                        .csv
+                        .header(&mut csv, columns.columns.iter().map(|c| &c.name))?;
+                    csv.flush()?;
+                }
+            }
+            OutputFormat::Graphml => graph::begin_graphml(&mut self.writer, self.encoder.encoding)?,
+            OutputFormat::Ndjson
+            | OutputFormat::Yaml
+            | OutputFormat::EsBulk
+            | OutputFormat::PgCopy
+            | OutputFormat::Bson
+            | OutputFormat::Sqlite
+            | OutputFormat::Duckdb
+            | OutputFormat::Parquet
+            | OutputFormat::Arrow
+            | OutputFormat::Feather
+            | OutputFormat::Cypher => {}
+        }
+        Ok(())
+    }
+}
+
+fn not_a_document(format: OutputFormat) -> DissectError {
+    let kind = if format.is_graph() {
+        "a graph"
+    } else {
+        "a database"
+    };
+    DissectError::Parse(format!(
+        "{format:?} output is {kind}, documents can't be encoded in it one by one"
+    ))
+}
+
+/// Passes writes on while counting the bytes written
+struct Counting<W: Write> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
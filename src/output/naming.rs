@@ -0,0 +1,129 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::Document;
+use clap::ValueEnum;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+
+use crate::DissectError;
+
+/// How the files of documents are named
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Naming {
+    /// The batch and the place in it, like 12-345, documents read in another order get other names
+    Position,
+    /// The seahash of the document as bson, 16 hex digits
+    Seahash,
+    /// The sha256 of the document as bson, 64 hex digits
+    Sha256,
+}
+
+impl Naming {
+    /// The file name of a document without its extension
+    pub(crate) fn name(
+        self,
+        chunk: usize,
+        nth: usize,
+        doc: &Document,
+    ) -> Result<String, DissectError> {
+        Ok(match self {
+            Self::Position => format!("{chunk}-{nth}"),
+            Self::Seahash => format!("{:016x}", seahash::hash(&content(doc)?)),
+            Self::Sha256 => hex::encode(Sha256::digest(content(doc)?)),
+        })
+    }
+
+    /// Whether equal documents get the same name
+    pub fn by_content(self) -> bool {
+        self != Self::Position
+    }
+}
+
+/// The document after the transforms as bson, the same fields in the same order give the same name
+fn content(doc: &Document) -> Result<Vec<u8>, DissectError> {
+    let mut bytes = Vec::new();
+    doc.to_writer(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The names of the files written so far, a document named by its content whose name is taken already
+/// is a duplicate and needs no file of its own
+#[derive(Default)]
+pub(crate) struct Names {
+    /// Every name whole, see [`key`]
+    taken: Mutex<HashSet<Vec<u8>>>,
+    duplicates: AtomicUsize,
+}
+
+impl Names {
+    /// Take the name for a file, false when a file of the same name was written already
+    pub fn claim(&self, name: &str) -> bool {
+        let fresh = self.taken.lock().insert(key(name));
+        if !fresh {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+        }
+        fresh
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+}
+
+/// A name as it is kept: the hash of a content name as the bytes its hex digits spell, half their length,
+/// followed by the --partition-by directory it is in. Hashes of one kind have one length, so no two names share a key
+fn key(name: &str) -> Vec<u8> {
+    let (dir, file) = name.rsplit_once('/').unwrap_or(("", name));
+    match hex::decode(file) {
+        Ok(mut key) => {
+            key.extend_from_slice(dir.as_bytes());
+            key
+        }
+        Err(_) => name.as_bytes().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::{Names, Naming};
+
+    #[test]
+    fn names() {
+        let doc = doc! { "_id": 1, "name": "a" };
+        assert_eq!(Naming::Position.name(12, 345, &doc).unwrap(), "12-345");
+        let seahash = Naming::Seahash.name(0, 0, &doc).unwrap();
+        let sha256 = Naming::Sha256.name(0, 0, &doc).unwrap();
+        assert_eq!(seahash.len(), 16);
+        assert_eq!(sha256.len(), 64);
+        assert!(sha256.chars().all(|c| c.is_ascii_hexdigit()));
+        // the content names don't depend on where the document was read
+        assert_eq!(Naming::Seahash.name(3, 4, &doc).unwrap(), seahash);
+        assert_eq!(Naming::Sha256.name(3, 4, &doc).unwrap(), sha256);
+        // the same fields in another order are another document
+        let reordered = doc! { "name": "a", "_id": 1 };
+        assert_ne!(Naming::Sha256.name(0, 0, &reordered).unwrap(), sha256);
+        assert!(!Naming::Position.by_content());
+        assert!(Naming::Seahash.by_content() && Naming::Sha256.by_content());
+    }
+
+    #[test]
+    fn duplicates() {
+        let names = Names::default();
+        let sha256 = "ab".repeat(32);
+        assert!(names.claim(&sha256));
+        assert!(!names.claim(&sha256));
+        // a name differing in its last digit only is another document
+        assert!(names.claim(&format!("{}ac", "ab".repeat(31))));
+        // and so is the same content in another partition
+        assert!(names.claim(&format!("country=DE/{sha256}")));
+        assert!(names.claim(&format!("country=FR/{sha256}")));
+        assert!(!names.claim(&format!("country=DE/{sha256}")));
+        assert!(names.claim("0123456789abcdef"));
+        assert_eq!(names.duplicates(), 2);
+    }
+}
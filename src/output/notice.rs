@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use bson::{Bson, Document};
+
+use crate::DissectError;
+
+/// `_id` of the notice document unless the file gives it one, so readers can tell it from the data
+pub(crate) const NOTICE_ID: &str = "_notice";
+
+/// Load the notice document describing how an export may be used, written in json or yaml,
+/// extended json like `{"$date": ...}` is taken as such
+pub(crate) fn load(path: &Path) -> Result<Document, DissectError> {
+    let text = std::fs::read_to_string(path)?;
+    // yaml is a superset of json, one parser reads both
+    let value: serde_json::Value = serde_yaml::from_str(&text)?;
+    let mut notice = match Bson::try_from(value) {
+        Ok(Bson::Document(doc)) => doc,
+        Ok(other) => {
+            return Err(DissectError::Parse(format!(
+                "The notice in {} must be a document, got a {:?}",
+                path.display(),
+                other.element_type()
+            )))
+        }
+        Err(e) => {
+            return Err(DissectError::Parse(format!(
+                "Invalid extended json in {}: {e}",
+                path.display()
+            )))
+        }
+    };
+    if !notice.contains_key("_id") {
+        let mut with_id = Document::new();
+        with_id.insert("_id", NOTICE_ID);
+        with_id.extend(notice);
+        notice = with_id;
+    }
+    Ok(notice)
+}
@@ -0,0 +1,117 @@
+use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+use bson::{Bson, DateTime, Document};
+use parking_lot::Mutex;
+
+use crate::{docpath, stats::timeline::Unit, DissectError};
+
+/// Directory of the documents without a value at the partition field, named like Hive and Spark name it
+const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// A field documents are grouped into directories by, written `type`, or `created_at:month` to group dates
+/// by the minute, hour, day, week, month or year they fall in
+#[derive(Debug, Clone)]
+pub struct PartitionSpec {
+    pub(crate) field: String,
+    path: Vec<String>,
+    unit: Option<Unit>,
+}
+
+impl FromStr for PartitionSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, unit) = match s.rsplit_once(':') {
+            Some((field, unit)) => (field, Some(unit.parse()?)),
+            None => (s, None),
+        };
+        if field.is_empty() {
+            return Err(format!(
+                "expected a field like type or created_at:month, got {s}"
+            ));
+        }
+        Ok(Self {
+            field: field.into(),
+            path: docpath::parse(field),
+            unit,
+        })
+    }
+}
+
+impl PartitionSpec {
+    /// The directory of a document, `field=value` the way Hive lays out partitions
+    pub fn dir(&self, doc: &Document) -> String {
+        let mut value = None;
+        docpath::visit(doc, &self.path, &mut |found| {
+            value.get_or_insert_with(|| self.value(found));
+        });
+        let value = value.flatten().filter(|value| !value.is_empty());
+        format!(
+            "{}={}",
+            escape(&self.field),
+            value.as_deref().map_or(DEFAULT_PARTITION.into(), escape)
+        )
+    }
+
+    fn value(&self, value: &Bson) -> Option<String> {
+        let date = |date: DateTime| match self.unit {
+            Some(unit) => unit.label(unit.floor(date.timestamp_millis())),
+            None => date
+                .try_to_rfc3339_string()
+                .unwrap_or_else(|_| date.timestamp_millis().to_string()),
+        };
+        Some(match value {
+            Bson::Null | Bson::Undefined => return None,
+            Bson::DateTime(value) => date(*value),
+            // dates stored as text are bucketed too when they read as one
+            Bson::String(s) => match DateTime::parse_rfc3339_str(s) {
+                Ok(value) if self.unit.is_some() => date(value),
+                _ => s.clone(),
+            },
+            Bson::ObjectId(oid) => oid.to_hex(),
+            other => other.to_string(),
+        })
+    }
+}
+
+/// A partition value as a directory name, the characters Hive escapes are written as %XX
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_control() || "\"#%'*/:=?\\{[]^".contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    // the names that already mean something in a path
+    match escaped.as_str() {
+        "." => "%2E".into(),
+        ".." => "%2E%2E".into(),
+        _ => escaped,
+    }
+}
+
+/// The partitions an export wrote documents to
+#[derive(Default)]
+pub(crate) struct Partitions {
+    seen: Mutex<HashSet<String>>,
+}
+
+impl Partitions {
+    /// Note a document going to the partition `dir`, its directory is created in `out_dir` with the first one
+    pub fn enter(&self, dir: &str, out_dir: Option<&Path>) -> Result<(), DissectError> {
+        if self.seen.lock().contains(dir) {
+            return Ok(());
+        }
+        if let Some(out_dir) = out_dir {
+            fs::create_dir_all(out_dir.join(dir))?;
+        }
+        self.seen.lock().insert(dir.to_string());
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.seen.lock().len()
+    }
+}
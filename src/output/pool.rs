@@ -0,0 +1,59 @@
+use std::{sync::mpsc::sync_channel, thread};
+
+use parking_lot::Mutex;
+
+use crate::DissectError;
+
+/// Run `produce` with a function that hands items to the write stage, which runs `write` for every item
+///
+/// with `threads` set to 0 items are written inline by the producing thread, otherwise `threads` writer threads
+/// consume them from a queue holding at most `queue` items so producers block instead of piling up documents in RAM
+pub(crate) fn run<T, P, W>(
+    threads: usize,
+    queue: usize,
+    produce: P,
+    write: W,
+) -> Result<(), DissectError>
+where
+    T: Send,
+    P: FnOnce(&(dyn Fn(T) -> Result<(), DissectError> + Sync)),
+    W: Fn(T) -> Result<(), DissectError> + Sync,
+{
+    if threads == 0 {
+        produce(&write);
+        return Ok(());
+    }
+
+    let (sender, receiver) = sync_channel::<T>(queue.max(1));
+    let receiver = Mutex::new(receiver);
+    let failure = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let Ok(item) = receiver.lock().recv() else {
+                    return;
+                };
+                // keep draining after a failure so producers never block on a full queue
+                if failure.lock().is_some() {
+                    continue;
+                }
+                if let Err(e) = write(item) {
+                    failure.lock().get_or_insert(e);
+                }
+            });
+        }
+
+        produce(&|item| {
+            sender
+                .send(item)
+                .map_err(|_| DissectError::Unexpected("Write stage stopped".into()))
+        });
+        drop(sender);
+    });
+
+    match failure.into_inner() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
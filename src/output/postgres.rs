@@ -0,0 +1,281 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use bson::{Bson, DateTime, Document};
+use serde_json::Value;
+
+use super::{
+    flat::plain,
+    table::{quote, Cell, ColumnType, Keys, Schema},
+};
+
+/// Write the statement creating `table` with a column for every column of `schema`,
+/// foreign keys are added by [`foreign_keys`] once every table is loaded
+pub(crate) fn create_table<W: Write>(
+    out: &mut W,
+    table: &str,
+    schema: &Schema,
+    keys: &Keys,
+) -> io::Result<()> {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| {
+            let key = if keys.primary.as_ref() == Some(&column.name) {
+                " PRIMARY KEY"
+            } else {
+                ""
+            };
+            format!("  {} {}{key}", quote(&column.name), sql_type(column.kind))
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+    writeln!(out, "CREATE TABLE {} (\n{columns}\n);", quote(table))
+}
+
+/// Start a `COPY ... FROM stdin` block, rows follow with [`copy_rows`] and it ends with [`end_copy`]
+pub(crate) fn begin_copy<W: Write>(out: &mut W, table: &str, schema: &Schema) -> io::Result<()> {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| quote(&column.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(out, "COPY {} ({columns}) FROM stdin;", quote(table))
+}
+
+/// Write rows in the text format of COPY
+pub(crate) fn copy_rows<W: Write>(out: &mut W, rows: Vec<Vec<Cell>>) -> io::Result<()> {
+    for row in rows {
+        let line = row
+            .into_iter()
+            .map(copy_text)
+            .collect::<Vec<_>>()
+            .join("\t");
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+pub(crate) fn end_copy<W: Write>(out: &mut W) -> io::Result<()> {
+    writeln!(out, "\\.")
+}
+
+/// Write a document as a line of `_id` and `doc` columns for `COPY ... FROM`, the document as relaxed extended json
+pub(crate) fn copy_document<W: Write>(out: &mut W, doc: &Document) -> io::Result<()> {
+    let id = match doc.get("_id") {
+        None | Some(Bson::Null) => Cell::Null,
+        Some(id) => Cell::Text(match plain(id) {
+            Value::String(s) => s,
+            other => other.to_string(),
+        }),
+    };
+    let mut json = Bson::Document(doc.clone()).into_relaxed_extjson();
+    without_nul(&mut json);
+    copy_rows(out, vec![vec![id, Cell::Text(json.to_string())]])
+}
+
+/// jsonb can't hold the NUL character, it is replaced by U+FFFD in keys and strings
+fn without_nul(value: &mut Value) {
+    let clean = |s: &str| s.replace('\0', "\u{fffd}");
+    match value {
+        Value::String(s) if s.contains('\0') => *s = clean(s),
+        Value::Array(items) => items.iter_mut().for_each(without_nul),
+        Value::Object(fields) if fields.keys().any(|k| k.contains('\0')) => {
+            *fields = std::mem::take(fields)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    without_nul(&mut value);
+                    (clean(&key), value)
+                })
+                .collect();
+        }
+        Value::Object(fields) => fields.values_mut().for_each(without_nul),
+        _ => {}
+    }
+}
+
+/// Write a psql script creating `table` with the columns of [`copy_document`] and loading `data` into it,
+/// the primary key is added after the load so duplicate ids fail it without losing the rows
+pub(crate) fn copy_script<W: Write>(out: &mut W, table: &str, data: &Path) -> io::Result<()> {
+    let table = quote(table);
+    writeln!(
+        out,
+        "CREATE TABLE {table} (\n  \"_id\" TEXT,\n  \"doc\" JSONB NOT NULL\n);"
+    )?;
+    writeln!(
+        out,
+        "\\copy {table} (\"_id\", \"doc\") FROM '{}'",
+        data.display().to_string().replace('\'', "''")
+    )?;
+    writeln!(out, "ALTER TABLE {table} ADD PRIMARY KEY (\"_id\");")
+}
+
+/// Undo the backslash escapes of the COPY text format
+pub(crate) fn unescape(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            plain.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => plain.push('\t'),
+            Some('n') => plain.push('\n'),
+            Some('r') => plain.push('\r'),
+            Some(other) => plain.push(other),
+            None => plain.push('\\'),
+        }
+    }
+    plain
+}
+
+/// Add the foreign keys of `table` without checking the rows already loaded,
+/// references into documents missing from the dump are common and would fail the whole load
+pub(crate) fn foreign_keys<W: Write>(out: &mut W, table: &str, keys: &Keys) -> io::Result<()> {
+    for (column, target) in &keys.foreign {
+        writeln!(
+            out,
+            "ALTER TABLE {} ADD FOREIGN KEY ({}) REFERENCES {} (\"_id\") NOT VALID;",
+            quote(table),
+            quote(column),
+            quote(target)
+        )?;
+    }
+    Ok(())
+}
+
+fn sql_type(kind: ColumnType) -> &'static str {
+    match kind {
+        ColumnType::Boolean => "BOOLEAN",
+        ColumnType::BigInt => "BIGINT",
+        ColumnType::Double => "DOUBLE PRECISION",
+        ColumnType::Timestamp => "TIMESTAMPTZ",
+        ColumnType::Text => "TEXT",
+        ColumnType::Json => "JSONB",
+    }
+}
+
+fn copy_text(cell: Cell) -> String {
+    match cell {
+        Cell::Null => "\\N".to_string(),
+        Cell::Boolean(b) => if b { "t" } else { "f" }.to_string(),
+        Cell::BigInt(n) => n.to_string(),
+        Cell::Double(n) if n.is_nan() => "NaN".to_string(),
+        Cell::Double(n) if n.is_infinite() => {
+            if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+        }
+        Cell::Double(n) => n.to_string(),
+        Cell::Timestamp(millis) => DateTime::from_millis(millis)
+            .try_to_rfc3339_string()
+            .unwrap_or_else(|_| millis.to_string()),
+        Cell::Text(s) => escape(&s),
+    }
+}
+
+/// Backslash escapes of the COPY text format
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            // text columns can't hold the NUL character either
+            '\0' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Document};
+
+    use super::{
+        begin_copy, copy_document, copy_rows, create_table, end_copy, escape, foreign_keys,
+        unescape,
+    };
+    use crate::output::table::{Cell, Keys, Schema};
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).expect("writing to a vec");
+        String::from_utf8(out).expect("utf-8")
+    }
+
+    #[test]
+    fn text_escapes_round_trip() {
+        for text in [
+            "plain",
+            "tab\tline\nreturn\r",
+            "back\\slash",
+            "\\.",
+            "\\N",
+            "",
+        ] {
+            assert_eq!(unescape(&escape(text)), text);
+        }
+        assert_eq!(escape("a\tb\nc\\d\re"), "a\\tb\\nc\\\\d\\re");
+        // a lone backslash at the end stays as it is
+        assert_eq!(unescape("end\\"), "end\\");
+        assert_eq!(escape("nul\0"), "nul\u{fffd}");
+    }
+
+    #[test]
+    fn rows() {
+        let rows = vec![vec![
+            Cell::Null,
+            Cell::Boolean(true),
+            Cell::BigInt(-3),
+            Cell::Double(f64::NAN),
+            Cell::Double(f64::NEG_INFINITY),
+            Cell::Double(1.5),
+            Cell::Timestamp(0),
+            Cell::Text("\\N\tx".into()),
+        ]];
+        assert_eq!(
+            written(|out| copy_rows(out, rows)),
+            "\\N\tt\t-3\tNaN\t-Infinity\t1.5\t1970-01-01T00:00:00Z\t\\\\N\\tx\n"
+        );
+    }
+
+    #[test]
+    fn documents() {
+        let doc = doc! { "_id": 7, "note": "two\nlines", "k\0ey": "n\0ul" };
+        assert_eq!(
+            written(|out| copy_document(out, &doc)),
+            "7\t{\"_id\":7,\"note\":\"two\\\\nlines\",\"k\u{fffd}ey\":\"n\u{fffd}ul\"}\n"
+        );
+        assert_eq!(
+            written(|out| copy_document(out, &Document::new())),
+            "\\N\t{}\n"
+        );
+    }
+
+    #[test]
+    fn statements_quote_names() {
+        let schema = Schema::of(&doc! { "_id": 1, "owner": 2, "say \"hi\"": "x" });
+        let keys = Keys {
+            primary: Some("_id".into()),
+            foreign: vec![("owner".into(), "user\"s".into())],
+        };
+        let script = written(|out| {
+            create_table(out, "my \"table\"", &schema, &keys)?;
+            begin_copy(out, "my \"table\"", &schema)?;
+            end_copy(out)?;
+            foreign_keys(out, "my \"table\"", &keys)
+        });
+        assert_eq!(
+            script,
+            "CREATE TABLE \"my \"\"table\"\"\" (\n  \"_id\" BIGINT PRIMARY KEY,\n  \"owner\" BIGINT,\n  \
+             \"say \"\"hi\"\"\" TEXT\n);\nCOPY \"my \"\"table\"\"\" (\"_id\", \"owner\", \"say \"\"hi\"\"\") FROM stdin;\n\\.\n\
+             ALTER TABLE \"my \"\"table\"\"\" ADD FOREIGN KEY (\"owner\") REFERENCES \"user\"\"s\" (\"_id\") NOT VALID;\n"
+        );
+    }
+}
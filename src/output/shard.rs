@@ -0,0 +1,75 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::DissectError;
+
+/// The path of the nth file of a --single output split with --max-output-size, counting from 1,
+/// `out.json` becomes `out.0001.json` and `users.ndjson.gz` `users.0001.ndjson.gz`
+pub(crate) fn path(output: &Path, shard: usize) -> PathBuf {
+    let name = output
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    // the dot of a hidden file like `.out.json` is part of its stem
+    let dot = name.char_indices().skip(1).find(|&(_, c)| c == '.');
+    let name = match dot {
+        Some((at, _)) => format!("{}.{shard:04}.{}", &name[..at], &name[at + 1..]),
+        None => format!("{name}.{shard:04}"),
+    };
+    output.with_file_name(name)
+}
+
+/// Remove the shards an earlier, larger export of the same output left after the last one of this export,
+/// returns how many there were
+pub(crate) fn remove_stale(output: &Path, last: usize) -> Result<usize, DissectError> {
+    let mut removed = 0;
+    loop {
+        let stale = path(output, last + removed + 1);
+        if !stale.exists() {
+            return Ok(removed);
+        }
+        fs::remove_file(stale)?;
+        removed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{path, remove_stale};
+
+    #[test]
+    fn shard_names() {
+        let shard = |output: &str, nth| path(Path::new(output), nth);
+        assert_eq!(shard("out.json", 1), Path::new("out.0001.json"));
+        assert_eq!(
+            shard("users.ndjson.gz", 2),
+            Path::new("users.0002.ndjson.gz")
+        );
+        assert_eq!(shard("dir.d/out", 3), Path::new("dir.d/out.0003"));
+        assert_eq!(shard(".out.json", 4), Path::new(".out.0004.json"));
+        assert_eq!(shard(".out", 5), Path::new(".out.0005"));
+        assert_eq!(shard("out.json", 12345), Path::new("out.12345.json"));
+    }
+
+    #[test]
+    fn stale_shards() {
+        let dir = std::env::temp_dir().join(format!("dissbson-shard-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.json");
+        for nth in 1..=5 {
+            fs::write(path(&output, nth), "").unwrap();
+        }
+        // removing stops at the first missing shard, one after a gap is left
+        fs::write(path(&output, 7), "").unwrap();
+        assert_eq!(remove_stale(&output, 2).unwrap(), 3);
+        assert!(path(&output, 2).exists());
+        assert!(!path(&output, 3).exists() && !path(&output, 5).exists());
+        assert!(path(&output, 7).exists());
+        assert_eq!(remove_stale(&output, 2).unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use bson::DateTime;
+use rusqlite::{types::Value, Connection};
+
+use super::table::{quote, Cell, ColumnType, Keys, Schema};
+use crate::DissectError;
+
+/// Create `table` with a column for every column of `schema` and the constraints of `keys` unless it exists
+pub(crate) fn create_table(
+    connection: &Connection,
+    table: &str,
+    schema: &Schema,
+    keys: &Keys,
+) -> Result<(), DissectError> {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| {
+            let mut definition = format!("{} {}", quote(&column.name), sql_type(column.kind));
+            if keys.primary.as_ref() == Some(&column.name) {
+                definition.push_str(" PRIMARY KEY");
+            }
+            if let Some((_, target)) = keys.foreign.iter().find(|(c, _)| *c == column.name) {
+                definition.push_str(&format!(" REFERENCES {}(\"_id\")", quote(target)));
+            }
+            definition
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    connection.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} ({columns})",
+        quote(table)
+    ))?;
+    Ok(())
+}
+
+/// Index `column` of `table` unless it is indexed already
+pub(crate) fn create_index(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+) -> Result<(), DissectError> {
+    connection.execute_batch(&format!(
+        "CREATE INDEX IF NOT EXISTS {} ON {} ({})",
+        quote(&format!("{table}_{column}")),
+        quote(table),
+        quote(column)
+    ))?;
+    Ok(())
+}
+
+/// Insert `rows` into `table` in one transaction
+pub(crate) fn insert(
+    connection: &mut Connection,
+    table: &str,
+    schema: &Schema,
+    rows: Vec<Vec<Cell>>,
+) -> Result<(), DissectError> {
+    let placeholders = vec!["?"; schema.columns.len()].join(", ");
+    let transaction = connection.transaction()?;
+    {
+        let mut statement = transaction.prepare(&format!(
+            "INSERT INTO {} VALUES ({placeholders})",
+            quote(table)
+        ))?;
+        for row in rows {
+            statement.execute(rusqlite::params_from_iter(row.into_iter().map(value)))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn sql_type(kind: ColumnType) -> &'static str {
+    match kind {
+        ColumnType::Boolean | ColumnType::BigInt => "INTEGER",
+        ColumnType::Double => "REAL",
+        // sqlite has no date type, its date functions take ISO 8601 text
+        ColumnType::Timestamp | ColumnType::Text | ColumnType::Json => "TEXT",
+    }
+}
+
+fn value(cell: Cell) -> Value {
+    match cell {
+        Cell::Null => Value::Null,
+        Cell::Boolean(b) => Value::Integer(b as i64),
+        Cell::BigInt(n) => Value::Integer(n),
+        Cell::Double(n) => Value::Real(n),
+        Cell::Timestamp(millis) => {
+            let date = DateTime::from_millis(millis);
+            Value::Text(
+                date.try_to_rfc3339_string()
+                    .unwrap_or_else(|_| millis.to_string()),
+            )
+        }
+        Cell::Text(s) => Value::Text(s),
+    }
+}
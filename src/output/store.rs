@@ -0,0 +1,271 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+#[cfg(not(feature = "object-store"))]
+use crate::DissectError;
+
+/// Outputs given as a url with one of these schemes are uploaded to an object store
+const SCHEMES: [&str; 3] = ["s3://", "gs://", "file://"];
+
+/// The url of an output that goes to an object store, like `s3://bucket/prefix/`
+pub(crate) fn object_url(output: &Path) -> Option<&str> {
+    let url = output.to_str()?;
+    SCHEMES
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+        .then_some(url)
+}
+
+/// Where a --single or --archive output is written, a local file or an upload to an object store
+pub(crate) enum Destination {
+    File(File),
+    #[cfg(feature = "object-store")]
+    Object(upload::ObjectWriter),
+}
+
+impl Write for Destination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            #[cfg(feature = "object-store")]
+            Self::Object(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            #[cfg(feature = "object-store")]
+            Self::Object(writer) => writer.flush(),
+        }
+    }
+}
+
+#[cfg(feature = "object-store")]
+pub(crate) use upload::ObjectStore;
+
+/// Stands in for the object store of an export when dissbson is built without the object-store feature,
+/// none can ever be opened
+#[cfg(not(feature = "object-store"))]
+pub(crate) enum ObjectStore {}
+
+#[cfg(not(feature = "object-store"))]
+impl ObjectStore {
+    pub fn open(_url: &str, _concurrency: usize) -> Result<Self, DissectError> {
+        Err(DissectError::Unexpected(
+            "Output to an object store needs dissbson built with the object-store feature".into(),
+        ))
+    }
+
+    pub fn put(&self, _name: &str, _bytes: Vec<u8>) -> Result<(), DissectError> {
+        match *self {}
+    }
+
+    pub fn writer(&self, _url: &str) -> Result<Destination, DissectError> {
+        match *self {}
+    }
+
+    pub fn finish(&self) -> Result<usize, DissectError> {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "object-store")]
+mod upload {
+    use std::{
+        io::{self, Write},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use object_store::{
+        aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder, local::LocalFileSystem,
+        path::Path as ObjectPath, ObjectStore as Store, WriteMultipart,
+    };
+    use parking_lot::Mutex;
+    use tokio::{
+        runtime::{Handle, Runtime},
+        sync::Semaphore,
+    };
+
+    use super::Destination;
+    use crate::DissectError;
+
+    /// Uploads the outputs of an export to S3, Google Cloud Storage or a local directory given as a `file://` url,
+    /// credentials are taken from the usual AWS_* and GOOGLE_* environment variables
+    pub(crate) struct ObjectStore {
+        url: String,
+        runtime: Runtime,
+        store: Arc<dyn Store>,
+        /// The prefix the files of the documents are uploaded under
+        path: ObjectPath,
+        /// Held by every running upload, at most `concurrency` of them
+        permits: Arc<Semaphore>,
+        concurrency: usize,
+        failure: Arc<Mutex<Option<object_store::Error>>>,
+        uploaded: Arc<AtomicUsize>,
+    }
+
+    impl ObjectStore {
+        pub fn open(url: &str, concurrency: usize) -> Result<Self, DissectError> {
+            let (scheme, bucket, path) = locate(url);
+            let failed = |e: object_store::Error| {
+                DissectError::Parse(format!("Can't open the object store of {url}: {e}"))
+            };
+            let (store, path): (Arc<dyn Store>, _) = match scheme {
+                "s3" => (
+                    Arc::new(
+                        AmazonS3Builder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()
+                            .map_err(failed)?,
+                    ),
+                    path,
+                ),
+                "gs" => (
+                    Arc::new(
+                        GoogleCloudStorageBuilder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()
+                            .map_err(failed)?,
+                    ),
+                    path,
+                ),
+                _ => (Arc::new(LocalFileSystem::new()), path),
+            };
+            let concurrency = concurrency.max(1);
+            Ok(Self {
+                url: url.to_string(),
+                runtime: tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()?,
+                store,
+                path: ObjectPath::from(path),
+                permits: Arc::new(Semaphore::new(concurrency)),
+                concurrency,
+                failure: Arc::new(Mutex::new(None)),
+                uploaded: Arc::new(AtomicUsize::new(0)),
+            })
+        }
+
+        fn failed(&self, e: object_store::Error) -> DissectError {
+            DissectError::Unexpected(format!("Upload to {} failed: {e}", self.url))
+        }
+
+        /// Start uploading the file of a document under the prefix, waits while `concurrency` uploads are running
+        pub fn put(&self, name: &str, bytes: Vec<u8>) -> Result<(), DissectError> {
+            if let Some(e) = self.failure.lock().take() {
+                return Err(self.failed(e));
+            }
+            let permit = self
+                .runtime
+                .block_on(self.permits.clone().acquire_owned())
+                .expect("the semaphore is never closed");
+            let store = self.store.clone();
+            // the files of partitioned exports sit in directories below the prefix
+            let path = name
+                .split('/')
+                .fold(self.path.clone(), |path, part| path.child(part));
+            let failure = self.failure.clone();
+            let uploaded = self.uploaded.clone();
+            self.runtime.spawn(async move {
+                match store.put(&path, bytes.into()).await {
+                    Ok(_) => {
+                        uploaded.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        failure.lock().get_or_insert(e);
+                    }
+                }
+                drop(permit);
+            });
+            Ok(())
+        }
+
+        /// A multipart upload of the --single or --archive output, or one of its shards, to `url` in the same bucket,
+        /// uploading up to `concurrency` parts at once
+        pub fn writer(&self, url: &str) -> Result<Destination, DissectError> {
+            let upload = self
+                .runtime
+                .block_on(self.store.put_multipart(&ObjectPath::from(locate(url).2)))
+                .map_err(|e| self.failed(e))?;
+            Ok(Destination::Object(ObjectWriter {
+                url: self.url.clone(),
+                handle: self.runtime.handle().clone(),
+                upload: Mutex::new(WriteMultipart::new(upload)),
+                concurrency: self.concurrency,
+            }))
+        }
+
+        /// Wait for the running uploads, returns how many files were uploaded
+        pub fn finish(&self) -> Result<usize, DissectError> {
+            let _all = self
+                .runtime
+                .block_on(self.permits.acquire_many(self.concurrency as u32))
+                .expect("the semaphore is never closed");
+            if let Some(e) = self.failure.lock().take() {
+                return Err(self.failed(e));
+            }
+            Ok(self.uploaded.load(Ordering::Relaxed))
+        }
+    }
+
+    /// The scheme, bucket and path of an object url, a `file://` url has no bucket and an absolute path
+    fn locate(url: &str) -> (&str, &str, &str) {
+        let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+        match scheme {
+            "s3" | "gs" => {
+                let (bucket, path) = rest.split_once('/').unwrap_or((rest, ""));
+                (scheme, bucket, path)
+            }
+            _ => (scheme, "", rest),
+        }
+    }
+
+    /// Streams what is written to it into a multipart upload, parts are uploaded as they fill up
+    pub(crate) struct ObjectWriter {
+        url: String,
+        handle: Handle,
+        /// Only ever locked through `&mut self`, it makes the writer Sync for the shared --single output
+        upload: Mutex<WriteMultipart>,
+        concurrency: usize,
+    }
+
+    impl ObjectWriter {
+        /// Upload the last part and complete the object
+        pub fn finish(self) -> Result<(), DissectError> {
+            let Self {
+                url,
+                handle,
+                upload,
+                ..
+            } = self;
+            handle
+                .block_on(upload.into_inner().finish())
+                .map_err(|e| DissectError::Unexpected(format!("Upload to {url} failed: {e}")))?;
+            Ok(())
+        }
+    }
+
+    impl Write for ObjectWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            // parts are spawned onto the runtime of the store as the buffer fills up
+            let _runtime = self.handle.enter();
+            let upload = self.upload.get_mut();
+            self.handle
+                .block_on(upload.wait_for_capacity(self.concurrency))
+                .map_err(io::Error::other)?;
+            upload.write(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use bson::{Bson, Document};
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use super::flat::plain;
+use crate::{
+    docpath,
+    index::{DocOffset, DocReader, Input},
+    stats::profile::Profile,
+    DissectError,
+};
+
+/// The type of a table column, picked from the types a field was seen with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ColumnType {
+    Boolean,
+    BigInt,
+    Double,
+    Timestamp,
+    Text,
+    /// Arrays and fields of mixed types, kept as their json text
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Column {
+    /// The dotted path of the field, also the column name
+    pub(crate) name: String,
+    path: Vec<String>,
+    pub(crate) kind: ColumnType,
+}
+
+/// A value ready for a column of its type
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Cell {
+    Null,
+    Boolean(bool),
+    BigInt(i64),
+    Double(f64),
+    /// Milliseconds since the epoch
+    Timestamp(i64),
+    Text(String),
+}
+
+/// Columns for documents flattened into dotted fields, fields inside arrays stay in their array column
+#[derive(Debug, Clone)]
+pub(crate) struct Schema {
+    pub(crate) columns: Vec<Column>,
+}
+
+/// Key constraints of a table
+#[derive(Debug, Default)]
+pub(crate) struct Keys {
+    pub(crate) primary: Option<String>,
+    /// Columns holding the `_id` of a row in another table, with the name of that table
+    pub(crate) foreign: Vec<(String, String)>,
+}
+
+impl Schema {
+    /// One column per field of the profiled documents, typed by what the field held
+    pub fn infer(profile: &Profile) -> Self {
+        let in_array = |path: &str| {
+            path.match_indices('.')
+                .any(|(end, _)| profile.fields[&path[..end]].types.contains_key("array"))
+        };
+        let columns = profile
+            .fields
+            .iter()
+            .filter(|(path, _)| !in_array(path))
+            .filter_map(|(path, stats)| {
+                let types = stats
+                    .types
+                    .keys()
+                    .filter(|t| !matches!(**t, "null" | "undefined"))
+                    .copied()
+                    .collect::<Vec<_>>();
+                let kind = match types.as_slice() {
+                    // sub documents are flattened into columns of their own
+                    ["object"] => return None,
+                    ["bool"] => ColumnType::Boolean,
+                    ["date"] => ColumnType::Timestamp,
+                    t if !t.is_empty() && t.iter().all(|t| matches!(*t, "int" | "long")) => {
+                        ColumnType::BigInt
+                    }
+                    t if !t.is_empty()
+                        && t.iter().all(|t| matches!(*t, "int" | "long" | "double")) =>
+                    {
+                        ColumnType::Double
+                    }
+                    t if t.iter().any(|t| matches!(*t, "array" | "object")) => ColumnType::Json,
+                    _ => ColumnType::Text,
+                };
+                Some(Column {
+                    name: path.clone(),
+                    path: docpath::parse(path),
+                    kind,
+                })
+            })
+            .collect();
+        Self { columns }
+    }
+
+    /// One column per field of the documents, like the first ones an export writes
+    pub fn of_documents<'a>(docs: impl IntoIterator<Item = &'a Document>) -> Self {
+        let mut profile = Profile::default();
+        for doc in docs {
+            profile.record(doc, 0);
+        }
+        Self::infer(&profile)
+    }
+
+    /// The columns of a single document
+    pub fn of(doc: &Document) -> Self {
+        Self::of_documents([doc])
+    }
+
+    /// Text columns for the given dotted paths, in that order
+    pub fn named(names: &[String]) -> Self {
+        let columns = names
+            .iter()
+            .map(|name| Column {
+                name: name.clone(),
+                path: docpath::parse(name),
+                kind: ColumnType::Text,
+            })
+            .collect();
+        Self { columns }
+    }
+
+    /// The columns for `names` in that order, typed as they are here and text when missing
+    pub fn select(&self, names: &[String]) -> Self {
+        let columns = names
+            .iter()
+            .map(|name| {
+                self.columns
+                    .iter()
+                    .find(|column| column.name == *name)
+                    .cloned()
+                    .unwrap_or_else(|| Self::named(std::slice::from_ref(name)).columns.remove(0))
+            })
+            .collect();
+        Self { columns }
+    }
+
+    /// The cells of a document in column order, values that don't fit their column are written as text or left out
+    pub fn row(&self, doc: &Document) -> Vec<Cell> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let mut cell = Cell::Null;
+                docpath::visit(doc, &column.path, &mut |value| {
+                    if cell == Cell::Null {
+                        cell = column.cell(value);
+                    }
+                });
+                cell
+            })
+            .collect()
+    }
+
+    /// Decode the documents at `offsets` into rows, in rounds of a batch per thread handed to `f` one by one
+    /// so a large input is never held in memory
+    pub fn scan(
+        &self,
+        input: &Input,
+        offsets: &[DocOffset],
+        threads: usize,
+        batch: usize,
+        f: &mut dyn FnMut(Vec<Vec<Cell>>) -> Result<(), DissectError>,
+    ) -> Result<(), DissectError> {
+        let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        let batch = batch.max(1);
+        for round in offsets.chunks(batch * threads.max(1)) {
+            let rows = thread_pool.install(|| {
+                round
+                    .par_chunks(batch)
+                    .map(|offsets| {
+                        let mut reader = DocReader::new(input);
+                        offsets
+                            .iter()
+                            .map(|offset| Ok(self.row(&reader.read_document(offset)?)))
+                            .collect::<Result<Vec<_>, DissectError>>()
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })?;
+            f(rows.into_iter().flatten().collect())?;
+        }
+        Ok(())
+    }
+}
+
+impl Column {
+    fn cell(&self, value: &Bson) -> Cell {
+        match (self.kind, value) {
+            (_, Bson::Null | Bson::Undefined) => Cell::Null,
+            // fields that are sometimes documents have their content in columns of their own
+            (ColumnType::Text, Bson::Document(_)) => Cell::Null,
+            (ColumnType::Boolean, Bson::Boolean(b)) => Cell::Boolean(*b),
+            (ColumnType::BigInt, Bson::Int32(n)) => Cell::BigInt(*n as i64),
+            (ColumnType::BigInt, Bson::Int64(n)) => Cell::BigInt(*n),
+            (ColumnType::Double, Bson::Int32(n)) => Cell::Double(*n as f64),
+            (ColumnType::Double, Bson::Int64(n)) => Cell::Double(*n as f64),
+            (ColumnType::Double, Bson::Double(n)) => Cell::Double(*n),
+            (ColumnType::Timestamp, Bson::DateTime(d)) => Cell::Timestamp(d.timestamp_millis()),
+            (ColumnType::Json, value) => {
+                Cell::Text(value.clone().into_relaxed_extjson().to_string())
+            }
+            (_, value) => match plain(value) {
+                serde_json::Value::String(s) => Cell::Text(s),
+                other => Cell::Text(other.to_string()),
+            },
+        }
+    }
+}
+
+/// Quote an identifier for SQL, dotted field paths are valid column names this way
+pub(crate) fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use bson::Document;
+use quick_xml::events::Event;
+use serde::de::IgnoredAny;
+
+use super::{postgres, Encoder, OutputFormat};
+use crate::DissectError;
+
+/// A document as written to a single output: where it starts, how long it is and its checksum
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Written {
+    start: u64,
+    len: u64,
+    sum: u64,
+}
+
+impl Written {
+    pub fn new(start: u64, bytes: &[u8]) -> Self {
+        Self {
+            start,
+            len: bytes.len() as u64,
+            sum: seahash::hash(bytes),
+        }
+    }
+}
+
+/// Re-read the documents written to the single output at `path` and check they parse and match their checksums,
+/// only a `sample` share of them is checked when it is below 1
+pub(crate) fn verify_stream(
+    encoder: &Encoder,
+    path: &Path,
+    written: &[Written],
+    sample: f64,
+) -> Result<(), DissectError> {
+    let mut file = File::open(path)?;
+    let mut buf = Vec::new();
+    let mut report = Report::default();
+    for (nth, doc) in written.iter().enumerate() {
+        if !sampled(nth, sample) {
+            continue;
+        }
+        buf.resize(doc.len as usize, 0);
+        file.seek(SeekFrom::Start(doc.start))?;
+        let problem = match file.read_exact(&mut buf) {
+            Ok(()) => check(encoder, &buf, doc.sum),
+            Err(e) => Some(e.to_string()),
+        };
+        report.add(format!("document {nth} at byte {}", doc.start), problem);
+    }
+    report.finish(path)
+}
+
+/// Re-read the per-document files and check they parse and match their checksums,
+/// only a `sample` share of them is checked when it is below 1
+pub(crate) fn verify_files(
+    encoder: &Encoder,
+    out_dir: &Path,
+    written: &[(PathBuf, u64)],
+    sample: f64,
+) -> Result<(), DissectError> {
+    let mut report = Report::default();
+    for (nth, (path, sum)) in written.iter().enumerate() {
+        if !sampled(nth, sample) {
+            continue;
+        }
+        let problem = match std::fs::read(path) {
+            Ok(bytes) => check(encoder, &bytes, *sum),
+            Err(e) => Some(e.to_string()),
+        };
+        report.add(path.display().to_string(), problem);
+    }
+    report.finish(out_dir)
+}
+
+/// Spreads the sample evenly but unpredictably over the output
+fn sampled(nth: usize, sample: f64) -> bool {
+    sample >= 1.0 || (seahash::hash(&nth.to_le_bytes()) as f64 / u64::MAX as f64) < sample
+}
+
+/// What is wrong with the bytes of a document, if anything
+fn check(encoder: &Encoder, bytes: &[u8], sum: u64) -> Option<String> {
+    if seahash::hash(bytes) != sum {
+        return Some("checksum mismatch".into());
+    }
+    let parsed = match encoder.format {
+        OutputFormat::Json | OutputFormat::Ndjson => serde_json::from_slice::<IgnoredAny>(bytes)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        OutputFormat::EsBulk => bytes
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .try_for_each(|line| serde_json::from_slice::<IgnoredAny>(line).map(|_| ()))
+            .map_err(|e| e.to_string()),
+        OutputFormat::PgCopy => std::str::from_utf8(bytes)
+            .map_err(|e| e.to_string())
+            .and_then(
+                |line| match line.trim_end_matches('\n').split('\t').collect::<Vec<_>>()[..] {
+                    [_, doc] => serde_json::from_str::<IgnoredAny>(&postgres::unescape(doc))
+                        .map(|_| ())
+                        .map_err(|e| e.to_string()),
+                    _ => Err("not an _id and doc line".into()),
+                },
+            ),
+        OutputFormat::Yaml => serde_yaml::from_slice::<IgnoredAny>(bytes)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        OutputFormat::Csv => csv::ReaderBuilder::new()
+            .delimiter(encoder.csv.csv_delimiter)
+            .has_headers(false)
+            .from_reader(bytes)
+            .records()
+            .try_for_each(|record| record.map(|_| ()))
+            .map_err(|e| e.to_string()),
+        OutputFormat::Xml | OutputFormat::Graphml => parse_xml(bytes),
+        OutputFormat::Bson => Document::from_reader(bytes)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        OutputFormat::Cypher => Err("cypher outputs can't be verified".into()),
+        OutputFormat::Sqlite
+        | OutputFormat::Duckdb
+        | OutputFormat::Parquet
+        | OutputFormat::Arrow
+        | OutputFormat::Feather => Err("database outputs can't be verified".into()),
+    };
+    parsed.err()
+}
+
+fn parse_xml(bytes: &[u8]) -> Result<(), String> {
+    let mut reader = quick_xml::Reader::from_reader(bytes);
+    reader.config_mut().check_end_names = true;
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => return Ok(()),
+            Ok(_) => buf.clear(),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    checked: usize,
+    failed: Vec<String>,
+}
+
+impl Report {
+    fn add(&mut self, what: String, problem: Option<String>) {
+        self.checked += 1;
+        if let Some(problem) = problem {
+            self.failed.push(format!("{what}: {problem}"));
+        }
+    }
+
+    fn finish(self, output: &Path) -> Result<(), DissectError> {
+        if self.failed.is_empty() {
+            println!(
+                "Verified {} documents in {}",
+                self.checked,
+                output.display()
+            );
+            return Ok(());
+        }
+        for failure in self.failed.iter().take(10) {
+            eprintln!("{failure}");
+        }
+        Err(DissectError::Unexpected(format!(
+            "{} of {} verified documents in {} are damaged",
+            self.failed.len(),
+            self.checked,
+            output.display()
+        )))
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use bson::{Bson, Document};
+
+use super::{encoding::TextEncoding, XmlOptions};
+
+pub(super) fn write_declaration<W: Write>(
+    writer: &mut W,
+    encoding: TextEncoding,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        r#"<?xml version="1.0" encoding="{}"?>"#,
+        encoding.label()
+    )
+}
+
+/// Write a document as an element named after `opts.element`,
+/// `indent` is the nesting depth when pretty printing and `None` otherwise
+pub(super) fn write_document<W: Write>(
+    writer: &mut W,
+    opts: &XmlOptions,
+    doc: &Document,
+    indent: Option<usize>,
+) -> io::Result<()> {
+    pad(writer, indent)?;
+    write_fields(writer, opts, &element_name(&opts.element), doc, indent)?;
+    newline(writer, indent)
+}
+
+fn write_node<W: Write>(
+    writer: &mut W,
+    opts: &XmlOptions,
+    name: &str,
+    value: &Bson,
+    indent: Option<usize>,
+) -> io::Result<()> {
+    pad(writer, indent)?;
+    let name = element_name(name);
+    match value {
+        Bson::Document(doc) => write_fields(writer, opts, &name, doc, indent)?,
+        Bson::Array(items) if items.is_empty() => write!(writer, "<{name}/>")?,
+        Bson::Array(items) => {
+            write!(writer, "<{name}>")?;
+            newline(writer, indent)?;
+            for item in items {
+                write_node(writer, opts, "item", item, indent.map(|d| d + 1))?;
+            }
+            pad(writer, indent)?;
+            write!(writer, "</{name}>")?;
+        }
+        Bson::Null | Bson::Undefined => write!(writer, "<{name}/>")?,
+        scalar => write!(writer, "<{name}>{}</{name}>", escape(&scalar_text(scalar)))?,
+    }
+    newline(writer, indent)
+}
+
+fn write_fields<W: Write>(
+    writer: &mut W,
+    opts: &XmlOptions,
+    name: &str,
+    doc: &Document,
+    indent: Option<usize>,
+) -> io::Result<()> {
+    write!(writer, "<{name}")?;
+    let mut children = Vec::with_capacity(doc.len());
+    // keys like `a b` and `a_b` have the same name, an element may repeat where an attribute may not
+    let mut attributes = HashSet::new();
+    for (key, value) in doc {
+        let attribute = element_name(key);
+        if opts.attributes && is_scalar(value) && !attributes.contains(&attribute) {
+            write!(
+                writer,
+                r#" {attribute}="{}""#,
+                escape_attribute(&scalar_text(value))
+            )?;
+            attributes.insert(attribute);
+        } else {
+            children.push((key, value));
+        }
+    }
+
+    if children.is_empty() {
+        return write!(writer, "/>");
+    }
+
+    write!(writer, ">")?;
+    newline(writer, indent)?;
+    for (key, value) in children {
+        write_node(writer, opts, key, value, indent.map(|d| d + 1))?;
+    }
+    pad(writer, indent)?;
+    write!(writer, "</{name}>")
+}
+
+fn is_scalar(value: &Bson) -> bool {
+    !matches!(
+        value,
+        Bson::Document(_) | Bson::Array(_) | Bson::Null | Bson::Undefined
+    )
+}
+
+fn scalar_text(value: &Bson) -> String {
+    match value {
+        Bson::String(s) | Bson::Symbol(s) | Bson::JavaScriptCode(s) => s.clone(),
+        Bson::JavaScriptCodeWithScope(c) => c.code.clone(),
+        Bson::ObjectId(o) => o.to_hex(),
+        Bson::DateTime(d) => d
+            .try_to_rfc3339_string()
+            .unwrap_or_else(|_| d.timestamp_millis().to_string()),
+        Bson::Binary(b) => b.bytes.iter().map(|b| format!("{b:02x}")).collect(),
+        Bson::RegularExpression(r) => format!("/{}/{}", r.pattern, r.options),
+        Bson::Timestamp(t) => format!("{}:{}", t.time, t.increment),
+        Bson::MaxKey => "MaxKey".into(),
+        Bson::MinKey => "MinKey".into(),
+        other => other.to_string(),
+    }
+}
+
+/// Turn a field name into a valid xml element name,
+/// invalid characters are replaced with underscores
+pub(super) fn element_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !out.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        out.insert(0, '_');
+    }
+    out
+}
+
+pub(super) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c.is_control() => out.push(char::REPLACEMENT_CHARACTER),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape the value of an attribute, where parsers would turn tabs and line breaks into spaces
+fn escape_attribute(text: &str) -> String {
+    escape(text)
+        .replace('\t', "&#9;")
+        .replace('\n', "&#10;")
+        .replace('\r', "&#13;")
+}
+
+fn pad<W: Write>(writer: &mut W, indent: Option<usize>) -> io::Result<()> {
+    if let Some(depth) = indent {
+        write!(writer, "{:width$}", "", width = depth * 2)?;
+    }
+    Ok(())
+}
+
+fn newline<W: Write>(writer: &mut W, indent: Option<usize>) -> io::Result<()> {
+    if indent.is_some() {
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Document};
+
+    use super::{element_name, escape, write_document, XmlOptions};
+
+    fn xml(doc: &Document, attributes: bool) -> String {
+        let opts = XmlOptions {
+            root: "documents".into(),
+            element: "document".into(),
+            attributes,
+        };
+        let mut out = Vec::new();
+        write_document(&mut out, &opts, doc, None).expect("writing to a vec");
+        String::from_utf8(out).expect("utf-8")
+    }
+
+    #[test]
+    fn element_names() {
+        assert_eq!(element_name("name"), "name");
+        assert_eq!(element_name("a b/c"), "a_b_c");
+        assert_eq!(element_name("$oid"), "_oid");
+        assert_eq!(element_name("1st"), "_1st");
+        assert_eq!(element_name("-x"), "_-x");
+        assert_eq!(element_name("ünï.côdé"), "ünï.côdé");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &apos;Jerry&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape("tab\there\r\n"), "tab\there\r\n");
+        assert_eq!(escape("bell\u{7}"), "bell\u{fffd}");
+    }
+
+    #[test]
+    fn elements() {
+        let doc = doc! {
+            "name": "a < b",
+            "tags": ["x", 1],
+            "empty": [],
+            "none": null,
+            "address": { "city": "Köln" },
+        };
+        assert_eq!(
+            xml(&doc, false),
+            "<document><name>a &lt; b</name><tags><item>x</item><item>1</item></tags><empty/><none/>\
+             <address><city>Köln</city></address></document>"
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let doc = doc! { "id": 1, "note": "say \"hi\"\n\tbye", "address": { "city": "Paris" } };
+        assert_eq!(
+            xml(&doc, true),
+            "<document id=\"1\" note=\"say &quot;hi&quot;&#10;&#9;bye\"><address city=\"Paris\"/></document>"
+        );
+    }
+
+    #[test]
+    fn attributes_with_the_same_name_fall_back_to_elements() {
+        let doc = doc! { "a b": 1, "a_b": 2, "a/b": 3 };
+        assert_eq!(
+            xml(&doc, true),
+            "<document a_b=\"1\"><a_b>2</a_b><a_b>3</a_b></document>"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    hash::Hasher,
+    path::{Path, PathBuf},
+};
+
+use seahash::SeaHasher;
+
+use super::QueryResult;
+use crate::{
+    index::{read_compressed, write_compressed, Input},
+    DissectError,
+};
+
+/// How many results are kept, the oldest are dropped first
+const ENTRIES: usize = 64;
+
+/// Query results remembered next to the input, stored like index files
+pub(crate) struct Cache {
+    path: PathBuf,
+    entries: Vec<(u64, QueryResult)>,
+}
+
+impl Cache {
+    /// The cache of a dump is `<dump>.query.dat`, the cache of a directory `dissbson.query.dat` inside it
+    pub fn open(input: &Path) -> Result<Self, DissectError> {
+        let path = if input.is_dir() {
+            input.join("dissbson.query.dat")
+        } else {
+            input.with_extension("query.dat")
+        };
+        let entries = if path.exists() {
+            let mut dat = read_compressed(&path)?;
+            // a cache written by another version is not worth failing over
+            postcard::from_bytes_cobs(&mut dat).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, entries })
+    }
+
+    pub fn get(&self, key: u64) -> Option<&QueryResult> {
+        self.entries.iter().find(|(k, _)| *k == key).map(|(_, r)| r)
+    }
+
+    pub fn insert(mut self, key: u64, result: QueryResult) -> Result<(), DissectError> {
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push((key, result));
+        let excess = self.entries.len().saturating_sub(ENTRIES);
+        self.entries.drain(..excess);
+        write_compressed(&self.path, &self.entries)
+    }
+}
+
+/// Key of a query over `input`: the index of every file and the normalized query,
+/// so a re-indexed or grown dump never answers from an old result
+pub(crate) fn key(input: &Input, table: &str, sql: &str) -> u64 {
+    let mut hasher = SeaHasher::new();
+    for file in &input.files {
+        hasher.write(file.file_name().unwrap_or_default().as_encoded_bytes());
+        hasher.write_u64(file.metadata().map_or(0, |m| m.len()));
+    }
+    for offset in &input.offsets {
+        hasher.write_usize(offset.source);
+        hasher.write_usize(offset.offset);
+        hasher.write_usize(offset.size);
+    }
+    hasher.write(table.as_bytes());
+    hasher.write_u8(0);
+    hasher.write(normalize(sql).as_bytes());
+    hasher.finish()
+}
+
+/// Lowercase and collapse whitespace outside of quotes and drop a trailing `;`,
+/// keywords and identifiers are case insensitive in SQLite but literals are not
+fn normalize(sql: &str) -> String {
+    let mut normalized = String::with_capacity(sql.len());
+    let mut quote = None;
+    for c in sql.trim().trim_end_matches(';').trim_end().chars() {
+        match quote {
+            Some(end) => {
+                normalized.push(c);
+                if c == end {
+                    quote = None;
+                }
+            }
+            None if c.is_whitespace() => {
+                if !normalized.ends_with(' ') {
+                    normalized.push(' ');
+                }
+            }
+            None => {
+                quote = match c {
+                    '\'' | '"' | '`' => Some(c),
+                    '[' => Some(']'),
+                    _ => None,
+                };
+                normalized.extend(c.to_lowercase());
+            }
+        }
+    }
+    normalized
+}
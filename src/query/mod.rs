@@ -0,0 +1,266 @@
+use std::{io::Write, path::PathBuf};
+
+use clap::ValueEnum;
+use rusqlite::{types::ValueRef, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+
+use crate::{
+    index::{self, Input},
+    output::{
+        dialect::CsvDialect,
+        sqlite,
+        table::{Keys, Schema},
+    },
+    stats::profile_input,
+    DissectError,
+};
+
+mod cache;
+
+/// Answer an SQL query over a dump, the documents are flattened into a table of an in-memory SQLite database
+#[derive(Debug, clap::Args)]
+pub struct QueryArgs {
+    /// The input file or directory to read
+    pub input: PathBuf,
+
+    /// The query, like "SELECT status, count(*) FROM docs GROUP BY 1"
+    pub sql: String,
+
+    /// Name of the table holding the documents
+    #[clap(long, default_value = "docs")]
+    pub table: String,
+
+    /// How the result is printed
+    #[clap(long, value_enum, default_value_t = ResultFormat::Table)]
+    pub format: ResultFormat,
+
+    #[clap(flatten)]
+    pub csv: CsvDialect,
+
+    /// The number of threads to use
+    #[clap(short, long, default_value = "4")]
+    pub threads: usize,
+
+    /// How many documents each thread reads at a time
+    #[clap(short, long, default_value = "100")]
+    pub batch: usize,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+
+    /// Run the query even if its result over the same index is cached
+    #[clap(long)]
+    pub no_cache: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResultFormat {
+    /// Aligned columns for reading
+    Table,
+    /// Csv with a header
+    Csv,
+    /// One json object per row
+    Ndjson,
+}
+
+/// The columns and rows a query answered with
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct QueryResult {
+    columns: Vec<String>,
+    rows: Vec<Vec<Field>>,
+}
+
+/// A value of a result row, owned so results can be cached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Field {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl From<ValueRef<'_>> for Field {
+    fn from(value: ValueRef) -> Self {
+        match value {
+            ValueRef::Null => Self::Null,
+            ValueRef::Integer(n) => Self::Integer(n),
+            ValueRef::Real(n) => Self::Real(n),
+            ValueRef::Text(s) => Self::Text(String::from_utf8_lossy(s).to_string()),
+            ValueRef::Blob(bytes) => Self::Blob(bytes.to_vec()),
+        }
+    }
+}
+
+pub(crate) fn run(args: &QueryArgs) -> Result<(), DissectError> {
+    let input = index::load_input(&args.input, args.inspect)?;
+    let key = cache::key(&input, &args.table, &args.sql);
+    let cache = if args.no_cache {
+        None
+    } else {
+        Some(cache::Cache::open(&args.input)?)
+    };
+    match cache.as_ref().and_then(|c| c.get(key)) {
+        Some(result) => {
+            eprintln!("Answered from the query cache, pass --no-cache to run it again");
+            print(args, result)
+        }
+        None => {
+            let result = execute(args, &input)?;
+            print(args, &result)?;
+            match cache {
+                Some(cache) => cache.insert(key, result),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+fn execute(args: &QueryArgs, input: &Input) -> Result<QueryResult, DissectError> {
+    let schema = Schema::infer(&profile_input(input, args.threads, args.batch, 0)?);
+    let mut connection = Connection::open_in_memory()?;
+    sqlite::create_table(&connection, &args.table, &schema, &Keys::default())?;
+    load(&mut connection, args, input, &schema)?;
+
+    let mut statement = connection.prepare(&args.sql)?;
+    let columns = statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let mut rows = Vec::new();
+    let mut result = statement.query([])?;
+    while let Some(row) = result.next()? {
+        rows.push(
+            (0..columns.len())
+                .map(|i| row.get_ref(i).map(Field::from))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
+    Ok(QueryResult { columns, rows })
+}
+
+fn print(args: &QueryArgs, result: &QueryResult) -> Result<(), DissectError> {
+    let QueryResult { columns, rows } = result;
+    let mut out = std::io::stdout().lock();
+    match args.format {
+        ResultFormat::Table => print_table(&mut out, columns, rows)?,
+        ResultFormat::Csv => {
+            let mut csv = args.csv.writer(&mut out);
+            args.csv.header(&mut csv, columns)?;
+            for row in rows {
+                csv.write_record(row.iter().map(|value| match value {
+                    Field::Null => args.csv.csv_null.clone(),
+                    value => text(value),
+                }))?;
+            }
+            csv.flush()?;
+        }
+        ResultFormat::Ndjson => {
+            for row in rows {
+                let object = columns
+                    .iter()
+                    .cloned()
+                    .zip(row.iter().map(json))
+                    .collect::<Map<_, _>>();
+                serde_json::to_writer(&mut out, &object)?;
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Insert every round of decoded documents as it comes
+fn load(
+    connection: &mut Connection,
+    args: &QueryArgs,
+    input: &Input,
+    schema: &Schema,
+) -> Result<(), DissectError> {
+    schema.scan(
+        input,
+        &input.offsets,
+        args.threads,
+        args.batch,
+        &mut |rows| sqlite::insert(connection, &args.table, schema, rows),
+    )?;
+    eprintln!(
+        "Loaded {} documents into {} columns",
+        input.offsets.len(),
+        schema.columns.len()
+    );
+    Ok(())
+}
+
+fn print_table<W: Write>(
+    out: &mut W,
+    columns: &[String],
+    rows: &[Vec<Field>],
+) -> Result<(), DissectError> {
+    let cells = rows
+        .iter()
+        .map(|row| row.iter().map(text).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain([name.chars().count()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let line = |out: &mut W, values: &mut dyn Iterator<Item = &String>| {
+        let padded = values
+            .zip(&widths)
+            .map(|(value, width)| format!("{value:width$}"))
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", padded.join(" | ").trim_end())
+    };
+    line(out, &mut columns.iter())?;
+    writeln!(
+        out,
+        "{}",
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-")
+    )?;
+    for row in &cells {
+        line(out, &mut row.iter())?;
+    }
+    writeln!(
+        out,
+        "({} row{})",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" }
+    )?;
+    Ok(())
+}
+
+fn text(value: &Field) -> String {
+    match value {
+        Field::Null => String::new(),
+        Field::Integer(n) => n.to_string(),
+        Field::Real(n) => n.to_string(),
+        Field::Text(s) => s.clone(),
+        Field::Blob(bytes) => hex::encode(bytes),
+    }
+}
+
+fn json(value: &Field) -> Json {
+    match value {
+        Field::Null => Json::Null,
+        Field::Integer(n) => Json::from(*n),
+        Field::Real(n) => Json::from(*n),
+        Field::Text(s) => Json::String(s.clone()),
+        Field::Blob(bytes) => Json::String(hex::encode(bytes)),
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use rusqlite::Connection;
+
+use crate::{
+    index::{self, Input},
+    output::{
+        postgres, sqlite,
+        table::{Keys, Schema},
+    },
+    stats::profile_input,
+    DissectError,
+};
+
+/// Export the collection dumps of a directory into one relational database, a table per collection
+/// named after its file, `_id` as the primary key and DBRef fields as foreign keys
+#[derive(Debug, clap::Args)]
+pub struct RelationalArgs {
+    /// Directory holding a `.bson` dump per collection, like the output of mongodump
+    pub input: PathBuf,
+
+    /// The database file, or the sql script with --target postgres
+    pub output: PathBuf,
+
+    /// What the export is loaded into
+    #[clap(long, value_enum, default_value_t = Target::Sqlite)]
+    pub target: Target,
+
+    /// The number of threads to use
+    #[clap(short, long, default_value = "4")]
+    pub threads: usize,
+
+    /// How many documents each thread reads at a time
+    #[clap(short, long, default_value = "100")]
+    pub batch: usize,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    /// A SQLite database file
+    Sqlite,
+    /// A sql script for psql creating the tables and loading them with COPY
+    Postgres,
+}
+
+/// A collection and the table it becomes
+struct Collection {
+    name: String,
+    input: Input,
+    schema: Schema,
+    keys: Keys,
+}
+
+pub(crate) fn run(args: &RelationalArgs) -> Result<(), DissectError> {
+    if !args.input.is_dir() {
+        return Err(DissectError::Parse(format!(
+            "{} is not a directory of collection dumps",
+            args.input.display()
+        )));
+    }
+    let input = index::load_input(&args.input, args.inspect)?;
+
+    let mut offsets = vec![Vec::new(); input.files.len()];
+    for offset in &input.offsets {
+        offsets[offset.source].push(*offset);
+    }
+    let names = input
+        .files
+        .iter()
+        .map(|file| {
+            file.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let mut collections = Vec::new();
+    for (name, offsets) in names.iter().zip(offsets) {
+        let input = Input {
+            files: input.files.clone(),
+            offsets,
+        };
+        // two samples are enough to tell whether a DBRef always points into the same collection
+        let profile = profile_input(&input, args.threads, args.batch, 2)?;
+        let schema = Schema::infer(&profile);
+        let primary = schema
+            .columns
+            .iter()
+            .any(|c| c.name == "_id")
+            .then(|| "_id".to_string());
+        let foreign = schema
+            .columns
+            .iter()
+            .filter_map(|column| {
+                let field = column.name.strip_suffix(".$id")?;
+                match profile
+                    .fields
+                    .get(&format!("{field}.$ref"))?
+                    .samples
+                    .as_slice()
+                {
+                    [target] if names.contains(target) => {
+                        Some((column.name.clone(), target.clone()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        collections.push(Collection {
+            name: name.clone(),
+            input,
+            schema,
+            keys: Keys { primary, foreign },
+        });
+    }
+
+    match args.target {
+        Target::Sqlite => to_sqlite(args, &collections)?,
+        Target::Postgres => to_postgres(args, &collections)?,
+    }
+
+    for c in &collections {
+        println!(
+            "Exported {} documents of {} into {} columns",
+            c.input.offsets.len(),
+            c.name,
+            c.schema.columns.len()
+        );
+        for (column, target) in &c.keys.foreign {
+            println!("  {column} references {target}._id");
+        }
+    }
+    println!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+fn to_sqlite(args: &RelationalArgs, collections: &[Collection]) -> Result<(), DissectError> {
+    let mut connection = Connection::open(&args.output)?;
+    // the keys document the links, references to documents missing from the dump must not fail the load
+    connection.pragma_update(None, "foreign_keys", false)?;
+    for c in collections {
+        sqlite::create_table(&connection, &c.name, &c.schema, &c.keys)?;
+    }
+    for c in collections {
+        c.schema.scan(
+            &c.input,
+            &c.input.offsets,
+            args.threads,
+            args.batch,
+            &mut |rows| sqlite::insert(&mut connection, &c.name, &c.schema, rows),
+        )?;
+    }
+    Ok(())
+}
+
+fn to_postgres(args: &RelationalArgs, collections: &[Collection]) -> Result<(), DissectError> {
+    let mut out = BufWriter::new(File::create(&args.output)?);
+    writeln!(out, "BEGIN;")?;
+    for c in collections {
+        writeln!(out)?;
+        postgres::create_table(&mut out, &c.name, &c.schema, &c.keys)?;
+        postgres::begin_copy(&mut out, &c.name, &c.schema)?;
+        c.schema.scan(
+            &c.input,
+            &c.input.offsets,
+            args.threads,
+            args.batch,
+            &mut |rows| Ok(postgres::copy_rows(&mut out, rows)?),
+        )?;
+        postgres::end_copy(&mut out)?;
+    }
+    writeln!(out)?;
+    for c in collections {
+        postgres::foreign_keys(&mut out, &c.name, &c.keys)?;
+    }
+    writeln!(out, "COMMIT;")?;
+    out.flush()?;
+    Ok(())
+}
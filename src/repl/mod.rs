@@ -0,0 +1,286 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bson::Bson;
+use rlua::{Context, Function, MultiValue, Table, ToLua, Value};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    env::Env,
+    index::{self, DocReader, Input},
+    lua_engine::{LuaBsonRepr, LuaEngine},
+    DissectError,
+};
+
+/// The helpers of the prompt, printed when it opens
+const HELP: &str = "\
+get(n)          document n
+count()         the number of documents
+sample(k)       k documents spread over the dump
+filter(f, k)    the positions of the documents f returns true for out of k spread over the dump,
+                and an estimate of how many of all documents it would keep
+run(script, n)  run a script, or a script file, on document n like an export does and return doc
+json(value)     print a value as json, the values of expressions typed at the prompt are printed this way
+Ctrl-D quits
+";
+
+/// How many documents `filter` tries a function on when it isn't told
+const FILTER_SAMPLE: usize = 1000;
+
+/// Explore a dump in a Lua prompt, fetch documents by position and try scripts and filters on them
+#[derive(Debug, clap::Args)]
+pub struct ReplArgs {
+    /// The input file or directory to read
+    pub input: PathBuf,
+
+    /// Lua file run before the prompt opens, like the script being worked on and the functions it defines
+    #[clap(short = 'S', long)]
+    pub script: Option<PathBuf>,
+
+    /// Set a variable for scripts, as env("KEY"), KEY alone passes on the environment variable
+    #[clap(long, value_name = "KEY=VALUE")]
+    pub env: Vec<String>,
+
+    /// Read variables like --env from a file of KEY=VALUE lines, can be given many times
+    #[clap(long)]
+    pub env_file: Vec<PathBuf>,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+pub(crate) fn run(args: &ReplArgs) -> Result<(), DissectError> {
+    let input = Arc::new(index::load_input(&args.input, args.inspect)?);
+    let env = Env::from_lists(&args.env_file, &args.env)?;
+    let engine = LuaEngine::new(&env)
+        .map_err(|e| DissectError::Unexpected(format!("Failed to create Lua context: {e}")))?;
+    engine.state.context(|ctx| helpers(ctx, &input))?;
+    if let Some(script) = &args.script {
+        engine.load_script(&fs::read_to_string(script)?)?;
+    }
+
+    println!(
+        "{} documents in {}, documents are numbered from 0",
+        input.offsets.len(),
+        args.input.display()
+    );
+    println!("{HELP}");
+
+    let mut editor = DefaultEditor::new().map_err(readline_error)?;
+    let mut pending = String::new();
+    loop {
+        let prompt = if pending.is_empty() { "> " } else { ">> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C drops the statement being typed
+            Err(ReadlineError::Interrupted) => {
+                pending.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(readline_error(e)),
+        };
+        pending.push_str(&line);
+        pending.push('\n');
+        if !engine.state.context(|ctx| eval(ctx, &pending)) {
+            continue;
+        }
+        // a failed history entry is no reason to end the session
+        let _ = editor.add_history_entry(pending.trim_end());
+        pending.clear();
+    }
+    Ok(())
+}
+
+/// Run a line or the lines of a statement typed so far, expressions have their values printed,
+/// returns false when the statement isn't complete yet
+fn eval(ctx: Context, source: &str) -> bool {
+    let chunk = match ctx.load(&format!("return {source}")).into_function() {
+        Ok(chunk) => chunk,
+        Err(_) => match ctx.load(source).into_function() {
+            Ok(chunk) => chunk,
+            Err(rlua::Error::SyntaxError {
+                incomplete_input: true,
+                ..
+            }) => return false,
+            Err(e) => {
+                println!("{}", message(&e));
+                return true;
+            }
+        },
+    };
+    match chunk.call::<_, MultiValue>(()) {
+        Ok(values) => {
+            for value in values {
+                print_json(&value);
+            }
+        }
+        Err(e) => println!("{}", message(&e)),
+    }
+    true
+}
+
+/// What went wrong without the traceback of the helpers written in Rust, which has nothing to show
+fn message(e: &rlua::Error) -> String {
+    match e {
+        rlua::Error::CallbackError { cause, .. } => message(cause),
+        other => other.to_string(),
+    }
+}
+
+/// The functions the prompt adds to those of scripts
+fn helpers(ctx: Context, input: &Arc<Input>) -> rlua::Result<()> {
+    let globals = ctx.globals();
+
+    let count = input.offsets.len();
+    globals.set("count", ctx.create_function(move |_, ()| Ok(count))?)?;
+
+    let get = {
+        let input = input.clone();
+        ctx.create_function(move |ctx, n: usize| document(ctx, &input, n))?
+    };
+    globals.set("get", get)?;
+
+    let sample = {
+        let input = input.clone();
+        ctx.create_function(move |ctx, k: Option<usize>| {
+            spread(input.offsets.len(), k.unwrap_or(10))
+                .into_iter()
+                .map(|n| document(ctx, &input, n))
+                .collect::<rlua::Result<Vec<_>>>()
+        })?
+    };
+    globals.set("sample", sample)?;
+
+    let filter = {
+        let input = input.clone();
+        ctx.create_function(move |ctx, (f, k): (Function, Option<usize>)| {
+            let sampled = spread(input.offsets.len(), k.unwrap_or(FILTER_SAMPLE));
+            let mut matched = Vec::new();
+            for &n in &sampled {
+                let keep: Value = f.call(document(ctx, &input, n)?)?;
+                if !matches!(keep, Value::Nil | Value::Boolean(false)) {
+                    matched.push(n);
+                }
+            }
+            let share = matched.len() as f64 / sampled.len().max(1) as f64;
+            println!(
+                "{} of {} sampled documents matched ({:.1}%), about {} of all {}",
+                matched.len(),
+                sampled.len(),
+                100.0 * share,
+                (share * input.offsets.len() as f64).round(),
+                input.offsets.len()
+            );
+            Ok(matched)
+        })?
+    };
+    globals.set("filter", filter)?;
+
+    let run = {
+        let input = input.clone();
+        ctx.create_function(move |ctx, (script, n): (String, usize)| {
+            // the argument is a script file when one exists by that name
+            let script = match Path::new(&script).is_file() {
+                true => fs::read_to_string(&script).map_err(rlua::Error::external)?,
+                false => script,
+            };
+            ctx.globals().set("doc", document(ctx, &input, n)?)?;
+            ctx.load(&script).exec()?;
+            ctx.globals().get::<_, Value>("doc")
+        })?
+    };
+    globals.set("run", run)?;
+
+    globals.set(
+        "json",
+        ctx.create_function(|_, value: Value| {
+            print_json(&value);
+            Ok(())
+        })?,
+    )?;
+    Ok(())
+}
+
+/// Document `n` of the input as the table scripts see it
+fn document<'lua>(ctx: Context<'lua>, input: &Input, n: usize) -> rlua::Result<Value<'lua>> {
+    let offset = input.offsets.get(n).ok_or_else(|| {
+        rlua::Error::RuntimeError(format!(
+            "No document {n} in a dump of {} documents",
+            input.offsets.len()
+        ))
+    })?;
+    let doc = DocReader::new(input)
+        .read_document(offset)
+        .map_err(|e| rlua::Error::RuntimeError(e.to_string()))?;
+    LuaBsonRepr::from(Bson::Document(doc)).to_lua(ctx)
+}
+
+/// Positions of `k` documents spread evenly over `count`
+fn spread(count: usize, k: usize) -> Vec<usize> {
+    let k = k.min(count);
+    (0..k).map(|nth| nth * count / k).collect()
+}
+
+fn print_json(value: &Value) {
+    match serde_json::to_string_pretty(&to_json(value)) {
+        Ok(json) => println!("{json}"),
+        Err(e) => println!("{e}"),
+    }
+}
+
+/// A Lua value as json, tables numbered from 1 are arrays and the ObjectIds of scripts `{"$oid": ...}`
+fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Nil => serde_json::Value::Null,
+        Value::Boolean(b) => (*b).into(),
+        Value::Integer(i) => (*i).into(),
+        Value::Number(n) => (*n).into(),
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into(),
+        Value::Table(table) => table_json(table),
+        other => format!("<{}>", other.type_name()).into(),
+    }
+}
+
+fn table_json(table: &Table) -> serde_json::Value {
+    if table
+        .get::<_, String>("__type")
+        .is_ok_and(|t| t == "ObjectId")
+    {
+        let hex = table.get::<_, String>("stringRepr").unwrap_or_default();
+        return serde_json::json!({ "$oid": hex });
+    }
+    let pairs = table
+        .clone()
+        .pairs::<Value, Value>()
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    let len = table.raw_len() as usize;
+    if len > 0 && len == pairs.len() {
+        return (1..=len)
+            .map(|i| to_json(&table.raw_get::<_, Value>(i).unwrap_or(Value::Nil)))
+            .collect::<Vec<_>>()
+            .into();
+    }
+    pairs
+        .iter()
+        .map(|(key, value)| {
+            let key = match key {
+                Value::String(s) => String::from_utf8_lossy(s.as_bytes()).to_string(),
+                Value::Integer(i) => i.to_string(),
+                Value::Number(n) => n.to_string(),
+                other => format!("<{}>", other.type_name()),
+            };
+            (key, to_json(value))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn readline_error(e: ReadlineError) -> DissectError {
+    DissectError::Unexpected(format!("Prompt error: {e}"))
+}
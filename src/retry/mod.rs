@@ -0,0 +1,60 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::DissectError;
+
+/// Retries operations failing with IO errors, waiting twice as long before every new attempt
+#[derive(Debug)]
+pub(crate) struct Retry {
+    attempts: usize,
+    backoff: Duration,
+    retried: AtomicUsize,
+    recovered: AtomicUsize,
+}
+
+impl Retry {
+    /// `attempts` retries after the first failure, the first one `backoff` after it
+    pub fn new(attempts: usize, backoff: Duration) -> Self {
+        Self {
+            attempts,
+            backoff,
+            retried: AtomicUsize::new(0),
+            recovered: AtomicUsize::new(0),
+        }
+    }
+
+    /// Run `op` until it succeeds, fails with something else than an IO error or runs out of attempts
+    pub fn run<T>(
+        &self,
+        mut op: impl FnMut() -> Result<T, DissectError>,
+    ) -> Result<T, DissectError> {
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Ok(value) => {
+                    if attempt > 0 {
+                        self.recovered.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Ok(value);
+                }
+                Err(DissectError::Io(_)) if attempt < self.attempts => {
+                    thread::sleep(self.backoff * 2u32.saturating_pow(attempt as u32));
+                    attempt += 1;
+                    self.retried.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Retries made so far and how many operations succeeded thanks to them
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.retried.load(Ordering::Relaxed),
+            self.recovered.load(Ordering::Relaxed),
+        )
+    }
+}
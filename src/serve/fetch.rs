@@ -0,0 +1,142 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufReader, BufWriter, Seek, SeekFrom, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+};
+
+use super::{read_frame, write_request};
+use crate::DissectError;
+
+/// Documents written between two saves of the progress
+const CHECKPOINT: usize = 1000;
+
+/// Download the documents a `dissbson serve-bson` sends into a bson dump
+///
+/// The dump index to ask for next and the bytes written so far are kept in a `.progress` file next to the
+/// dump, running the same command again after a broken transfer resumes from there.
+#[derive(Debug, clap::Args)]
+pub struct FetchArgs {
+    /// Address of the server, like host:9001
+    pub address: String,
+
+    /// Directory to write the dump into
+    pub output: PathBuf,
+
+    /// Name of the dump in the output directory
+    #[clap(long, default_value = "fetched.bson")]
+    pub name: String,
+
+    /// Start over even if an earlier transfer left progress behind
+    #[clap(long)]
+    pub restart: bool,
+}
+
+/// How far a transfer got, the dump is cut back to `bytes` before resuming
+#[derive(Debug, Default, Clone, Copy)]
+struct Progress {
+    next: u64,
+    bytes: u64,
+}
+
+impl Progress {
+    fn load(path: &Path) -> Result<Option<Self>, DissectError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut numbers = text.split_whitespace().map(str::parse::<u64>);
+        match (numbers.next(), numbers.next()) {
+            (Some(Ok(next)), Some(Ok(bytes))) => Ok(Some(Self { next, bytes })),
+            _ => Err(DissectError::Parse(format!(
+                "Unexpected progress in {}, run with --restart to start over",
+                path.display()
+            ))),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), DissectError> {
+        fs::write(path, format!("{} {}\n", self.next, self.bytes))?;
+        Ok(())
+    }
+}
+
+pub(crate) fn run(args: &FetchArgs) -> Result<(), DissectError> {
+    fs::create_dir_all(&args.output)?;
+    let path = args.output.join(&args.name);
+    let progress_path = args.output.join(format!("{}.progress", args.name));
+
+    let mut progress = if args.restart {
+        None
+    } else {
+        Progress::load(&progress_path)?
+    };
+    if progress.is_none() && path.exists() && !args.restart {
+        return Err(DissectError::Parse(format!(
+            "{} exists without progress to resume, run with --restart to overwrite it",
+            path.display()
+        )));
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(progress.is_none())
+        .open(&path)?;
+    if let Some(progress) = progress {
+        // whatever was written after the last save may be cut short, it is sent again
+        file.set_len(progress.bytes)?;
+        println!(
+            "Resuming at document {} of the remote dump, {} bytes already fetched",
+            progress.next, progress.bytes
+        );
+    }
+    let progress = progress.get_or_insert_with(Progress::default);
+    file.seek(SeekFrom::End(0))?;
+    let mut writer = BufWriter::new(file);
+
+    let stream = TcpStream::connect(&args.address)?;
+    write_request(&mut &stream, progress.next)?;
+    let mut reader = BufReader::new(&stream);
+    println!("Fetching from {} into {}", args.address, path.display());
+
+    let mut fetched = 0;
+    let result = loop {
+        match read_frame(&mut reader) {
+            Ok(Some((index, raw))) => {
+                if let Err(e) = writer.write_all(&raw) {
+                    break Err(e.into());
+                }
+                progress.next = index + 1;
+                progress.bytes += raw.len() as u64;
+                fetched += 1;
+                if fetched % CHECKPOINT == 0 {
+                    writer.flush()?;
+                    progress.save(&progress_path)?;
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+    writer.flush()?;
+    progress.save(&progress_path)?;
+
+    match result {
+        Ok(()) => {
+            println!(
+                "Fetched {fetched} documents, {} holds {} bytes",
+                path.display(),
+                progress.bytes
+            );
+            Ok(())
+        }
+        Err(e) => {
+            println!(
+                "Transfer broke after {fetched} documents, run the same command again to resume at document {}",
+                progress.next
+            );
+            Err(e)
+        }
+    }
+}
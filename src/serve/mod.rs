@@ -0,0 +1,145 @@
+use std::{
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use bson::Document;
+
+use crate::{
+    docpath::FieldValue,
+    index::{self, DocReader, Input},
+    DissectError,
+};
+
+pub(crate) mod fetch;
+
+/// First word of the request a client sends, followed by the index to start at
+const REQUEST: &str = "DISSBSON";
+
+/// Index closing the stream, no document follows it
+const END: u64 = u64::MAX;
+
+/// Stream the documents of a dump as bson to clients connecting over tcp, like `dissbson fetch`
+///
+/// A client sends `DISSBSON <index>\n` and gets every matching document from that position of the dump on,
+/// each as its index in the dump in 8 little endian bytes followed by the raw document, then the index
+/// `u64::MAX` closing the stream. Resuming after a broken connection is asking again from the index after
+/// the last document received.
+#[derive(Debug, clap::Args)]
+pub struct ServeArgs {
+    /// The input file or directory to serve
+    pub input: PathBuf,
+
+    /// Address to listen on, `:9001` listens on every interface
+    #[clap(long, default_value = ":9001")]
+    pub listen: String,
+
+    /// Only send documents whose field holds this value, like status=active, repeat to require several
+    #[clap(long, value_name = "FIELD=VALUE")]
+    pub filter: Vec<FieldValue>,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+pub(crate) fn run(args: &ServeArgs) -> Result<(), DissectError> {
+    let input = Arc::new(index::load_input(&args.input, args.inspect)?);
+    let filters = Arc::new(args.filter.clone());
+    let address = match args.listen.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{port}"),
+        None => args.listen.clone(),
+    };
+    let listener = TcpListener::bind(&address)?;
+    println!(
+        "Serving {} documents on {}",
+        input.offsets.len(),
+        listener.local_addr()?
+    );
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let input = input.clone();
+        let filters = filters.clone();
+        std::thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map_or_else(|_| "a client".to_string(), |a| a.to_string());
+            match serve(stream, &input, &filters) {
+                Ok((from, sent)) => println!("Sent {sent} documents from {from} on to {peer}"),
+                Err(e) => eprintln!("Serving {peer} failed: {e}"),
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Answer the request of one client, returns where it started and how many documents were sent
+fn serve(
+    stream: TcpStream,
+    input: &Input,
+    filters: &[FieldValue],
+) -> Result<(u64, usize), DissectError> {
+    let from = read_request(&mut BufReader::new(stream.try_clone()?))?;
+    let mut writer = BufWriter::new(stream);
+    let mut reader = DocReader::new(input);
+    let mut sent = 0;
+    for (index, offset) in input.offsets.iter().enumerate().skip(from as usize) {
+        let raw = reader.read_raw(offset)?;
+        if !filters.is_empty() {
+            let doc = Document::from_reader(raw.as_slice())?;
+            if !filters.iter().all(|f| f.matches(&doc)) {
+                continue;
+            }
+        }
+        write_frame(&mut writer, index as u64, &raw)?;
+        sent += 1;
+    }
+    writer.write_all(&END.to_le_bytes())?;
+    writer.flush()?;
+    Ok((from, sent))
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> Result<u64, DissectError> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    line.trim()
+        .strip_prefix(REQUEST)
+        .and_then(|from| from.trim().parse().ok())
+        .ok_or_else(|| DissectError::Parse(format!("Unexpected request {:?}", line.trim())))
+}
+
+/// Ask a server for the documents from `from` on
+pub(crate) fn write_request<W: Write>(writer: &mut W, from: u64) -> std::io::Result<()> {
+    writeln!(writer, "{REQUEST} {from}")?;
+    writer.flush()
+}
+
+fn write_frame<W: Write>(writer: &mut W, index: u64, raw: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&index.to_le_bytes())?;
+    writer.write_all(raw)
+}
+
+/// The next document of a stream and its index in the dump, none once the server closed the stream
+pub(crate) fn read_frame<R: Read>(reader: &mut R) -> Result<Option<(u64, Vec<u8>)>, DissectError> {
+    let mut index = [0; 8];
+    reader.read_exact(&mut index)?;
+    let index = u64::from_le_bytes(index);
+    if index == END {
+        return Ok(None);
+    }
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let size = i32::from_le_bytes(len);
+    if size < 5 {
+        return Err(DissectError::Parse(format!(
+            "Document {index} has an invalid length of {size} bytes"
+        )));
+    }
+    let mut raw = vec![0; size as usize];
+    raw[..4].copy_from_slice(&len);
+    reader.read_exact(&mut raw[4..])?;
+    Ok(Some((index, raw)))
+}
@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use bson::{doc, Bson, Document};
+use console::style;
+use humansize::{format_size, DECIMAL};
+
+use crate::{
+    index::{self, DocReader},
+    stats::profile::type_name,
+    DissectError,
+};
+
+/// Longest scalar printed in a tree before it is cut short
+const MAX_VALUE_CHARS: usize = 60;
+
+/// Print a single document of a dump
+#[derive(Debug, clap::Args)]
+pub struct ShowArgs {
+    /// The input file or directory to read
+    pub input: PathBuf,
+
+    /// Position of the document in the dump, starting at 0
+    #[clap(long)]
+    pub doc: usize,
+
+    /// Render the document as an indented tree with the type and encoded size of every field
+    #[clap(long)]
+    pub tree: bool,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+pub(crate) fn run(args: &ShowArgs) -> Result<(), DissectError> {
+    let input = index::load_input(&args.input, args.inspect)?;
+    let offset = input.offsets.get(args.doc).ok_or_else(|| {
+        DissectError::Parse(format!(
+            "No document {} in a dump of {} documents",
+            args.doc,
+            input.offsets.len()
+        ))
+    })?;
+    let doc = DocReader::new(&input).read_document(offset)?;
+
+    if !args.tree {
+        let json = Bson::Document(doc).into_relaxed_extjson();
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    println!(
+        "{} {}",
+        style(format!("document {}", args.doc)).bold(),
+        style(format!(
+            "object, {} fields, {}",
+            doc.len(),
+            size_text(offset.size)
+        ))
+        .dim()
+    );
+    print_fields(fields(&doc), "");
+    Ok(())
+}
+
+/// The children of a document
+fn fields(doc: &Document) -> Vec<(String, &Bson)> {
+    doc.iter()
+        .map(|(key, value)| (key.clone(), value))
+        .collect()
+}
+
+/// The items of an array, named by their position
+fn items(items: &[Bson]) -> Vec<(String, &Bson)> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(n, value)| (format!("[{n}]"), value))
+        .collect()
+}
+
+fn print_fields(children: Vec<(String, &Bson)>, prefix: &str) {
+    let count = children.len();
+    for (nth, (name, value)) in children.into_iter().enumerate() {
+        let last = nth + 1 == count;
+        let branch = if last { "└─ " } else { "├─ " };
+        let kind = type_name(value);
+        let size = size_text(encoded_size(value));
+        let nested = match value {
+            Bson::Document(doc) => Some((format!("{kind}, {} fields", doc.len()), fields(doc))),
+            Bson::Array(values) => Some((format!("{kind}, {} items", values.len()), items(values))),
+            _ => None,
+        };
+        match nested {
+            Some((summary, children)) => {
+                println!(
+                    "{prefix}{branch}{} {}",
+                    style(&name).cyan(),
+                    style(format!("{summary}, {size}")).dim()
+                );
+                let prefix = format!("{prefix}{}", if last { "   " } else { "│  " });
+                print_fields(children, &prefix);
+            }
+            None => println!(
+                "{prefix}{branch}{}: {} {}",
+                style(&name).cyan(),
+                scalar_text(value),
+                style(format!("{kind}, {size}")).dim()
+            ),
+        }
+    }
+}
+
+/// A scalar colored by its type
+fn scalar_text(value: &Bson) -> String {
+    let text = match value {
+        Bson::String(s) => format!("{s:?}"),
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::DateTime(date) => date
+            .try_to_rfc3339_string()
+            .unwrap_or_else(|_| date.timestamp_millis().to_string()),
+        other => other.clone().into_relaxed_extjson().to_string(),
+    };
+    let text = match text.char_indices().nth(MAX_VALUE_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    };
+    match value {
+        Bson::String(_) => style(text).green(),
+        Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) => {
+            style(text).yellow()
+        }
+        Bson::Boolean(_) => style(text).magenta(),
+        Bson::ObjectId(_) | Bson::DateTime(_) => style(text).blue(),
+        Bson::Null | Bson::Undefined => style(text).dim(),
+        _ => style(text),
+    }
+    .to_string()
+}
+
+/// Bytes the value takes in the document, without its field name
+fn encoded_size(value: &Bson) -> usize {
+    // a document holding only the value under an empty name adds the length, type, name and end bytes
+    bson::to_vec(&doc! { "": value.clone() }).map_or(0, |bytes| bytes.len().saturating_sub(7))
+}
+
+fn size_text(size: usize) -> String {
+    format_size(size, DECIMAL)
+}
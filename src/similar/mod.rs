@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::Hasher,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bson::Document;
+use rayon::{prelude::*, ThreadPoolBuilder};
+use seahash::SeaHasher;
+use serde_json::{json, Value};
+
+use crate::{
+    index::{self, DocReader, Input},
+    output::flat::{flatten, plain},
+    DissectError,
+};
+
+/// Length of a MinHash signature
+const HASHES: usize = 64;
+/// Signature slots per LSH band, 16 bands of 4 catch 99% of the pairs above 0.7 similarity
+const ROWS: usize = 4;
+
+type Signature = [u32; HASHES];
+
+/// Find near-duplicate documents: every document gets a MinHash signature over its `field=value` pairs and
+/// documents sharing a band of it are compared, similar ones are clustered
+#[derive(Debug, clap::Args)]
+pub struct SimilarArgs {
+    /// The input file or directory to read
+    pub input: PathBuf,
+
+    /// Share of equal field values from which two documents count as near-duplicates
+    #[clap(long, default_value = "0.7")]
+    pub threshold: f64,
+
+    /// Fields left out of the comparison, with everything below them
+    #[clap(long, default_value = "_id")]
+    pub ignore: Vec<String>,
+
+    /// How many of the largest clusters to print
+    #[clap(long, default_value = "10")]
+    pub show: usize,
+
+    /// Write every cluster to this file as ndjson
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// The number of threads to use
+    #[clap(short, long, default_value = "4")]
+    pub threads: usize,
+
+    /// How many documents each thread reads at a time
+    #[clap(short, long, default_value = "100")]
+    pub batch: usize,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+pub(crate) fn run(args: &SimilarArgs) -> Result<(), DissectError> {
+    let input = index::load_input(&args.input, args.inspect)?;
+    let thread_pool = ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+    let signatures = thread_pool.install(|| {
+        input
+            .offsets
+            .par_chunks(args.batch.max(1))
+            .map(|offsets| {
+                let mut reader = DocReader::new(&input);
+                offsets
+                    .iter()
+                    .map(|offset| Ok(signature(&reader.read_document(offset)?, &args.ignore)))
+                    .collect::<Result<Vec<_>, DissectError>>()
+            })
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    let signatures = signatures.into_iter().flatten().collect::<Vec<_>>();
+
+    let clusters = cluster(&signatures, args.threshold);
+    let clustered = clusters.iter().map(Vec::len).sum::<usize>();
+    println!(
+        "Found {} clusters of near-duplicates holding {clustered} of {} documents",
+        clusters.len(),
+        signatures.len()
+    );
+
+    let mut reader = DocReader::new(&input);
+    for (n, members) in clusters.iter().take(args.show).enumerate() {
+        let ids = describe(&mut reader, &input, &signatures, members)?;
+        let lowest = ids.iter().map(|(_, _, s)| *s).fold(1.0, f64::min);
+        println!();
+        println!(
+            "Cluster {}: {} documents, at least {:.0}% alike",
+            n + 1,
+            members.len(),
+            lowest * 100.0
+        );
+        for (index, id, similarity) in ids.iter().take(5) {
+            println!("  #{index} {id} ({:.0}%)", similarity * 100.0);
+        }
+        if ids.len() > 5 {
+            println!("  and {} more", ids.len() - 5);
+        }
+    }
+
+    if let Some(output) = &args.output {
+        let mut writer = BufWriter::new(File::create(output)?);
+        for (n, members) in clusters.iter().enumerate() {
+            let documents = describe(&mut reader, &input, &signatures, members)?
+                .into_iter()
+                .map(|(index, id, similarity)| {
+                    json!({ "index": index, "_id": id, "similarity": similarity })
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_writer(
+                &mut writer,
+                &json!({ "cluster": n + 1, "size": members.len(), "documents": documents }),
+            )?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        println!("Wrote the clusters to {}", output.display());
+    }
+    Ok(())
+}
+
+/// The MinHash signature of the flattened fields of `doc`, documents without any compared field have none
+fn signature(doc: &Document, ignore: &[String]) -> Option<Signature> {
+    let mut signature = [u32::MAX; HASHES];
+    let mut empty = true;
+    for (key, value) in flatten(doc) {
+        if ignore.iter().any(|i| {
+            key == *i
+                || key
+                    .strip_prefix(i.as_str())
+                    .is_some_and(|r| r.starts_with('.'))
+        }) {
+            continue;
+        }
+        empty = false;
+        let mut hasher = SeaHasher::new();
+        Hasher::write(&mut hasher, key.as_bytes());
+        hasher.write_u8(0);
+        Hasher::write(&mut hasher, value.to_string().as_bytes());
+        let token = hasher.finish();
+        for (i, slot) in signature.iter_mut().enumerate() {
+            let (a, b) = (mix(2 * i as u64) | 1, mix(2 * i as u64 + 1));
+            *slot = (*slot).min((token.wrapping_mul(a).wrapping_add(b) >> 32) as u32);
+        }
+    }
+    (!empty).then_some(signature)
+}
+
+/// splitmix64, a fixed source of the multipliers of the signature hash functions
+fn mix(n: u64) -> u64 {
+    let mut z = n.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The estimated Jaccard similarity of two documents
+fn similarity(a: &Signature, b: &Signature) -> f64 {
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / HASHES as f64
+}
+
+/// Group documents into clusters of near-duplicates, largest first, each listed by position in the input.
+/// A document is compared with the first document it shares a band with, so clusters grow without
+/// comparing every pair of a large group of copies
+fn cluster(signatures: &[Option<Signature>], threshold: f64) -> Vec<Vec<usize>> {
+    let mut parent = (0..signatures.len()).collect::<Vec<_>>();
+    let mut buckets = HashMap::new();
+    for (index, signature) in signatures.iter().enumerate() {
+        let Some(signature) = signature else {
+            continue;
+        };
+        for (band, rows) in signature.chunks(ROWS).enumerate() {
+            let mut hasher = SeaHasher::new();
+            hasher.write_usize(band);
+            rows.iter().for_each(|r| hasher.write_u32(*r));
+            let first = *buckets.entry(hasher.finish()).or_insert(index);
+            if first != index
+                && signatures[first]
+                    .as_ref()
+                    .is_some_and(|f| similarity(f, signature) >= threshold)
+            {
+                let (a, b) = (root(&mut parent, first), root(&mut parent, index));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut groups = HashMap::<usize, Vec<usize>>::new();
+    for index in 0..signatures.len() {
+        let root = root(&mut parent, index);
+        groups.entry(root).or_default().push(index);
+    }
+    let mut clusters = groups
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+    clusters
+}
+
+fn root(parent: &mut [usize], mut index: usize) -> usize {
+    while parent[index] != index {
+        parent[index] = parent[parent[index]];
+        index = parent[index];
+    }
+    index
+}
+
+/// The position, `_id` and similarity to the first member of every document of a cluster
+fn describe(
+    reader: &mut DocReader,
+    input: &Input,
+    signatures: &[Option<Signature>],
+    members: &[usize],
+) -> Result<Vec<(usize, Value, f64)>, DissectError> {
+    let first = signatures[members[0]].as_ref();
+    members
+        .iter()
+        .map(|&index| {
+            let doc = reader.read_document(&input.offsets[index])?;
+            let id = doc.get("_id").map_or(Value::Null, plain);
+            let similarity = match (first, signatures[index].as_ref()) {
+                (Some(a), Some(b)) => similarity(a, b),
+                _ => 0.0,
+            };
+            Ok((index, id, similarity))
+        })
+        .collect()
+}
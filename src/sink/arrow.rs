@@ -0,0 +1,136 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMillisecondArray,
+};
+use arrow_schema::{DataType, Field, TimeUnit};
+
+use crate::{
+    output::table::{Cell, ColumnType, Schema},
+    DissectError,
+};
+
+/// Turns rows of flattened documents into arrow record batches, a column per column of the sampled schema
+pub(crate) struct Columns {
+    pub(crate) schema: Schema,
+    pub(crate) arrow: Arc<arrow_schema::Schema>,
+    /// Values that don't fit the type their column was given from the sample, written as nulls
+    misfits: AtomicUsize,
+}
+
+impl Columns {
+    pub fn new(schema: Schema) -> Self {
+        let arrow = Arc::new(arrow_schema::Schema::new(
+            schema
+                .columns
+                .iter()
+                .map(|column| Field::new(&column.name, data_type(column.kind), true))
+                .collect::<Vec<_>>(),
+        ));
+        Self {
+            schema,
+            arrow,
+            misfits: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn batch(&self, rows: &[Vec<Cell>]) -> Result<RecordBatch, DissectError> {
+        let columns = self
+            .schema
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(nth, column)| self.column(column.kind, rows.iter().map(|row| &row[nth])))
+            .collect::<Vec<_>>();
+        RecordBatch::try_new(self.arrow.clone(), columns).map_err(arrow_error)
+    }
+
+    pub fn misfits(&self) -> usize {
+        self.misfits.load(Ordering::Relaxed)
+    }
+
+    fn misfit<T>(&self) -> Option<T> {
+        self.misfits.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// The cells of one column as an arrow array of its type
+    fn column<'a>(&self, kind: ColumnType, cells: impl Iterator<Item = &'a Cell>) -> ArrayRef {
+        match kind {
+            ColumnType::Boolean => Arc::new(
+                cells
+                    .map(|cell| match cell {
+                        Cell::Null => None,
+                        Cell::Boolean(b) => Some(*b),
+                        _ => self.misfit(),
+                    })
+                    .collect::<BooleanArray>(),
+            ),
+            ColumnType::BigInt => Arc::new(
+                cells
+                    .map(|cell| match cell {
+                        Cell::Null => None,
+                        Cell::BigInt(n) => Some(*n),
+                        _ => self.misfit(),
+                    })
+                    .collect::<Int64Array>(),
+            ),
+            ColumnType::Double => Arc::new(
+                cells
+                    .map(|cell| match cell {
+                        Cell::Null => None,
+                        Cell::Double(n) => Some(*n),
+                        _ => self.misfit(),
+                    })
+                    .collect::<Float64Array>(),
+            ),
+            ColumnType::Timestamp => Arc::new(
+                cells
+                    .map(|cell| match cell {
+                        Cell::Null => None,
+                        Cell::Timestamp(millis) => Some(*millis),
+                        _ => self.misfit(),
+                    })
+                    .collect::<TimestampMillisecondArray>()
+                    .with_timezone("UTC"),
+            ),
+            ColumnType::Text | ColumnType::Json => Arc::new(
+                cells
+                    .map(|cell| match cell {
+                        Cell::Null => None,
+                        Cell::Text(s) => Some(s.as_str()),
+                        _ => self.misfit(),
+                    })
+                    .collect::<StringArray>(),
+            ),
+        }
+    }
+}
+
+/// What the summary of an arrow based output adds when values had to be left out
+pub(crate) fn misfits_note(misfits: usize) -> String {
+    if misfits == 0 {
+        return String::new();
+    }
+    format!(
+        ", {misfits} values didn't fit the type their column got from the sample and were left null"
+    )
+}
+
+fn data_type(kind: ColumnType) -> DataType {
+    match kind {
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::BigInt => DataType::Int64,
+        ColumnType::Double => DataType::Float64,
+        ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        ColumnType::Text | ColumnType::Json => DataType::Utf8,
+    }
+}
+
+pub(crate) fn arrow_error(e: impl std::fmt::Display) -> DissectError {
+    DissectError::Unexpected(format!("Arrow: {e}"))
+}
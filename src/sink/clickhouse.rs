@@ -0,0 +1,176 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+
+use super::{
+    http::HttpSink,
+    template::{Template, UNRESERVED},
+};
+use crate::{Args, DissectError};
+
+/// Insert documents into a ClickHouse table over its http interface, given as
+/// `clickhouse://[user[:password]@]host[:port]/[database.]table[?secure=true]`
+///
+/// documents are flattened into dotted columns and sent as JSONEachRow, fields without a column are skipped
+/// so the table decides which part of the documents is kept
+pub(crate) fn connect(url: &str, args: &Args) -> Result<HttpSink, DissectError> {
+    let (endpoint, headers) = endpoint(url)?;
+    Ok(HttpSink::build(
+        Template::literal(&endpoint),
+        headers,
+        args.clickhouse_batch,
+        true,
+        args,
+    )
+    .flat())
+}
+
+/// The url the rows are posted to and the headers with the credentials. The parts of the url are percent-decoded,
+/// the table is quoted into the INSERT and the query percent-encoded again
+fn endpoint(url: &str) -> Result<(String, Vec<(String, String)>), DissectError> {
+    let invalid = || {
+        DissectError::Parse(format!(
+            "Expected clickhouse://[user[:password]@]host[:port]/[database.]table, got {url}"
+        ))
+    };
+    let decode = |part: &str| {
+        percent_decode_str(part)
+            .decode_utf8()
+            .map(|part| part.into_owned())
+            .map_err(|_| invalid())
+    };
+    let rest = url.strip_prefix("clickhouse://").ok_or_else(invalid)?;
+    let (rest, secure) = match rest.split_once('?') {
+        Some((rest, query)) => (
+            rest,
+            query
+                .split('&')
+                .any(|p| p == "secure=true" || p == "secure=1"),
+        ),
+        None => (rest, false),
+    };
+    let (authority, table) = rest.split_once('/').ok_or_else(invalid)?;
+    if table.is_empty() {
+        return Err(invalid());
+    }
+    let (credentials, host) = match authority.rsplit_once('@') {
+        Some((credentials, host)) => (Some(credentials), host),
+        None => (None, authority),
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut headers = Vec::new();
+    if let Some(credentials) = credentials {
+        let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+        headers.push(("X-ClickHouse-User".to_string(), decode(user)?));
+        headers.push(("X-ClickHouse-Key".to_string(), decode(password)?));
+    }
+
+    let (scheme, port) = if secure {
+        ("https", 8443)
+    } else {
+        ("http", 8123)
+    };
+    // the colons of an IPv6 address like [::1] are inside its brackets, a port follows them
+    let has_port = match host.rsplit_once(']') {
+        Some((_, after)) => after.starts_with(':'),
+        None => host.contains(':'),
+    };
+    let host = if has_port {
+        host.to_string()
+    } else {
+        format!("{host}:{port}")
+    };
+    let table = table
+        .split('.')
+        .map(|name| Ok(quote(&decode(name)?)))
+        .collect::<Result<Vec<_>, DissectError>>()?
+        .join(".");
+    let query = format!("INSERT INTO {table} FORMAT JSONEachRow");
+    let endpoint = format!(
+        "{scheme}://{host}/?query={}&input_format_skip_unknown_fields=1&date_time_input_format=best_effort",
+        utf8_percent_encode(&query, UNRESERVED)
+    );
+    Ok((endpoint, headers))
+}
+
+/// A ClickHouse identifier in backquotes, which may hold any character
+fn quote(name: &str) -> String {
+    format!("`{}`", name.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::endpoint;
+
+    fn query(url: &str) -> String {
+        let (endpoint, _) = endpoint(url).unwrap();
+        endpoint
+            .split_once("?query=")
+            .and_then(|(_, rest)| rest.split_once('&'))
+            .map(|(query, _)| {
+                percent_encoding::percent_decode_str(query)
+                    .decode_utf8()
+                    .unwrap()
+                    .into_owned()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn default_ports() {
+        let host = |url: &str| {
+            let (endpoint, _) = endpoint(url).unwrap();
+            endpoint[..endpoint.find("/?").unwrap()].to_string()
+        };
+        assert_eq!(host("clickhouse://db/t"), "http://db:8123");
+        assert_eq!(host("clickhouse://db:9000/t"), "http://db:9000");
+        assert_eq!(host("clickhouse://db/t?secure=true"), "https://db:8443");
+        assert_eq!(host("clickhouse://[::1]/t"), "http://[::1]:8123");
+        assert_eq!(host("clickhouse://[::1]:9000/t"), "http://[::1]:9000");
+    }
+
+    #[test]
+    fn tables_are_quoted_and_encoded() {
+        assert_eq!(
+            query("clickhouse://db/logs.events"),
+            "INSERT INTO `logs`.`events` FORMAT JSONEachRow"
+        );
+        assert_eq!(
+            query("clickhouse://db/my%20table"),
+            "INSERT INTO `my table` FORMAT JSONEachRow"
+        );
+        // braces are no fields, a backquote doesn't end the name and a & doesn't start a parameter
+        assert_eq!(
+            query("clickhouse://db/{type}`x&y=1"),
+            "INSERT INTO `{type}\\`x&y=1` FORMAT JSONEachRow"
+        );
+        let (endpoint, _) = endpoint("clickhouse://db/{type}").unwrap();
+        assert!(endpoint.contains("%7Btype%7D"), "{endpoint}");
+    }
+
+    #[test]
+    fn credentials_are_decoded() {
+        let (_, headers) = endpoint("clickhouse://app%40corp:p%3Ass%2Fw0rd@db/t").unwrap();
+        assert_eq!(
+            headers,
+            [
+                ("X-ClickHouse-User".to_string(), "app@corp".to_string()),
+                ("X-ClickHouse-Key".to_string(), "p:ss/w0rd".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_urls() {
+        for url in [
+            "http://db/t",
+            "clickhouse://db",
+            "clickhouse://db/",
+            "clickhouse:///t",
+            "clickhouse://u:%ff@db/t",
+        ] {
+            assert!(endpoint(url).is_err(), "{url}");
+        }
+    }
+}
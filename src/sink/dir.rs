@@ -0,0 +1,48 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::Document;
+
+use super::Sink;
+use crate::{output::Encoder, Args, DissectError};
+
+/// Writes every document to its own file in a directory, named by the order they arrive in
+pub(crate) struct DirSink {
+    dir: PathBuf,
+    encoder: Encoder,
+    count: AtomicUsize,
+}
+
+impl DirSink {
+    pub fn create(dir: &Path, args: &Args) -> Result<Self, DissectError> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            encoder: Encoder::from_args(args),
+            count: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Sink for DirSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        let nth = self.count.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{nth}.{}", self.encoder.extension()));
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.encoder.encode(&mut writer, doc)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        Ok(format!(
+            "Wrote {} documents to {}",
+            self.count.into_inner(),
+            self.dir.display()
+        ))
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bson::Document;
+use duckdb::{
+    params_from_iter,
+    types::{TimeUnit, Value},
+    Connection,
+};
+use parking_lot::Mutex;
+
+use super::Sink;
+use crate::{
+    output::table::{quote, Cell, ColumnType, Schema},
+    DissectError,
+};
+
+/// Rows appended to the table at once
+const APPEND_BATCH: usize = 10_000;
+
+/// Loads the flattened documents into a table of a DuckDB database file with the appender API,
+/// or into a table per shape of document
+pub(crate) struct DuckDbSink {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+    tables: Mutex<Tables>,
+}
+
+/// The names of the top level fields of a document, sorted
+type Shape = Vec<String>;
+
+struct Tables {
+    /// Name of the single table, shape tables are named after it with their number appended
+    name: String,
+    list: Vec<Table>,
+    /// Position in `list` of the table of every shape, by the sorted names of the top level fields,
+    /// none when all documents go to a single table
+    shapes: Option<HashMap<Shape, usize>>,
+}
+
+struct Table {
+    name: String,
+    schema: Arc<Schema>,
+    rows: Vec<Vec<Value>>,
+    count: usize,
+}
+
+impl DuckDbSink {
+    /// Open or create the database at `path` and create `table` for `schema` unless it exists
+    pub fn create(path: &Path, table: &str, schema: Schema) -> Result<Self, DissectError> {
+        let connection = Connection::open(path).map_err(duckdb_error)?;
+        create_table(&connection, table, &schema)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection: Mutex::new(connection),
+            tables: Mutex::new(Tables {
+                name: table.into(),
+                list: vec![Table::new(table, schema)],
+                shapes: None,
+            }),
+        })
+    }
+
+    /// Open or create the database at `path` with a table for every set of top level fields found in `sample`,
+    /// `table_1` for the most common one and so on, each with the columns of its own documents
+    pub fn per_shape(path: &Path, table: &str, sample: &[Document]) -> Result<Self, DissectError> {
+        let connection = Connection::open(path).map_err(duckdb_error)?;
+        let mut list = Vec::new();
+        let mut shapes = HashMap::new();
+        for (shape, docs) in find_shapes(sample) {
+            let schema = Schema::of_documents(docs.iter().copied());
+            let name = format!("{table}_{}", list.len() + 1);
+            create_table(&connection, &name, &schema)?;
+            println!(
+                "Table {name} takes {} of the first {} documents with {} columns",
+                docs.len(),
+                sample.len(),
+                schema.columns.len()
+            );
+            shapes.insert(shape, list.len());
+            list.push(Table::new(&name, schema));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection: Mutex::new(connection),
+            tables: Mutex::new(Tables {
+                name: table.into(),
+                list,
+                shapes: Some(shapes),
+            }),
+        })
+    }
+
+    /// The position and schema of the table `doc` goes to, a shape the sample didn't have gets a new table
+    /// typed by this document, scripts and transforms can add fields
+    fn table_of(&self, doc: &Document) -> Result<(usize, Arc<Schema>), DissectError> {
+        let mut tables = self.tables.lock();
+        let Tables { name, list, shapes } = &mut *tables;
+        let nth = match shapes {
+            None => 0,
+            Some(shapes) => {
+                let shape = doc_shape(doc);
+                match shapes.get(&shape) {
+                    Some(nth) => *nth,
+                    None => {
+                        let schema = Schema::of(doc);
+                        let name = format!("{name}_{}", list.len() + 1);
+                        create_table(&self.connection.lock(), &name, &schema)?;
+                        shapes.insert(shape, list.len());
+                        list.push(Table::new(&name, schema));
+                        list.len() - 1
+                    }
+                }
+            }
+        };
+        Ok((nth, list[nth].schema.clone()))
+    }
+}
+
+impl Table {
+    fn new(name: &str, schema: Schema) -> Self {
+        Self {
+            name: name.into(),
+            schema: Arc::new(schema),
+            rows: Vec::new(),
+            count: 0,
+        }
+    }
+}
+
+impl Sink for DuckDbSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        let (nth, schema) = self.table_of(doc)?;
+        let row = schema.row(doc).into_iter().map(value).collect();
+        let full = {
+            let mut tables = self.tables.lock();
+            let table = &mut tables.list[nth];
+            table.rows.push(row);
+            table.count += 1;
+            (table.rows.len() >= APPEND_BATCH)
+                .then(|| (table.name.clone(), std::mem::take(&mut table.rows)))
+        };
+        if let Some((table, rows)) = full {
+            append(&self.connection.lock(), &table, rows)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        let Self {
+            path,
+            connection,
+            tables,
+        } = *self;
+        let (connection, mut tables) = (connection.into_inner(), tables.into_inner());
+        for table in &mut tables.list {
+            let rows = std::mem::take(&mut table.rows);
+            append(&connection, &table.name, rows)?;
+        }
+        if tables.shapes.is_none() {
+            return Ok(format!(
+                "Loaded {} documents into table {} of {}",
+                tables.list[0].count,
+                tables.name,
+                path.display()
+            ));
+        }
+        let mut summary = format!(
+            "Loaded {} documents into {} tables of {}",
+            tables.list.iter().map(|t| t.count).sum::<usize>(),
+            tables.list.len(),
+            path.display()
+        );
+        for table in &tables.list {
+            summary.push_str(&format!(
+                "\n  {:<20} {:>10} documents, {}",
+                table.name,
+                table.count,
+                table
+                    .schema
+                    .columns
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        Ok(summary)
+    }
+}
+
+/// The documents grouped by their top level fields, the most common shape first
+fn find_shapes(docs: &[Document]) -> Vec<(Shape, Vec<&Document>)> {
+    let mut shapes: HashMap<Shape, Vec<&Document>> = HashMap::new();
+    for doc in docs {
+        shapes.entry(doc_shape(doc)).or_default().push(doc);
+    }
+    let mut shapes = shapes.into_iter().collect::<Vec<_>>();
+    shapes.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+    shapes
+}
+
+fn doc_shape(doc: &Document) -> Shape {
+    let mut shape = doc.keys().cloned().collect::<Vec<_>>();
+    shape.sort();
+    shape
+}
+
+fn append(connection: &Connection, table: &str, rows: Vec<Vec<Value>>) -> Result<(), DissectError> {
+    let mut appender = connection.appender(table).map_err(duckdb_error)?;
+    for row in rows {
+        appender
+            .append_row(params_from_iter(row))
+            .map_err(duckdb_error)?;
+    }
+    appender.flush().map_err(duckdb_error)
+}
+
+fn create_table(connection: &Connection, table: &str, schema: &Schema) -> Result<(), DissectError> {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| format!("{} {}", quote(&column.name), sql_type(column.kind)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    connection
+        .execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} ({columns})",
+            quote(table)
+        ))
+        .map_err(duckdb_error)
+}
+
+fn sql_type(kind: ColumnType) -> &'static str {
+    match kind {
+        ColumnType::Boolean => "BOOLEAN",
+        ColumnType::BigInt => "BIGINT",
+        ColumnType::Double => "DOUBLE",
+        ColumnType::Timestamp => "TIMESTAMP",
+        ColumnType::Text | ColumnType::Json => "VARCHAR",
+    }
+}
+
+fn value(cell: Cell) -> Value {
+    match cell {
+        Cell::Null => Value::Null,
+        Cell::Boolean(b) => Value::Boolean(b),
+        Cell::BigInt(n) => Value::BigInt(n),
+        Cell::Double(n) => Value::Double(n),
+        Cell::Timestamp(millis) => Value::Timestamp(TimeUnit::Millisecond, millis),
+        Cell::Text(s) => Value::Text(s),
+    }
+}
+
+fn duckdb_error(e: duckdb::Error) -> DissectError {
+    DissectError::Unexpected(format!("DuckDB: {e}"))
+}
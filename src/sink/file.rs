@@ -0,0 +1,78 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::Document;
+use parking_lot::Mutex;
+
+use super::Sink;
+use crate::{
+    output::{Encoder, OutputFormat, StreamWriter},
+    Args, DissectError,
+};
+
+/// How a file sink is written, taken from its extension
+pub(crate) fn format_of(path: &Path) -> Result<OutputFormat, String> {
+    let format = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(OutputFormat::from_extension);
+    match format {
+        Some(
+            format @ (OutputFormat::Json
+            | OutputFormat::Ndjson
+            | OutputFormat::Yaml
+            | OutputFormat::Xml
+            | OutputFormat::EsBulk
+            | OutputFormat::PgCopy
+            | OutputFormat::Graphml
+            | OutputFormat::Cypher),
+        ) => Ok(format),
+        _ => Err(format!(
+            "can't tell the format of {}, use a .json, .ndjson, .yaml, .xml, .bulk, .copy, .graphml or .cypher file",
+            path.display()
+        )),
+    }
+}
+
+/// Writes documents to a single file like --single does
+pub(crate) struct FileSink {
+    path: PathBuf,
+    writer: Mutex<StreamWriter<BufWriter<File>>>,
+    count: AtomicUsize,
+}
+
+impl FileSink {
+    pub fn create(path: &Path, args: &Args) -> Result<Self, DissectError> {
+        let file = BufWriter::new(File::create(path)?);
+        let encoder = Encoder {
+            format: format_of(path).map_err(DissectError::Parse)?,
+            ..Encoder::from_args(args)
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Mutex::new(encoder.stream(file)),
+            count: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        self.writer.lock().write(doc)?;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        self.writer.into_inner().finish()?.flush()?;
+        Ok(format!(
+            "Wrote {} documents to {}",
+            self.count.into_inner(),
+            self.path.display()
+        ))
+    }
+}
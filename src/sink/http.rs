@@ -0,0 +1,173 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use bson::Document;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+
+use super::{template::Template, Sink};
+use crate::{output::flat::flatten, retry::Retry, Args, DissectError};
+
+/// POSTs batches of documents to an endpoint, which may name fields like `http://api/ingest/{type}`
+/// to send every document to the endpoint its values select. Delivery is at least once: a batch that failed with
+/// a 429, a 5xx or a broken connection is sent again with the same `Idempotency-Key`, the sha256 of its url and body
+pub(crate) struct HttpSink {
+    agent: ureq::Agent,
+    endpoint: Template,
+    headers: Vec<(String, String)>,
+    batch: usize,
+    ndjson: bool,
+    /// Send documents flattened into dotted columns
+    flat: bool,
+    retry: Retry,
+    /// Encoded documents waiting for their batch to fill up, by endpoint
+    pending: Mutex<BTreeMap<String, Vec<Vec<u8>>>>,
+    sent: AtomicUsize,
+    requests: AtomicUsize,
+}
+
+impl HttpSink {
+    pub fn new(url: &str, args: &Args) -> Result<Self, DissectError> {
+        let headers = args
+            .http_header
+            .iter()
+            .map(|header| match header.split_once(':') {
+                Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+                None => Err(DissectError::Parse(format!(
+                    "Expected a header like 'Authorization: Bearer ...', got {header}"
+                ))),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::build(
+            Template::url(url)?,
+            headers,
+            args.http_batch,
+            args.http_ndjson,
+            args,
+        ))
+    }
+
+    /// A sink posting to `endpoint` with the given headers, the retries follow `args`
+    pub fn build(
+        endpoint: Template,
+        headers: Vec<(String, String)>,
+        batch: usize,
+        ndjson: bool,
+        args: &Args,
+    ) -> Self {
+        Self {
+            agent: ureq::agent(),
+            endpoint,
+            headers,
+            batch: batch.max(1),
+            ndjson,
+            flat: false,
+            retry: Retry::new(args.retries, Duration::from_millis(args.retry_backoff)),
+            pending: Mutex::new(BTreeMap::new()),
+            sent: AtomicUsize::new(0),
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    /// Send documents flattened into dotted columns with plain values
+    pub fn flat(mut self) -> Self {
+        self.flat = true;
+        self
+    }
+
+    fn post(&self, url: &str, docs: Vec<Vec<u8>>) -> Result<(), DissectError> {
+        let body = if self.ndjson {
+            docs.iter()
+                .flat_map(|doc| doc.iter().chain(b"\n"))
+                .copied()
+                .collect()
+        } else {
+            let mut body = b"[".to_vec();
+            for (nth, doc) in docs.iter().enumerate() {
+                if nth > 0 {
+                    body.push(b',');
+                }
+                body.extend_from_slice(doc);
+            }
+            body.push(b']');
+            body
+        };
+        let content_type = if self.ndjson {
+            "application/x-ndjson"
+        } else {
+            "application/json"
+        };
+
+        // a batch the server took but failed to answer is sent again, the key lets it tell the copies apart
+        let key = hex::encode(
+            Sha256::new()
+                .chain_update(url)
+                .chain_update(&body)
+                .finalize(),
+        );
+        self.retry.run(|| {
+            let mut request = self
+                .agent
+                .post(url)
+                .set("Content-Type", content_type)
+                .set("Idempotency-Key", &key);
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+            match request.send_bytes(&body) {
+                Ok(_) => Ok(()),
+                // busy or failing servers and broken connections are worth another try
+                Err(ureq::Error::Status(code, _)) if code == 429 || code >= 500 => Err(
+                    DissectError::Io(std::io::Error::other(format!("{url} answered {code}"))),
+                ),
+                Err(ureq::Error::Transport(e)) => Err(DissectError::Io(std::io::Error::other(
+                    format!("{url}: {e}"),
+                ))),
+                Err(ureq::Error::Status(code, response)) => Err(DissectError::Unexpected(format!(
+                    "{url} rejected a batch with {code}: {}",
+                    response.into_string().unwrap_or_default()
+                ))),
+            }
+        })?;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.sent.fetch_add(docs.len(), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Sink for HttpSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        let url = self.endpoint.render(doc);
+        let encoded = if self.flat {
+            serde_json::to_vec(&flatten(doc))?
+        } else {
+            serde_json::to_vec(doc)?
+        };
+        let full = {
+            let mut pending = self.pending.lock();
+            let docs = pending.entry(url.clone()).or_default();
+            docs.push(encoded);
+            (docs.len() >= self.batch).then(|| std::mem::take(docs))
+        };
+        match full {
+            Some(docs) => self.post(&url, docs),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        for (url, docs) in std::mem::take(&mut *self.pending.lock()) {
+            if !docs.is_empty() {
+                self.post(&url, docs)?;
+            }
+        }
+        Ok(format!(
+            "Posted {} documents in {} requests",
+            self.sent.into_inner(),
+            self.requests.into_inner()
+        ))
+    }
+}
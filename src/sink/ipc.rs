@@ -0,0 +1,102 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use bson::Document;
+use parking_lot::Mutex;
+
+use super::{
+    arrow::{arrow_error, misfits_note, Columns},
+    Sink,
+};
+use crate::{output::table::Schema, DissectError};
+
+/// The stream format for readers consuming batches as they come, the file format also known as Feather v2
+enum Writer {
+    Stream(StreamWriter<BufWriter<File>>),
+    File(FileWriter<BufWriter<File>>),
+}
+
+impl Writer {
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), DissectError> {
+        match self {
+            Self::Stream(w) => w.write(batch),
+            Self::File(w) => w.write(batch),
+        }
+        .map_err(arrow_error)
+    }
+
+    fn finish(&mut self) -> Result<(), DissectError> {
+        match self {
+            Self::Stream(w) => w.finish(),
+            Self::File(w) => w.finish(),
+        }
+        .map_err(arrow_error)
+    }
+}
+
+/// Writes every batch of the export as an arrow record batch of the flattened documents
+pub(crate) struct IpcSink {
+    path: PathBuf,
+    columns: Columns,
+    writer: Mutex<Writer>,
+    count: AtomicUsize,
+    batches: AtomicUsize,
+}
+
+impl IpcSink {
+    pub fn create(path: &Path, schema: Schema, feather: bool) -> Result<Self, DissectError> {
+        let columns = Columns::new(schema);
+        let file = BufWriter::new(File::create(path)?);
+        let writer = if feather {
+            Writer::File(FileWriter::try_new(file, &columns.arrow).map_err(arrow_error)?)
+        } else {
+            Writer::Stream(StreamWriter::try_new(file, &columns.arrow).map_err(arrow_error)?)
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            columns,
+            writer: Mutex::new(writer),
+            count: AtomicUsize::new(0),
+            batches: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Sink for IpcSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        self.write_batch(std::slice::from_ref(doc))
+    }
+
+    fn write_batch(&self, docs: &[Document]) -> Result<(), DissectError> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        let rows = docs
+            .iter()
+            .map(|doc| self.columns.schema.row(doc))
+            .collect::<Vec<_>>();
+        let batch = self.columns.batch(&rows)?;
+        self.writer.lock().write(&batch)?;
+        self.count.fetch_add(docs.len(), Ordering::Relaxed);
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        self.writer.lock().finish()?;
+        Ok(format!(
+            "Wrote {} documents in {} record batches of {} columns to {}{}",
+            self.count.into_inner(),
+            self.batches.into_inner(),
+            self.columns.schema.columns.len(),
+            self.path.display(),
+            misfits_note(self.columns.misfits())
+        ))
+    }
+}
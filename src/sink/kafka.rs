@@ -0,0 +1,71 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use bson::Document;
+use rdkafka::{
+    error::{KafkaError, RDKafkaErrorCode},
+    producer::{BaseRecord, DefaultProducerContext, Producer, ThreadedProducer},
+    ClientConfig,
+};
+
+use super::Sink;
+use crate::{index::document_key, DissectError};
+
+/// How long finishing waits for queued messages to be delivered
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Sends every document as a json message to a kafka topic, keyed by its `_id`
+pub(crate) struct KafkaSink {
+    producer: ThreadedProducer<DefaultProducerContext>,
+    topic: String,
+    count: AtomicUsize,
+}
+
+impl KafkaSink {
+    pub fn connect(brokers: &str, topic: &str) -> Result<Self, DissectError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()
+            .map_err(kafka_error)?;
+        Ok(Self {
+            producer,
+            topic: topic.into(),
+            count: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Sink for KafkaSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        let payload = serde_json::to_vec(doc)?;
+        let key = document_key(doc, "_id").unwrap_or_default();
+        loop {
+            let record = BaseRecord::to(&self.topic).payload(&payload).key(&key);
+            match self.producer.send(record) {
+                Ok(()) => break,
+                // the local queue is full, wait for the background thread to deliver some
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                Err((e, _)) => return Err(kafka_error(e)),
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        self.producer.flush(FLUSH_TIMEOUT).map_err(kafka_error)?;
+        Ok(format!(
+            "Sent {} documents to kafka topic {}",
+            self.count.into_inner(),
+            self.topic
+        ))
+    }
+}
+
+fn kafka_error(e: KafkaError) -> DissectError {
+    DissectError::Unexpected(format!("Kafka: {e}"))
+}
@@ -0,0 +1,295 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use bson::Document;
+
+use crate::{
+    env::Env,
+    output::{table::Schema, OutputFormat},
+    Args, DissectError,
+};
+
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+mod arrow;
+mod clickhouse;
+mod dir;
+#[cfg(feature = "duckdb")]
+mod duckdb;
+mod file;
+mod http;
+#[cfg(feature = "arrow")]
+mod ipc;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod pace;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod plugin;
+mod redis;
+mod route;
+mod sqlite;
+mod stats;
+mod template;
+
+pub(crate) use pace::Pacer;
+pub use pace::{PaceBy, Rate};
+pub use route::RouteSpec;
+pub(crate) use route::Routes;
+
+/// An extra destination for the exported documents, given to --sink as `out.ndjson`, `out.json`, `out.yaml`,
+/// `out.xml`, `dir/`, `stats:fields.ndjson`, `kafka://host:9092/topic`, `http://api/ingest`, `redis://host:6379`
+/// `clickhouse://host/db.table` or `plugin:name[:argument]`
+#[derive(Debug, Clone)]
+pub enum SinkSpec {
+    /// A single file, its format picked by the extension
+    File(PathBuf),
+    /// One file per document in a directory
+    Dir(PathBuf),
+    /// The field statistics of the exported documents, as ndjson or csv by the extension
+    Stats(PathBuf),
+    /// A kafka topic, one message per document
+    Kafka { brokers: String, topic: String },
+    /// An http endpoint documents are posted to in batches
+    Http(String),
+    /// A redis server documents are stored in by key or appended to a stream on
+    Redis(String),
+    /// A ClickHouse table the flattened documents are inserted into
+    ClickHouse(String),
+    /// An external program reading the documents as ndjson, `dissbson-sink-<name>` on the plugin path or PATH
+    Plugin { name: String, arg: Option<String> },
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("stats:") {
+            return Ok(Self::Stats(path.into()));
+        }
+        if let Some(rest) = s.strip_prefix("kafka://") {
+            return match rest.split_once('/') {
+                Some((brokers, topic)) if !brokers.is_empty() && !topic.is_empty() => {
+                    Ok(Self::Kafka {
+                        brokers: brokers.into(),
+                        topic: topic.into(),
+                    })
+                }
+                _ => Err(format!("expected kafka://<brokers>/<topic>, got {s}")),
+            };
+        }
+        if let Some(rest) = s.strip_prefix("plugin:") {
+            let (name, arg) = match rest.split_once(':') {
+                Some((name, arg)) => (name, Some(arg.to_string())),
+                None => (rest, None),
+            };
+            if name.is_empty() {
+                return Err(format!("expected plugin:<name>[:<argument>], got {s}"));
+            }
+            return Ok(Self::Plugin {
+                name: name.into(),
+                arg,
+            });
+        }
+        if s.starts_with("clickhouse://") {
+            return Ok(Self::ClickHouse(s.into()));
+        }
+        if s.starts_with("redis://") || s.starts_with("rediss://") {
+            return Ok(Self::Redis(s.into()));
+        }
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(Self::Http(s.into()));
+        }
+        if s.ends_with('/') || Path::new(s).is_dir() {
+            return Ok(Self::Dir(s.into()));
+        }
+        file::format_of(Path::new(s))?;
+        Ok(Self::File(s.into()))
+    }
+}
+
+impl SinkSpec {
+    /// The sink for --explain, what kind of output it is and where it is
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Self::File(path) => format!("file {}", path.display()),
+            Self::Dir(path) => format!("a file per document in {}", path.display()),
+            Self::Stats(path) => format!("field statistics in {}", path.display()),
+            Self::Kafka { brokers, topic } => format!("kafka topic {topic} on {brokers}"),
+            Self::Http(url) => format!("http endpoint {url}"),
+            Self::Redis(url) => format!("redis {url}"),
+            Self::ClickHouse(url) => format!("clickhouse {url}"),
+            Self::Plugin { name, arg } => match arg {
+                Some(arg) => format!("plugin dissbson-sink-{name} with {arg}"),
+                None => format!("plugin dissbson-sink-{name}"),
+            },
+        }
+    }
+
+    /// The spec with the `${KEY}` variables of --env in its urls, paths and arguments replaced
+    pub(crate) fn expand(&self, env: &Env) -> Result<Self, DissectError> {
+        let path = |path: &PathBuf| -> Result<PathBuf, DissectError> {
+            Ok(env.expand(&path.to_string_lossy())?.into())
+        };
+        Ok(match self {
+            Self::File(p) => Self::File(path(p)?),
+            Self::Dir(p) => Self::Dir(path(p)?),
+            Self::Stats(p) => Self::Stats(path(p)?),
+            Self::Kafka { brokers, topic } => Self::Kafka {
+                brokers: env.expand(brokers)?,
+                topic: env.expand(topic)?,
+            },
+            Self::Http(url) => Self::Http(env.expand(url)?),
+            Self::Redis(url) => Self::Redis(env.expand(url)?),
+            Self::ClickHouse(url) => Self::ClickHouse(env.expand(url)?),
+            Self::Plugin { name, arg } => Self::Plugin {
+                name: name.clone(),
+                arg: arg.as_deref().map(|arg| env.expand(arg)).transpose()?,
+            },
+        })
+    }
+}
+
+/// A destination documents are copied to, shared by the export threads
+pub(crate) trait Sink: Send + Sync {
+    fn write(&self, doc: &Document) -> Result<(), DissectError>;
+
+    /// Write the documents of one batch of the export, outputs grouping rows write them together
+    fn write_batch(&self, docs: &[Document]) -> Result<(), DissectError> {
+        docs.iter().try_for_each(|doc| self.write(doc))
+    }
+
+    /// Complete the output, returns a line for the export summary
+    fn finish(self: Box<Self>) -> Result<String, DissectError>;
+}
+
+/// Every --sink of an export, each exported document goes to all of them
+#[derive(Default)]
+pub(crate) struct Sinks(Vec<Box<dyn Sink>>);
+
+impl Sinks {
+    pub fn from_args(args: &Args) -> Result<Self, DissectError> {
+        args.sink
+            .iter()
+            .map(|spec| open(spec, args))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        self.0.iter().try_for_each(|sink| sink.write(doc))
+    }
+
+    /// Complete every sink, returns their lines for the export summary
+    pub fn finish(self) -> Result<Vec<String>, DissectError> {
+        self.0.into_iter().map(|sink| sink.finish()).collect()
+    }
+}
+
+/// The sink writing the main output when --format is a database, the columns are picked from `sample`, the first
+/// documents of the export as the stages left them
+pub(crate) fn open_database(
+    args: &Args,
+    path: &Path,
+    sample: &[Document],
+) -> Result<Box<dyn Sink>, DissectError> {
+    let schema = |kind: &str| {
+        let schema = Schema::of_documents(sample);
+        println!(
+            "Picked {} {kind} columns from the first {} documents",
+            schema.columns.len(),
+            sample.len()
+        );
+        schema
+    };
+    match args.output_format() {
+        OutputFormat::Sqlite => {
+            let promote = args
+                .promote
+                .iter()
+                .filter(|name| {
+                    let excluded = args
+                        .exclude_fields
+                        .as_ref()
+                        .is_some_and(|exclude| exclude.excludes(name));
+                    if excluded {
+                        println!(
+                            "--promote {name} is removed by --exclude-fields, it gets no column"
+                        );
+                    }
+                    !excluded
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let promoted = Schema::of_documents(sample).select(&promote);
+            Ok(Box::new(sqlite::SqliteSink::create(
+                path,
+                &args.table,
+                promoted,
+            )?))
+        }
+        #[cfg(feature = "duckdb")]
+        OutputFormat::Duckdb if args.table_per_shape => Ok(Box::new(
+            duckdb::DuckDbSink::per_shape(path, &args.table, sample)?,
+        )),
+        #[cfg(feature = "duckdb")]
+        OutputFormat::Duckdb => Ok(Box::new(duckdb::DuckDbSink::create(
+            path,
+            &args.table,
+            schema("duckdb"),
+        )?)),
+        #[cfg(not(feature = "duckdb"))]
+        OutputFormat::Duckdb => {
+            let _ = (path, schema);
+            Err(DissectError::Unexpected(
+                "DuckDB output needs dissbson built with the duckdb feature".into(),
+            ))
+        }
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => Ok(Box::new(parquet::ParquetSink::create(
+            path,
+            schema("parquet"),
+            args.row_group_size,
+        )?)),
+        #[cfg(not(feature = "parquet"))]
+        OutputFormat::Parquet => Err(DissectError::Unexpected(
+            "Parquet output needs dissbson built with the parquet feature".into(),
+        )),
+        #[cfg(feature = "arrow")]
+        OutputFormat::Arrow | OutputFormat::Feather => Ok(Box::new(ipc::IpcSink::create(
+            path,
+            schema("arrow"),
+            args.output_format() == OutputFormat::Feather,
+        )?)),
+        #[cfg(not(feature = "arrow"))]
+        OutputFormat::Arrow | OutputFormat::Feather => Err(DissectError::Unexpected(
+            "Arrow output needs dissbson built with the arrow feature".into(),
+        )),
+        format => Err(DissectError::Parse(format!(
+            "{format:?} output is not a database"
+        ))),
+    }
+}
+
+fn open(spec: &SinkSpec, args: &Args) -> Result<Box<dyn Sink>, DissectError> {
+    Ok(match spec {
+        SinkSpec::File(path) => Box::new(file::FileSink::create(path, args)?),
+        SinkSpec::Dir(path) => Box::new(dir::DirSink::create(path, args)?),
+        SinkSpec::Stats(path) => Box::new(stats::StatsSink::new(path, &args.csv)),
+        SinkSpec::Http(url) => Box::new(http::HttpSink::new(url, args)?),
+        SinkSpec::Redis(url) => Box::new(redis::RedisSink::connect(url, args)?),
+        SinkSpec::ClickHouse(url) => Box::new(clickhouse::connect(url, args)?),
+        SinkSpec::Plugin { name, arg } => {
+            Box::new(plugin::PluginSink::spawn(name, arg.as_deref())?)
+        }
+        #[cfg(feature = "kafka")]
+        SinkSpec::Kafka { brokers, topic } => Box::new(kafka::KafkaSink::connect(brokers, topic)?),
+        #[cfg(not(feature = "kafka"))]
+        SinkSpec::Kafka { .. } => {
+            return Err(DissectError::Unexpected(
+                "Kafka sinks need dissbson built with the kafka feature".into(),
+            ))
+        }
+    })
+}
@@ -0,0 +1,146 @@
+use std::{
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bson::{Bson, DateTime, Document};
+use parking_lot::Mutex;
+
+use crate::{docpath, Args};
+
+/// A fixed document rate, given as `500/s`, `300/m` or `1000/h`
+#[derive(Debug, Clone, Copy)]
+pub struct Rate(Duration);
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, unit) = s.split_once('/').unwrap_or((s, "s"));
+        let per = match unit {
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(format!("unknown unit {unit}, expected s|m|h")),
+        };
+        match count.parse::<f64>() {
+            Ok(count) if count > 0.0 => Ok(Self(Duration::from_secs_f64(per / count))),
+            _ => Err(format!("expected a rate like 500/s, got {s}")),
+        }
+    }
+}
+
+impl Rate {
+    /// The rate for --explain in documents a second
+    pub(crate) fn describe(&self) -> String {
+        format!("{:.2} documents a second", 1.0 / self.0.as_secs_f64())
+    }
+}
+
+/// Replays documents as far apart as the timestamps in one of their fields, given as `created_at` or
+/// `created_at:60` to replay an hour of events in a minute
+#[derive(Debug, Clone)]
+pub struct PaceBy {
+    path: Vec<String>,
+    speedup: f64,
+}
+
+impl FromStr for PaceBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, speedup) = match s.split_once(':') {
+            Some((path, speedup)) => match speedup.parse::<f64>() {
+                Ok(speedup) if speedup > 0.0 => (path, speedup),
+                _ => return Err(format!("expected a positive speedup, got {speedup}")),
+            },
+            None => (s, 1.0),
+        };
+        Ok(Self {
+            path: docpath::parse(path),
+            speedup,
+        })
+    }
+}
+
+impl PaceBy {
+    /// The field and speedup for --explain
+    pub(crate) fn describe(&self) -> String {
+        format!(
+            "the dates in {} sped up {}x",
+            self.path.join("."),
+            self.speedup
+        )
+    }
+}
+
+/// Holds back documents on their way to the outputs to replay them at --rate or --pace-by
+pub(crate) struct Pacer {
+    interval: Option<Duration>,
+    /// When the next document may go out at the fixed rate
+    next: Mutex<Instant>,
+    pace: Option<PaceBy>,
+    /// The start of the replay and the timestamp of the first paced document
+    start: Mutex<Option<(Instant, i64)>>,
+}
+
+impl Pacer {
+    pub fn from_args(args: &Args) -> Option<Self> {
+        if args.rate.is_none() && args.pace_by.is_none() {
+            return None;
+        }
+        Some(Self {
+            interval: args.rate.map(|rate| rate.0),
+            next: Mutex::new(Instant::now()),
+            pace: args.pace_by.clone(),
+            start: Mutex::new(None),
+        })
+    }
+
+    /// Block until `doc` is due
+    pub fn wait(&self, doc: &Document) {
+        if let Some(pace) = &self.pace {
+            if let Some(millis) = timestamp(doc, &pace.path) {
+                let (started, first) = *self.start.lock().get_or_insert((Instant::now(), millis));
+                // documents older than the first one are late already and go out at once
+                let offset = (millis - first).max(0) as f64 / 1000.0 / pace.speedup;
+                sleep_until(started + Duration::from_secs_f64(offset));
+            }
+        }
+        if let Some(interval) = self.interval {
+            let slot = {
+                let mut next = self.next.lock();
+                let slot = (*next).max(Instant::now());
+                *next = slot + interval;
+                slot
+            };
+            sleep_until(slot);
+        }
+    }
+}
+
+/// Milliseconds since the epoch of the first date, ObjectId or RFC 3339 string at `path`
+fn timestamp(doc: &Document, path: &[String]) -> Option<i64> {
+    let mut found = None;
+    docpath::visit(doc, path, &mut |value| {
+        if found.is_none() {
+            found = match value {
+                Bson::DateTime(date) => Some(date.timestamp_millis()),
+                Bson::ObjectId(oid) => Some(oid.timestamp().timestamp_millis()),
+                Bson::String(s) => DateTime::parse_rfc3339_str(s)
+                    .ok()
+                    .map(|date| date.timestamp_millis()),
+                _ => None,
+            };
+        }
+    });
+    found
+}
+
+fn sleep_until(at: Instant) {
+    let now = Instant::now();
+    if at > now {
+        thread::sleep(at - now);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::Document;
+use parking_lot::Mutex;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+use super::{
+    arrow::{misfits_note, Columns},
+    Sink,
+};
+use crate::{
+    output::table::{Cell, Schema},
+    DissectError,
+};
+
+/// Rows converted into columns and handed to the writer at once
+const WRITE_BATCH: usize = 10_000;
+
+/// Writes the flattened documents into a parquet file, a column per field of the sampled schema
+pub(crate) struct ParquetSink {
+    path: PathBuf,
+    columns: Columns,
+    writer: Mutex<ArrowWriter<File>>,
+    rows: Mutex<Vec<Vec<Cell>>>,
+    count: AtomicUsize,
+}
+
+impl ParquetSink {
+    pub fn create(path: &Path, schema: Schema, row_group: usize) -> Result<Self, DissectError> {
+        let columns = Columns::new(schema);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(row_group.max(1))
+            .build();
+        let writer =
+            ArrowWriter::try_new(File::create(path)?, columns.arrow.clone(), Some(properties))
+                .map_err(parquet_error)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            columns,
+            writer: Mutex::new(writer),
+            rows: Mutex::new(Vec::new()),
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    fn append(&self, rows: Vec<Vec<Cell>>) -> Result<(), DissectError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let batch = self.columns.batch(&rows)?;
+        self.writer.lock().write(&batch).map_err(parquet_error)
+    }
+}
+
+impl Sink for ParquetSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        let row = self.columns.schema.row(doc);
+        let full = {
+            let mut rows = self.rows.lock();
+            rows.push(row);
+            (rows.len() >= WRITE_BATCH).then(|| std::mem::take(&mut *rows))
+        };
+        if let Some(rows) = full {
+            self.append(rows)?;
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        let rows = std::mem::take(&mut *self.rows.lock());
+        self.append(rows)?;
+        self.writer.into_inner().close().map_err(parquet_error)?;
+        Ok(format!(
+            "Wrote {} documents in {} columns to {}{}",
+            self.count.into_inner(),
+            self.columns.schema.columns.len(),
+            self.path.display(),
+            misfits_note(self.columns.misfits())
+        ))
+    }
+}
+
+fn parquet_error(e: impl std::fmt::Display) -> DissectError {
+    DissectError::Unexpected(format!("Parquet: {e}"))
+}
@@ -0,0 +1,169 @@
+use std::{
+    env,
+    io::{BufWriter, Read, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::JoinHandle,
+};
+
+use bson::{Bson, Document};
+use parking_lot::Mutex;
+
+use super::Sink;
+use crate::DissectError;
+
+/// Executables providing the sink `name` are called `dissbson-sink-<name>`
+const PREFIX: &str = "dissbson-sink-";
+
+/// Version of the protocol spoken with plugins, passed to them in DISSBSON_PLUGIN_PROTOCOL
+const PROTOCOL: &str = "1";
+
+/// Directories searched for plugins before PATH
+const PLUGIN_PATH: &str = "DISSBSON_PLUGIN_PATH";
+
+/// Hands every document to an external program as a line of relaxed extended json on its stdin.
+/// The program gets the argument of the spec, has to exit successfully once its stdin closes
+/// and the last line it prints is shown in the export summary
+pub(crate) struct PluginSink {
+    name: String,
+    child: Mutex<Child>,
+    stdin: Mutex<Option<BufWriter<ChildStdin>>>,
+    /// Collects the output of the plugin so it never blocks on a full pipe
+    stdout: JoinHandle<std::io::Result<String>>,
+    count: AtomicUsize,
+}
+
+impl PluginSink {
+    pub fn spawn(name: &str, arg: Option<&str>) -> Result<Self, DissectError> {
+        let program = find(name)?;
+        let mut child = Command::new(&program)
+            .args(arg)
+            .env("DISSBSON_PLUGIN_PROTOCOL", PROTOCOL)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                DissectError::Unexpected(format!("Failed to start {}: {e}", program.display()))
+            })?;
+        let stdin = child.stdin.take().map(BufWriter::new);
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let stdout = std::thread::spawn(move || {
+            let mut text = String::new();
+            stdout.read_to_string(&mut text)?;
+            Ok(text)
+        });
+        Ok(Self {
+            name: name.to_string(),
+            child: Mutex::new(child),
+            stdin: Mutex::new(stdin),
+            stdout,
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    fn failed(&self, e: impl std::fmt::Display) -> DissectError {
+        DissectError::Unexpected(format!("Sink plugin {}: {e}", self.name))
+    }
+}
+
+impl Sink for PluginSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        self.write_batch(std::slice::from_ref(doc))
+    }
+
+    fn write_batch(&self, docs: &[Document]) -> Result<(), DissectError> {
+        let mut lines = Vec::new();
+        for doc in docs {
+            serde_json::to_writer(
+                &mut lines,
+                &Bson::Document(doc.clone()).into_relaxed_extjson(),
+            )?;
+            lines.push(b'\n');
+        }
+        let mut stdin = self.stdin.lock();
+        let stdin = stdin
+            .as_mut()
+            .ok_or_else(|| self.failed("stdin is closed"))?;
+        stdin.write_all(&lines).map_err(|e| self.failed(e))?;
+        self.count.fetch_add(docs.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        if let Some(mut stdin) = self.stdin.lock().take() {
+            stdin.flush().map_err(|e| self.failed(e))?;
+        }
+        let status = self.child.lock().wait()?;
+        let Self {
+            name,
+            stdout,
+            count,
+            ..
+        } = *self;
+        let output = stdout.join().map_err(|_| {
+            DissectError::Unexpected(format!("Sink plugin {name}: reading its output panicked"))
+        })??;
+        if !status.success() {
+            return Err(DissectError::Unexpected(format!(
+                "Sink plugin {name}: exited with {status}"
+            )));
+        }
+        let count = count.into_inner();
+        Ok(
+            match output.lines().rev().find(|line| !line.trim().is_empty()) {
+                Some(line) => format!("Sent {count} documents to sink plugin {name}: {line}"),
+                None => format!("Sent {count} documents to sink plugin {name}"),
+            },
+        )
+    }
+}
+
+/// Directories plugins are looked up in, DISSBSON_PLUGIN_PATH first, then PATH
+fn search_path() -> Vec<PathBuf> {
+    [PLUGIN_PATH, "PATH"]
+        .into_iter()
+        .filter_map(env::var_os)
+        .flat_map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .collect()
+}
+
+/// The executable of plugin `name`, a path is taken as is
+fn find(name: &str) -> Result<PathBuf, DissectError> {
+    if name.contains(std::path::MAIN_SEPARATOR) {
+        return Ok(PathBuf::from(name));
+    }
+    let file = format!("{PREFIX}{name}");
+    search_path()
+        .into_iter()
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            let found = discover();
+            DissectError::Parse(format!(
+                "No sink plugin {name}, looked for {file} in {PLUGIN_PATH} and PATH, {}",
+                if found.is_empty() {
+                    "none are installed".to_string()
+                } else {
+                    format!("installed are {}", found.join(", "))
+                }
+            ))
+        })
+}
+
+/// Names of every plugin on the search path
+fn discover() -> Vec<String> {
+    let mut names = search_path()
+        .into_iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_prefix(PREFIX).map(str::to_string)
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bson::Document;
+use parking_lot::Mutex;
+use redis::{Connection, Pipeline, RedisError};
+
+use super::{template::Template, Sink};
+use crate::{Args, DissectError};
+
+/// Commands sent to the server in one round trip
+const PIPELINE: usize = 100;
+
+/// Stores every document as json under a key or appends it to a stream
+pub(crate) struct RedisSink {
+    target: Target,
+    ttl: Option<u64>,
+    /// The connection and the commands not sent yet
+    connection: Mutex<(Connection, Pipeline, usize)>,
+    count: AtomicUsize,
+}
+
+enum Target {
+    /// SET of the key the template gives for a document
+    Key(Template),
+    /// XADD to the stream the template gives, the document in its `doc` field
+    Stream(Template),
+}
+
+impl RedisSink {
+    pub fn connect(url: &str, args: &Args) -> Result<Self, DissectError> {
+        let target = match &args.redis_stream {
+            Some(stream) => Target::Stream(Template::parse(stream)?),
+            None => Target::Key(Template::parse(&args.redis_key)?),
+        };
+        let connection = redis::Client::open(url)
+            .and_then(|client| client.get_connection())
+            .map_err(redis_error)?;
+        Ok(Self {
+            target,
+            ttl: args.redis_ttl,
+            connection: Mutex::new((connection, redis::pipe(), 0)),
+            count: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Sink for RedisSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        let json = serde_json::to_string(doc)?;
+        let mut guard = self.connection.lock();
+        let (connection, pipeline, queued) = &mut *guard;
+        match &self.target {
+            Target::Key(key) => {
+                let command = pipeline.cmd("SET").arg(key.render(doc)).arg(json);
+                if let Some(ttl) = self.ttl {
+                    command.arg("EX").arg(ttl);
+                }
+                command.ignore();
+            }
+            Target::Stream(stream) => {
+                pipeline
+                    .cmd("XADD")
+                    .arg(stream.render(doc))
+                    .arg("*")
+                    .arg("doc")
+                    .arg(json)
+                    .ignore();
+            }
+        }
+        *queued += 1;
+        if *queued >= PIPELINE {
+            send(connection, pipeline)?;
+            *queued = 0;
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        let (mut connection, mut pipeline, _) = self.connection.into_inner();
+        send(&mut connection, &mut pipeline)?;
+        let what = match self.target {
+            Target::Key(_) => "keys",
+            Target::Stream(_) => "stream entries",
+        };
+        Ok(format!(
+            "Wrote {} documents to redis {what}",
+            self.count.into_inner()
+        ))
+    }
+}
+
+fn send(connection: &mut Connection, pipeline: &mut Pipeline) -> Result<(), DissectError> {
+    pipeline.query::<()>(connection).map_err(redis_error)?;
+    pipeline.clear();
+    Ok(())
+}
+
+fn redis_error(e: RedisError) -> DissectError {
+    DissectError::Unexpected(format!("Redis: {e}"))
+}
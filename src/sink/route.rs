@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use bson::Document;
+
+use super::{open, Sink, SinkSpec};
+use crate::{docpath::FieldValue, env::Env, Args, DissectError};
+
+/// Sends documents whose field has a value to a sink of their own, given to --route as `type=error:errors.ndjson`
+#[derive(Debug, Clone)]
+pub struct RouteSpec {
+    field: FieldValue,
+    sink: SinkSpec,
+}
+
+impl FromStr for RouteSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the value ends at the first colon, sinks like urls have colons of their own
+        let split = s
+            .find('=')
+            .and_then(|eq| s[eq..].find(':').map(|colon| eq + colon));
+        let Some(split) = split else {
+            return Err(format!("expected field=value:sink, got {s}"));
+        };
+        Ok(Self {
+            field: s[..split].parse()?,
+            sink: s[split + 1..].parse()?,
+        })
+    }
+}
+
+impl RouteSpec {
+    /// The rule for --explain, the documents it matches and where they go
+    pub(crate) fn describe(&self) -> String {
+        format!("{} to {}", self.field.describe(), self.sink.describe())
+    }
+
+    /// The rule with the `${KEY}` variables of --env in its sink replaced
+    pub(crate) fn expand(&self, env: &Env) -> Result<Self, DissectError> {
+        Ok(Self {
+            field: self.field.clone(),
+            sink: self.sink.expand(env)?,
+        })
+    }
+}
+
+/// The --route rules of an export, a document goes to the sink of the first rule it matches instead of the outputs
+#[derive(Default)]
+pub(crate) struct Routes {
+    routes: Vec<(RouteSpec, Box<dyn Sink>)>,
+}
+
+impl Routes {
+    pub fn from_args(args: &Args) -> Result<Self, DissectError> {
+        let routes = args
+            .route
+            .iter()
+            .map(|spec| Ok((spec.clone(), open(&spec.sink, args)?)))
+            .collect::<Result<_, DissectError>>()?;
+        Ok(Self { routes })
+    }
+
+    /// Write the document to the sink of the first rule it matches, returns whether it was taken
+    pub fn route(&self, doc: &Document) -> Result<bool, DissectError> {
+        let Some((_, sink)) = self.routes.iter().find(|(spec, _)| spec.field.matches(doc)) else {
+            return Ok(false);
+        };
+        sink.write(doc)?;
+        Ok(true)
+    }
+
+    /// Complete every route sink, returns their lines for the export summary
+    pub fn finish(self) -> Result<Vec<String>, DissectError> {
+        self.routes
+            .into_iter()
+            .map(|(_, sink)| sink.finish())
+            .collect()
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::{Bson, Document};
+use parking_lot::Mutex;
+use rusqlite::Connection;
+
+use super::Sink;
+use crate::{
+    output::{
+        sqlite,
+        table::{Cell, ColumnType, Keys, Schema},
+    },
+    DissectError,
+};
+
+/// Rows inserted in one transaction
+const INSERT_BATCH: usize = 10_000;
+
+/// Column holding the whole document as json
+const DOC_COLUMN: &str = "doc";
+
+/// Loads every document into a table of a SQLite database as its `_id`, its json and the promoted fields
+pub(crate) struct SqliteSink {
+    path: PathBuf,
+    table: String,
+    /// `_id` followed by the promoted fields, the json of the document comes after them
+    columns: Schema,
+    /// The columns of the table, including the json one
+    schema: Schema,
+    connection: Mutex<Connection>,
+    rows: Mutex<Vec<Vec<Cell>>>,
+    count: AtomicUsize,
+}
+
+impl SqliteSink {
+    /// Open or create the database at `path` and create `table` with the `promoted` columns unless it exists
+    pub fn create(path: &Path, table: &str, promoted: Schema) -> Result<Self, DissectError> {
+        let mut columns = Schema::named(&["_id".to_string()]);
+        columns.columns.extend(
+            promoted
+                .columns
+                .into_iter()
+                .filter(|column| column.name != "_id"),
+        );
+        if columns
+            .columns
+            .iter()
+            .any(|column| column.name == DOC_COLUMN)
+        {
+            return Err(DissectError::Parse(format!(
+                "{DOC_COLUMN} holds the json of the documents, it can't be promoted"
+            )));
+        }
+        let mut schema = columns.clone();
+        let mut doc = Schema::named(&[DOC_COLUMN.to_string()]).columns.remove(0);
+        doc.kind = ColumnType::Json;
+        schema.columns.push(doc);
+
+        let connection = Connection::open(path)?;
+        sqlite::create_table(&connection, table, &schema, &Keys::default())?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            table: table.into(),
+            columns,
+            schema,
+            connection: Mutex::new(connection),
+            rows: Mutex::new(Vec::new()),
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    fn insert(&self, rows: Vec<Vec<Cell>>) -> Result<(), DissectError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        sqlite::insert(&mut self.connection.lock(), &self.table, &self.schema, rows)
+    }
+}
+
+impl Sink for SqliteSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        let mut row = self.columns.row(doc);
+        let json = Bson::Document(doc.clone()).into_relaxed_extjson();
+        row.push(Cell::Text(json.to_string()));
+        let full = {
+            let mut rows = self.rows.lock();
+            rows.push(row);
+            (rows.len() >= INSERT_BATCH).then(|| std::mem::take(&mut *rows))
+        };
+        if let Some(rows) = full {
+            self.insert(rows)?;
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        let rows = std::mem::take(&mut *self.rows.lock());
+        self.insert(rows)?;
+        // indexing once everything is loaded is faster than keeping the indexes up to date on every insert
+        let connection = self.connection.into_inner();
+        for column in &self.columns.columns {
+            sqlite::create_index(&connection, &self.table, &column.name)?;
+        }
+        Ok(format!(
+            "Loaded {} documents into table {} of {}, indexed on {}",
+            self.count.into_inner(),
+            self.table,
+            self.path.display(),
+            self.columns
+                .columns
+                .iter()
+                .map(|column| column.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bson::Document;
+use parking_lot::Mutex;
+
+use super::Sink;
+use crate::{
+    output::dialect::CsvDialect,
+    stats::{profile::Profile, write_csv, write_ndjson},
+    DissectError,
+};
+
+/// Distinct sample values kept per field
+const SAMPLES: usize = 3;
+
+/// Profiles the fields of the exported documents and writes the statistics once the export is done
+pub(crate) struct StatsSink {
+    path: PathBuf,
+    dialect: CsvDialect,
+    profile: Mutex<Profile>,
+}
+
+impl StatsSink {
+    pub fn new(path: &Path, dialect: &CsvDialect) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            dialect: dialect.clone(),
+            profile: Mutex::new(Profile::default()),
+        }
+    }
+}
+
+impl Sink for StatsSink {
+    fn write(&self, doc: &Document) -> Result<(), DissectError> {
+        self.profile.lock().record(doc, SAMPLES);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, DissectError> {
+        let profile = self.profile.into_inner();
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        match self.path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => write_csv(&mut writer, &profile, &self.dialect)?,
+            _ => write_ndjson(&mut writer, &profile)?,
+        }
+        writer.flush()?;
+        Ok(format!(
+            "Wrote statistics of {} fields to {}",
+            profile.fields.len(),
+            self.path.display()
+        ))
+    }
+}
@@ -0,0 +1,85 @@
+use bson::{Bson, Document};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::{docpath, DissectError};
+
+/// What stays as it is of a value rendered into a url, the unreserved characters of RFC 3986
+pub(super) const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Text naming document fields in braces, like `http://api/{type}` or `user:{_id}`
+pub(crate) struct Template {
+    parts: Vec<Part>,
+    /// Whether the values are percent-encoded, so a value like `a/b?c` stays one path segment or query value
+    url: bool,
+}
+
+enum Part {
+    Text(String),
+    Field(Vec<String>),
+}
+
+impl Template {
+    /// A template whose values are inserted as they are, like a redis key
+    pub fn parse(template: &str) -> Result<Self, DissectError> {
+        Self::new(template, false)
+    }
+
+    /// A url template, its values are percent-encoded
+    pub fn url(template: &str) -> Result<Self, DissectError> {
+        Self::new(template, true)
+    }
+
+    /// A url taken as it is, without fields
+    pub fn literal(url: &str) -> Self {
+        Self {
+            parts: vec![Part::Text(url.to_string())],
+            url: true,
+        }
+    }
+
+    fn new(template: &str, url: bool) -> Result<Self, DissectError> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            let Some(close) = rest[open..].find('}') else {
+                return Err(DissectError::Parse(format!("Unclosed {{ in {template}")));
+            };
+            parts.push(Part::Text(rest[..open].to_string()));
+            parts.push(Part::Field(docpath::parse(&rest[open + 1..open + close])));
+            rest = &rest[open + close + 1..];
+        }
+        parts.push(Part::Text(rest.to_string()));
+        Ok(Self { parts, url })
+    }
+
+    /// The text with every field replaced by its first value in `doc`, missing fields are left empty
+    pub fn render(&self, doc: &Document) -> String {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(literal) => text.push_str(literal),
+                Part::Field(path) => {
+                    let mut value = None;
+                    docpath::visit(doc, path, &mut |v| {
+                        value.get_or_insert_with(|| match v {
+                            Bson::String(s) => s.clone(),
+                            Bson::ObjectId(oid) => oid.to_hex(),
+                            other => other.to_string(),
+                        });
+                    });
+                    let value = value.unwrap_or_default();
+                    if self.url {
+                        text.extend(utf8_percent_encode(&value, UNRESERVED));
+                    } else {
+                        text.push_str(&value);
+                    }
+                }
+            }
+        }
+        text
+    }
+}
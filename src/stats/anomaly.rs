@@ -0,0 +1,110 @@
+use bson::{Bson, Document};
+use humansize::{format_size, DECIMAL};
+
+use crate::{index::Input, DissectError};
+
+use super::{
+    profile::{type_name, walk, Profile},
+    profile_input,
+};
+
+/// Field added to flagged documents, listing why they were flagged
+pub(crate) const ANOMALY_FIELD: &str = "_anomalies";
+
+/// Flags documents whose size or field types stray from what the rest of the dump looks like
+#[derive(Debug)]
+pub(crate) struct Detector {
+    profile: Profile,
+    log_mean: f64,
+    log_std: f64,
+    sigma: f64,
+    rate: f64,
+}
+
+impl Detector {
+    /// Profile the whole input, sizes come from the index and types from a pass over every document
+    pub fn build(
+        input: &Input,
+        threads: usize,
+        batch: usize,
+        sigma: f64,
+        rate: f64,
+    ) -> Result<Self, DissectError> {
+        let logs = input
+            .offsets
+            .iter()
+            .map(|o| (o.size.max(1) as f64).ln())
+            .collect::<Vec<_>>();
+        let n = logs.len().max(1) as f64;
+        let log_mean = logs.iter().sum::<f64>() / n;
+        let log_std = (logs.iter().map(|l| (l - log_mean).powi(2)).sum::<f64>() / n).sqrt();
+
+        let profile = profile_input(input, threads, batch, 0)?;
+        println!(
+            "Profiled {} documents and {} fields for anomaly detection",
+            profile.documents,
+            profile.fields.len()
+        );
+
+        Ok(Self {
+            profile,
+            log_mean,
+            log_std,
+            sigma,
+            rate,
+        })
+    }
+
+    /// Reasons `doc` looks out of place, empty when it doesn't
+    pub fn check(&self, doc: &Document, size: usize) -> Vec<String> {
+        let mut reasons = Vec::new();
+
+        if self.log_std > 0.0 {
+            let z = ((size.max(1) as f64).ln() - self.log_mean) / self.log_std;
+            if z.abs() > self.sigma {
+                reasons.push(format!(
+                    "size {} is {z:.1} sigma from the typical {}",
+                    format_size(size, DECIMAL),
+                    format_size(self.log_mean.exp() as usize, DECIMAL)
+                ));
+            }
+        }
+
+        let documents = self.profile.documents.max(1) as f64;
+        walk("", doc, &mut |path, value| {
+            let Some(stats) = self.profile.fields.get(path) else {
+                return;
+            };
+            let presence = stats.count as f64 / documents;
+            if presence < self.rate {
+                reasons.push(format!(
+                    "field {path} is present in {:.2}% of documents",
+                    presence * 100.0
+                ));
+            }
+            let name = type_name(value);
+            let share = stats.types.get(name).copied().unwrap_or(0) as f64 / stats.count as f64;
+            if share < self.rate {
+                reasons.push(format!(
+                    "field {path} is {name} in {:.2}% of values",
+                    share * 100.0
+                ));
+            }
+        });
+
+        reasons
+    }
+
+    /// Check `doc` and list the reasons in [`ANOMALY_FIELD`], returns whether it was flagged
+    pub fn mark(&self, doc: &mut Document, size: usize) -> bool {
+        let reasons = self.check(doc, size);
+        if reasons.is_empty() {
+            return false;
+        }
+        doc.insert(
+            ANOMALY_FIELD,
+            reasons.into_iter().map(Bson::String).collect::<Vec<_>>(),
+        );
+        true
+    }
+}
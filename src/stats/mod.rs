@@ -0,0 +1,291 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use humansize::{format_size, DECIMAL};
+use rayon::{prelude::*, ThreadPoolBuilder};
+use serde_json::json;
+
+use crate::{
+    index::{self, DocReader, Input},
+    output::dialect::CsvDialect,
+    transform::Redaction,
+    DissectError,
+};
+
+pub(crate) mod anomaly;
+pub(crate) mod numeric;
+pub(crate) mod pii;
+pub(crate) mod profile;
+pub(crate) mod sizes;
+pub(crate) mod timeline;
+
+use profile::Profile;
+
+/// Print statistics about a dump, document sizes come from the index and --deep profiles every field
+#[derive(Debug, clap::Args)]
+pub struct StatsArgs {
+    /// The input file or directory to read
+    pub input: PathBuf,
+
+    /// Profile every field of every document: types, null rates, min/max and sample values
+    #[clap(long)]
+    pub deep: bool,
+
+    /// Report which field paths take the most bytes over all documents, read from the raw documents
+    #[clap(long)]
+    pub field_sizes: bool,
+
+    /// Propose the heavy fields to drop so the documents fit in --target-size
+    #[clap(long, requires = "target_size")]
+    pub suggest_projection: bool,
+
+    /// Size budget of --suggest-projection like 5GB or 512MiB, measured on the bson encoding of the documents
+    #[clap(long, value_parser = sizes::parse_size)]
+    pub target_size: Option<u64>,
+
+    /// Count documents per time bucket of a date field, like created_at:hour, the bucket is minute, hour, day, week,
+    /// month or year, ObjectIds count by their creation time
+    #[clap(long, value_name = "FIELD[:BUCKET]")]
+    pub timeline: Option<timeline::TimelineSpec>,
+
+    /// Draw the --timeline as a sparkline above the table
+    #[clap(long, requires = "timeline")]
+    pub sparkline: bool,
+
+    /// How many of the heaviest fields --field-sizes lists
+    #[clap(long, default_value = "25")]
+    pub top: usize,
+
+    /// Write the per-field findings of --deep to this file as a dataset
+    #[clap(short, long, requires = "deep")]
+    pub output: Option<PathBuf>,
+
+    /// Format of the dataset written to --output
+    #[clap(long, value_enum, default_value_t = EmitFormat::Ndjson)]
+    pub emit: EmitFormat,
+
+    #[clap(flatten)]
+    pub csv: CsvDialect,
+
+    /// How many distinct sample values to keep and print per field
+    #[clap(long, default_value = "3")]
+    pub samples: usize,
+
+    /// Take the sample values of --deep from documents redacted by the rules of this file, like the one given to
+    /// the export, so the report can be shared along with the redacted dump
+    #[clap(long, requires = "deep")]
+    pub redact: Option<PathBuf>,
+
+    /// The number of threads to use
+    #[clap(short, long, default_value = "4")]
+    pub threads: usize,
+
+    /// How many documents each thread profiles at a time
+    #[clap(short, long, default_value = "100")]
+    pub batch: usize,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EmitFormat {
+    /// One json object per field
+    Ndjson,
+    /// One row per field with a header
+    Csv,
+}
+
+pub(crate) fn run(args: &StatsArgs) -> Result<(), DissectError> {
+    let input = index::load_input(&args.input, args.inspect)?;
+    print_sizes(&input);
+
+    if args.field_sizes || args.suggest_projection {
+        let sizes = sizes::measure_input(&input, args.threads, args.batch)?;
+        if args.field_sizes {
+            sizes::print_field_sizes(&sizes, args.top);
+        }
+        if let (true, Some(target)) = (args.suggest_projection, args.target_size) {
+            sizes::print_projection(&sizes, target);
+        }
+    }
+
+    if let Some(spec) = &args.timeline {
+        let timeline = timeline::count_input(&input, spec, args.threads, args.batch)?;
+        timeline::print_timeline(&timeline, spec, args.sparkline);
+    }
+
+    if !args.deep {
+        return Ok(());
+    }
+
+    let redaction = args.redact.as_deref().map(Redaction::load).transpose()?;
+    let profile = profile_redacted(
+        &input,
+        args.threads,
+        args.batch,
+        args.samples,
+        redaction.as_ref(),
+    )?;
+    print_profile(&profile);
+
+    if let Some(output) = &args.output {
+        let mut writer = BufWriter::new(File::create(output)?);
+        match args.emit {
+            EmitFormat::Ndjson => write_ndjson(&mut writer, &profile)?,
+            EmitFormat::Csv => write_csv(&mut writer, &profile, &args.csv)?,
+        }
+        writer.flush()?;
+        println!("Wrote field statistics to {}", output.display());
+    }
+    Ok(())
+}
+
+/// Profile every document of the input in parallel
+pub(crate) fn profile_input(
+    input: &Input,
+    threads: usize,
+    batch: usize,
+    samples: usize,
+) -> Result<Profile, DissectError> {
+    profile_redacted(input, threads, batch, samples, None)
+}
+
+/// Profile every document of the input in parallel, the samples taken after redaction when there are rules
+fn profile_redacted(
+    input: &Input,
+    threads: usize,
+    batch: usize,
+    samples: usize,
+    redaction: Option<&Redaction>,
+) -> Result<Profile, DissectError> {
+    let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+    thread_pool.install(|| {
+        input
+            .offsets
+            .par_chunks(batch.max(1))
+            .map(|offsets| {
+                let mut reader = DocReader::new(input);
+                let mut profile = Profile::default();
+                for offset in offsets {
+                    let doc = reader.read_document(offset)?;
+                    match redaction {
+                        Some(redaction) => {
+                            let mut redacted = doc.clone();
+                            redaction.apply(&mut redacted);
+                            profile.record_redacted(&doc, &redacted, samples);
+                        }
+                        None => profile.record(&doc, samples),
+                    }
+                }
+                Ok(profile)
+            })
+            .try_reduce(Profile::default, |a, b| Ok(a.merge(b, samples)))
+    })
+}
+
+fn print_sizes(input: &Input) {
+    let sizes = input.offsets.iter().map(|o| o.size);
+    let total: usize = sizes.clone().sum();
+    let count = input.offsets.len();
+    println!("Files:     {}", input.files.len());
+    println!("Documents: {count}");
+    println!("Total:     {}", format_size(total, DECIMAL));
+    if let (Some(min), Some(max), Some(avg)) =
+        (sizes.clone().min(), sizes.max(), total.checked_div(count))
+    {
+        println!("Smallest:  {}", format_size(min, DECIMAL));
+        println!("Largest:   {}", format_size(max, DECIMAL));
+        println!("Average:   {}", format_size(avg, DECIMAL));
+    }
+}
+
+fn print_profile(profile: &Profile) {
+    println!();
+    println!(
+        "{:<40} {:>9} {:>7}  {:<24} {:<24} {:<24} samples",
+        "field", "present", "null", "types", "min", "max"
+    );
+    for (path, stats) in &profile.fields {
+        println!(
+            "{:<40} {:>8.1}% {:>6.1}%  {:<24} {:<24} {:<24} {}",
+            path,
+            stats.count as f64 * 100.0 / profile.documents.max(1) as f64,
+            stats.null_rate(profile.documents) * 100.0,
+            stats.types_text(),
+            cell(stats.min()).unwrap_or_default(),
+            cell(stats.max()).unwrap_or_default(),
+            stats.samples.join(" | "),
+        );
+    }
+}
+
+pub(crate) fn write_ndjson<W: Write>(
+    writer: &mut W,
+    profile: &Profile,
+) -> Result<(), DissectError> {
+    for (path, stats) in &profile.fields {
+        let record = json!({
+            "path": path,
+            "count": stats.count,
+            "documents": profile.documents,
+            "null_rate": stats.null_rate(profile.documents),
+            "types": stats.types,
+            "min": stats.min(),
+            "max": stats.max(),
+            "samples": stats.samples,
+        });
+        serde_json::to_writer(&mut *writer, &record)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_csv<W: Write>(
+    writer: &mut W,
+    profile: &Profile,
+    dialect: &CsvDialect,
+) -> Result<(), DissectError> {
+    let mut csv = dialect.writer(writer);
+    dialect.header(
+        &mut csv,
+        [
+            "path",
+            "count",
+            "documents",
+            "null_rate",
+            "types",
+            "min",
+            "max",
+            "samples",
+        ],
+    )?;
+    for (path, stats) in &profile.fields {
+        csv.write_record([
+            path.clone(),
+            stats.count.to_string(),
+            profile.documents.to_string(),
+            stats.null_rate(profile.documents).to_string(),
+            stats.types_text(),
+            dialect.cell(cell(stats.min())),
+            dialect.cell(cell(stats.max())),
+            stats.samples.join(" | "),
+        ])?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+/// Plain text of a min/max bound, none when the field has none
+fn cell(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s),
+        other => Some(other.to_string()),
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use bson::{Bson, Document};
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+
+use crate::{docpath, Args, DissectError};
+
+/// How finely a t-digest keeps the distribution, more centroids for a higher value
+const COMPRESSION: f64 = 100.0;
+
+/// Values collected before they are merged into the centroids
+const BUFFER: usize = 512;
+
+/// A merging t-digest, quantile estimates that are most accurate near the tails and can be merged across workers
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TDigest {
+    /// Mean and weight of every centroid, in order of their means
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<f64>,
+}
+
+impl TDigest {
+    pub fn push(&mut self, value: f64) {
+        self.buffer.push(value);
+        if self.buffer.len() >= BUFFER {
+            self.compress();
+        }
+    }
+
+    pub fn merge(&mut self, other: TDigest) {
+        self.centroids.extend(other.centroids);
+        self.buffer.extend(other.buffer);
+        self.compress();
+    }
+
+    /// Fold the buffered values into the centroids, merging neighbours as long as the scale function allows
+    fn compress(&mut self) {
+        let mut all = std::mem::take(&mut self.centroids);
+        all.extend(self.buffer.drain(..).map(|value| (value, 1.0)));
+        if all.is_empty() {
+            return;
+        }
+        all.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total: f64 = all.iter().map(|(_, weight)| weight).sum();
+        // k1 scale function, centroids near the tails stay small
+        let k = |q: f64| COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).clamp(-1.0, 1.0).asin();
+        let k_inv = |k: f64| {
+            ((2.0 * PI * k / COMPRESSION)
+                .clamp(-PI / 2.0, PI / 2.0)
+                .sin()
+                + 1.0)
+                / 2.0
+        };
+
+        let mut merged = Vec::new();
+        let mut done = 0.0;
+        let mut limit = total * k_inv(k(0.0) + 1.0);
+        let mut current = all[0];
+        for &(mean, weight) in &all[1..] {
+            if done + current.1 + weight <= limit {
+                let sum = current.1 + weight;
+                current = (current.0 + (mean - current.0) * weight / sum, sum);
+            } else {
+                done += current.1;
+                merged.push(current);
+                limit = total * k_inv(k(done / total) + 1.0);
+                current = (mean, weight);
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// The estimated value below which `q` of the values lie, between the known `min` and `max`
+    pub fn quantile(&mut self, q: f64, min: f64, max: f64) -> Option<f64> {
+        self.compress();
+        let centroids = &self.centroids;
+        let total: f64 = centroids.iter().map(|(_, weight)| weight).sum();
+        let (first, last) = (centroids.first()?, centroids.last()?);
+        if centroids.len() == 1 {
+            return Some(first.0);
+        }
+        let target = q.clamp(0.0, 1.0) * total;
+        let mut below = 0.0;
+        for (nth, &(mean, weight)) in centroids.iter().enumerate() {
+            let center = below + weight / 2.0;
+            if target < center {
+                if nth == 0 {
+                    return Some(min + (mean - min) * (target / center));
+                }
+                let (previous, previous_weight) = centroids[nth - 1];
+                let previous_center = below - previous_weight / 2.0;
+                let t = (target - previous_center) / (center - previous_center);
+                return Some(previous + (mean - previous) * t);
+            }
+            below += weight;
+        }
+        let last_center = total - last.1 / 2.0;
+        let t = ((target - last_center) / (total - last_center)).clamp(0.0, 1.0);
+        Some(last.0 + (max - last.0) * t)
+    }
+}
+
+/// Count, range, moments and distribution of the numbers a field held
+#[derive(Debug, Clone)]
+pub(crate) struct NumericStats {
+    pub(crate) count: u64,
+    pub(crate) min: f64,
+    pub(crate) max: f64,
+    pub(crate) mean: f64,
+    /// Sum of the squared deviations from the mean
+    m2: f64,
+    digest: TDigest,
+}
+
+impl Default for NumericStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            digest: TDigest::default(),
+        }
+    }
+}
+
+impl NumericStats {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.digest.push(value);
+    }
+
+    /// Combine the statistics of two disjoint sets of values
+    pub fn merge(&mut self, other: NumericStats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other;
+            return;
+        }
+        let (a, b) = (self.count as f64, other.count as f64);
+        let delta = other.mean - self.mean;
+        self.mean += delta * b / (a + b);
+        self.m2 += other.m2 + delta * delta * a * b / (a + b);
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.digest.merge(other.digest);
+    }
+
+    /// Sample standard deviation
+    pub fn stddev(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        (self.m2 / (self.count - 1) as f64).sqrt()
+    }
+
+    pub fn percentile(&mut self, percent: f64) -> Option<f64> {
+        self.digest.quantile(percent / 100.0, self.min, self.max)
+    }
+}
+
+/// Statistics of the --numeric-stats fields over the exported documents, gathered a batch at a time by every worker
+pub(crate) struct NumericFields {
+    fields: Vec<(String, Vec<String>)>,
+    percentiles: Vec<f64>,
+    totals: Mutex<Vec<NumericStats>>,
+}
+
+impl NumericFields {
+    pub fn from_args(args: &Args) -> Option<Self> {
+        if args.numeric_stats.is_empty() {
+            return None;
+        }
+        Some(Self {
+            fields: args
+                .numeric_stats
+                .iter()
+                .map(|field| (field.clone(), docpath::parse(field)))
+                .collect(),
+            percentiles: args.percentiles.clone(),
+            totals: Mutex::new(vec![NumericStats::default(); args.numeric_stats.len()]),
+        })
+    }
+
+    /// Count the numbers in the fields of a batch, numbers inside arrays count one by one
+    pub fn record<'a>(&self, docs: impl IntoIterator<Item = &'a Document>) {
+        let mut batch = vec![NumericStats::default(); self.fields.len()];
+        for doc in docs {
+            for ((_, path), stats) in self.fields.iter().zip(&mut batch) {
+                docpath::visit(doc, path, &mut |value| {
+                    if let Some(n) = number(value) {
+                        stats.push(n);
+                    }
+                });
+            }
+        }
+        let mut totals = self.totals.lock();
+        for (total, stats) in totals.iter_mut().zip(batch) {
+            total.merge(stats);
+        }
+    }
+
+    /// The statistics of every field as json, printing them as a table along the way
+    pub fn finish(self) -> Value {
+        let mut report = Map::new();
+        let headers = self
+            .percentiles
+            .iter()
+            .map(|p| format!("p{p}"))
+            .collect::<Vec<_>>();
+        println!();
+        println!(
+            "{:<24} {:>10} {:>12} {:>12} {:>12} {:>12} {}",
+            "field",
+            "count",
+            "min",
+            "max",
+            "mean",
+            "stddev",
+            headers
+                .iter()
+                .map(|h| format!("{h:>12}"))
+                .collect::<String>()
+        );
+        for ((field, _), mut stats) in self.fields.into_iter().zip(self.totals.into_inner()) {
+            if stats.count == 0 {
+                println!("{field:<24} {:>10}", 0);
+                report.insert(field, json!({ "count": 0 }));
+                continue;
+            }
+            let percentiles = self
+                .percentiles
+                .iter()
+                .map(|p| stats.percentile(*p).unwrap_or(f64::NAN))
+                .collect::<Vec<_>>();
+            println!(
+                "{field:<24} {:>10} {:>12} {:>12} {:>12} {:>12} {}",
+                stats.count,
+                short(stats.min),
+                short(stats.max),
+                short(stats.mean),
+                short(stats.stddev()),
+                percentiles
+                    .iter()
+                    .map(|v| format!("{:>12}", short(*v)))
+                    .collect::<String>()
+            );
+            report.insert(
+                field,
+                json!({
+                    "count": stats.count,
+                    "min": stats.min,
+                    "max": stats.max,
+                    "mean": stats.mean,
+                    "stddev": stats.stddev(),
+                    "percentiles": headers.iter().zip(&percentiles).map(|(h, v)| (h.clone(), json!(v))).collect::<Map<_, _>>(),
+                }),
+            );
+        }
+        Value::Object(report)
+    }
+}
+
+pub(crate) fn save(report: &Value, path: &Path) -> Result<(), DissectError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, report)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(n) => Some(*n as f64),
+        Bson::Int64(n) => Some(*n as f64),
+        Bson::Double(n) if !n.is_nan() => Some(*n),
+        _ => None,
+    }
+}
+
+/// A number short enough for a table column
+fn short(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e12 {
+        format!("{value}")
+    } else if value.abs() >= 1e6 || (value != 0.0 && value.abs() < 1e-3) {
+        format!("{value:.4e}")
+    } else {
+        format!("{value:.3}")
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bson::{Bson, Document};
+use rayon::{prelude::*, ThreadPoolBuilder};
+use regex::Regex;
+use serde_json::json;
+
+use crate::{
+    index::{self, DocReader},
+    DissectError,
+};
+
+use super::profile::walk;
+
+/// Kinds of personal data or secrets a value can look like
+const KINDS: [&str; 7] = [
+    "email",
+    "phone",
+    "national-id",
+    "card",
+    "iban",
+    "ip",
+    "secret",
+];
+
+/// Field names that suggest personal data whatever the values look like
+const NAME_HINTS: [&str; 14] = [
+    "email", "mail", "phone", "mobile", "ssn", "passport", "birth", "dob", "address", "password",
+    "secret", "token", "iban", "card",
+];
+
+/// Flag fields likely holding personal data or secrets, from the shape of sampled values and field names,
+/// a starting point for the redaction rules of a dump about to be shared
+#[derive(Debug, clap::Args)]
+pub struct PiiScanArgs {
+    /// The input file or directory to read
+    pub input: PathBuf,
+
+    /// How many documents to sample, spread evenly over the input, 0 scans all of them
+    #[clap(long, default_value = "10000")]
+    pub sample: usize,
+
+    /// Share of a field's values that have to look alike for it to be flagged
+    #[clap(long, default_value = "0.5")]
+    pub min_share: f64,
+
+    /// Write the flagged fields to this file as ndjson
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// The number of threads to use
+    #[clap(short, long, default_value = "4")]
+    pub threads: usize,
+
+    /// How many documents each thread reads at a time
+    #[clap(short, long, default_value = "100")]
+    pub batch: usize,
+
+    /// Inspect the input again even if an index file exists
+    #[clap(long)]
+    pub inspect: bool,
+}
+
+/// Recognizes the value shapes of [`KINDS`]
+struct Detectors {
+    email: Regex,
+    phone: Regex,
+    national_id: Regex,
+    card: Regex,
+    iban: Regex,
+    ip: Regex,
+}
+
+impl Detectors {
+    fn new() -> Self {
+        let regex = |pattern| Regex::new(pattern).expect("valid pattern");
+        Self {
+            email: regex(r"^[\w.%+-]+@[\w-]+(\.[\w-]+)*\.[A-Za-z]{2,}$"),
+            phone: regex(r"^\+?\(?\d{1,4}\)?([ .-]?\(?\d{1,4}\)?){2,6}$"),
+            // US SSN, UK NINO, French INSEE number
+            national_id: regex(
+                r"^(\d{3}-\d{2}-\d{4}|[A-CEGHJ-PR-TW-Z]{2}\d{6}[A-D]|[12]\d{2}(0[1-9]|1[0-2])\d{8}(\d{2})?)$",
+            ),
+            card: regex(r"^\d{4}([ -]?\d{4}){2}[ -]?\d{1,7}$"),
+            iban: regex(r"^[A-Z]{2}\d{2}( ?[A-Z0-9]{4}){2,7}( ?[A-Z0-9]{1,4})?$"),
+            ip: regex(r"^((25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(25[0-5]|2[0-4]\d|1?\d?\d)$"),
+        }
+    }
+
+    /// Position in [`KINDS`] of what `value` looks like
+    fn detect(&self, value: &str) -> Option<usize> {
+        let value = value.trim();
+        if self.email.is_match(value) {
+            Some(0)
+        } else if self.card.is_match(value) && luhn(value) {
+            Some(3)
+        } else if self.national_id.is_match(value) {
+            Some(2)
+        } else if self.iban.is_match(value) {
+            Some(4)
+        } else if self.ip.is_match(value) {
+            Some(5)
+        } else if self.phone.is_match(value) && is_phone_number(value) {
+            Some(1)
+        } else if is_secret(value) {
+            Some(6)
+        } else {
+            None
+        }
+    }
+}
+
+/// Bare runs of digits are more likely counters or timestamps than phone numbers
+fn is_phone_number(value: &str) -> bool {
+    let digits = value.chars().filter(char::is_ascii_digit).count();
+    (8..=15).contains(&digits) && (value.starts_with('+') || digits < value.len())
+}
+
+/// Payment card numbers carry a Luhn check digit
+fn luhn(value: &str) -> bool {
+    let digits = value
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .collect::<Vec<_>>();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2, d * 2) {
+            (0, _) => d,
+            (_, double) if double > 9 => double - 9,
+            (_, double) => double,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Long unbroken tokens with a high Shannon entropy, like API keys and password hashes
+fn is_secret(value: &str) -> bool {
+    if value.len() < 20 || value.contains(char::is_whitespace) {
+        return false;
+    }
+    let mut counts = HashMap::new();
+    for c in value.chars() {
+        *counts.entry(c).or_insert(0usize) += 1;
+    }
+    let len = value.chars().count() as f64;
+    let entropy = counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum::<f64>();
+    entropy >= 4.0
+}
+
+/// What the sampled values of one field looked like
+#[derive(Debug, Default)]
+struct FieldScan {
+    values: usize,
+    hits: [usize; KINDS.len()],
+    /// The first value seen of each kind
+    examples: [Option<String>; KINDS.len()],
+}
+
+impl FieldScan {
+    fn merge(&mut self, other: FieldScan) {
+        self.values += other.values;
+        for kind in 0..KINDS.len() {
+            self.hits[kind] += other.hits[kind];
+            if self.examples[kind].is_none() {
+                self.examples[kind] = other.examples[kind].clone();
+            }
+        }
+    }
+}
+
+/// A flagged field
+struct Finding {
+    path: String,
+    kind: &'static str,
+    share: f64,
+    values: usize,
+    example: String,
+    named: bool,
+}
+
+pub(crate) fn run(args: &PiiScanArgs) -> Result<(), DissectError> {
+    let input = index::load_input(&args.input, args.inspect)?;
+    let step = match args.sample {
+        0 => 1,
+        sample => input.offsets.len().div_ceil(sample).max(1),
+    };
+    let sampled = input
+        .offsets
+        .iter()
+        .step_by(step)
+        .copied()
+        .collect::<Vec<_>>();
+    let detectors = Detectors::new();
+
+    let thread_pool = ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+    let fields = thread_pool.install(|| {
+        sampled
+            .par_chunks(args.batch.max(1))
+            .map(|offsets| {
+                let mut reader = DocReader::new(&input);
+                let mut fields = HashMap::new();
+                for offset in offsets {
+                    scan(&reader.read_document(offset)?, &detectors, &mut fields);
+                }
+                Ok(fields)
+            })
+            .try_reduce(HashMap::new, |mut a, b| {
+                for (path, scan) in b {
+                    a.entry(path).or_insert_with(FieldScan::default).merge(scan);
+                }
+                Ok::<_, DissectError>(a)
+            })
+    })?;
+    println!(
+        "Scanned {} fields of {} sampled documents",
+        fields.len(),
+        sampled.len()
+    );
+
+    let mut findings = fields
+        .into_iter()
+        .filter_map(|(path, scan)| finding(path, scan, args.min_share))
+        .collect::<Vec<_>>();
+    findings.sort_by(|a, b| b.share.total_cmp(&a.share).then(a.path.cmp(&b.path)));
+
+    if findings.is_empty() {
+        println!("No field looks like it holds personal data");
+    } else {
+        let width = findings
+            .iter()
+            .map(|f| f.path.len())
+            .max()
+            .unwrap_or(0)
+            .max(5);
+        println!();
+        println!(
+            "{:width$}  {:11}  {:>5}  Example",
+            "Field", "Looks like", "Share"
+        );
+        for f in &findings {
+            if f.share > 0.0 {
+                println!(
+                    "{:width$}  {:11}  {:>4.0}%  {}",
+                    f.path,
+                    f.kind,
+                    f.share * 100.0,
+                    f.example
+                );
+            } else {
+                println!("{:width$}  {:11}  {:>5}", f.path, "(its name)", "-");
+            }
+        }
+    }
+
+    if let Some(output) = &args.output {
+        let mut writer = BufWriter::new(File::create(output)?);
+        for f in &findings {
+            serde_json::to_writer(
+                &mut writer,
+                &json!({
+                    "field": f.path,
+                    "kind": (f.share > 0.0).then_some(f.kind),
+                    "share": f.share,
+                    "values": f.values,
+                    "example": f.example,
+                    "name_hint": f.named,
+                }),
+            )?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        println!(
+            "Wrote {} flagged fields to {}",
+            findings.len(),
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+/// Count what the string and integer values of every field of `doc` look like,
+/// scalars inside arrays count towards the array field
+fn scan(doc: &Document, detectors: &Detectors, fields: &mut HashMap<String, FieldScan>) {
+    walk("", doc, &mut |path, value| {
+        let values = match value {
+            Bson::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let text = match value {
+                Bson::String(s) => s.clone(),
+                Bson::Int64(n) => n.to_string(),
+                _ => continue,
+            };
+            let field = fields.entry(path.to_string()).or_default();
+            field.values += 1;
+            if let Some(kind) = detectors.detect(&text) {
+                field.hits[kind] += 1;
+                field.examples[kind].get_or_insert(text);
+            }
+        }
+    });
+}
+
+/// Flag a field when enough of its values look alike or its name hints at personal data
+fn finding(path: String, scan: FieldScan, min_share: f64) -> Option<Finding> {
+    let (kind, hits) = scan
+        .hits
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, hits)| **hits)
+        .map(|(kind, hits)| (kind, *hits))?;
+    let share = hits as f64 / scan.values.max(1) as f64;
+    let name = path.rsplit('.').next().unwrap_or(&path).to_lowercase();
+    let named = NAME_HINTS.iter().any(|hint| name.contains(hint));
+    if share < min_share && !named {
+        return None;
+    }
+    let share = if share < min_share { 0.0 } else { share };
+    Some(Finding {
+        kind: KINDS[kind],
+        example: scan.examples[kind].as_deref().map(mask).unwrap_or_default(),
+        values: scan.values,
+        share,
+        named,
+        path,
+    })
+}
+
+/// Keep the first and last two characters of an example, enough to recognize it without leaking it
+fn mask(value: &str) -> String {
+    let chars = value.chars().collect::<Vec<_>>();
+    if chars.len() <= 6 {
+        return "*".repeat(chars.len());
+    }
+    let (head, tail) = (&chars[..2], &chars[chars.len() - 2..]);
+    format!(
+        "{}{}{}",
+        head.iter().collect::<String>(),
+        "*".repeat(chars.len() - 4),
+        tail.iter().collect::<String>()
+    )
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+use bson::{Bson, Document};
+use serde_json::{json, Value};
+
+/// Per-field findings over a set of documents
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Profile {
+    pub(crate) documents: u64,
+    pub(crate) fields: BTreeMap<String, FieldStats>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FieldStats {
+    /// How often the field was seen, fields inside arrays count once per element
+    pub(crate) count: u64,
+    pub(crate) nulls: u64,
+    pub(crate) types: BTreeMap<&'static str, u64>,
+    pub(crate) min_number: Option<f64>,
+    pub(crate) max_number: Option<f64>,
+    pub(crate) min_date: Option<i64>,
+    pub(crate) max_date: Option<i64>,
+    pub(crate) samples: Vec<String>,
+}
+
+impl Profile {
+    pub fn record(&mut self, doc: &Document, samples: usize) {
+        self.documents += 1;
+        walk("", doc, &mut |path, value| {
+            self.fields
+                .entry(path.to_string())
+                .or_default()
+                .record(value, samples)
+        });
+    }
+
+    /// Record a document with its sample values taken from the redacted copy, so nothing a rule hides is shown,
+    /// fields the rules dropped get no samples at all
+    pub fn record_redacted(&mut self, doc: &Document, redacted: &Document, samples: usize) {
+        self.record(doc, 0);
+        walk("", redacted, &mut |path, value| {
+            if let Some(stats) = self.fields.get_mut(path) {
+                stats.sample(value, samples);
+            }
+        });
+    }
+
+    /// Combine the findings of two disjoint sets of documents
+    pub fn merge(mut self, other: Profile, samples: usize) -> Profile {
+        self.documents += other.documents;
+        for (path, stats) in other.fields {
+            match self.fields.get_mut(&path) {
+                Some(existing) => existing.merge(stats, samples),
+                None => {
+                    self.fields.insert(path, stats);
+                }
+            }
+        }
+        self
+    }
+}
+
+impl FieldStats {
+    fn record(&mut self, value: &Bson, samples: usize) {
+        self.count += 1;
+        *self.types.entry(type_name(value)).or_default() += 1;
+        match value {
+            Bson::Null | Bson::Undefined => self.nulls += 1,
+            Bson::Double(n) => self.number(*n),
+            Bson::Int32(n) => self.number(*n as f64),
+            Bson::Int64(n) => self.number(*n as f64),
+            Bson::DateTime(d) => self.date(d.timestamp_millis()),
+            _ => {}
+        }
+        self.sample(value, samples);
+    }
+
+    fn sample(&mut self, value: &Bson, samples: usize) {
+        if self.samples.len() < samples && !matches!(value, Bson::Document(_) | Bson::Array(_)) {
+            let sample = sample_text(value);
+            if !self.samples.contains(&sample) {
+                self.samples.push(sample);
+            }
+        }
+    }
+
+    fn number(&mut self, n: f64) {
+        self.min_number = Some(self.min_number.map_or(n, |m| m.min(n)));
+        self.max_number = Some(self.max_number.map_or(n, |m| m.max(n)));
+    }
+
+    fn date(&mut self, millis: i64) {
+        self.min_date = Some(self.min_date.map_or(millis, |m| m.min(millis)));
+        self.max_date = Some(self.max_date.map_or(millis, |m| m.max(millis)));
+    }
+
+    fn merge(&mut self, other: FieldStats, samples: usize) {
+        self.count += other.count;
+        self.nulls += other.nulls;
+        for (name, count) in other.types {
+            *self.types.entry(name).or_default() += count;
+        }
+        if let (Some(min), Some(max)) = (other.min_number, other.max_number) {
+            self.number(min);
+            self.number(max);
+        }
+        if let (Some(min), Some(max)) = (other.min_date, other.max_date) {
+            self.date(min);
+            self.date(max);
+        }
+        for sample in other.samples {
+            if self.samples.len() >= samples {
+                break;
+            }
+            if !self.samples.contains(&sample) {
+                self.samples.push(sample);
+            }
+        }
+    }
+
+    /// Smallest numeric or date value seen, dates take precedence
+    pub fn min(&self) -> Value {
+        match (self.min_date, self.min_number) {
+            (Some(date), _) => json!(date_text(date)),
+            (None, Some(n)) => json!(n),
+            _ => Value::Null,
+        }
+    }
+
+    /// Largest numeric or date value seen, dates take precedence
+    pub fn max(&self) -> Value {
+        match (self.max_date, self.max_number) {
+            (Some(date), _) => json!(date_text(date)),
+            (None, Some(n)) => json!(n),
+            _ => Value::Null,
+        }
+    }
+
+    /// Share of documents where the field is null
+    pub fn null_rate(&self, documents: u64) -> f64 {
+        self.nulls as f64 / documents.max(1) as f64
+    }
+
+    /// Types seen in the form `string:10;int:2`
+    pub fn types_text(&self) -> String {
+        self.types
+            .iter()
+            .map(|(name, count)| format!("{name}:{count}"))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+/// Visit every field under its dotted path, descending into documents and arrays of documents
+pub(crate) fn walk(prefix: &str, doc: &Document, f: &mut dyn FnMut(&str, &Bson)) {
+    for (key, value) in doc {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        f(&path, value);
+        match value {
+            Bson::Document(d) => walk(&path, d, f),
+            Bson::Array(items) => {
+                for item in items {
+                    if let Bson::Document(d) = item {
+                        walk(&path, d, f);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// MongoDB `$type` alias of a value
+pub(crate) fn type_name(value: &Bson) -> &'static str {
+    match value {
+        Bson::Double(_) => "double",
+        Bson::String(_) => "string",
+        Bson::Document(_) => "object",
+        Bson::Array(_) => "array",
+        Bson::Binary(_) => "binData",
+        Bson::Undefined => "undefined",
+        Bson::ObjectId(_) => "objectId",
+        Bson::Boolean(_) => "bool",
+        Bson::DateTime(_) => "date",
+        Bson::Null => "null",
+        Bson::RegularExpression(_) => "regex",
+        Bson::DbPointer(_) => "dbPointer",
+        Bson::JavaScriptCode(_) => "javascript",
+        Bson::Symbol(_) => "symbol",
+        Bson::JavaScriptCodeWithScope(_) => "javascriptWithScope",
+        Bson::Int32(_) => "int",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::Int64(_) => "long",
+        Bson::Decimal128(_) => "decimal",
+        Bson::MinKey => "minKey",
+        Bson::MaxKey => "maxKey",
+    }
+}
+
+fn sample_text(value: &Bson) -> String {
+    let text = match value {
+        Bson::String(s) => s.clone(),
+        other => other.clone().into_relaxed_extjson().to_string(),
+    };
+    match text.char_indices().nth(80) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+fn date_text(millis: i64) -> String {
+    let date = bson::DateTime::from_millis(millis);
+    date.try_to_rfc3339_string()
+        .unwrap_or_else(|_| millis.to_string())
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use bson::{spec::BinarySubtype, RawBsonRef, RawDocument, RawDocumentBuf};
+use humansize::{format_size, DECIMAL};
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use crate::{
+    index::{DocReader, Input},
+    DissectError,
+};
+
+/// Bytes taken by every field path over a set of documents
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FieldSizes {
+    pub(crate) documents: u64,
+    /// Bytes of the whole documents
+    pub(crate) bytes: u64,
+    pub(crate) fields: HashMap<String, FieldSize>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FieldSize {
+    /// Encoded bytes of the field including its name, a document or array includes everything inside it
+    pub(crate) bytes: u64,
+    /// How often the field was seen, fields inside arrays count once per element
+    pub(crate) count: u64,
+}
+
+impl FieldSizes {
+    /// Attribute the bytes of a raw document to its fields without decoding it
+    pub fn record(&mut self, raw: &[u8]) -> Result<(), DissectError> {
+        self.documents += 1;
+        self.bytes += raw.len() as u64;
+        self.walk("", RawDocument::from_bytes(raw)?)
+    }
+
+    fn walk(&mut self, prefix: &str, doc: &RawDocument) -> Result<(), DissectError> {
+        for element in doc {
+            let (key, value) = element?;
+            let path = if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            // element type byte and the name with its terminator
+            let size = 1 + key.len() + 1 + value_size(value);
+            let field = self.fields.entry(path.clone()).or_default();
+            field.bytes += size as u64;
+            field.count += 1;
+            match value {
+                RawBsonRef::Document(d) => self.walk(&path, d)?,
+                RawBsonRef::Array(items) => {
+                    for item in items {
+                        if let RawBsonRef::Document(d) = item? {
+                            self.walk(&path, d)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Combine the sizes of two disjoint sets of documents
+    pub fn merge(mut self, other: FieldSizes) -> FieldSizes {
+        self.documents += other.documents;
+        self.bytes += other.bytes;
+        for (path, size) in other.fields {
+            let field = self.fields.entry(path).or_default();
+            field.bytes += size.bytes;
+            field.count += size.count;
+        }
+        self
+    }
+
+    /// Fields by the bytes they take, largest first
+    pub fn heaviest(&self) -> Vec<(&String, FieldSize)> {
+        let mut fields = self
+            .fields
+            .iter()
+            .map(|(path, size)| (path, *size))
+            .collect::<Vec<_>>();
+        fields.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+        fields
+    }
+}
+
+/// Encoded size of a value without its type byte and name
+fn value_size(value: RawBsonRef) -> usize {
+    match value {
+        RawBsonRef::Double(_)
+        | RawBsonRef::DateTime(_)
+        | RawBsonRef::Timestamp(_)
+        | RawBsonRef::Int64(_) => 8,
+        RawBsonRef::Int32(_) => 4,
+        RawBsonRef::Decimal128(_) => 16,
+        RawBsonRef::ObjectId(_) => 12,
+        RawBsonRef::Boolean(_) => 1,
+        RawBsonRef::Null | RawBsonRef::Undefined | RawBsonRef::MinKey | RawBsonRef::MaxKey => 0,
+        RawBsonRef::String(s) | RawBsonRef::Symbol(s) | RawBsonRef::JavaScriptCode(s) => {
+            4 + s.len() + 1
+        }
+        RawBsonRef::Document(d) => d.as_bytes().len(),
+        RawBsonRef::Array(a) => a.as_bytes().len(),
+        RawBsonRef::Binary(b) => {
+            // the old binary subtype repeats the length inside the payload
+            let old = if b.subtype == BinarySubtype::BinaryOld {
+                4
+            } else {
+                0
+            };
+            4 + 1 + old + b.bytes.len()
+        }
+        RawBsonRef::RegularExpression(r) => r.pattern.len() + 1 + r.options.len() + 1,
+        RawBsonRef::JavaScriptCodeWithScope(c) => {
+            4 + 4 + c.code.len() + 1 + c.scope.as_bytes().len()
+        }
+        other => {
+            // deprecated types are measured by encoding them alone under an empty name
+            let mut doc = RawDocumentBuf::new();
+            doc.append("", other.to_raw_bson());
+            doc.as_bytes().len().saturating_sub(7)
+        }
+    }
+}
+
+/// Measure the fields of every document of the input in parallel
+pub(crate) fn measure_input(
+    input: &Input,
+    threads: usize,
+    batch: usize,
+) -> Result<FieldSizes, DissectError> {
+    let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+    thread_pool.install(|| {
+        input
+            .offsets
+            .par_chunks(batch.max(1))
+            .map(|offsets| {
+                let mut reader = DocReader::new(input);
+                let mut sizes = FieldSizes::default();
+                for offset in offsets {
+                    sizes.record(&reader.read_raw(offset)?)?;
+                }
+                Ok(sizes)
+            })
+            .try_reduce(FieldSizes::default, |a, b| Ok(a.merge(b)))
+    })
+}
+
+/// The fields to drop so the documents fit in `target` bytes, none when they already do.
+/// When no single field covers what is left to cut the heaviest one is dropped and the search goes on,
+/// otherwise the smallest field that covers it finishes the projection, so as little data as possible is lost
+pub(crate) fn suggest_projection(sizes: &FieldSizes, target: u64) -> Option<Vec<(String, u64)>> {
+    let need = sizes.bytes.checked_sub(target).filter(|need| *need > 0)?;
+    let overlaps = |a: &str, b: &str| {
+        a == b || a.starts_with(&format!("{b}.")) || b.starts_with(&format!("{a}."))
+    };
+    let mut dropped: Vec<(String, u64)> = Vec::new();
+    let mut removed = 0;
+    while removed < need {
+        let candidates = sizes
+            .heaviest()
+            .into_iter()
+            .filter(|(path, _)| *path != "_id" && !dropped.iter().any(|(d, _)| overlaps(path, d)))
+            .collect::<Vec<_>>();
+        let left = need - removed;
+        let pick = candidates
+            .iter()
+            .rev()
+            .find(|(_, size)| size.bytes >= left)
+            .or_else(|| candidates.first());
+        let Some((path, size)) = pick else {
+            // even dropping everything but the ids isn't enough
+            break;
+        };
+        removed += size.bytes;
+        dropped.push((path.to_string(), size.bytes));
+    }
+    Some(dropped)
+}
+
+pub(crate) fn print_projection(sizes: &FieldSizes, target: u64) {
+    println!();
+    let Some(dropped) = suggest_projection(sizes, target) else {
+        println!(
+            "The documents already fit in {}, nothing needs to be dropped",
+            format_size(target, DECIMAL)
+        );
+        return;
+    };
+    let removed: u64 = dropped.iter().map(|(_, bytes)| bytes).sum();
+    let left = sizes.bytes.saturating_sub(removed);
+    println!(
+        "Dropping {} fields takes the documents from {} to {} (target {})",
+        dropped.len(),
+        format_size(sizes.bytes, DECIMAL),
+        format_size(left, DECIMAL),
+        format_size(target, DECIMAL)
+    );
+    for (path, bytes) in &dropped {
+        println!("  {path:<40} {:>10}", format_size(*bytes, DECIMAL));
+    }
+    if left > target {
+        println!(
+            "Even without these fields the documents don't fit, only their ids are left to drop"
+        );
+    }
+    let projection = dropped
+        .iter()
+        .map(|(path, _)| format!("{}: 0", serde_json::Value::from(path.as_str())))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!();
+    println!(
+        "Fields:     {}",
+        dropped
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    println!("Projection: {{{projection}}}");
+}
+
+/// A byte size like 5GB, 512MiB or 1000000
+pub(crate) fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("expected a size like 5GB, got {s}"))?;
+    let scale: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        other => {
+            return Err(format!(
+                "unknown size unit {other}, use B, KB, MB, GB, TB or KiB..TiB"
+            ))
+        }
+    };
+    Ok((number * scale as f64) as u64)
+}
+
+pub(crate) fn print_field_sizes(sizes: &FieldSizes, top: usize) {
+    println!();
+    println!(
+        "{:<40} {:>10} {:>7} {:>10} {:>9}",
+        "field", "bytes", "share", "avg", "present"
+    );
+    for (path, size) in sizes.heaviest().into_iter().take(top) {
+        println!(
+            "{:<40} {:>10} {:>6.1}% {:>10} {:>8.1}%",
+            path,
+            format_size(size.bytes, DECIMAL),
+            size.bytes as f64 * 100.0 / sizes.bytes.max(1) as f64,
+            format_size(size.bytes / size.count.max(1), DECIMAL),
+            size.count as f64 * 100.0 / sizes.documents.max(1) as f64,
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use bson::{DateTime, RawBsonRef, RawDocument};
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use crate::{
+    docpath,
+    index::{DocReader, Input},
+    DissectError,
+};
+
+/// Most buckets printed with the empty ones between them, longer timelines only list the buckets holding documents
+const MAX_FILLED: usize = 10_000;
+
+/// Width of the bar drawn next to the largest bucket
+const BAR_WIDTH: usize = 40;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+const MINUTE: i64 = 60_000;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+
+/// Length of the time buckets of a timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Minute,
+    Hour,
+    Day,
+    /// Weeks starting on Monday
+    Week,
+    Month,
+    Year,
+}
+
+/// A date field and the buckets to count its documents in, written `created_at:hour`
+#[derive(Debug, Clone)]
+pub struct TimelineSpec {
+    pub(crate) field: String,
+    pub(crate) unit: Unit,
+}
+
+impl FromStr for TimelineSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, unit) = s.rsplit_once(':').unwrap_or((s, "day"));
+        Ok(Self {
+            field: field.into(),
+            unit: unit.parse()?,
+        })
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "minute" => Self::Minute,
+            "hour" => Self::Hour,
+            "day" => Self::Day,
+            "week" => Self::Week,
+            "month" => Self::Month,
+            "year" => Self::Year,
+            other => {
+                return Err(format!(
+                    "unknown bucket {other}, use minute, hour, day, week, month or year"
+                ))
+            }
+        })
+    }
+}
+
+impl Unit {
+    /// Start of the bucket holding the time, in milliseconds since the epoch
+    pub(crate) fn floor(self, millis: i64) -> i64 {
+        match self {
+            Self::Minute => millis.div_euclid(MINUTE) * MINUTE,
+            Self::Hour => millis.div_euclid(HOUR) * HOUR,
+            Self::Day => millis.div_euclid(DAY) * DAY,
+            // the epoch was a Thursday, three days after a Monday
+            Self::Week => ((millis.div_euclid(DAY) + 3).div_euclid(7) * 7 - 3) * DAY,
+            Self::Month => {
+                let (year, month, _) = civil(millis.div_euclid(DAY));
+                days(year, month, 1) * DAY
+            }
+            Self::Year => {
+                let (year, _, _) = civil(millis.div_euclid(DAY));
+                days(year, 1, 1) * DAY
+            }
+        }
+    }
+
+    /// Start of the bucket after the one starting at `start`
+    fn next(self, start: i64) -> i64 {
+        match self {
+            Self::Minute => start + MINUTE,
+            Self::Hour => start + HOUR,
+            Self::Day => start + DAY,
+            Self::Week => start + 7 * DAY,
+            Self::Month => {
+                let (year, month, _) = civil(start.div_euclid(DAY));
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                days(year, month, 1) * DAY
+            }
+            Self::Year => {
+                let (year, _, _) = civil(start.div_euclid(DAY));
+                days(year + 1, 1, 1) * DAY
+            }
+        }
+    }
+
+    /// The bucket starting at `start` as the leading part of its RFC 3339 date, like `2023-01` for a month
+    pub(crate) fn label(self, start: i64) -> String {
+        let text = DateTime::from_millis(start)
+            .try_to_rfc3339_string()
+            .unwrap_or_else(|_| start.to_string());
+        let len = match self {
+            Self::Minute | Self::Hour => 16,
+            Self::Day | Self::Week => 10,
+            Self::Month => 7,
+            Self::Year => 4,
+        };
+        text.get(..len).unwrap_or(&text).to_string()
+    }
+}
+
+/// The date of a day counted from the epoch, as year, month and day
+fn civil(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The day counted from the epoch of a date, the inverse of [`civil`]
+fn days(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Documents counted per time bucket
+#[derive(Debug, Default)]
+pub(crate) struct Timeline {
+    pub(crate) buckets: BTreeMap<i64, u64>,
+    /// Documents without a date in the field
+    pub(crate) undated: u64,
+}
+
+impl Timeline {
+    fn record(&mut self, raw: &[u8], path: &[String], unit: Unit) -> Result<(), DissectError> {
+        let millis = match docpath::raw_get(RawDocument::from_bytes(raw)?, path)? {
+            Some(RawBsonRef::DateTime(date)) => Some(date.timestamp_millis()),
+            Some(RawBsonRef::ObjectId(oid)) => Some(oid.timestamp().timestamp_millis()),
+            Some(RawBsonRef::String(s)) => DateTime::parse_rfc3339_str(s)
+                .ok()
+                .map(|date| date.timestamp_millis()),
+            _ => None,
+        };
+        match millis {
+            Some(millis) => *self.buckets.entry(unit.floor(millis)).or_default() += 1,
+            None => self.undated += 1,
+        }
+        Ok(())
+    }
+
+    fn merge(mut self, other: Timeline) -> Timeline {
+        for (start, count) in other.buckets {
+            *self.buckets.entry(start).or_default() += count;
+        }
+        self.undated += other.undated;
+        self
+    }
+
+    /// The buckets from the first to the last one holding documents, empty ones included unless there are too many
+    fn filled(&self, unit: Unit) -> Vec<(i64, u64)> {
+        let (Some(first), Some(last)) = (self.buckets.keys().next(), self.buckets.keys().last())
+        else {
+            return Vec::new();
+        };
+        let mut filled = Vec::new();
+        let mut start = *first;
+        while start <= *last && filled.len() <= MAX_FILLED {
+            filled.push((start, self.buckets.get(&start).copied().unwrap_or_default()));
+            start = unit.next(start);
+        }
+        if filled.len() > MAX_FILLED {
+            return self.buckets.iter().map(|(s, c)| (*s, *c)).collect();
+        }
+        filled
+    }
+}
+
+/// Count the documents of the input per time bucket of a date field, ObjectIds count by their creation time
+pub(crate) fn count_input(
+    input: &Input,
+    spec: &TimelineSpec,
+    threads: usize,
+    batch: usize,
+) -> Result<Timeline, DissectError> {
+    let path = docpath::parse(&spec.field);
+    let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+    thread_pool.install(|| {
+        input
+            .offsets
+            .par_chunks(batch.max(1))
+            .map(|offsets| {
+                let mut reader = DocReader::new(input);
+                let mut timeline = Timeline::default();
+                for offset in offsets {
+                    timeline.record(&reader.read_raw(offset)?, &path, spec.unit)?;
+                }
+                Ok(timeline)
+            })
+            .try_reduce(Timeline::default, |a, b| Ok(a.merge(b)))
+    })
+}
+
+pub(crate) fn print_timeline(timeline: &Timeline, spec: &TimelineSpec, sparkline: bool) {
+    println!();
+    let buckets = timeline.filled(spec.unit);
+    let Some(max) = buckets.iter().map(|(_, count)| *count).max() else {
+        println!("No document has a date in {}", spec.field);
+        return;
+    };
+    if sparkline {
+        let line = buckets
+            .iter()
+            .map(|(_, count)| SPARKS[(*count * (SPARKS.len() as u64 - 1)).div_ceil(max) as usize])
+            .collect::<String>();
+        println!("{line}");
+        println!();
+    }
+    let (peak, _) = buckets
+        .iter()
+        .max_by_key(|(start, count)| (*count, std::cmp::Reverse(*start)))
+        .expect("buckets aren't empty");
+    for (start, count) in &buckets {
+        let bar = "█".repeat((*count * BAR_WIDTH as u64).div_ceil(max) as usize);
+        let mark = if start == peak { " peak" } else { "" };
+        println!("{:<16} {count:>10} {bar}{mark}", spec.unit.label(*start));
+    }
+    if timeline.undated > 0 {
+        println!(
+            "{} documents have no date in {}",
+            timeline.undated, spec.field
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bson::{Bson, Document};
+use parking_lot::Mutex;
+use similar::TextDiff;
+
+use crate::{
+    index::{DocOffset, Input},
+    DissectError,
+};
+
+/// Follows one document through the pipeline for --trace-doc, printing it to stderr as it is read and a diff of
+/// what every stage after that changed, so a record that comes out wrong can be followed without editing scripts
+pub(crate) struct Tracer {
+    position: usize,
+    target: DocOffset,
+    source: String,
+    /// The document as the last stage left it, as pretty extended json
+    last: Mutex<String>,
+    seen: AtomicBool,
+}
+
+impl Tracer {
+    /// Trace document `position` of the input, numbered from 0 like `repl` numbers them
+    pub fn new(input: &Input, position: usize) -> Result<Self, DissectError> {
+        let target = *input.offsets.get(position).ok_or_else(|| {
+            DissectError::Parse(format!(
+                "--trace-doc {position} is past the last document, the input holds {}",
+                input.offsets.len()
+            ))
+        })?;
+        Ok(Self {
+            position,
+            target,
+            source: input.files[target.source].display().to_string(),
+            last: Mutex::new(String::new()),
+            seen: AtomicBool::new(false),
+        })
+    }
+
+    pub fn is(&self, offset: &DocOffset) -> bool {
+        offset.source == self.target.source && offset.offset == self.target.offset
+    }
+
+    fn find<'a>(&self, docs: &'a [(Document, DocOffset)]) -> Option<&'a Document> {
+        docs.iter()
+            .find(|(_, offset)| self.is(offset))
+            .map(|(doc, _)| doc)
+    }
+
+    /// Print the document as it was read if the batch holds it, returns whether it does
+    pub fn read(&self, docs: &[(Document, DocOffset)]) -> bool {
+        let Some(doc) = self.find(docs) else {
+            return false;
+        };
+        self.seen.store(true, Ordering::Relaxed);
+        let json = pretty(doc);
+        eprintln!(
+            "{} read from {} at byte {}, {} bytes\n{json}",
+            self.prefix(),
+            self.source,
+            self.target.offset,
+            self.target.size
+        );
+        *self.last.lock() = json;
+        true
+    }
+
+    /// Print what `stage` changed in the document
+    pub fn stage(&self, stage: &str, doc: &Document) {
+        let json = pretty(doc);
+        let mut last = self.last.lock();
+        if *last == json {
+            eprintln!("{} {stage} left it unchanged", self.prefix());
+        } else {
+            let diff = TextDiff::from_lines(last.as_str(), json.as_str());
+            eprint!(
+                "{} changed by {stage}\n{}",
+                self.prefix(),
+                diff.unified_diff().header("before", stage)
+            );
+            *last = json;
+        }
+    }
+
+    /// Print what `stage` did to the document, it is looked for among the documents the stage left,
+    /// returns whether it is still there
+    pub fn after(&self, stage: &str, docs: &[(Document, DocOffset)]) -> bool {
+        match self.find(docs) {
+            Some(doc) => {
+                self.stage(stage, doc);
+                true
+            }
+            None => {
+                eprintln!("{} dropped by {stage}", self.prefix());
+                false
+            }
+        }
+    }
+
+    /// Print whether a stage that only drops documents kept it, returns whether it did
+    pub fn kept(&self, stage: &str, docs: &[(Document, DocOffset)]) -> bool {
+        let kept = self.find(docs).is_some();
+        match kept {
+            true => eprintln!("{} kept by {stage}", self.prefix()),
+            false => eprintln!("{} dropped by {stage}", self.prefix()),
+        }
+        kept
+    }
+
+    /// Print that the document made it through every stage
+    pub fn done(&self) {
+        eprintln!("{} handed to the output", self.prefix());
+    }
+
+    /// A line for the summary when the document never reached the pipeline
+    pub fn finish(self) -> Option<String> {
+        (!self.seen.into_inner()).then(|| {
+            format!(
+                "Document {} never reached the pipeline, it isn't selected or was skipped, nothing was traced",
+                self.position
+            )
+        })
+    }
+
+    fn prefix(&self) -> String {
+        format!("[trace-doc {}]", self.position)
+    }
+}
+
+fn pretty(doc: &Document) -> String {
+    let json = Bson::Document(doc.clone()).into_relaxed_extjson();
+    let mut pretty = serde_json::to_string_pretty(&json).unwrap_or_else(|_| json.to_string());
+    pretty.push('\n');
+    pretty
+}
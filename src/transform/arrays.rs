@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bson::{doc, Bson, Document};
+use clap::ValueEnum;
+
+/// What happens to arrays longer than --max-array-len
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArrayOverflow {
+    /// Keep the first elements and append a `{"$truncated": <dropped>}` marker
+    Truncate,
+    /// Replace the array with `{"count", "first", "last"}`
+    Summarize,
+    /// Leave the array alone, only count it
+    Keep,
+}
+
+/// Limits the length of every array in a document, nested ones included
+#[derive(Debug)]
+pub(crate) struct ArrayLimit {
+    max: usize,
+    overflow: ArrayOverflow,
+    hits: AtomicUsize,
+}
+
+impl ArrayLimit {
+    pub fn new(max: usize, overflow: ArrayOverflow) -> Self {
+        Self {
+            max,
+            overflow,
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn apply(&self, doc: &mut Document) {
+        for (_, value) in doc.iter_mut() {
+            self.limit(value);
+        }
+    }
+
+    fn limit(&self, value: &mut Bson) {
+        if let Bson::Array(items) = value {
+            if items.len() > self.max {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                match self.overflow {
+                    ArrayOverflow::Truncate => {
+                        let dropped = items.len() - self.max;
+                        items.truncate(self.max);
+                        items.push(Bson::Document(doc! { "$truncated": dropped as i64 }));
+                    }
+                    ArrayOverflow::Summarize => {
+                        let summary = doc! {
+                            "count": items.len() as i64,
+                            "first": items.first().cloned().unwrap_or(Bson::Null),
+                            "last": items.last().cloned().unwrap_or(Bson::Null),
+                        };
+                        *value = Bson::Document(summary);
+                    }
+                    ArrayOverflow::Keep => {}
+                }
+            }
+        }
+        // only what is left after the limit is descended into
+        match value {
+            Bson::Document(doc) => self.apply(doc),
+            Bson::Array(items) => items.iter_mut().for_each(|item| self.limit(item)),
+            _ => {}
+        }
+    }
+
+    /// Line for the export summary
+    pub fn summary(&self) -> Option<String> {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let action = match self.overflow {
+            ArrayOverflow::Truncate => "Truncated",
+            ArrayOverflow::Summarize => "Summarized",
+            ArrayOverflow::Keep => "Kept",
+        };
+        (hits > 0).then(|| format!("{action} {hits} arrays longer than {} elements", self.max))
+    }
+}
@@ -0,0 +1,103 @@
+use std::{io::Read, str::FromStr};
+
+use bson::{spec::BinarySubtype, Binary, Bson, Document};
+use flate2::read::ZlibDecoder;
+
+use crate::{docpath, DissectError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Snappy,
+    Zlib,
+}
+
+/// What the inflated bytes are turned into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inflated {
+    Binary,
+    String,
+    Bson,
+}
+
+/// A binary field inflated during export, given as `path:codec[:binary|string|bson]`
+#[derive(Debug, Clone)]
+pub struct DecompressField {
+    path: Vec<String>,
+    codec: Codec,
+    into: Inflated,
+}
+
+impl FromStr for DecompressField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let path = parts.next().filter(|p| !p.is_empty());
+        let codec = match parts.next() {
+            Some("snappy") => Codec::Snappy,
+            Some("zlib") => Codec::Zlib,
+            _ => return Err(format!("expected path:snappy|zlib, got {s}")),
+        };
+        let into = match parts.next() {
+            None | Some("binary") => Inflated::Binary,
+            Some("string") => Inflated::String,
+            Some("bson") => Inflated::Bson,
+            Some(other) => {
+                return Err(format!(
+                    "unknown target {other}, expected binary|string|bson"
+                ))
+            }
+        };
+        match path {
+            Some(path) if parts.next().is_none() => Ok(Self {
+                path: docpath::parse(path),
+                codec,
+                into,
+            }),
+            _ => Err(format!("expected path:snappy|zlib, got {s}")),
+        }
+    }
+}
+
+impl DecompressField {
+    pub(crate) fn apply(&self, doc: &mut Document) -> Result<(), DissectError> {
+        let mut result = Ok(());
+        docpath::visit_mut(doc, &self.path, &mut |value| {
+            if result.is_ok() {
+                result = self.inflate(value);
+            }
+        });
+        result
+    }
+
+    fn inflate(&self, value: &mut Bson) -> Result<(), DissectError> {
+        let Bson::Binary(bin) = value else {
+            return Ok(());
+        };
+        let bytes = match self.codec {
+            Codec::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(&bin.bytes)
+                .map_err(|e| self.error(e))?,
+            Codec::Zlib => {
+                let mut bytes = Vec::new();
+                ZlibDecoder::new(bin.bytes.as_slice())
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| self.error(e))?;
+                bytes
+            }
+        };
+        *value = match self.into {
+            Inflated::Binary => Bson::Binary(Binary {
+                subtype: BinarySubtype::Generic,
+                bytes,
+            }),
+            Inflated::String => Bson::String(String::from_utf8(bytes).map_err(|e| self.error(e))?),
+            Inflated::Bson => Bson::Document(Document::from_reader(bytes.as_slice())?),
+        };
+        Ok(())
+    }
+
+    fn error<E: std::fmt::Display>(&self, e: E) -> DissectError {
+        DissectError::Parse(format!("Failed to inflate {}: {e}", self.path.join(".")))
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    io::{BufRead, BufReader, BufWriter, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::{Bson, Document};
+use parking_lot::Mutex;
+
+use crate::DissectError;
+
+/// Pipes the documents through an external command, one process per worker thread kept for the whole export.
+/// Every document is a line of relaxed extended json on its stdin and the command answers every line with one of
+/// its own, the transformed document or an empty line or `null` to drop it
+pub(crate) struct ExecFilter {
+    command: String,
+    /// Processes not busy with a batch
+    idle: Mutex<Vec<Worker>>,
+    started: AtomicUsize,
+    piped: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+struct Worker {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExecFilter {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            idle: Mutex::new(Vec::new()),
+            started: AtomicUsize::new(0),
+            piped: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Send a batch through an idle process, one is started when all of them are busy,
+    /// the documents that are kept keep what they were paired with
+    pub fn apply<T>(&self, docs: Vec<(Document, T)>) -> Result<Vec<(Document, T)>, DissectError> {
+        let idle = self.idle.lock().pop();
+        let mut worker = match idle {
+            Some(worker) => worker,
+            None => self.spawn()?,
+        };
+        let count = docs.len();
+        let (docs, tags): (Vec<_>, Vec<_>) = docs.into_iter().unzip();
+        // a process that failed a batch may be halfway through an answer, it isn't used again
+        let answers = match self.exchange(&mut worker, docs) {
+            Ok(answers) => answers,
+            Err(e) => {
+                worker.kill();
+                return Err(e);
+            }
+        };
+        self.idle.lock().push(worker);
+        let kept = answers
+            .into_iter()
+            .zip(tags)
+            .filter_map(|(doc, tag)| Some((doc?, tag)))
+            .collect::<Vec<_>>();
+        self.piped.fetch_add(count, Ordering::Relaxed);
+        self.dropped
+            .fetch_add(count - kept.len(), Ordering::Relaxed);
+        Ok(kept)
+    }
+
+    fn spawn(&self) -> Result<Worker, DissectError> {
+        #[cfg(unix)]
+        let mut command = {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&self.command);
+            // in a group of its own so whatever the shell started is stopped with it
+            std::os::unix::process::CommandExt::process_group(&mut command, 0);
+            command
+        };
+        #[cfg(not(unix))]
+        let mut command = {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(&self.command);
+            command
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.failed(format!("failed to start: {e}")))?;
+        self.started.fetch_add(1, Ordering::Relaxed);
+        Ok(Worker {
+            stdin: BufWriter::new(child.stdin.take().expect("stdin is piped")),
+            stdout: BufReader::new(child.stdout.take().expect("stdout is piped")),
+            child,
+        })
+    }
+
+    /// Write the batch while reading the answers, so neither side blocks on a full pipe,
+    /// returns the answer to every document, none for the dropped ones
+    fn exchange(
+        &self,
+        worker: &mut Worker,
+        docs: Vec<Document>,
+    ) -> Result<Vec<Option<Document>>, DissectError> {
+        let count = docs.len();
+        let Worker {
+            child,
+            stdin,
+            stdout,
+        } = worker;
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(move || -> Result<(), DissectError> {
+                for doc in docs {
+                    serde_json::to_writer(
+                        &mut *stdin,
+                        &Bson::Document(doc).into_relaxed_extjson(),
+                    )?;
+                    stdin.write_all(b"\n")?;
+                }
+                stdin.flush()?;
+                Ok(())
+            });
+            let answers = self.answers(stdout, count);
+            if answers.is_err() {
+                // the writer waits on a process that stopped reading, stopping it closes the pipe
+                stop(child);
+            }
+            let written = writer
+                .join()
+                .map_err(|_| self.failed("writing to it panicked"));
+            let answers = answers?;
+            written??;
+            Ok(answers)
+        })
+    }
+
+    fn answers(
+        &self,
+        stdout: &mut BufReader<ChildStdout>,
+        count: usize,
+    ) -> Result<Vec<Option<Document>>, DissectError> {
+        let mut answers = Vec::with_capacity(count);
+        let mut line = String::new();
+        for _ in 0..count {
+            line.clear();
+            if stdout.read_line(&mut line)? == 0 {
+                return Err(self.failed("exited before answering every document"));
+            }
+            answers.push(self.parse(line.trim())?);
+        }
+        Ok(answers)
+    }
+
+    fn parse(&self, line: &str) -> Result<Option<Document>, DissectError> {
+        if line.is_empty() || line == "null" {
+            return Ok(None);
+        }
+        let value = serde_json::from_str::<serde_json::Value>(line)
+            .map_err(|e| self.failed(format!("answered with invalid json: {e}")))?;
+        match Bson::try_from(value) {
+            Ok(Bson::Document(doc)) => Ok(Some(doc)),
+            Ok(other) => Err(self.failed(format!(
+                "answered with a {:?} instead of a document",
+                other.element_type()
+            ))),
+            Err(e) => Err(self.failed(format!("answered with invalid extended json: {e}"))),
+        }
+    }
+
+    fn failed(&self, e: impl std::fmt::Display) -> DissectError {
+        DissectError::Unexpected(format!("--exec-filter {}: {e}", self.command))
+    }
+
+    /// Close the input of every process and wait for them to exit, returns a line for the export summary
+    pub fn finish(self) -> Result<String, DissectError> {
+        let mut failed = None;
+        for mut worker in self.idle.into_inner() {
+            drop(worker.stdin);
+            // every process is waited for even after one failed so none is left behind
+            match worker.child.wait() {
+                Ok(status) if !status.success() => {
+                    failed.get_or_insert_with(|| {
+                        DissectError::Unexpected(format!(
+                            "--exec-filter {}: exited with {status}",
+                            self.command
+                        ))
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    failed.get_or_insert(e.into());
+                }
+            }
+        }
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        Ok(format!(
+            "Piped {} documents through {} in {} processes, {} were dropped",
+            self.piped.into_inner(),
+            self.command,
+            self.started.into_inner(),
+            self.dropped.into_inner()
+        ))
+    }
+}
+
+impl Worker {
+    /// Stop a process that failed a batch and wait for it to exit
+    fn kill(mut self) {
+        stop(&mut self.child);
+        let _ = self.child.wait();
+    }
+}
+
+/// Stop a process and, on unix, everything in its group
+fn stop(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: the process is the leader of its own group and not reaped yet, so its id wasn't reused
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
@@ -0,0 +1,281 @@
+use std::cmp::Ordering;
+
+use serde_json::{Map, Value};
+
+use super::{equal, Node};
+
+/// The built-in functions with how many arguments they take, the last ones take at least that many
+const FUNCTIONS: [(&str, usize); 26] = [
+    ("abs", 1),
+    ("avg", 1),
+    ("ceil", 1),
+    ("contains", 2),
+    ("ends_with", 2),
+    ("floor", 1),
+    ("join", 2),
+    ("keys", 1),
+    ("length", 1),
+    ("map", 2),
+    ("max", 1),
+    ("max_by", 2),
+    ("min", 1),
+    ("min_by", 2),
+    ("reverse", 1),
+    ("sort", 1),
+    ("sort_by", 2),
+    ("starts_with", 2),
+    ("sum", 1),
+    ("to_array", 1),
+    ("to_number", 1),
+    ("to_string", 1),
+    ("type", 1),
+    ("values", 1),
+    ("merge", 1),
+    ("not_null", 1),
+];
+
+/// Functions taking any number of arguments from their count in `FUNCTIONS` on
+const VARIADIC: [&str; 2] = ["merge", "not_null"];
+
+/// Whether `name` is a function taking `count` arguments, checked when the expression is parsed
+pub(super) fn check(name: &str, count: usize) -> Result<(), String> {
+    let Some(&(_, takes)) = FUNCTIONS.iter().find(|(function, _)| *function == name) else {
+        return Err(format!("unknown function {name}()"));
+    };
+    let plural = if takes == 1 { "" } else { "s" };
+    match VARIADIC.contains(&name) {
+        true if count < takes => Err(format!(
+            "{name}() takes at least {takes} argument{plural}, got {count}"
+        )),
+        false if count != takes => Err(format!(
+            "{name}() takes {takes} argument{plural}, got {count}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub(super) fn call(name: &str, args: &[Node], current: &Value) -> Result<Value, String> {
+    // these take an &expression run on every item instead of its value
+    match name {
+        "map" => {
+            let node = expref(name, &args[0])?;
+            let items = array(name, args[1].eval(current)?)?;
+            return Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| node.eval(item))
+                    .collect::<Result<_, _>>()?,
+            ));
+        }
+        "sort_by" | "max_by" | "min_by" => {
+            let items = array(name, args[0].eval(current)?)?;
+            let node = expref(name, &args[1])?;
+            let mut keyed = items
+                .into_iter()
+                .map(|item| Ok((node.eval(&item)?, item)))
+                .collect::<Result<Vec<_>, String>>()?;
+            let keys = keyed.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+            sortable(name, &keys)?;
+            return Ok(match name {
+                "sort_by" => {
+                    keyed.sort_by(|(a, _), (b, _)| order(a, b));
+                    Value::Array(keyed.into_iter().map(|(_, item)| item).collect())
+                }
+                "max_by" => keyed
+                    .into_iter()
+                    .max_by(|(a, _), (b, _)| order(a, b))
+                    .map_or(Value::Null, |(_, item)| item),
+                _ => keyed
+                    .into_iter()
+                    .min_by(|(a, _), (b, _)| order(a, b))
+                    .map_or(Value::Null, |(_, item)| item),
+            });
+        }
+        _ => {}
+    }
+    let mut values = args
+        .iter()
+        .map(|arg| arg.eval(current))
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = values.remove(0);
+    Ok(match name {
+        "abs" => number_value(number(name, &first)?.abs()),
+        "ceil" => number_value(number(name, &first)?.ceil()),
+        "floor" => number_value(number(name, &first)?.floor()),
+        "avg" | "sum" => {
+            let numbers = array(name, first)?
+                .iter()
+                .map(|item| number(name, item))
+                .collect::<Result<Vec<_>, _>>()?;
+            let sum = numbers.iter().sum::<f64>();
+            match (name, numbers.len()) {
+                ("avg", 0) => Value::Null,
+                ("avg", count) => number_value(sum / count as f64),
+                _ => number_value(sum),
+            }
+        }
+        "contains" => match (&first, &values[0]) {
+            (Value::Array(items), search) => {
+                Value::Bool(items.iter().any(|item| equal(item, search)))
+            }
+            (Value::String(s), Value::String(search)) => Value::Bool(s.contains(search.as_str())),
+            (Value::String(_), _) => Value::Bool(false),
+            (other, _) => return Err(invalid(name, other)),
+        },
+        "starts_with" | "ends_with" => match (&first, &values[0]) {
+            (Value::String(s), Value::String(affix)) => Value::Bool(match name {
+                "starts_with" => s.starts_with(affix.as_str()),
+                _ => s.ends_with(affix.as_str()),
+            }),
+            (Value::String(_), other) | (other, _) => return Err(invalid(name, other)),
+        },
+        "join" => {
+            let Value::String(glue) = &first else {
+                return Err(invalid(name, &first));
+            };
+            let parts = array(name, values.remove(0))?
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s),
+                    other => Err(invalid(name, &other)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Value::String(parts.join(glue))
+        }
+        "keys" => match first {
+            Value::Object(fields) => fields
+                .into_iter()
+                .map(|(key, _)| Value::String(key))
+                .collect(),
+            other => return Err(invalid(name, &other)),
+        },
+        "values" => match first {
+            Value::Object(fields) => fields.into_iter().map(|(_, value)| value).collect(),
+            other => return Err(invalid(name, &other)),
+        },
+        "length" => match &first {
+            Value::String(s) => Value::from(s.chars().count()),
+            Value::Array(items) => Value::from(items.len()),
+            Value::Object(fields) => Value::from(fields.len()),
+            other => return Err(invalid(name, other)),
+        },
+        "max" | "min" | "sort" => {
+            let mut items = array(name, first)?;
+            sortable(name, &items)?;
+            items.sort_by(order);
+            match name {
+                "max" => items.pop().unwrap_or(Value::Null),
+                "min" => items.into_iter().next().unwrap_or(Value::Null),
+                _ => Value::Array(items),
+            }
+        }
+        "merge" => {
+            let mut merged = Map::new();
+            for value in std::iter::once(first).chain(values) {
+                match value {
+                    Value::Object(fields) => merged.extend(fields),
+                    other => return Err(invalid(name, &other)),
+                }
+            }
+            Value::Object(merged)
+        }
+        "not_null" => std::iter::once(first)
+            .chain(values)
+            .find(|value| !value.is_null())
+            .unwrap_or(Value::Null),
+        "reverse" => match first {
+            Value::Array(mut items) => {
+                items.reverse();
+                Value::Array(items)
+            }
+            Value::String(s) => Value::String(s.chars().rev().collect()),
+            other => return Err(invalid(name, &other)),
+        },
+        "to_array" => match first {
+            Value::Array(items) => Value::Array(items),
+            other => Value::Array(vec![other]),
+        },
+        "to_number" => match first {
+            Value::Number(n) => Value::Number(n),
+            Value::String(s) => match s.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => s.parse::<f64>().map_or(Value::Null, number_value),
+            },
+            _ => Value::Null,
+        },
+        "to_string" => match first {
+            Value::String(s) => Value::String(s),
+            other => Value::String(other.to_string()),
+        },
+        "type" => Value::from(match first {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }),
+        _ => unreachable!("{name}() is checked when the expression is parsed"),
+    })
+}
+
+fn expref<'a>(name: &str, arg: &'a Node) -> Result<&'a Node, String> {
+    match arg {
+        Node::Expref(node) => Ok(node),
+        _ => Err(format!("{name}() takes an &expression")),
+    }
+}
+
+fn array(name: &str, value: Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(items) => Ok(items),
+        other => Err(invalid(name, &other)),
+    }
+}
+
+fn number(name: &str, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) => Ok(n.as_f64().unwrap_or(f64::NAN)),
+        other => Err(invalid(name, other)),
+    }
+}
+
+/// A number as json, whole numbers as integers
+fn number_value(n: f64) -> Value {
+    match n.fract() == 0.0 && n.abs() < 9e15 {
+        true => Value::from(n as i64),
+        false => serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number),
+    }
+}
+
+/// Whether the values are all numbers or all strings, the only values sorting compares
+fn sortable(name: &str, values: &[Value]) -> Result<(), String> {
+    let all = |kind: fn(&Value) -> bool| values.iter().all(kind);
+    match all(Value::is_number) || all(Value::is_string) {
+        true => Ok(()),
+        false => Err(format!("{name}() compares only numbers or only strings")),
+    }
+}
+
+fn order(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
+fn invalid(name: &str, value: &Value) -> String {
+    let kind = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    };
+    format!("{name}() can't take {kind}")
+}
@@ -0,0 +1,304 @@
+use std::{
+    cmp::Ordering,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+use bson::{Bson, Document};
+use serde_json::Value;
+
+use crate::DissectError;
+
+mod functions;
+mod parse;
+#[cfg(test)]
+mod tests;
+
+/// Reshapes and filters every document with a JMESPath expression, the query language of the AWS CLI. The document
+/// goes in as relaxed extended json like for --jq, an object the expression comes to is the new document, `null`
+/// drops it and an array, like a projection makes, turns it into a document per object in it
+pub(crate) struct JmesPath {
+    code: String,
+    node: Node,
+    /// Documents the expression came to null or an empty array for
+    dropped: AtomicUsize,
+    /// Documents added by arrays of more than one object
+    added: AtomicUsize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// `@`, and what a projection runs when nothing follows it
+    Current,
+    Field(String),
+    /// The right side on what the left one comes to, like `a.b` and `a[0]`
+    Sub(Box<Node>, Box<Node>),
+    Index(i64),
+    Slice([Option<i64>; 3]),
+    /// The right side on every item of the array on the left, the nulls it comes to left out
+    Project(Box<Node>, Box<Node>),
+    /// The right side on every value of the object on the left, the nulls it comes to left out
+    ProjectValues(Box<Node>, Box<Node>),
+    /// The last side on the items of the array on the left the condition in the middle holds for
+    Filter(Box<Node>, Box<Node>, Box<Node>),
+    /// The items of the arrays in an array side by side
+    Flatten(Box<Node>),
+    List(Vec<Node>),
+    Hash(Vec<(String, Node)>),
+    Literal(Value),
+    Pipe(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Cmp(Cmp, Box<Node>, Box<Node>),
+    Function(String, Vec<Node>),
+    /// `&expression`, handed to functions like `sort_by` to run on every item
+    Expref(Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl JmesPath {
+    pub fn new(code: &str) -> Result<Self, DissectError> {
+        let node = parse::parse(code)
+            .map_err(|e| DissectError::Parse(format!("Invalid --jmespath {code}: {e}")))?;
+        Ok(Self {
+            code: code.to_string(),
+            node,
+            dropped: AtomicUsize::new(0),
+            added: AtomicUsize::new(0),
+        })
+    }
+
+    /// Run the expression over a batch, the documents it comes to keep what their document was paired with
+    pub fn apply<T: Clone>(
+        &self,
+        docs: Vec<(Document, T)>,
+    ) -> Result<Vec<(Document, T)>, DissectError> {
+        let mut out = Vec::with_capacity(docs.len());
+        for (doc, tag) in docs {
+            let input = Bson::Document(doc).into_relaxed_extjson();
+            let values = match self.node.eval(&input).map_err(|e| self.failed(e))? {
+                Value::Array(items) => items,
+                value => vec![value],
+            };
+            let before = out.len();
+            for value in values {
+                if let Some(doc) = self.document(value)? {
+                    out.push((doc, tag.clone()));
+                }
+            }
+            match out.len() - before {
+                0 => self.dropped.fetch_add(1, AtomicOrdering::Relaxed),
+                yielded => self.added.fetch_add(yielded - 1, AtomicOrdering::Relaxed),
+            };
+        }
+        Ok(out)
+    }
+
+    fn document(&self, value: Value) -> Result<Option<Document>, DissectError> {
+        match Bson::try_from(value) {
+            Ok(Bson::Document(doc)) => Ok(Some(doc)),
+            Ok(Bson::Null) => Ok(None),
+            Ok(other) => Err(self.failed(format!(
+                "came to a {:?} instead of an object",
+                other.element_type()
+            ))),
+            Err(e) => Err(self.failed(format!("came to invalid extended json: {e}"))),
+        }
+    }
+
+    fn failed(&self, e: impl std::fmt::Display) -> DissectError {
+        DissectError::Unexpected(format!("--jmespath {}: {e}", self.code))
+    }
+
+    /// A line for the export summary
+    pub fn summary(&self) -> String {
+        format!(
+            "--jmespath dropped {} documents and added {}",
+            self.dropped.load(AtomicOrdering::Relaxed),
+            self.added.load(AtomicOrdering::Relaxed)
+        )
+    }
+}
+
+impl Node {
+    fn eval(&self, value: &Value) -> Result<Value, String> {
+        Ok(match self {
+            Self::Current => value.clone(),
+            Self::Field(name) => value.get(name).cloned().unwrap_or(Value::Null),
+            Self::Sub(left, right) | Self::Pipe(left, right) => right.eval(&left.eval(value)?)?,
+            Self::Index(n) => match value {
+                Value::Array(items) => {
+                    let at = if *n < 0 { items.len() as i64 + n } else { *n };
+                    usize::try_from(at)
+                        .ok()
+                        .and_then(|at| items.get(at))
+                        .cloned()
+                        .unwrap_or(Value::Null)
+                }
+                _ => Value::Null,
+            },
+            Self::Slice(parts) => match value {
+                Value::Array(items) => Value::Array(slice(items, *parts)),
+                _ => Value::Null,
+            },
+            Self::Project(left, right) => match left.eval(value)? {
+                Value::Array(items) => project(items.iter(), right)?,
+                _ => Value::Null,
+            },
+            Self::ProjectValues(left, right) => match left.eval(value)? {
+                Value::Object(fields) => project(fields.values(), right)?,
+                _ => Value::Null,
+            },
+            Self::Filter(left, condition, right) => match left.eval(value)? {
+                Value::Array(items) => {
+                    let mut kept = Vec::with_capacity(items.len());
+                    for item in &items {
+                        if truthy(&condition.eval(item)?) {
+                            kept.push(item);
+                        }
+                    }
+                    project(kept.into_iter(), right)?
+                }
+                _ => Value::Null,
+            },
+            Self::Flatten(inner) => match inner.eval(value)? {
+                Value::Array(items) => Value::Array(
+                    items
+                        .into_iter()
+                        .flat_map(|item| match item {
+                            Value::Array(items) => items,
+                            item => vec![item],
+                        })
+                        .collect(),
+                ),
+                _ => Value::Null,
+            },
+            Self::List(_) | Self::Hash(_) if value.is_null() => Value::Null,
+            Self::List(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| item.eval(value))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Self::Hash(pairs) => Value::Object(
+                pairs
+                    .iter()
+                    .map(|(key, node)| Ok((key.clone(), node.eval(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            Self::Literal(literal) => literal.clone(),
+            Self::Or(left, right) => match left.eval(value)? {
+                left if truthy(&left) => left,
+                _ => right.eval(value)?,
+            },
+            Self::And(left, right) => match left.eval(value)? {
+                left if truthy(&left) => right.eval(value)?,
+                left => left,
+            },
+            Self::Not(inner) => Value::Bool(!truthy(&inner.eval(value)?)),
+            Self::Cmp(cmp, left, right) => cmp.apply(&left.eval(value)?, &right.eval(value)?),
+            Self::Function(name, args) => functions::call(name, args, value)?,
+            Self::Expref(_) => return Err("an &expression can only be handed to a function".into()),
+        })
+    }
+}
+
+impl Cmp {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+
+    /// Equality holds for any two values, the orderings only compare numbers and are null otherwise
+    fn apply(self, left: &Value, right: &Value) -> Value {
+        let ordering = match (left, right) {
+            (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+            _ => None,
+        };
+        match (self, ordering) {
+            (Self::Eq, _) => Value::Bool(equal(left, right)),
+            (Self::Ne, _) => Value::Bool(!equal(left, right)),
+            (_, None) => Value::Null,
+            (Self::Lt, Some(o)) => Value::Bool(o == Ordering::Less),
+            (Self::Le, Some(o)) => Value::Bool(o != Ordering::Greater),
+            (Self::Gt, Some(o)) => Value::Bool(o == Ordering::Greater),
+            (Self::Ge, Some(o)) => Value::Bool(o != Ordering::Less),
+        }
+    }
+}
+
+/// Equality of json values with `1` equal to `1.0`
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| equal(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// Whether a value counts as true, everything but null, false and empty strings, arrays and objects does
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => false,
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+        _ => true,
+    }
+}
+
+fn project<'a>(items: impl Iterator<Item = &'a Value>, node: &Node) -> Result<Value, String> {
+    let mut projected = Vec::new();
+    for item in items {
+        match node.eval(item)? {
+            Value::Null => {}
+            value => projected.push(value),
+        }
+    }
+    Ok(Value::Array(projected))
+}
+
+/// The items of `[start:stop:step]`, the parts left out and negative ones work like in Python
+fn slice(items: &[Value], [start, stop, step]: [Option<i64>; 3]) -> Vec<Value> {
+    let len = items.len() as i64;
+    let step = step.unwrap_or(1);
+    let bound = |at: i64| {
+        let at = if at < 0 { at + len } else { at };
+        match step < 0 {
+            true => at.clamp(-1, len - 1),
+            false => at.clamp(0, len),
+        }
+    };
+    let (mut at, stop) = match step < 0 {
+        true => (start.map_or(len - 1, bound), stop.map_or(-1, bound)),
+        false => (start.map_or(0, bound), stop.map_or(len, bound)),
+    };
+    let mut sliced = Vec::new();
+    while (step > 0 && at < stop) || (step < 0 && at > stop) {
+        sliced.push(items[at as usize].clone());
+        at += step;
+    }
+    sliced
+}
@@ -0,0 +1,470 @@
+use serde_json::Value;
+
+use super::{functions, Cmp, Node};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A quoted identifier, which can't name a function
+    Quoted(String),
+    Number(i64),
+    Literal(Value),
+    Dot,
+    Star,
+    /// `[]`
+    Flatten,
+    /// `[?`
+    Filter,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    Comma,
+    Colon,
+    Pipe,
+    Or,
+    And,
+    Not,
+    Cmp(Cmp),
+    /// `@`
+    Current,
+    /// `&`
+    Expref,
+    End,
+}
+
+/// How tightly a token binds the expression before it, tokens binding less than a projection end it
+fn binding(token: &Token) -> u8 {
+    match token {
+        Token::Pipe => 1,
+        Token::Or => 2,
+        Token::And => 3,
+        Token::Cmp(_) => 5,
+        Token::Flatten => 9,
+        Token::Star => 20,
+        Token::Filter => 21,
+        Token::Dot => 40,
+        Token::Not => 45,
+        Token::LBrace => 50,
+        Token::LBracket => 55,
+        Token::LParen => 60,
+        _ => 0,
+    }
+}
+
+/// Tokens binding at least this much continue a projection
+const PROJECTED: u8 = 10;
+
+pub(super) fn parse(s: &str) -> Result<Node, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        at: 0,
+    };
+    let node = parser.expression(0)?;
+    match parser.peek(0) {
+        Token::End => Ok(node),
+        _ => Err(parser.unexpected(parser.at)),
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        let mut followed_by = |next: char| chars.next_if(|&(_, c)| c == next).is_some();
+        let token =
+            match c {
+                c if c.is_whitespace() => continue,
+                '.' => Token::Dot,
+                '*' => Token::Star,
+                ']' => Token::RBracket,
+                '{' => Token::LBrace,
+                '}' => Token::RBrace,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                ':' => Token::Colon,
+                '@' => Token::Current,
+                '[' if followed_by('?') => Token::Filter,
+                '[' if followed_by(']') => Token::Flatten,
+                '[' => Token::LBracket,
+                '|' if followed_by('|') => Token::Or,
+                '|' => Token::Pipe,
+                '&' if followed_by('&') => Token::And,
+                '&' => Token::Expref,
+                '!' if followed_by('=') => Token::Cmp(Cmp::Ne),
+                '!' => Token::Not,
+                '<' if followed_by('=') => Token::Cmp(Cmp::Le),
+                '<' => Token::Cmp(Cmp::Lt),
+                '>' if followed_by('=') => Token::Cmp(Cmp::Ge),
+                '>' => Token::Cmp(Cmp::Gt),
+                '=' if followed_by('=') => Token::Cmp(Cmp::Eq),
+                '"' => {
+                    let raw = delimited(&mut chars, '"', at)?;
+                    Token::Quoted(serde_json::from_str(&format!("\"{raw}\"")).map_err(|e| {
+                        format!("invalid quoted identifier at column {}: {e}", at + 1)
+                    })?)
+                }
+                '\'' => Token::Literal(Value::String(
+                    delimited(&mut chars, '\'', at)?.replace("\\'", "'"),
+                )),
+                '`' => {
+                    let raw = delimited(&mut chars, '`', at)?.replace("\\`", "`");
+                    // older expressions leave the quotes off string literals
+                    let literal = serde_json::from_str(raw.trim())
+                        .or_else(|_| serde_json::from_str(&format!("\"{raw}\"")))
+                        .map_err(|e| format!("invalid literal at column {}: {e}", at + 1))?;
+                    Token::Literal(literal)
+                }
+                c if c.is_ascii_digit() || c == '-' => {
+                    let mut number = c.to_string();
+                    while let Some((_, digit)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                        number.push(digit);
+                    }
+                    Token::Number(
+                        number
+                            .parse()
+                            .map_err(|_| format!("invalid number {number} at column {}", at + 1))?,
+                    )
+                }
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut name = c.to_string();
+                    while let Some((_, c)) =
+                        chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                    {
+                        name.push(c);
+                    }
+                    Token::Ident(name)
+                }
+                c => return Err(format!("unexpected {c} at column {}", at + 1)),
+            };
+        tokens.push((at, token));
+    }
+    tokens.push((s.len(), Token::End));
+    Ok(tokens)
+}
+
+/// The text up to the closing `end`, escapes of it are left in
+fn delimited(
+    chars: &mut std::iter::Peekable<std::str::CharIndices>,
+    end: char,
+    at: usize,
+) -> Result<String, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some((_, c)) if c == end => return Ok(text),
+            Some((_, '\\')) => {
+                text.push('\\');
+                if let Some((_, escaped)) = chars.next() {
+                    text.push(escaped);
+                }
+            }
+            Some((_, c)) => text.push(c),
+            None => return Err(format!("unterminated {end} at column {}", at + 1)),
+        }
+    }
+}
+
+/// A Pratt parser, every token parses what it starts and what it continues by how tightly it binds
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self, ahead: usize) -> &Token {
+        self.tokens
+            .get(self.at + ahead)
+            .map_or(&Token::End, |(_, token)| token)
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek(0).clone();
+        self.at = (self.at + 1).min(self.tokens.len() - 1);
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match *self.peek(0) == token {
+            true => {
+                self.next();
+                Ok(())
+            }
+            false => Err(self.unexpected(self.at)),
+        }
+    }
+
+    fn unexpected(&self, nth: usize) -> String {
+        match &self.tokens[nth] {
+            (_, Token::End) => "unexpected end of expression".into(),
+            (at, token) => format!("unexpected {} at column {}", describe(token), at + 1),
+        }
+    }
+
+    fn expression(&mut self, power: u8) -> Result<Node, String> {
+        let mut node = self.prefix()?;
+        while power < binding(self.peek(0)) {
+            node = self.infix(node)?;
+        }
+        Ok(node)
+    }
+
+    /// What a token parses at the start of an expression
+    fn prefix(&mut self) -> Result<Node, String> {
+        let start = self.at;
+        Ok(match self.next() {
+            Token::Literal(value) => Node::Literal(value),
+            Token::Ident(name) => Node::Field(name),
+            Token::Quoted(_) if *self.peek(0) == Token::LParen => {
+                return Err(format!(
+                    "a quoted identifier can't name a function, {}",
+                    self.unexpected(start)
+                ))
+            }
+            Token::Quoted(name) => Node::Field(name),
+            Token::Star => {
+                let right = match self.peek(0) {
+                    Token::RBracket => Node::Current,
+                    _ => self.projection(binding(&Token::Star))?,
+                };
+                Node::ProjectValues(Box::new(Node::Current), Box::new(right))
+            }
+            Token::Filter => self.filter(Node::Current)?,
+            Token::LBrace => self.hash()?,
+            Token::LParen => {
+                let node = self.expression(0)?;
+                self.expect(Token::RParen)?;
+                node
+            }
+            Token::Flatten => {
+                let right = self.projection(binding(&Token::Flatten))?;
+                Node::Project(
+                    Box::new(Node::Flatten(Box::new(Node::Current))),
+                    Box::new(right),
+                )
+            }
+            Token::Not => Node::Not(Box::new(self.expression(binding(&Token::Not))?)),
+            Token::LBracket => match (self.peek(0), self.peek(1)) {
+                (Token::Number(_) | Token::Colon, _) => {
+                    let right = self.index()?;
+                    self.sliced(Node::Current, right)?
+                }
+                (Token::Star, Token::RBracket) => {
+                    self.at += 2;
+                    let right = self.projection(binding(&Token::Star))?;
+                    Node::Project(Box::new(Node::Current), Box::new(right))
+                }
+                _ => self.list()?,
+            },
+            Token::Current => Node::Current,
+            Token::Expref => Node::Expref(Box::new(self.expression(0)?)),
+            _ => return Err(self.unexpected(start)),
+        })
+    }
+
+    /// What a token parses after the expression before it
+    fn infix(&mut self, left: Node) -> Result<Node, String> {
+        let start = self.at;
+        let token = self.next();
+        let power = binding(&token);
+        let left = Box::new(left);
+        Ok(match token {
+            Token::Dot if *self.peek(0) == Token::Star => {
+                self.next();
+                Node::ProjectValues(left, Box::new(self.projection(power)?))
+            }
+            Token::Dot => Node::Sub(left, Box::new(self.dotted(power)?)),
+            Token::Pipe => Node::Pipe(left, Box::new(self.expression(power)?)),
+            Token::Or => Node::Or(left, Box::new(self.expression(power)?)),
+            Token::And => Node::And(left, Box::new(self.expression(power)?)),
+            Token::Cmp(cmp) => Node::Cmp(cmp, left, Box::new(self.expression(power)?)),
+            Token::LParen => {
+                let Node::Field(name) = *left else {
+                    return Err(format!(
+                        "only a function name can be called, {}",
+                        self.unexpected(start)
+                    ));
+                };
+                let mut args = Vec::new();
+                // a comma is followed by another argument, `f(a b)` and `f(a,)` are errors
+                if *self.peek(0) != Token::RParen {
+                    args.push(self.expression(0)?);
+                    while *self.peek(0) == Token::Comma {
+                        self.next();
+                        args.push(self.expression(0)?);
+                    }
+                }
+                self.expect(Token::RParen)?;
+                functions::check(&name, args.len())?;
+                Node::Function(name, args)
+            }
+            Token::Filter => self.filter(*left)?,
+            Token::Flatten => Node::Project(
+                Box::new(Node::Flatten(left)),
+                Box::new(self.projection(power)?),
+            ),
+            Token::LBracket => match self.peek(0) {
+                Token::Number(_) | Token::Colon => {
+                    let right = self.index()?;
+                    self.sliced(*left, right)?
+                }
+                _ => {
+                    self.expect(Token::Star)?;
+                    self.expect(Token::RBracket)?;
+                    let right = self.projection(binding(&Token::Star))?;
+                    Node::Project(left, Box::new(right))
+                }
+            },
+            _ => return Err(self.unexpected(start)),
+        })
+    }
+
+    /// What a projection runs on every item, nothing when the next token ends it
+    fn projection(&mut self, power: u8) -> Result<Node, String> {
+        match self.peek(0) {
+            token if binding(token) < PROJECTED => Ok(Node::Current),
+            Token::LBracket | Token::Filter => self.expression(power),
+            Token::Dot => {
+                self.next();
+                self.dotted(power)
+            }
+            _ => Err(self.unexpected(self.at)),
+        }
+    }
+
+    /// What may follow a dot, a name, `*` or a multiselect
+    fn dotted(&mut self, power: u8) -> Result<Node, String> {
+        match self.peek(0) {
+            Token::Ident(_) | Token::Quoted(_) | Token::Star => self.expression(power),
+            Token::LBracket => {
+                self.next();
+                self.list()
+            }
+            Token::LBrace => {
+                self.next();
+                self.hash()
+            }
+            _ => Err(self.unexpected(self.at)),
+        }
+    }
+
+    /// The condition and projection after `[?`
+    fn filter(&mut self, left: Node) -> Result<Node, String> {
+        let condition = self.expression(0)?;
+        self.expect(Token::RBracket)?;
+        let right = match self.peek(0) {
+            Token::Flatten => Node::Current,
+            _ => self.projection(binding(&Token::Filter))?,
+        };
+        Ok(Node::Filter(
+            Box::new(left),
+            Box::new(condition),
+            Box::new(right),
+        ))
+    }
+
+    /// An index or a slice after `[`
+    fn index(&mut self) -> Result<Node, String> {
+        if *self.peek(0) != Token::Colon && *self.peek(1) != Token::Colon {
+            let start = self.at;
+            let Token::Number(n) = self.next() else {
+                return Err(self.unexpected(start));
+            };
+            self.expect(Token::RBracket)?;
+            return Ok(Node::Index(n));
+        }
+        let mut parts = [None; 3];
+        let mut nth = 0;
+        loop {
+            match *self.peek(0) {
+                Token::RBracket => break,
+                Token::Colon if nth < 2 => nth += 1,
+                Token::Number(n) if parts[nth].is_none() => parts[nth] = Some(n),
+                _ => return Err(self.unexpected(self.at)),
+            }
+            self.next();
+        }
+        self.expect(Token::RBracket)?;
+        if parts[2] == Some(0) {
+            return Err("the step of a slice can't be 0".into());
+        }
+        Ok(Node::Slice(parts))
+    }
+
+    /// An index of `left`, a slice projects the items it keeps
+    fn sliced(&mut self, left: Node, right: Node) -> Result<Node, String> {
+        let slice = matches!(right, Node::Slice(_));
+        let node = Node::Sub(Box::new(left), Box::new(right));
+        Ok(match slice {
+            true => Node::Project(
+                Box::new(node),
+                Box::new(self.projection(binding(&Token::Star))?),
+            ),
+            false => node,
+        })
+    }
+
+    /// A list of expressions after `[`
+    fn list(&mut self) -> Result<Node, String> {
+        let mut items = vec![self.expression(0)?];
+        while *self.peek(0) == Token::Comma {
+            self.next();
+            items.push(self.expression(0)?);
+        }
+        self.expect(Token::RBracket)?;
+        Ok(Node::List(items))
+    }
+
+    /// Names and expressions after `{`
+    fn hash(&mut self) -> Result<Node, String> {
+        let mut pairs = Vec::new();
+        loop {
+            let key = match self.peek(0) {
+                Token::Ident(key) | Token::Quoted(key) => key.clone(),
+                _ => return Err(self.unexpected(self.at)),
+            };
+            self.next();
+            self.expect(Token::Colon)?;
+            pairs.push((key, self.expression(0)?));
+            match self.peek(0) {
+                Token::Comma => self.next(),
+                Token::RBrace => break,
+                _ => return Err(self.unexpected(self.at)),
+            };
+        }
+        self.next();
+        Ok(Node::Hash(pairs))
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(name) => name.clone(),
+        Token::Quoted(name) => format!("{name:?}"),
+        Token::Number(n) => n.to_string(),
+        Token::Literal(value) => format!("`{value}`"),
+        Token::Dot => ".".into(),
+        Token::Star => "*".into(),
+        Token::Flatten => "[]".into(),
+        Token::Filter => "[?".into(),
+        Token::LBracket => "[".into(),
+        Token::RBracket => "]".into(),
+        Token::LBrace => "{".into(),
+        Token::RBrace => "}".into(),
+        Token::LParen => "(".into(),
+        Token::RParen => ")".into(),
+        Token::Comma => ",".into(),
+        Token::Colon => ":".into(),
+        Token::Pipe => "|".into(),
+        Token::Or => "||".into(),
+        Token::And => "&&".into(),
+        Token::Not => "!".into(),
+        Token::Cmp(cmp) => cmp.symbol().into(),
+        Token::Current => "@".into(),
+        Token::Expref => "&".into(),
+        Token::End => "end".into(),
+    }
+}
@@ -0,0 +1,367 @@
+//! Cases of the JMESPath specification and its compliance suite, run on the parser and evaluator
+
+use serde_json::{json, Value};
+
+use super::parse::parse;
+
+fn search(expression: &str, data: Value) -> Value {
+    parse(expression)
+        .and_then(|node| node.eval(&data))
+        .unwrap_or_else(|e| panic!("{expression} failed: {e}"))
+}
+
+fn invalid(expression: &str) -> String {
+    match parse(expression) {
+        Ok(node) => panic!("{expression} parsed as {node:?}"),
+        Err(e) => e,
+    }
+}
+
+fn fails(expression: &str, data: Value) -> String {
+    let node = parse(expression).unwrap_or_else(|e| panic!("{expression} failed to parse: {e}"));
+    match node.eval(&data) {
+        Ok(value) => panic!("{expression} came to {value}"),
+        Err(e) => e,
+    }
+}
+
+#[test]
+fn identifiers_and_subexpressions() {
+    let data = json!({"a": {"b": {"c": {"d": "value"}}}, "with space": 1});
+    assert_eq!(search("a.b.c.d", data.clone()), json!("value"));
+    assert_eq!(search("a.b.c", data.clone()), json!({"d": "value"}));
+    assert_eq!(search("a.x.c", data.clone()), Value::Null);
+    assert_eq!(search(r#""with space""#, data.clone()), json!(1));
+    assert_eq!(search("a.b.c.d.e", data), Value::Null);
+}
+
+#[test]
+fn indexes() {
+    let data = json!({"a": ["x", "y", "z"], "b": [[0, 1], [2, 3]]});
+    assert_eq!(search("a[0]", data.clone()), json!("x"));
+    assert_eq!(search("a[-1]", data.clone()), json!("z"));
+    assert_eq!(search("a[3]", data.clone()), Value::Null);
+    assert_eq!(search("a[-4]", data.clone()), Value::Null);
+    assert_eq!(search("b[1][0]", data.clone()), json!(2));
+    assert_eq!(search("b.c[0]", data), Value::Null);
+}
+
+#[test]
+fn slices() {
+    let data = json!([0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    assert_eq!(search("[0:5]", data.clone()), json!([0, 1, 2, 3, 4]));
+    assert_eq!(search("[5:10]", data.clone()), json!([5, 6, 7, 8, 9]));
+    assert_eq!(search("[:3]", data.clone()), json!([0, 1, 2]));
+    assert_eq!(search("[::2]", data.clone()), json!([0, 2, 4, 6, 8]));
+    assert_eq!(
+        search("[::-1]", data.clone()),
+        json!([9, 8, 7, 6, 5, 4, 3, 2, 1, 0])
+    );
+    assert_eq!(search("[-3:]", data.clone()), json!([7, 8, 9]));
+    assert_eq!(search("[8:2:-2]", data.clone()), json!([8, 6, 4]));
+    assert_eq!(search("[20:]", data.clone()), json!([]));
+    assert_eq!(search("foo[0:1]", json!({"foo": "text"})), Value::Null);
+    let people = json!([{"a": 1}, {"a": 2}, {"a": 3}]);
+    assert_eq!(search("[1:].a", people), json!([2, 3]));
+}
+
+#[test]
+fn list_projections() {
+    let data = json!({"people": [
+        {"first": "James", "last": "d"},
+        {"first": "Jacob", "last": "e"},
+        {"first": "Jayden", "last": "f"},
+        {"missing": "different"}
+    ]});
+    assert_eq!(
+        search("people[*].first", data.clone()),
+        json!(["James", "Jacob", "Jayden"])
+    );
+    assert_eq!(
+        search("people[:2].first", data.clone()),
+        json!(["James", "Jacob"])
+    );
+    assert_eq!(search("people[*].first[0]", data.clone()), json!([]));
+    assert_eq!(search("missing[*].first", data), Value::Null);
+}
+
+#[test]
+fn object_projections() {
+    let data = json!({"ops": {
+        "functionA": {"numArgs": 2},
+        "functionB": {"numArgs": 3},
+        "functionC": {"variadic": true}
+    }});
+    assert_eq!(search("ops.*.numArgs", data.clone()), json!([2, 3]));
+    assert_eq!(search("ops.functionA.*", data.clone()), json!([2]));
+    assert_eq!(search("ops.*.missing", data), json!([]));
+}
+
+#[test]
+fn flatten_projections() {
+    let data = json!({"reservations": [
+        {"instances": [{"state": "running"}, {"state": "stopped"}]},
+        {"instances": [{"state": "terminated"}, {"state": "running"}]}
+    ]});
+    assert_eq!(
+        search("reservations[*].instances[*].state", data.clone()),
+        json!([["running", "stopped"], ["terminated", "running"]])
+    );
+    assert_eq!(
+        search("reservations[].instances[].state", data),
+        json!(["running", "stopped", "terminated", "running"])
+    );
+    assert_eq!(
+        search("[]", json!([[0, 1], 2, [3], 4, [5, [6, 7]]])),
+        json!([0, 1, 2, 3, 4, 5, [6, 7]])
+    );
+    assert_eq!(
+        search("[][]", json!([[0, 1], [5, [6, 7]]])),
+        json!([0, 1, 5, 6, 7])
+    );
+}
+
+#[test]
+fn filter_projections() {
+    let data = json!({"machines": [
+        {"name": "a", "state": "running", "cpu": 4},
+        {"name": "b", "state": "stopped", "cpu": 2},
+        {"name": "c", "state": "running", "cpu": 8}
+    ]});
+    assert_eq!(
+        search("machines[?state=='running'].name", data.clone()),
+        json!(["a", "c"])
+    );
+    assert_eq!(
+        search("machines[?cpu > `2`].name", data.clone()),
+        json!(["a", "c"])
+    );
+    assert_eq!(
+        search("machines[?cpu <= `4`].name", data.clone()),
+        json!(["a", "b"])
+    );
+    assert_eq!(
+        search(
+            "machines[?state=='running' && cpu > `4`].name",
+            data.clone()
+        ),
+        json!(["c"])
+    );
+    assert_eq!(
+        search("machines[?!(state=='running')].name", data.clone()),
+        json!(["b"])
+    );
+    assert_eq!(search("machines[?missing].name", data.clone()), json!([]));
+    assert_eq!(search("machines[?name < 'b'].name", data), json!([]));
+    assert_eq!(search("[?@ > `1`]", json!([1, 2, 3])), json!([2, 3]));
+}
+
+#[test]
+fn pipes_stop_projections() {
+    let data = json!({"people": [{"first": "James"}, {"first": "Jacob"}]});
+    assert_eq!(
+        search("people[*].first | [0]", data.clone()),
+        json!("James")
+    );
+    assert_eq!(search("people[*].first[0]", data.clone()), json!([]));
+    assert_eq!(search("people | [1].first", data), json!("Jacob"));
+}
+
+#[test]
+fn multiselects() {
+    let data = json!({"people": [
+        {"name": "a", "state": {"name": "up"}},
+        {"name": "b", "state": {"name": "down"}}
+    ]});
+    assert_eq!(
+        search("people[].[name, state.name]", data.clone()),
+        json!([["a", "up"], ["b", "down"]])
+    );
+    assert_eq!(
+        search("people[].{Name: name, State: state.name}", data.clone()),
+        json!([{"Name": "a", "State": "up"}, {"Name": "b", "State": "down"}])
+    );
+    assert_eq!(
+        search(r#"people[0].{"quoted key": name}"#, data.clone()),
+        json!({"quoted key": "a"})
+    );
+    assert_eq!(search("missing.[a, b]", data.clone()), Value::Null);
+    assert_eq!(search("missing.{a: a}", data), Value::Null);
+}
+
+#[test]
+fn literals() {
+    assert_eq!(search("`\"text\"`", json!({})), json!("text"));
+    assert_eq!(search("`[1, {\"a\": 2}]`", json!({})), json!([1, {"a": 2}]));
+    assert_eq!(search("'raw string'", json!({})), json!("raw string"));
+    assert_eq!(search(r"'it\'s'", json!({})), json!("it's"));
+    assert_eq!(search("`null`", json!({})), Value::Null);
+}
+
+#[test]
+fn or_and_not() {
+    let data = json!({"a": null, "b": "", "c": [], "d": "x", "e": false});
+    assert_eq!(search("a || d", data.clone()), json!("x"));
+    assert_eq!(search("b || c || d", data.clone()), json!("x"));
+    assert_eq!(search("d || a", data.clone()), json!("x"));
+    assert_eq!(search("d && b", data.clone()), json!(""));
+    assert_eq!(search("c && d", data.clone()), json!([]));
+    assert_eq!(search("!e", data.clone()), json!(true));
+    assert_eq!(search("!d", data), json!(false));
+}
+
+#[test]
+fn comparisons() {
+    let data = json!({"one": 1, "two": 2, "also_one": 1.0, "list": [1, 2], "text": "a"});
+    assert_eq!(search("one < two", data.clone()), json!(true));
+    assert_eq!(search("one >= two", data.clone()), json!(false));
+    assert_eq!(search("one == also_one", data.clone()), json!(true));
+    assert_eq!(search("list == `[1, 2]`", data.clone()), json!(true));
+    assert_eq!(search("one != text", data.clone()), json!(true));
+    assert_eq!(search("text < one", data), Value::Null);
+}
+
+#[test]
+fn current_node() {
+    assert_eq!(search("@", json!({"a": 1})), json!({"a": 1}));
+    assert_eq!(search("@.a", json!({"a": 1})), json!(1));
+    assert_eq!(search("[*].[@]", json!([1, 2])), json!([[1], [2]]));
+}
+
+#[test]
+fn numeric_functions() {
+    let data = json!({"n": -1.5, "list": [1, 2, 3, 4], "empty": []});
+    assert_eq!(search("abs(n)", data.clone()), json!(1.5));
+    assert_eq!(search("ceil(n)", data.clone()), json!(-1));
+    assert_eq!(search("floor(n)", data.clone()), json!(-2));
+    assert_eq!(search("sum(list)", data.clone()), json!(10));
+    assert_eq!(search("avg(list)", data.clone()), json!(2.5));
+    assert_eq!(search("avg(empty)", data.clone()), Value::Null);
+    assert_eq!(search("sum(empty)", data.clone()), json!(0));
+    assert_eq!(search("max(list)", data.clone()), json!(4));
+    assert_eq!(search("min(list)", data.clone()), json!(1));
+    assert_eq!(search("max(empty)", data.clone()), Value::Null);
+    assert!(fails("abs('x')", data.clone()).contains("abs()"));
+    assert!(fails("sum(n)", data).contains("sum()"));
+}
+
+#[test]
+fn string_functions() {
+    let data = json!({"s": "dissbson", "parts": ["a", "b", "c"]});
+    assert_eq!(search("contains(s, 'bson')", data.clone()), json!(true));
+    assert_eq!(search("contains(parts, 'b')", data.clone()), json!(true));
+    assert_eq!(search("contains(parts, 'z')", data.clone()), json!(false));
+    assert_eq!(search("starts_with(s, 'diss')", data.clone()), json!(true));
+    assert_eq!(search("ends_with(s, 'diss')", data.clone()), json!(false));
+    assert_eq!(search("join('-', parts)", data.clone()), json!("a-b-c"));
+    assert_eq!(search("length(s)", data.clone()), json!(8));
+    assert_eq!(search("reverse(s)", data.clone()), json!("nosbssid"));
+    assert!(fails("join('-', `[1]`)", data.clone()).contains("join()"));
+    assert!(fails("starts_with(parts, 'a')", data).contains("starts_with()"));
+}
+
+#[test]
+fn collection_functions() {
+    let data = json!({"o": {"b": 2, "a": 1}, "list": [3, 1, 2], "words": ["b", "c", "a"]});
+    assert_eq!(search("sort(keys(o))", data.clone()), json!(["a", "b"]));
+    assert_eq!(search("sort(values(o))", data.clone()), json!([1, 2]));
+    assert_eq!(search("length(o)", data.clone()), json!(2));
+    assert_eq!(search("length(list)", data.clone()), json!(3));
+    assert_eq!(search("sort(list)", data.clone()), json!([1, 2, 3]));
+    assert_eq!(search("sort(words)", data.clone()), json!(["a", "b", "c"]));
+    assert_eq!(search("reverse(list)", data.clone()), json!([2, 1, 3]));
+    assert_eq!(
+        search("merge(o, `{\"a\": 9, \"c\": 3}`)", data.clone()),
+        json!({"a": 9, "b": 2, "c": 3})
+    );
+    assert_eq!(
+        search("not_null(missing, `null`, list[0])", data.clone()),
+        json!(3)
+    );
+    assert_eq!(search("not_null(missing)", data.clone()), Value::Null);
+    assert!(fails("sort(`[1, \"a\"]`)", data.clone()).contains("sort()"));
+    assert!(fails("keys(list)", data).contains("keys()"));
+}
+
+#[test]
+fn expression_functions() {
+    let data = json!({"people": [
+        {"name": "b", "age": 30},
+        {"name": "a", "age": 50},
+        {"name": "c", "age": 40}
+    ]});
+    assert_eq!(
+        search("sort_by(people, &age)[*].name", data.clone()),
+        json!(["b", "c", "a"])
+    );
+    assert_eq!(
+        search("sort_by(people, &name)[0].age", data.clone()),
+        json!(50)
+    );
+    assert_eq!(
+        search("max_by(people, &age).name", data.clone()),
+        json!("a")
+    );
+    assert_eq!(
+        search("min_by(people, &age).name", data.clone()),
+        json!("b")
+    );
+    assert_eq!(
+        search("map(&name, people)", data.clone()),
+        json!(["b", "a", "c"])
+    );
+    assert_eq!(
+        search("map(&missing, people)", data.clone()),
+        json!([null, null, null])
+    );
+    assert!(fails("sort_by(people, name)", data.clone()).contains("&expression"));
+    assert!(fails("&name", data).contains("&expression"));
+}
+
+#[test]
+fn conversion_functions() {
+    let data = json!({"n": 12, "s": "3.5", "bad": "x", "list": [1]});
+    assert_eq!(search("to_string(n)", data.clone()), json!("12"));
+    assert_eq!(search("to_string(s)", data.clone()), json!("3.5"));
+    assert_eq!(search("to_string(list)", data.clone()), json!("[1]"));
+    assert_eq!(search("to_number(s)", data.clone()), json!(3.5));
+    assert_eq!(search("to_number('7')", data.clone()), json!(7));
+    assert_eq!(search("to_number(bad)", data.clone()), Value::Null);
+    assert_eq!(search("to_array(n)", data.clone()), json!([12]));
+    assert_eq!(search("to_array(list)", data.clone()), json!([1]));
+    assert_eq!(search("type(n)", data.clone()), json!("number"));
+    assert_eq!(search("type(s)", data.clone()), json!("string"));
+    assert_eq!(search("type(list)", data.clone()), json!("array"));
+    assert_eq!(search("type(@)", data.clone()), json!("object"));
+    assert_eq!(search("type(missing)", data.clone()), json!("null"));
+    assert_eq!(search("type(`true`)", data), json!("boolean"));
+}
+
+#[test]
+fn function_arguments_are_checked_when_parsed() {
+    assert!(invalid("nope(@)").contains("unknown function nope()"));
+    assert!(invalid("length(a, b)").contains("length() takes 1 argument, got 2"));
+    assert!(invalid("join('-')").contains("join() takes 2 arguments, got 1"));
+    assert!(invalid("merge()").contains("merge() takes at least 1 argument, got 0"));
+    assert!(invalid(r#""length"(a)"#).contains("can't name a function"));
+}
+
+#[test]
+fn function_arguments_need_commas() {
+    invalid("length(a b)");
+    invalid("join('-' parts)");
+    invalid("length(a,)");
+    invalid("length(,a)");
+    invalid("length(a");
+    assert_eq!(search("length(a)", json!({"a": [1, 2]})), json!(2));
+    assert_eq!(search("not_null(a, b, c)", json!({"c": 1})), json!(1));
+}
+
+#[test]
+fn syntax_errors() {
+    for expression in [
+        "", "a.", "a[", "a[0", "[1, 2", "{a: b", "{a b}", "a..b", "a || ", "'open",
+    ] {
+        invalid(expression);
+    }
+}
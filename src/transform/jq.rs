@@ -0,0 +1,121 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bson::{Bson, Document};
+use jaq_core::{
+    compile::Undefined,
+    load::{Arena, File, Loader},
+    Compiler, Ctx, Filter, Native, RcIter,
+};
+use jaq_json::Val;
+
+use crate::DissectError;
+
+/// Runs a jq filter over every document, compiled once with the jq standard library. The document goes in as
+/// relaxed extended json and each object the filter yields comes out as a document, yielding nothing or `null`
+/// drops the document and yielding several objects turns it into several documents
+pub(crate) struct Jq {
+    code: String,
+    filter: Filter<Native<Val>>,
+    /// Documents the filter yielded nothing for
+    dropped: AtomicUsize,
+    /// Documents added by filters yielding more than one object
+    added: AtomicUsize,
+}
+
+impl Jq {
+    pub fn new(code: &str) -> Result<Self, DissectError> {
+        let program = File { code, path: () };
+        let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+        let arena = Arena::default();
+        let modules = loader.load(&arena, program).map_err(|errors| {
+            let reasons = errors
+                .into_iter()
+                .flat_map(|(_, error)| match error {
+                    jaq_core::load::Error::Io(errors) => {
+                        errors.into_iter().map(|(_, e)| e).collect::<Vec<_>>()
+                    }
+                    jaq_core::load::Error::Lex(errors) => errors
+                        .into_iter()
+                        .map(|(expected, at)| format!("expected {} at {at:?}", expected.as_str()))
+                        .collect(),
+                    jaq_core::load::Error::Parse(errors) => errors
+                        .into_iter()
+                        .map(|(expected, at)| format!("expected {} at {at:?}", expected.as_str()))
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+            DissectError::Parse(format!("Invalid --jq {code}: {}", reasons.join(", ")))
+        })?;
+        let filter = Compiler::default()
+            .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+            .compile(modules)
+            .map_err(|errors| {
+                let undefined = errors
+                    .into_iter()
+                    .flat_map(|(_, errors)| errors)
+                    .map(|(name, kind)| match kind {
+                        Undefined::Filter(arity) => format!("undefined filter {name}/{arity}"),
+                        other => {
+                            format!("undefined {} {name}", format!("{other:?}").to_lowercase())
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                DissectError::Parse(format!("Invalid --jq {code}: {}", undefined.join(", ")))
+            })?;
+        Ok(Self {
+            code: code.to_string(),
+            filter,
+            dropped: AtomicUsize::new(0),
+            added: AtomicUsize::new(0),
+        })
+    }
+
+    /// Run the filter over a batch, the documents it yields keep what their document was paired with
+    pub fn apply<T: Clone>(
+        &self,
+        docs: Vec<(Document, T)>,
+    ) -> Result<Vec<(Document, T)>, DissectError> {
+        let inputs = RcIter::new(core::iter::empty());
+        let mut out = Vec::with_capacity(docs.len());
+        for (doc, tag) in docs {
+            let input = Val::from(Bson::Document(doc).into_relaxed_extjson());
+            let before = out.len();
+            for value in self.filter.run((Ctx::new([], &inputs), input)) {
+                let value = value.map_err(|e| self.failed(e))?;
+                if let Some(doc) = self.document(value)? {
+                    out.push((doc, tag.clone()));
+                }
+            }
+            match out.len() - before {
+                0 => self.dropped.fetch_add(1, Ordering::Relaxed),
+                yielded => self.added.fetch_add(yielded - 1, Ordering::Relaxed),
+            };
+        }
+        Ok(out)
+    }
+
+    fn document(&self, value: Val) -> Result<Option<Document>, DissectError> {
+        match Bson::try_from(serde_json::Value::from(value)) {
+            Ok(Bson::Document(doc)) => Ok(Some(doc)),
+            Ok(Bson::Null) => Ok(None),
+            Ok(other) => Err(self.failed(format!(
+                "yielded a {:?} instead of an object",
+                other.element_type()
+            ))),
+            Err(e) => Err(self.failed(format!("yielded invalid extended json: {e}"))),
+        }
+    }
+
+    fn failed(&self, e: impl std::fmt::Display) -> DissectError {
+        DissectError::Unexpected(format!("--jq {}: {e}", self.code))
+    }
+
+    /// A line for the export summary
+    pub fn summary(&self) -> String {
+        format!(
+            "--jq dropped {} documents and added {}",
+            self.dropped.load(Ordering::Relaxed),
+            self.added.load(Ordering::Relaxed)
+        )
+    }
+}
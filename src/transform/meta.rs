@@ -0,0 +1,76 @@
+use bson::{doc, DateTime, Document};
+
+use crate::index::{DocOffset, Input};
+
+/// Records where every document comes from in a field of its own
+#[derive(Debug)]
+pub(crate) struct Meta {
+    field: String,
+    files: Vec<String>,
+    /// Byte offsets of the documents of every input file, in order, to find the index of a document in its file
+    offsets: Vec<Vec<usize>>,
+    exported_at: DateTime,
+}
+
+impl Meta {
+    pub fn new(field: &str, input: &Input) -> Self {
+        let mut offsets = vec![Vec::new(); input.files.len()];
+        for offset in &input.offsets {
+            offsets[offset.source].push(offset.offset);
+        }
+        Self {
+            field: field.into(),
+            files: input
+                .files
+                .iter()
+                .map(|f| f.display().to_string())
+                .collect(),
+            offsets,
+            exported_at: DateTime::now(),
+        }
+    }
+
+    pub fn apply(&self, doc: &mut Document, offset: &DocOffset) {
+        let index = self.offsets[offset.source]
+            .binary_search(&offset.offset)
+            .unwrap_or_default();
+        doc.insert(
+            &self.field,
+            doc! {
+                "source_file": &self.files[offset.source],
+                "doc_index": index as i64,
+                "byte_offset": offset.offset as i64,
+                "exported_at": self.exported_at,
+            },
+        );
+    }
+}
+
+/// Names every document by the collection it comes from, the input file name without its extension
+#[derive(Debug)]
+pub(crate) struct SourceTag {
+    field: String,
+    collections: Vec<String>,
+}
+
+impl SourceTag {
+    pub fn new(field: &str, input: &Input) -> Self {
+        Self {
+            field: field.into(),
+            collections: input
+                .files
+                .iter()
+                .map(|f| {
+                    f.file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect(),
+        }
+    }
+
+    pub fn apply(&self, doc: &mut Document, offset: &DocOffset) {
+        doc.insert(&self.field, &self.collections[offset.source]);
+    }
+}
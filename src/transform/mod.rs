@@ -0,0 +1,266 @@
+use std::path::Path;
+
+use bson::Document;
+
+use crate::{
+    explain::value_name,
+    index::{DocOffset, Input},
+    stats::anomaly::Detector,
+    Args, DissectError,
+};
+
+mod arrays;
+mod decompress;
+mod exec;
+mod jmespath;
+mod jq;
+mod meta;
+mod nulls;
+mod redact;
+mod refs;
+mod reid;
+mod strings;
+
+use arrays::ArrayLimit;
+pub use arrays::ArrayOverflow;
+pub use decompress::DecompressField;
+pub(crate) use exec::ExecFilter;
+pub(crate) use jmespath::JmesPath;
+pub(crate) use jq::Jq;
+use meta::{Meta, SourceTag};
+use nulls::Nulls;
+pub use nulls::{MissingAs, NullAs};
+pub(crate) use redact::dry_run as redaction_dry_run;
+pub(crate) use redact::Redaction;
+pub use refs::RefMode;
+use refs::Refs;
+use reid::ReId;
+use strings::StringLimit;
+
+/// Transformations applied to every document after it is loaded and before any script runs
+#[derive(Debug, Default)]
+pub(crate) struct Transforms {
+    anomalies: Option<Detector>,
+    decompress: Vec<DecompressField>,
+    refs: Option<Refs>,
+    reid: Option<ReId>,
+    redaction: Option<Redaction>,
+    arrays: Option<ArrayLimit>,
+    strings: Option<StringLimit>,
+    nulls: Option<Nulls>,
+    meta: Option<Meta>,
+    source: Option<SourceTag>,
+}
+
+impl Transforms {
+    /// Build the transforms selected in `args`, the ones needing a pass over the input run it here
+    pub fn from_args(args: &Args, path: &Path, input: &Input) -> Result<Self, DissectError> {
+        let reid = if args.reid {
+            let map_path = match &args.reid_map {
+                Some(map_path) => map_path.clone(),
+                None if path.is_dir() => path.join("dissbson.reid.dat"),
+                None => path.with_extension("reid.dat"),
+            };
+            Some(ReId::load_or_build(input, &map_path, &args.reid_refs)?)
+        } else {
+            None
+        };
+
+        let anomalies = if args.flag_anomalies || args.anomalies.is_some() {
+            Some(Detector::build(
+                input,
+                args.threads,
+                args.batch,
+                args.anomaly_sigma,
+                args.anomaly_rate,
+            )?)
+        } else {
+            None
+        };
+
+        let refs = match &args.resolve_refs {
+            Some(dir) => Some(Refs::build(
+                dir,
+                args.ref_depth,
+                args.ref_mode,
+                args.threads,
+                args.batch,
+            )?),
+            None => None,
+        };
+
+        let strings = match args.max_string_len {
+            Some(max) => Some(StringLimit::new(max, args.extract_strings.clone())?),
+            None => None,
+        };
+
+        let nulls = if args.missing_as != MissingAs::Omit || args.null_as != NullAs::Null {
+            Some(Nulls::build(
+                input,
+                args.threads,
+                args.batch,
+                args.missing_as,
+                args.null_as,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            anomalies,
+            decompress: args.decompress_field.clone(),
+            refs,
+            reid,
+            redaction: args.redact.as_deref().map(Redaction::load).transpose()?,
+            arrays: args
+                .max_array_len
+                .map(|max| ArrayLimit::new(max, args.array_overflow)),
+            strings,
+            nulls,
+            meta: args
+                .add_meta
+                .as_deref()
+                .map(|field| Meta::new(field, input)),
+            source: args
+                .tag_source
+                .as_deref()
+                .map(|field| SourceTag::new(field, input)),
+        })
+    }
+
+    /// Apply the transforms to a document stored at `offset` in the input
+    pub fn apply(&self, doc: &mut Document, offset: &DocOffset) -> Result<(), DissectError> {
+        self.apply_traced(doc, offset, &mut |_, _| {})
+    }
+
+    /// Like `apply`, calling `traced` with the option of every transform run and the document it left, for --trace-doc
+    pub fn apply_traced(
+        &self,
+        doc: &mut Document,
+        offset: &DocOffset,
+        traced: &mut dyn FnMut(&str, &Document),
+    ) -> Result<(), DissectError> {
+        if let Some(detector) = &self.anomalies {
+            detector.mark(doc, offset.size);
+            traced("--flag-anomalies", doc);
+        }
+        for field in &self.decompress {
+            field.apply(doc)?;
+            traced("--decompress-field", doc);
+        }
+        if let Some(refs) = &self.refs {
+            refs.apply(doc)?;
+            traced("--resolve-refs", doc);
+        }
+        if let Some(reid) = &self.reid {
+            reid.apply(doc)?;
+            traced("--reid", doc);
+        }
+        if let Some(redaction) = &self.redaction {
+            redaction.apply(doc);
+            traced("--redact", doc);
+        }
+        if let Some(arrays) = &self.arrays {
+            arrays.apply(doc);
+            traced("--max-array-len", doc);
+        }
+        if let Some(strings) = &self.strings {
+            strings.apply(doc)?;
+            traced("--max-string-len", doc);
+        }
+        if let Some(nulls) = &self.nulls {
+            nulls.apply(doc);
+            traced("--missing-as and --null-as", doc);
+        }
+        if let Some(meta) = &self.meta {
+            meta.apply(doc, offset);
+            traced("--add-meta", doc);
+        }
+        if let Some(source) = &self.source {
+            source.apply(doc, offset);
+            traced("--tag-source", doc);
+        }
+        Ok(())
+    }
+
+    /// The transforms `apply` runs for these arguments in the order it runs them, for --explain,
+    /// taken from the arguments alone so the passes some of them need over the input aren't made
+    pub fn plan(args: &Args) -> Vec<String> {
+        let mut stages = Vec::new();
+        if args.flag_anomalies || args.anomalies.is_some() {
+            stages.push(format!(
+                "flag anomalies {} sigma off the typical size or rarer than {}, profiled in a pass over the input",
+                args.anomaly_sigma, args.anomaly_rate
+            ));
+        }
+        if !args.decompress_field.is_empty() {
+            stages.push(format!(
+                "inflate {} compressed fields",
+                args.decompress_field.len()
+            ));
+        }
+        if let Some(dir) = &args.resolve_refs {
+            stages.push(format!(
+                "resolve DBRefs against {} up to {} levels deep, --ref-mode {}",
+                dir.display(),
+                args.ref_depth,
+                value_name(args.ref_mode)
+            ));
+        }
+        if args.reid {
+            stages.push(match args.reid_refs.is_empty() {
+                true => "give every document a fresh ObjectId".to_string(),
+                false => format!(
+                    "give every document a fresh ObjectId and rewrite the references in {}",
+                    args.reid_refs.join(", ")
+                ),
+            });
+        }
+        if let Some(rules) = &args.redact {
+            stages.push(format!("redact by the rules of {}", rules.display()));
+        }
+        if let Some(max) = args.max_array_len {
+            stages.push(format!(
+                "cap arrays at {max} items, --array-overflow {}",
+                value_name(args.array_overflow)
+            ));
+        }
+        if let Some(max) = args.max_string_len {
+            stages.push(match &args.extract_strings {
+                Some(dir) => format!(
+                    "cut strings longer than {max} characters, their full text saved to {}",
+                    dir.display()
+                ),
+                None => format!("cut strings longer than {max} characters"),
+            });
+        }
+        if args.missing_as != MissingAs::Omit || args.null_as != NullAs::Null {
+            stages.push(format!(
+                "fill in fields, --missing-as {} and --null-as {}, profiled in a pass over the input",
+                value_name(args.missing_as),
+                value_name(args.null_as)
+            ));
+        }
+        if let Some(field) = &args.add_meta {
+            stages.push(format!("record where the document comes from in {field}"));
+        }
+        if let Some(field) = &args.tag_source {
+            stages.push(format!("name the collection of the document in {field}"));
+        }
+        stages
+    }
+
+    /// Lines for the export summary on what the transforms changed
+    pub fn summary(&self) -> Vec<String> {
+        let refs = self.refs.iter().filter_map(|r| r.summary());
+        let redaction = self.redaction.iter().flat_map(|r| r.summary());
+        let arrays = self.arrays.iter().filter_map(|a| a.summary());
+        let strings = self.strings.iter().filter_map(|s| s.summary());
+        let nulls = self.nulls.iter().flat_map(|n| n.summary());
+        refs.chain(redaction)
+            .chain(arrays)
+            .chain(strings)
+            .chain(nulls)
+            .collect()
+    }
+}
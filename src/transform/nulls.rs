@@ -0,0 +1,140 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bson::{Bson, Document};
+use clap::ValueEnum;
+
+use crate::{index::Input, stats::profile_input, DissectError};
+
+/// How fields that other documents have but a document lacks are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MissingAs {
+    /// Leave them out
+    Omit,
+    /// Write them as explicit nulls
+    Null,
+}
+
+/// How fields explicitly set to null are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NullAs {
+    /// Keep them as nulls
+    Null,
+    /// Leave them out as if they were missing
+    Omit,
+}
+
+/// Makes the difference between absent and null fields explicit in the output
+#[derive(Debug)]
+pub(crate) struct Nulls {
+    /// Every field path seen in the input, parents before their children, empty unless missing fields are filled
+    fields: Vec<String>,
+    omit_nulls: bool,
+    filled: AtomicUsize,
+    omitted: AtomicUsize,
+}
+
+impl Nulls {
+    /// Missing fields can only be told apart with the full set of fields, so filling them profiles `input` first
+    pub fn build(
+        input: &Input,
+        threads: usize,
+        batch: usize,
+        missing: MissingAs,
+        null: NullAs,
+    ) -> Result<Self, DissectError> {
+        let fields = match missing {
+            MissingAs::Omit => Vec::new(),
+            MissingAs::Null => profile_input(input, threads, batch, 0)?
+                .fields
+                .into_keys()
+                .collect(),
+        };
+        Ok(Self {
+            fields,
+            omit_nulls: null == NullAs::Omit,
+            filled: AtomicUsize::new(0),
+            omitted: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn apply(&self, doc: &mut Document) {
+        // nulls standing in for missing fields are added after explicit nulls are dropped so they stay
+        let missing = self
+            .fields
+            .iter()
+            .filter(|path| is_missing(doc, path))
+            .collect::<Vec<_>>();
+        if self.omit_nulls {
+            self.omit(doc);
+        }
+        for path in missing {
+            if fill(doc, path) {
+                self.filled.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn omit(&self, doc: &mut Document) {
+        let nulls = doc
+            .iter()
+            .filter(|(_, value)| matches!(value, Bson::Null | Bson::Undefined))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        self.omitted.fetch_add(nulls.len(), Ordering::Relaxed);
+        for key in nulls {
+            doc.remove(&key);
+        }
+        for (_, value) in doc.iter_mut() {
+            self.omit_nested(value);
+        }
+    }
+
+    /// Null array elements are kept, dropping them would shift the positions of the others
+    fn omit_nested(&self, value: &mut Bson) {
+        match value {
+            Bson::Document(doc) => self.omit(doc),
+            Bson::Array(items) => items.iter_mut().for_each(|item| self.omit_nested(item)),
+            _ => {}
+        }
+    }
+
+    /// Lines for the export summary
+    pub fn summary(&self) -> Vec<String> {
+        let filled = self.filled.load(Ordering::Relaxed);
+        let omitted = self.omitted.load(Ordering::Relaxed);
+        let mut lines = Vec::new();
+        if filled > 0 {
+            lines.push(format!("Wrote {filled} missing fields as null"));
+        }
+        if omitted > 0 {
+            lines.push(format!("Left out {omitted} null fields"));
+        }
+        lines
+    }
+}
+
+/// Whether the field at the dotted `path` is absent while its parent document is present,
+/// fields below arrays or scalars are never missing
+fn is_missing(doc: &Document, path: &str) -> bool {
+    match path.split_once('.') {
+        None => !doc.contains_key(path),
+        Some((head, rest)) => match doc.get(head) {
+            Some(Bson::Document(inner)) => is_missing(inner, rest),
+            _ => false,
+        },
+    }
+}
+
+/// Set the field at the dotted `path` to null, unless its parent is no longer a document
+fn fill(doc: &mut Document, path: &str) -> bool {
+    match path.split_once('.') {
+        None => {
+            doc.insert(path, Bson::Null);
+            true
+        }
+        Some((head, rest)) => match doc.get_mut(head) {
+            Some(Bson::Document(inner)) => fill(inner, rest),
+            _ => false,
+        },
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::{Bson, Document};
+use rayon::{prelude::*, ThreadPoolBuilder};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    docpath::glob,
+    index::{DocOffset, DocReader, Input},
+    DissectError,
+};
+
+/// A rules file as written by hand
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    /// Prepended to values before hashing so hashes can't be looked up in a table of common values
+    #[serde(default)]
+    salt: String,
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: Option<String>,
+    paths: Vec<String>,
+    #[serde(default)]
+    except: Vec<String>,
+    strategy: Strategy,
+    /// Replacement of the `replace` strategy
+    value: Option<serde_yaml::Value>,
+    /// Characters left readable at the start by the `mask` strategy
+    #[serde(default)]
+    keep: usize,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Strategy {
+    Drop,
+    Null,
+    Mask,
+    Hash,
+    Replace,
+}
+
+#[derive(Debug)]
+enum Action {
+    Drop,
+    Null,
+    Mask(usize),
+    Hash,
+    Replace(Bson),
+}
+
+/// A redaction rule and how much it touched
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    paths: Vec<Vec<String>>,
+    except: Vec<Vec<String>>,
+    action: Action,
+    fields: AtomicUsize,
+    documents: AtomicUsize,
+}
+
+impl Rule {
+    fn matches(&self, path: &[String]) -> bool {
+        self.paths.iter().any(|p| glob(p, path)) && !self.except.iter().any(|p| glob(p, path))
+    }
+}
+
+/// Redacts fields by the rules of a YAML file, the first rule matching a field applies
+/// and nothing below a redacted field is looked at
+#[derive(Debug)]
+pub(crate) struct Redaction {
+    salt: String,
+    rules: Vec<Rule>,
+}
+
+impl Redaction {
+    pub fn load(path: &Path) -> Result<Self, DissectError> {
+        let file: RuleFile = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|spec| {
+                let action = match spec.strategy {
+                    Strategy::Drop => Action::Drop,
+                    Strategy::Null => Action::Null,
+                    Strategy::Mask => Action::Mask(spec.keep),
+                    Strategy::Hash => Action::Hash,
+                    Strategy::Replace => {
+                        let value = spec.value.ok_or_else(|| {
+                            DissectError::Parse(format!(
+                                "Redaction rule for {} replaces without a value",
+                                spec.paths.join(", ")
+                            ))
+                        })?;
+                        Action::Replace(bson::to_bson(&value).map_err(|e| {
+                            DissectError::Parse(format!("Invalid replacement value: {e}"))
+                        })?)
+                    }
+                };
+                let pattern = |p: &String| p.split('.').map(str::to_string).collect();
+                Ok(Rule {
+                    name: spec.name.unwrap_or_else(|| spec.paths.join(", ")),
+                    paths: spec.paths.iter().map(pattern).collect(),
+                    except: spec.except.iter().map(pattern).collect(),
+                    action,
+                    fields: AtomicUsize::new(0),
+                    documents: AtomicUsize::new(0),
+                })
+            })
+            .collect::<Result<Vec<_>, DissectError>>()?;
+        Ok(Self {
+            salt: file.salt,
+            rules,
+        })
+    }
+
+    pub fn apply(&self, doc: &mut Document) {
+        let mut touched = vec![0; self.rules.len()];
+        self.redact(doc, &mut Vec::new(), &mut touched);
+        for (rule, fields) in self.rules.iter().zip(touched) {
+            if fields > 0 {
+                rule.fields.fetch_add(fields, Ordering::Relaxed);
+                rule.documents.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn redact(&self, doc: &mut Document, path: &mut Vec<String>, touched: &mut [usize]) {
+        let keys = doc.keys().cloned().collect::<Vec<_>>();
+        for key in keys {
+            path.push(key.clone());
+            let key = key.as_str();
+            match self.rules.iter().position(|rule| rule.matches(path)) {
+                Some(n) => {
+                    touched[n] += 1;
+                    let replacement =
+                        match &self.rules[n].action {
+                            Action::Drop => None,
+                            Action::Null => Some(Bson::Null),
+                            Action::Mask(keep) => Some(Bson::String(mask(&text(&doc[key]), *keep))),
+                            Action::Hash => Some(Bson::String(hex::encode(Sha256::digest(
+                                format!("{}{}", self.salt, text(&doc[key])),
+                            )))),
+                            Action::Replace(value) => Some(value.clone()),
+                        };
+                    match replacement {
+                        Some(value) => doc.insert(key, value),
+                        None => doc.remove(key),
+                    };
+                }
+                None => match doc.get_mut(key) {
+                    Some(Bson::Document(inner)) => self.redact(inner, path, touched),
+                    Some(Bson::Array(items)) => {
+                        for item in items {
+                            if let Bson::Document(inner) = item {
+                                self.redact(inner, path, touched);
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+            }
+            path.pop();
+        }
+    }
+
+    /// Lines for the export summary
+    pub fn summary(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                format!(
+                    "Redacted {} fields in {} documents by rule {}",
+                    rule.fields.load(Ordering::Relaxed),
+                    rule.documents.load(Ordering::Relaxed),
+                    rule.name
+                )
+            })
+            .collect()
+    }
+}
+
+/// Run the rules over the selected documents without writing anything and print how much each would touch
+pub(crate) fn dry_run(
+    rules: &Path,
+    input: &Input,
+    offsets: &[DocOffset],
+    threads: usize,
+    batch: usize,
+) -> Result<(), DissectError> {
+    let redaction = Redaction::load(rules)?;
+    let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+    thread_pool.install(|| {
+        offsets.par_chunks(batch.max(1)).try_for_each(|offsets| {
+            let mut reader = DocReader::new(input);
+            for offset in offsets {
+                redaction.apply(&mut reader.read_document(offset)?);
+            }
+            Ok::<_, DissectError>(())
+        })
+    })?;
+
+    println!("Redaction dry run over {} documents:", offsets.len());
+    for rule in &redaction.rules {
+        let fields = rule.fields.load(Ordering::Relaxed);
+        let documents = rule.documents.load(Ordering::Relaxed);
+        if fields == 0 {
+            println!("  {}: matches nothing", rule.name);
+        } else {
+            println!(
+                "  {}: {fields} fields in {documents} documents ({:.1}%)",
+                rule.name,
+                documents as f64 * 100.0 / offsets.len().max(1) as f64
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The text a value is masked or hashed from
+fn text(value: &Bson) -> String {
+    match value {
+        Bson::String(s) => s.clone(),
+        Bson::ObjectId(oid) => oid.to_hex(),
+        value => value.clone().into_relaxed_extjson().to_string(),
+    }
+}
+
+fn mask(text: &str, keep: usize) -> String {
+    text.chars()
+        .enumerate()
+        .map(|(i, c)| if i < keep { c } else { '*' })
+        .collect()
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::{Bson, Document};
+use clap::ValueEnum;
+use parking_lot::Mutex;
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use crate::{
+    index::{self, raw_document_key, read_document, value_key, DocOffset, DocReader},
+    DissectError,
+};
+
+/// Field holding the referenced document with `--ref-mode annotate`
+const RESOLVED_FIELD: &str = "_resolved";
+
+/// What a resolved DBRef becomes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RefMode {
+    /// Replace the reference with the referenced document
+    Embed,
+    /// Keep the reference and add the referenced document to it as `_resolved`
+    Annotate,
+}
+
+/// Resolves DBRefs against sibling collection dumps, the collection of a reference is the dump file named after it
+#[derive(Debug)]
+pub(crate) struct Refs {
+    files: Vec<Mutex<File>>,
+    /// Offsets of every document by collection name and `_id` key
+    ids: HashMap<String, HashMap<String, DocOffset>>,
+    depth: usize,
+    mode: RefMode,
+    resolved: AtomicUsize,
+    dangling: AtomicUsize,
+}
+
+impl Refs {
+    /// Index the ids of every dump in `dir`, only the `_id` of each document is read
+    pub fn build(
+        dir: &Path,
+        depth: usize,
+        mode: RefMode,
+        threads: usize,
+        batch: usize,
+    ) -> Result<Self, DissectError> {
+        let input = index::load_input(dir, false)?;
+        let thread_pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        let keys = thread_pool.install(|| {
+            input
+                .offsets
+                .par_chunks(batch.max(1))
+                .map(|offsets| {
+                    let mut reader = DocReader::new(&input);
+                    offsets
+                        .iter()
+                        .map(|offset| {
+                            Ok((raw_document_key(&reader.read_raw(offset)?, "_id")?, *offset))
+                        })
+                        .collect::<Result<Vec<_>, DissectError>>()
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        let names = input
+            .files
+            .iter()
+            .map(|f| {
+                f.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        let mut ids = HashMap::<String, HashMap<String, DocOffset>>::new();
+        for (key, offset) in keys.into_iter().flatten() {
+            ids.entry(names[offset.source].clone())
+                .or_default()
+                .insert(key, offset);
+        }
+        println!(
+            "Indexed the ids of {} documents in {} collections to resolve references",
+            input.offsets.len(),
+            ids.len()
+        );
+
+        Ok(Self {
+            files: input
+                .files
+                .iter()
+                .map(|f| Ok(Mutex::new(File::open(f)?)))
+                .collect::<Result<_, DissectError>>()?,
+            ids,
+            depth,
+            mode,
+            resolved: AtomicUsize::new(0),
+            dangling: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn apply(&self, doc: &mut Document) -> Result<(), DissectError> {
+        self.resolve_in(doc, self.depth)
+    }
+
+    fn resolve_in(&self, doc: &mut Document, depth: usize) -> Result<(), DissectError> {
+        if depth == 0 {
+            return Ok(());
+        }
+        for (_, value) in doc.iter_mut() {
+            self.resolve_value(value, depth)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_value(&self, value: &mut Bson, depth: usize) -> Result<(), DissectError> {
+        match value {
+            Bson::Document(doc) => match self.target(doc)? {
+                Some(mut target) => {
+                    self.resolved.fetch_add(1, Ordering::Relaxed);
+                    // references inside the referenced document count as one level deeper
+                    self.resolve_in(&mut target, depth - 1)?;
+                    match self.mode {
+                        RefMode::Embed => *value = Bson::Document(target),
+                        RefMode::Annotate => {
+                            doc.insert(RESOLVED_FIELD, target);
+                        }
+                    }
+                }
+                None => self.resolve_in(doc, depth)?,
+            },
+            Bson::Array(items) => {
+                for item in items {
+                    self.resolve_value(item, depth)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The document a DBRef points at, none when `doc` isn't a reference into a known collection
+    fn target(&self, doc: &Document) -> Result<Option<Document>, DissectError> {
+        let (Ok(collection), Some(id)) = (doc.get_str("$ref"), doc.get("$id")) else {
+            return Ok(None);
+        };
+        let Some(ids) = self.ids.get(collection) else {
+            return Ok(None);
+        };
+        match ids.get(&value_key(id.clone())?) {
+            Some(offset) => {
+                let mut file = self.files[offset.source].lock();
+                Ok(Some(read_document(&mut *file, offset)?))
+            }
+            None => {
+                self.dangling.fetch_add(1, Ordering::Relaxed);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Line for the export summary
+    pub fn summary(&self) -> Option<String> {
+        let resolved = self.resolved.load(Ordering::Relaxed);
+        let dangling = self.dangling.load(Ordering::Relaxed);
+        (resolved + dangling > 0).then(|| {
+            format!("Resolved {resolved} references, {dangling} pointed at missing documents")
+        })
+    }
+}
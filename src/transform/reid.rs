@@ -0,0 +1,94 @@
+use std::{collections::HashMap, path::Path};
+
+use bson::{oid::ObjectId, Bson, Document};
+
+use crate::{
+    docpath,
+    index::{raw_document_key, read_compressed, value_key, write_compressed, DocReader, Input},
+    DissectError,
+};
+
+/// Replaces every `_id` with a fresh ObjectId and rewrites references to the old ids,
+/// the mapping is persisted so every run and every collection sharing it stays consistent
+#[derive(Debug)]
+pub(crate) struct ReId {
+    map: HashMap<String, ObjectId>,
+    refs: Vec<Vec<String>>,
+}
+
+impl ReId {
+    /// Load the mapping at `map_path` and extend it with the ids of `input` it doesn't cover yet
+    pub fn load_or_build(
+        input: &Input,
+        map_path: &Path,
+        refs: &[String],
+    ) -> Result<Self, DissectError> {
+        let mut map = HashMap::new();
+        if map_path.exists() {
+            let mut dat = read_compressed(map_path)?;
+            let stored = postcard::from_bytes_cobs::<Vec<(String, [u8; 12])>>(&mut dat)?;
+            map.extend(
+                stored
+                    .into_iter()
+                    .map(|(k, v)| (k, ObjectId::from_bytes(v))),
+            );
+            println!(
+                "Loaded {} id mappings from {}",
+                map.len(),
+                map_path.display()
+            );
+        }
+
+        let known = map.len();
+        let mut reader = DocReader::new(input);
+        for offset in &input.offsets {
+            let raw = reader.read_raw(offset)?;
+            map.entry(raw_document_key(&raw, "_id")?)
+                .or_insert_with(ObjectId::new);
+        }
+
+        if map.len() != known {
+            let stored = map
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.bytes()))
+                .collect::<Vec<_>>();
+            write_compressed(map_path, &stored)?;
+            println!(
+                "Mapped {} new ids, saved mapping to {}",
+                map.len() - known,
+                map_path.display()
+            );
+        }
+
+        Ok(Self {
+            map,
+            refs: refs.iter().map(|r| docpath::parse(r)).collect(),
+        })
+    }
+
+    pub fn apply(&self, doc: &mut Document) -> Result<(), DissectError> {
+        if let Some(id) = doc.get_mut("_id") {
+            self.rewrite(id)?;
+        }
+        let mut result = Ok(());
+        for path in &self.refs {
+            docpath::visit_mut(doc, path, &mut |value| {
+                if result.is_ok() {
+                    result = self.rewrite(value);
+                }
+            });
+        }
+        result
+    }
+
+    /// Swap a value, or every element of an array of values, for its new id
+    fn rewrite(&self, value: &mut Bson) -> Result<(), DissectError> {
+        if let Bson::Array(items) = value {
+            return items.iter_mut().try_for_each(|item| self.rewrite(item));
+        }
+        if let Some(new) = self.map.get(&value_key(value.clone())?) {
+            *value = Bson::ObjectId(*new);
+        }
+        Ok(())
+    }
+}
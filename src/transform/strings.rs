@@ -0,0 +1,78 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bson::{Bson, Document};
+
+use crate::DissectError;
+
+/// Cuts strings longer than a limit down to it, optionally saving the full text next to the output
+#[derive(Debug)]
+pub(crate) struct StringLimit {
+    max: usize,
+    extract: Option<PathBuf>,
+    hits: AtomicUsize,
+}
+
+impl StringLimit {
+    /// Strings longer than `max` characters are cut, their full text written to `extract` when set
+    pub fn new(max: usize, extract: Option<PathBuf>) -> Result<Self, DissectError> {
+        if let Some(dir) = &extract {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(Self {
+            max,
+            extract,
+            hits: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn apply(&self, doc: &mut Document) -> Result<(), DissectError> {
+        doc.iter_mut().try_for_each(|(_, value)| self.limit(value))
+    }
+
+    fn limit(&self, value: &mut Bson) -> Result<(), DissectError> {
+        match value {
+            Bson::Document(doc) => self.apply(doc),
+            Bson::Array(items) => items.iter_mut().try_for_each(|item| self.limit(item)),
+            Bson::String(text) => {
+                let Some((end, _)) = text.char_indices().nth(self.max) else {
+                    return Ok(());
+                };
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                let dropped = text[end..].chars().count();
+                let marker = match &self.extract {
+                    // named by content so repeated blobs are stored once
+                    Some(dir) => {
+                        let path = dir.join(format!("{:016x}.txt", seahash::hash(text.as_bytes())));
+                        if !path.exists() {
+                            std::fs::write(&path, text.as_bytes())?;
+                        }
+                        format!("…[{dropped} more characters in {}]", path.display())
+                    }
+                    None => format!("…[{dropped} more characters]"),
+                };
+                text.truncate(end);
+                text.push_str(&marker);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Line for the export summary
+    pub fn summary(&self) -> Option<String> {
+        let hits = self.hits.load(Ordering::Relaxed);
+        (hits > 0).then(|| {
+            let mut line = format!(
+                "Truncated {hits} strings longer than {} characters",
+                self.max
+            );
+            if let Some(dir) = &self.extract {
+                line.push_str(&format!(", full texts are in {}", dir.display()));
+            }
+            line
+        })
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::DissectError;
+
+/// A lock file holding the id of the run that has it. The file stays locked for as long as the run has it, so the
+/// lock of a run that crashed is let go of with the run and its id is only ever shown, never trusted
+pub(crate) struct Lock {
+    path: PathBuf,
+    /// Unlocked when it's closed, after the lock is removed
+    _file: File,
+}
+
+impl Lock {
+    /// Take the lock at `path`, none when another run has it
+    pub fn try_take(path: &Path) -> Result<Option<Self>, DissectError> {
+        Self::take_with(path, false)
+    }
+
+    /// Take the lock at `path`, waiting for the run that has it to let go first
+    pub fn take(path: &Path) -> Result<Self, DissectError> {
+        Ok(Self::take_with(path, true)?.expect("a lock that is waited for is taken"))
+    }
+
+    fn take_with(path: &Path, wait: bool) -> Result<Option<Self>, DissectError> {
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) if wait => {
+                    println!(
+                        "Waiting for {}, {} is its lock",
+                        holder(path),
+                        path.display()
+                    );
+                    file.lock()?;
+                }
+                Err(TryLockError::WouldBlock) => return Ok(None),
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+            // the run that had it may have removed it before letting go, a lock on a removed file keeps nobody out
+            if !is_at(&file, path)? {
+                continue;
+            }
+            let mut previous = String::new();
+            file.read_to_string(&mut previous)?;
+            if let Ok(pid) = previous.trim().parse::<u32>() {
+                println!(
+                    "Taking over {} from dissbson run {pid} that didn't finish",
+                    path.display()
+                );
+            }
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            writeln!(file, "{}", std::process::id())?;
+            return Ok(Some(Self {
+                path: path.to_path_buf(),
+                _file: file,
+            }));
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // removed while still locked, a run waiting on it takes it again at the same path
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The run that has the lock at `path`, for messages
+pub(crate) fn holder(path: &Path) -> String {
+    // written right after the lock is taken, a run that was just started may not have written it yet
+    match fs::read_to_string(path) {
+        Ok(pid) if !pid.trim().is_empty() => format!("dissbson run {}", pid.trim()),
+        _ => "another dissbson run".to_string(),
+    }
+}
+
+/// Whether an open file is still the one at `path`
+#[cfg(unix)]
+fn is_at(file: &File, path: &Path) -> Result<bool, DissectError> {
+    use std::{io::ErrorKind, os::unix::fs::MetadataExt};
+    let open = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(at) => Ok(open.dev() == at.dev() && open.ino() == at.ino()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether an open file is still the one at `path`, here a removed file keeps its path until it's closed and
+/// opening it fails instead
+#[cfg(not(unix))]
+fn is_at(_file: &File, _path: &Path) -> Result<bool, DissectError> {
+    Ok(true)
+}
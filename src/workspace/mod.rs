@@ -0,0 +1,63 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{manifest::with_suffix, DissectError};
+use lock::Lock;
+
+pub(crate) mod lock;
+
+/// The files of an export next to its output: `<output>.lock` keeps a second run from writing to the same output at
+/// the same time and the --single output is written to `<output>.partial` until it is complete.
+/// Both are removed when the run ends, a run that crashed leaves them behind and the next one takes them over
+pub(crate) struct Workspace {
+    partial: PathBuf,
+    /// Dropped after the partial output is removed
+    _lock: Lock,
+}
+
+impl Workspace {
+    pub fn acquire(output: &Path) -> Result<Self, DissectError> {
+        // `out/` keeps its lock next to the directory as `out.lock`, not inside it
+        let output = output.components().as_path();
+        let lock = with_suffix(output, ".lock");
+        let workspace = Self {
+            partial: with_suffix(output, ".partial"),
+            _lock: Lock::try_take(&lock)?.ok_or_else(|| {
+                DissectError::Parse(format!(
+                    "{} is being written by {}, {} is its lock",
+                    output.display(),
+                    lock::holder(&lock),
+                    lock.display()
+                ))
+            })?,
+        };
+        if workspace.partial.exists() {
+            fs::remove_file(&workspace.partial)?;
+            println!(
+                "Removed the partial output {} of a run that didn't finish",
+                workspace.partial.display()
+            );
+        }
+        Ok(workspace)
+    }
+
+    /// Where the --single output is written until the export is complete
+    pub fn partial(&self) -> &Path {
+        &self.partial
+    }
+
+    /// Move the complete --single output, or one of its --max-output-size shards, into place
+    pub fn commit(&self, to: &Path) -> Result<(), DissectError> {
+        fs::rename(&self.partial, to)?;
+        Ok(())
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        // a failed export leaves no half written output behind
+        let _ = fs::remove_file(&self.partial);
+    }
+}
@@ -0,0 +1,76 @@
+//! Helpers running the dissbson binary on dumps written by the tests
+
+// every test binary uses only some of them
+#![allow(dead_code)]
+
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use bson::Document;
+
+/// A directory of its own for a test under the target directory, emptied first
+pub fn workdir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("Failed to create test directory");
+    dir
+}
+
+/// Write the documents as a bson dump like mongodump does
+pub fn dump(path: &Path, docs: &[Document]) {
+    let mut out = BufWriter::new(File::create(path).expect("Failed to create dump"));
+    for doc in docs {
+        doc.to_writer(&mut out).expect("Failed to write document");
+    }
+}
+
+/// Run dissbson with the arguments in `dir`
+pub fn dissbson(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dissbson"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("Failed to run dissbson")
+}
+
+/// Run dissbson and return what it printed, failing the test when it fails
+pub fn run(dir: &Path, args: &[&str]) -> String {
+    let output = dissbson(dir, args);
+    let text = printed(&output);
+    assert!(output.status.success(), "dissbson {args:?} failed:\n{text}");
+    text
+}
+
+/// Everything a run printed, stdout then stderr
+pub fn printed(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+/// The number in the line of the summary starting with `prefix`, like `Exported 3 documents`
+pub fn count(text: &str, prefix: &str) -> usize {
+    let line = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(prefix))
+        .unwrap_or_else(|| panic!("No line starting with {prefix} in:\n{text}"));
+    line.split_whitespace()
+        .next()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_else(|| panic!("No count after {prefix} in:\n{text}"))
+}
+
+/// The lines of an ndjson file
+pub fn lines(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .expect("Failed to read output")
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
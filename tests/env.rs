@@ -0,0 +1,49 @@
+//! The values of --env variables are scrubbed from the errors of a failed export
+
+mod common;
+
+use bson::doc;
+use common::{dissbson, dump, printed, workdir};
+
+const TOKEN: &str = "s3cr3t-token";
+
+/// An export to an http sink nothing listens on, with the token in its url
+fn failing(name: &str, extra: &[&str]) -> String {
+    let dir = workdir(name);
+    dump(&dir.join("one.bson"), &[doc! { "_id": 1 }]);
+    let env = format!("TOKEN={TOKEN}");
+    let mut args = vec![
+        "one.bson",
+        "out.ndjson",
+        "--single",
+        "--env",
+        &env,
+        "--retries",
+        "0",
+        "--sink",
+        // nothing listens on port 1
+        "http://127.0.0.1:1/ingest?token=${TOKEN}",
+    ];
+    args.extend(extra);
+    let output = dissbson(&dir, &args);
+    let text = printed(&output);
+    assert!(!output.status.success(), "{text}");
+    text
+}
+
+#[test]
+fn error_returned_by_the_export_is_scrubbed() {
+    // the batch is posted when the export finishes
+    let text = failing("env_error", &[]);
+    assert!(text.contains("token=***"), "{text}");
+    assert!(!text.contains(TOKEN), "{text}");
+}
+
+#[test]
+fn panic_writing_to_a_sink_is_scrubbed() {
+    // a full batch is posted while documents are written
+    let text = failing("env_panic", &["--http-batch", "1"]);
+    assert!(text.contains("Failed to write to sink"), "{text}");
+    assert!(text.contains("token=***"), "{text}");
+    assert!(!text.contains(TOKEN), "{text}");
+}
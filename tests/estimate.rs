@@ -0,0 +1,19 @@
+//! --estimate on files that aren't dumps finds no documents instead of failing
+
+mod common;
+
+use std::fs;
+
+use common::{run, workdir};
+
+#[test]
+fn a_large_file_without_documents_has_none() {
+    let dir = workdir("estimate_noise");
+    // no header is plausible anywhere and places more than the largest document before the end have none after
+    fs::write(dir.join("noise.bin"), vec![0xff; 20 * 1024 * 1024]).expect("Failed to write file");
+    let text = run(
+        &dir,
+        &["noise.bin", "--estimate", "--estimate-samples", "8"],
+    );
+    assert!(text.contains("noise.bin: no documents found"), "{text}");
+}
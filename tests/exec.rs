@@ -0,0 +1,54 @@
+//! An --exec-filter command that fails a batch stops the export instead of leaving it waiting on the command
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use bson::doc;
+use common::{dissbson, dump, printed, workdir};
+
+/// Documents large enough that a batch fills the pipe to a command that stopped reading
+fn large(dir: &std::path::Path) {
+    let docs = (0..500)
+        .map(|n| doc! { "_id": n, "text": "x".repeat(4096) })
+        .collect::<Vec<_>>();
+    dump(&dir.join("large.bson"), &docs);
+}
+
+fn fails_quickly(name: &str, command: &str, error: &str) {
+    let dir = workdir(name);
+    large(&dir);
+    let started = Instant::now();
+    let output = dissbson(
+        &dir,
+        &[
+            "large.bson",
+            "out.ndjson",
+            "--single",
+            "--exec-filter",
+            command,
+        ],
+    );
+    let text = printed(&output);
+    assert!(!output.status.success(), "{text}");
+    assert!(text.contains(error), "{text}");
+    assert!(started.elapsed() < Duration::from_secs(20), "{text}");
+}
+
+#[test]
+fn invalid_answer_from_a_command_that_stopped_reading() {
+    fails_quickly(
+        "exec_invalid_answer",
+        "read line; echo nope; sleep 60",
+        "answered with invalid json",
+    );
+}
+
+#[test]
+fn command_exiting_before_answering_every_document() {
+    fails_quickly(
+        "exec_early_exit",
+        "read line; echo null; exec >&-; sleep 60",
+        "exited before answering every document",
+    );
+}
@@ -0,0 +1,124 @@
+//! The format and the compression of a --single output are taken from its name when they aren't given, files of
+//! one document each are compressed one by one
+
+mod common;
+
+use std::{fs, io::Read, path::Path};
+
+use bson::doc;
+use common::{dissbson, dump, lines, printed, run, workdir};
+
+/// Three documents in `docs.bson`
+fn docs(dir: &Path) {
+    let docs = (0..3)
+        .map(|n| doc! { "_id": n, "name": format!("n{n}") })
+        .collect::<Vec<_>>();
+    dump(&dir.join("docs.bson"), &docs);
+}
+
+#[test]
+fn extension_picks_the_format() {
+    let dir = workdir("format_ndjson");
+    docs(&dir);
+    run(&dir, &["docs.bson", "out.ndjson", "--single"]);
+    let lines = lines(&dir.join("out.ndjson"));
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with('{'), "{lines:?}");
+}
+
+#[test]
+fn given_format_wins() {
+    let dir = workdir("format_given");
+    docs(&dir);
+    run(
+        &dir,
+        &["docs.bson", "out.ndjson", "--single", "--format", "json"],
+    );
+    let text = fs::read_to_string(dir.join("out.ndjson")).expect("Failed to read output");
+    assert!(text.trim_start().starts_with('['), "{text}");
+}
+
+#[test]
+fn gz_suffix_compresses() {
+    let dir = workdir("format_gz");
+    docs(&dir);
+    run(&dir, &["docs.bson", "out.ndjson.gz", "--single"]);
+    let file = fs::File::open(dir.join("out.ndjson.gz")).expect("Failed to open output");
+    let mut text = String::new();
+    flate2::read::GzDecoder::new(file)
+        .read_to_string(&mut text)
+        .expect("Output isn't gzip");
+    assert_eq!(text.lines().count(), 3);
+}
+
+#[test]
+fn zst_suffix_compresses() {
+    let dir = workdir("format_zst");
+    docs(&dir);
+    run(&dir, &["docs.bson", "out.csv.zst", "--single"]);
+    let bytes = fs::read(dir.join("out.csv.zst")).expect("Failed to read output");
+    let text = String::from_utf8(zstd::decode_all(&bytes[..]).expect("Output isn't zstd"))
+        .expect("Output isn't text");
+    // a header and a row per document
+    assert_eq!(text.lines().count(), 4);
+    assert!(text.starts_with("_id,name"), "{text}");
+}
+
+#[test]
+fn compressed_output_cannot_be_verified() {
+    let dir = workdir("format_gz_verify");
+    docs(&dir);
+    let output = dissbson(
+        &dir,
+        &["docs.bson", "out.ndjson.gz", "--single", "--verify"],
+    );
+    let text = printed(&output);
+    assert!(!output.status.success(), "{text}");
+    assert!(text.contains("is compressed"), "{text}");
+}
+
+#[test]
+fn directory_output_is_named_freely() {
+    let dir = workdir("format_directory");
+    docs(&dir);
+    run(&dir, &["docs.bson", "out.sqlite"]);
+    let out = dir.join("out.sqlite");
+    assert!(out.is_dir());
+    let files = fs::read_dir(&out)
+        .expect("Failed to list output")
+        .map(|entry| entry.expect("Failed to list output").path())
+        .collect::<Vec<_>>();
+    assert!(!files.is_empty());
+    for file in files {
+        let text = fs::read_to_string(&file).expect("Failed to read output");
+        assert!(
+            text.trim_start().starts_with(['{', '[']),
+            "{file:?}: {text}"
+        );
+    }
+}
+
+#[test]
+fn every_file_is_compressed_on_its_own() {
+    let dir = workdir("format_compress_files");
+    docs(&dir);
+    // one thread writes every file with the same buffers
+    run(
+        &dir,
+        &["docs.bson", "out", "--compress", "zstd", "--threads", "1"],
+    );
+    let mut ids = fs::read_dir(dir.join("out"))
+        .expect("Failed to list output")
+        .map(|entry| {
+            let path = entry.expect("Failed to list output").path();
+            assert!(path.to_string_lossy().ends_with(".json.zst"), "{path:?}");
+            let bytes = fs::read(&path).expect("Failed to read output");
+            let json: serde_json::Value =
+                serde_json::from_slice(&zstd::decode_all(&bytes[..]).expect("Output isn't zstd"))
+                    .expect("Output isn't json");
+            json["_id"].as_i64().expect("No _id")
+        })
+        .collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, [0, 1, 2]);
+}
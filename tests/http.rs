@@ -0,0 +1,137 @@
+//! Fields named in the url of an http sink are percent-encoded and a batch sent again keeps its idempotency key
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use bson::doc;
+use common::{dump, run, workdir};
+
+/// A request a test server received, its request line and headers with lowercase names
+struct Request {
+    line: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Answer a request with each status in turn and return the requests
+fn serve(listener: TcpListener, statuses: Vec<u16>) -> thread::JoinHandle<Vec<Request>> {
+    thread::spawn(move || {
+        let mut requests = Vec::new();
+        for status in statuses {
+            let (stream, _) = listener.accept().expect("Failed to accept");
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("Failed to read request");
+            let mut headers = Vec::new();
+            loop {
+                let mut header = String::new();
+                reader
+                    .read_line(&mut header)
+                    .expect("Failed to read header");
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+                }
+            }
+            let request = Request {
+                line: line.trim().to_string(),
+                headers,
+            };
+            let length = request
+                .header("content-length")
+                .map_or(0, |length| length.parse().expect("Invalid content length"));
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).expect("Failed to read body");
+            reader
+                .get_mut()
+                .write_all(
+                    format!("HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .as_bytes(),
+                )
+                .expect("Failed to answer");
+            requests.push(request);
+        }
+        requests
+    })
+}
+
+/// Answer one request with 200 and return its request line
+fn serve_once(listener: TcpListener) -> thread::JoinHandle<String> {
+    let server = serve(listener, vec![200]);
+    thread::spawn(move || {
+        let mut requests = server.join().expect("Server panicked");
+        requests.remove(0).line
+    })
+}
+
+#[test]
+fn field_in_url_is_percent_encoded() {
+    let dir = workdir("http_template");
+    dump(
+        &dir.join("one.bson"),
+        &[doc! { "_id": 1, "type": "a b/c?d=é" }],
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+    let port = listener.local_addr().expect("No address").port();
+    let server = serve_once(listener);
+    let sink = format!("http://127.0.0.1:{port}/ingest/{{type}}?v=1");
+    run(
+        &dir,
+        &["one.bson", "out.ndjson", "--single", "--sink", &sink],
+    );
+    assert_eq!(
+        server.join().expect("Server panicked"),
+        "POST /ingest/a%20b%2Fc%3Fd%3D%C3%A9?v=1 HTTP/1.1"
+    );
+}
+
+#[test]
+fn a_retried_batch_keeps_its_idempotency_key() {
+    let dir = workdir("http_retry");
+    dump(
+        &dir.join("one.bson"),
+        &[doc! { "_id": 1 }, doc! { "_id": 2 }],
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+    let port = listener.local_addr().expect("No address").port();
+    let server = serve(listener, vec![503, 200]);
+    let sink = format!("http://127.0.0.1:{port}/ingest");
+    run(
+        &dir,
+        &[
+            "one.bson",
+            "out.ndjson",
+            "--single",
+            "--sink",
+            &sink,
+            "--retry-backoff",
+            "1",
+        ],
+    );
+    let requests = server.join().expect("Server panicked");
+    let keys = requests
+        .iter()
+        .map(|request| {
+            request
+                .header("idempotency-key")
+                .expect("No idempotency key")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(keys[0].len(), 64);
+    assert_eq!(keys[0], keys[1]);
+}
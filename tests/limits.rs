@@ -0,0 +1,30 @@
+//! The open file estimate counts the input files a batch reads, not every input file for every thread
+
+mod common;
+
+use std::process::Command;
+
+use bson::doc;
+use common::{dump, lines, printed, workdir};
+
+#[test]
+fn many_input_files_fit_a_low_limit() {
+    let dir = workdir("limits_many_files");
+    let input = dir.join("input");
+    std::fs::create_dir(&input).expect("Failed to create input directory");
+    for n in 0..100 {
+        dump(&input.join(format!("{n:03}.bson")), &[doc! { "_id": n }]);
+    }
+    let output = Command::new("sh")
+        .current_dir(&dir)
+        .arg("-c")
+        .arg(format!(
+            "ulimit -n 128 && exec {} input out.ndjson --single --threads 8 --batch 10",
+            env!("CARGO_BIN_EXE_dissbson")
+        ))
+        .output()
+        .expect("Failed to run dissbson");
+    let text = printed(&output);
+    assert!(output.status.success(), "{text}");
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 100);
+}
@@ -0,0 +1,67 @@
+//! Documents are selected and ordered by the ObjectId of their `_id`, wherever it is in the document
+
+mod common;
+
+use bson::{doc, oid::ObjectId, Document};
+use common::{dump, lines, run, workdir};
+
+/// An ObjectId created at `seconds` since the epoch
+fn oid(seconds: u32) -> ObjectId {
+    let mut bytes = [0; 12];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+/// Documents created in 2020, 2022, 2021 and 2023, the one of 2021 with its `_id` last, and one without an ObjectId
+fn docs(dir: &std::path::Path) {
+    let docs: Vec<Document> = vec![
+        doc! { "_id": oid(1_590_000_000), "n": 2020 },
+        doc! { "_id": oid(1_650_000_000), "n": 2022 },
+        doc! { "n": 2021, "_id": oid(1_620_000_000) },
+        doc! { "_id": "plain", "n": 0 },
+        doc! { "_id": oid(1_680_000_000), "n": 2023 },
+    ];
+    dump(&dir.join("docs.bson"), &docs);
+}
+
+/// The `n` of every exported document in order
+fn years(path: &std::path::Path) -> Vec<i64> {
+    lines(path)
+        .iter()
+        .map(|line| {
+            let doc: serde_json::Value = serde_json::from_str(line).expect("Invalid json");
+            doc["n"].as_i64().expect("No n")
+        })
+        .collect()
+}
+
+#[test]
+fn order_by_oid() {
+    let dir = workdir("oid_order");
+    docs(&dir);
+    run(
+        &dir,
+        &["docs.bson", "out.ndjson", "--single", "--order-by-oid"],
+    );
+    assert_eq!(years(&dir.join("out.ndjson")), [2020, 2021, 2022, 2023, 0]);
+}
+
+#[test]
+fn oid_range() {
+    let dir = workdir("oid_range");
+    docs(&dir);
+    run(
+        &dir,
+        &[
+            "docs.bson",
+            "out.ndjson",
+            "--single",
+            "--oid-after",
+            "2021-01-01",
+            "--oid-before",
+            "2023-01-01",
+            "--order-by-oid",
+        ],
+    );
+    assert_eq!(years(&dir.join("out.ndjson")), [2021, 2022]);
+}
@@ -0,0 +1,27 @@
+//! A `rediss://` sink connects over TLS
+
+mod common;
+
+use bson::doc;
+use common::{dissbson, dump, printed, workdir};
+
+#[test]
+fn rediss_url_is_supported() {
+    let dir = workdir("redis_tls");
+    dump(&dir.join("one.bson"), &[doc! { "_id": 1 }]);
+    // nothing listens on port 1, the connection is refused before a TLS handshake
+    let output = dissbson(
+        &dir,
+        &[
+            "one.bson",
+            "out.ndjson",
+            "--single",
+            "--sink",
+            "rediss://127.0.0.1:1/",
+        ],
+    );
+    let text = printed(&output);
+    assert!(!output.status.success(), "{text}");
+    assert!(!text.contains("feature is not enabled"), "{text}");
+    assert!(text.contains("Connection refused"), "{text}");
+}
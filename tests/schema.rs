@@ -0,0 +1,154 @@
+//! The columns of csv and database outputs are picked from the documents the export writes, after the selection,
+//! the filters and the transforms
+
+mod common;
+
+use std::path::Path;
+
+use bson::doc;
+use common::{dump, lines, run, workdir};
+
+/// Five people with an `age`, then five with a `city`, every one with a `secret`
+fn people(dir: &Path) {
+    let docs = (0..10)
+        .map(|n| {
+            let mut doc = doc! { "_id": n, "name": format!("p{n}"), "secret": "x" };
+            if n < 5 {
+                doc.insert("age", 20 + n);
+            } else {
+                doc.insert("city", "Paris");
+            }
+            doc
+        })
+        .collect::<Vec<_>>();
+    dump(&dir.join("people.bson"), &docs);
+}
+
+/// The header of a csv output
+fn header(path: &Path) -> Vec<String> {
+    lines(path)[0].split(',').map(str::to_string).collect()
+}
+
+#[test]
+fn csv_columns_follow_the_transforms() {
+    let dir = workdir("schema_csv_transforms");
+    people(&dir);
+    run(
+        &dir,
+        &[
+            "people.bson",
+            "out.csv",
+            "--single",
+            "--exclude-fields",
+            "secret",
+            "--jq",
+            ". + {score: 1}",
+        ],
+    );
+    let header = header(&dir.join("out.csv"));
+    assert!(header.contains(&"score".to_string()), "{header:?}");
+    assert!(!header.contains(&"secret".to_string()), "{header:?}");
+}
+
+#[test]
+fn csv_columns_come_from_the_selected_documents() {
+    let dir = workdir("schema_csv_selected");
+    people(&dir);
+    run(
+        &dir,
+        &[
+            "people.bson",
+            "out.csv",
+            "--single",
+            "--query",
+            r#"{"city": "Paris"}"#,
+            "--schema-sample",
+            "3",
+        ],
+    );
+    let header = header(&dir.join("out.csv"));
+    assert!(header.contains(&"city".to_string()), "{header:?}");
+    assert!(!header.contains(&"age".to_string()), "{header:?}");
+    assert_eq!(lines(&dir.join("out.csv")).len(), 6);
+}
+
+#[test]
+fn sqlite_promotes_fields_the_transforms_add() {
+    let dir = workdir("schema_sqlite_promote");
+    people(&dir);
+    run(
+        &dir,
+        &[
+            "people.bson",
+            "out.sqlite",
+            "--single",
+            "--jq",
+            ". + {score: 7}",
+            "--exclude-fields",
+            "secret",
+            "--promote",
+            "score,secret",
+        ],
+    );
+    let db = rusqlite::Connection::open(dir.join("out.sqlite")).expect("Failed to open output");
+    let columns = db
+        .prepare("SELECT name FROM pragma_table_info('docs')")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .expect("Failed to list columns");
+    // removed before the output, it gets no column
+    assert!(!columns.contains(&"secret".to_string()), "{columns:?}");
+    let score: String = db
+        .query_row("SELECT typeof(score) FROM docs LIMIT 1", [], |row| {
+            row.get(0)
+        })
+        .expect("No score column");
+    assert_eq!(score, "integer");
+}
+
+/// The summary line of the columns a database output picked, like `Picked 4 parquet columns from the first 3 documents`
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+fn picked(dir: &Path, output: &str) -> String {
+    people(dir);
+    let text = run(
+        dir,
+        &[
+            "people.bson",
+            output,
+            "--single",
+            "--slice",
+            "..3",
+            "--exclude-fields",
+            "secret",
+            "--jq",
+            ". + {score: 1}",
+        ],
+    );
+    text.lines()
+        .find(|line| line.starts_with("Picked"))
+        .unwrap_or_else(|| panic!("No columns picked in:\n{text}"))
+        .to_string()
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_columns_follow_the_selection_and_the_transforms() {
+    let dir = workdir("schema_parquet");
+    assert_eq!(
+        picked(&dir, "out.parquet"),
+        "Picked 4 parquet columns from the first 3 documents"
+    );
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn arrow_columns_follow_the_selection_and_the_transforms() {
+    let dir = workdir("schema_arrow");
+    assert_eq!(
+        picked(&dir, "out.feather"),
+        "Picked 4 arrow columns from the first 3 documents"
+    );
+}
@@ -0,0 +1,134 @@
+//! The export summary counts the documents the outputs got, whatever the stages before them added or dropped
+
+mod common;
+
+use bson::doc;
+use common::{count, dump, lines, run, workdir};
+
+/// Three orders of two items each
+fn orders(dir: &std::path::Path) {
+    let docs = (0..3)
+        .map(|n| {
+            doc! {
+                "_id": n,
+                "status": if n == 0 { "open" } else { "paid" },
+                "items": [
+                    { "sku": format!("a{n}"), "kind": "book" },
+                    { "sku": format!("b{n}"), "kind": "toy" },
+                ],
+            }
+        })
+        .collect::<Vec<_>>();
+    dump(&dir.join("orders.bson"), &docs);
+}
+
+#[test]
+fn jq_splitting_documents_is_counted() {
+    let dir = workdir("summary_jq_split");
+    orders(&dir);
+    let text = run(
+        &dir,
+        &["orders.bson", "out.ndjson", "--single", "--jq", ".items[]"],
+    );
+    assert_eq!(count(&text, "Exported"), 6);
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 6);
+}
+
+#[test]
+fn jq_dropping_documents_is_counted() {
+    let dir = workdir("summary_jq_drop");
+    orders(&dir);
+    let text = run(
+        &dir,
+        &[
+            "orders.bson",
+            "out.ndjson",
+            "--single",
+            "--jq",
+            r#"select(.status == "paid")"#,
+        ],
+    );
+    assert_eq!(count(&text, "Exported"), 2);
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 2);
+}
+
+#[test]
+fn routing_more_documents_than_were_read_does_not_underflow() {
+    let dir = workdir("summary_jq_route");
+    orders(&dir);
+    let text = run(
+        &dir,
+        &[
+            "orders.bson",
+            "out.ndjson",
+            "--single",
+            "--jq",
+            ".items[]",
+            "--route",
+            "kind=book:books.ndjson",
+            "--route",
+            "kind=toy:toys.ndjson",
+        ],
+    );
+    assert_eq!(count(&text, "Exported"), 0);
+    assert_eq!(lines(&dir.join("books.ndjson")).len(), 3);
+    assert_eq!(lines(&dir.join("toys.ndjson")).len(), 3);
+}
+
+#[test]
+fn jmespath_projecting_documents_is_counted() {
+    let dir = workdir("summary_jmespath_split");
+    orders(&dir);
+    let text = run(
+        &dir,
+        &[
+            "orders.bson",
+            "out.ndjson",
+            "--single",
+            "--jmespath",
+            "items[*]",
+        ],
+    );
+    assert_eq!(count(&text, "Exported"), 6);
+    assert_eq!(count(&text, "--jmespath dropped"), 0);
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 6);
+}
+
+#[test]
+fn jmespath_filtering_documents_is_counted() {
+    let dir = workdir("summary_jmespath_drop");
+    orders(&dir);
+    let text = run(
+        &dir,
+        &[
+            "orders.bson",
+            "out.ndjson",
+            "--single",
+            "--jmespath",
+            "[@][?status == 'open'] | [0]",
+        ],
+    );
+    assert_eq!(count(&text, "Exported"), 1);
+    assert_eq!(count(&text, "--jmespath dropped"), 2);
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 1);
+}
+
+#[test]
+fn exec_filter_dropping_documents_is_counted() {
+    let dir = workdir("summary_exec_drop");
+    orders(&dir);
+    let text = run(
+        &dir,
+        &[
+            "orders.bson",
+            "out.ndjson",
+            "--single",
+            "--exec-filter",
+            r#"while IFS= read -r line; do case "$line" in *'"paid"'*) echo null;; *) printf '%s\n' "$line";; esac; done"#,
+        ],
+    );
+    assert_eq!(count(&text, "Exported"), 1);
+    assert_eq!(count(&text, "Piped"), 3);
+    assert!(text.contains("2 were dropped"), "{text}");
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 1);
+}
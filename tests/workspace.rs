@@ -0,0 +1,87 @@
+//! The lock of an output keeps a second run out while the first holds it, a lock left behind is taken over and
+//! runs sharing a dump take turns building its index
+
+mod common;
+
+use std::{
+    fs::{self, File},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use bson::doc;
+use common::{dissbson, dump, lines, printed, run, workdir};
+
+fn three(dir: &std::path::Path) {
+    dump(
+        &dir.join("dump.bson"),
+        &[doc! { "_id": 1 }, doc! { "_id": 2 }, doc! { "_id": 3 }],
+    );
+}
+
+#[test]
+fn a_held_lock_keeps_a_second_run_out() {
+    let dir = workdir("workspace_held");
+    three(&dir);
+    // held before the run that took it wrote its id
+    let held = File::create(dir.join("out.ndjson.lock")).expect("Failed to create lock");
+    held.lock().expect("Failed to lock");
+    let output = dissbson(&dir, &["dump.bson", "out.ndjson", "--single"]);
+    let text = printed(&output);
+    assert!(!output.status.success(), "{text}");
+    assert!(
+        text.contains("out.ndjson is being written by another dissbson run"),
+        "{text}"
+    );
+    assert!(!dir.join("out.ndjson").exists());
+
+    drop(held);
+    run(&dir, &["dump.bson", "out.ndjson", "--single"]);
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 3);
+    assert!(!dir.join("out.ndjson.lock").exists());
+}
+
+#[test]
+fn a_lock_left_behind_is_taken_over() {
+    let dir = workdir("workspace_left_behind");
+    three(&dir);
+    fs::write(dir.join("out.ndjson.lock"), "4194305\n").expect("Failed to write lock");
+    let text = run(&dir, &["dump.bson", "out.ndjson", "--single"]);
+    assert!(
+        text.contains("Taking over out.ndjson.lock from dissbson run 4194305"),
+        "{text}"
+    );
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 3);
+    assert!(!dir.join("out.ndjson.lock").exists());
+}
+
+#[test]
+fn a_run_waits_for_the_index_another_builds() {
+    let dir = workdir("workspace_index");
+    three(&dir);
+    let held = File::create(dir.join("dump.idx.dat.lock")).expect("Failed to create lock");
+    held.lock().expect("Failed to lock");
+    let mut waiting = Command::new(env!("CARGO_BIN_EXE_dissbson"))
+        .current_dir(&dir)
+        .args(["dump.bson", "out.ndjson", "--single"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run dissbson");
+    thread::sleep(Duration::from_millis(500));
+    assert!(
+        waiting.try_wait().expect("Failed to poll").is_none(),
+        "Built the index while another run held its lock"
+    );
+    assert!(!dir.join("dump.idx.dat").exists());
+
+    drop(held);
+    let output = waiting.wait_with_output().expect("Failed to wait");
+    let text = printed(&output);
+    assert!(output.status.success(), "{text}");
+    assert!(text.contains("Waiting for"), "{text}");
+    assert_eq!(lines(&dir.join("out.ndjson")).len(), 3);
+    assert!(dir.join("dump.idx.dat").exists());
+    assert!(!dir.join("dump.idx.dat.lock").exists());
+}